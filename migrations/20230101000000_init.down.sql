@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_products_stock;
+DROP INDEX IF EXISTS idx_products_expiry;
+DROP INDEX IF EXISTS idx_products_barcode;
+
+DROP TABLE IF EXISTS products;
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_products_deleted;
+
+ALTER TABLE products DROP COLUMN deleted_at;
+ALTER TABLE products DROP COLUMN updated_at;
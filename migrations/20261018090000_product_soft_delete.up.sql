@@ -0,0 +1,4 @@
+ALTER TABLE products ADD COLUMN updated_at TIMESTAMP;
+ALTER TABLE products ADD COLUMN deleted_at TIMESTAMP;
+
+CREATE INDEX IF NOT EXISTS idx_products_deleted ON products(deleted_at);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_lots_expiry;
+DROP INDEX IF EXISTS idx_lots_product;
+
+DROP TABLE IF EXISTS lots;
@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS lots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    product_id INTEGER NOT NULL REFERENCES products(id),
+    batch_id INTEGER NOT NULL,
+    lot_code TEXT NOT NULL,
+    quantity INTEGER NOT NULL,
+    production_date TEXT NOT NULL,
+    expiry_date TEXT NOT NULL,
+    supplier_invoice TEXT,
+    landed_cost REAL NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (product_id, batch_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_lots_product ON lots(product_id);
+CREATE INDEX IF NOT EXISTS idx_lots_expiry ON lots(expiry_date);
+
+-- Existing stock becomes one lot per product
+INSERT INTO lots (product_id, batch_id, lot_code, quantity, production_date, expiry_date, landed_cost)
+SELECT id, batch_id, internal_code, stock_quantity, production_date, expiry_date, purchase_price
+FROM products
+WHERE stock_quantity > 0;
@@ -0,0 +1,5 @@
+DROP TRIGGER IF EXISTS stock_movements_no_delete;
+DROP TRIGGER IF EXISTS stock_movements_no_update;
+DROP INDEX IF EXISTS idx_stock_movements_product;
+
+DROP TABLE IF EXISTS stock_movements;
@@ -0,0 +1,32 @@
+CREATE TABLE IF NOT EXISTS stock_movements (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    product_id INTEGER NOT NULL REFERENCES products(id),
+    lot_id INTEGER REFERENCES lots(id),
+    movement_type TEXT NOT NULL,
+    quantity INTEGER NOT NULL,
+    reason TEXT,
+    reference TEXT,
+    note TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_stock_movements_product ON stock_movements(product_id, created_at);
+
+-- The ledger is append-only; corrections are new movements
+CREATE TRIGGER IF NOT EXISTS stock_movements_no_update
+BEFORE UPDATE ON stock_movements
+BEGIN
+    SELECT RAISE(ABORT, 'stock_movements is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS stock_movements_no_delete
+BEFORE DELETE ON stock_movements
+BEGIN
+    SELECT RAISE(ABORT, 'stock_movements is append-only');
+END;
+
+-- Current lot quantities become opening balances
+INSERT INTO stock_movements (product_id, lot_id, movement_type, quantity)
+SELECT product_id, id, 'opening', quantity
+FROM lots
+WHERE quantity <> 0;
@@ -0,0 +1,19 @@
+ALTER TABLE products ADD COLUMN monthly_sales INTEGER NOT NULL DEFAULT 0;
+
+-- Last 30 days of sales, counting only the most authoritative source per day
+-- (pos, then ledger, then estimate)
+UPDATE products SET monthly_sales = (
+    SELECT COALESCE(SUM(d.quantity), 0) FROM daily_sales d
+    WHERE d.product_id = products.id
+      AND d.sale_date > date('now', '-30 days')
+      AND NOT EXISTS (
+          SELECT 1 FROM daily_sales better
+          WHERE better.product_id = d.product_id
+            AND better.sale_date = d.sale_date
+            AND instr('pos,ledger,estimate', better.source) < instr('pos,ledger,estimate', d.source)
+      )
+);
+
+DROP INDEX IF EXISTS idx_daily_sales_date;
+
+DROP TABLE IF EXISTS daily_sales;
@@ -0,0 +1,33 @@
+CREATE TABLE IF NOT EXISTS daily_sales (
+    product_id INTEGER NOT NULL REFERENCES products(id),
+    sale_date TEXT NOT NULL,
+    source TEXT NOT NULL,
+    quantity INTEGER NOT NULL,
+    PRIMARY KEY (product_id, sale_date, source)
+);
+
+CREATE INDEX IF NOT EXISTS idx_daily_sales_date ON daily_sales(sale_date);
+
+-- Sales and returns already in the stock ledger
+INSERT INTO daily_sales (product_id, sale_date, source, quantity)
+SELECT product_id, date(created_at), 'ledger', -SUM(quantity)
+FROM stock_movements
+WHERE movement_type IN ('sale', 'return')
+GROUP BY product_id, date(created_at);
+
+-- Products without recorded sales keep their typed-in monthly figure as an
+-- estimate, spread evenly over the last 30 days
+WITH RECURSIVE days(n) AS (
+    SELECT 0
+    UNION ALL
+    SELECT n + 1 FROM days WHERE n < 29
+)
+INSERT INTO daily_sales (product_id, sale_date, source, quantity)
+SELECT p.id, date('now', '-' || days.n || ' days'), 'estimate',
+       (p.monthly_sales * (days.n + 1)) / 30 - (p.monthly_sales * days.n) / 30
+FROM products p, days
+WHERE p.monthly_sales > 0
+  AND NOT EXISTS (SELECT 1 FROM daily_sales s WHERE s.product_id = p.id)
+  AND (p.monthly_sales * (days.n + 1)) / 30 - (p.monthly_sales * days.n) / 30 > 0;
+
+ALTER TABLE products DROP COLUMN monthly_sales;
@@ -0,0 +1,6 @@
+DROP TRIGGER IF EXISTS daily_sales_strict_dates_insert;
+DROP TRIGGER IF EXISTS lots_strict_dates_update;
+DROP TRIGGER IF EXISTS lots_strict_dates_insert;
+DROP TRIGGER IF EXISTS products_strict_dates_update;
+DROP TRIGGER IF EXISTS products_strict_dates_insert;
+DROP TABLE IF EXISTS date_repairs;
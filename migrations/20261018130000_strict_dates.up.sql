@@ -0,0 +1,75 @@
+-- Dates are stored as ISO 8601 text (YYYY-MM-DD). Normalize values SQLite can
+-- read, e.g. with a time part; unreadable production dates become today and
+-- unreadable expiry dates the production date, so they show up as expired.
+-- Every value changed is kept in date_repairs with what replaced it, so the
+-- real dates can be looked up and put back.
+CREATE TABLE IF NOT EXISTS date_repairs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_name TEXT NOT NULL,
+    row_id INTEGER NOT NULL,
+    column_name TEXT NOT NULL,
+    original_value TEXT,
+    replaced_with TEXT,
+    repaired_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO date_repairs (table_name, row_id, column_name, original_value, replaced_with)
+SELECT 'products', id, 'production_date', production_date, COALESCE(date(production_date), date('now'))
+FROM products WHERE production_date IS NOT date(production_date);
+UPDATE products SET production_date = COALESCE(date(production_date), date('now'))
+WHERE production_date IS NOT date(production_date);
+
+INSERT INTO date_repairs (table_name, row_id, column_name, original_value, replaced_with)
+SELECT 'products', id, 'expiry_date', expiry_date, COALESCE(date(expiry_date), production_date)
+FROM products WHERE expiry_date IS NOT date(expiry_date);
+UPDATE products SET expiry_date = COALESCE(date(expiry_date), production_date)
+WHERE expiry_date IS NOT date(expiry_date);
+
+INSERT INTO date_repairs (table_name, row_id, column_name, original_value, replaced_with)
+SELECT 'lots', id, 'production_date', production_date, COALESCE(date(production_date), date('now'))
+FROM lots WHERE production_date IS NOT date(production_date);
+UPDATE lots SET production_date = COALESCE(date(production_date), date('now'))
+WHERE production_date IS NOT date(production_date);
+
+INSERT INTO date_repairs (table_name, row_id, column_name, original_value, replaced_with)
+SELECT 'lots', id, 'expiry_date', expiry_date, COALESCE(date(expiry_date), production_date)
+FROM lots WHERE expiry_date IS NOT date(expiry_date);
+UPDATE lots SET expiry_date = COALESCE(date(expiry_date), production_date)
+WHERE expiry_date IS NOT date(expiry_date);
+
+-- date() rolls invalid days over (2025-02-30 becomes 2025-03-02), so a value
+-- that does not survive it unchanged is rejected
+CREATE TRIGGER IF NOT EXISTS products_strict_dates_insert
+BEFORE INSERT ON products
+WHEN NEW.production_date IS NOT date(NEW.production_date) OR NEW.expiry_date IS NOT date(NEW.expiry_date)
+BEGIN
+    SELECT RAISE(ABORT, 'product dates must be valid YYYY-MM-DD dates');
+END;
+
+CREATE TRIGGER IF NOT EXISTS products_strict_dates_update
+BEFORE UPDATE OF production_date, expiry_date ON products
+WHEN NEW.production_date IS NOT date(NEW.production_date) OR NEW.expiry_date IS NOT date(NEW.expiry_date)
+BEGIN
+    SELECT RAISE(ABORT, 'product dates must be valid YYYY-MM-DD dates');
+END;
+
+CREATE TRIGGER IF NOT EXISTS lots_strict_dates_insert
+BEFORE INSERT ON lots
+WHEN NEW.production_date IS NOT date(NEW.production_date) OR NEW.expiry_date IS NOT date(NEW.expiry_date)
+BEGIN
+    SELECT RAISE(ABORT, 'lot dates must be valid YYYY-MM-DD dates');
+END;
+
+CREATE TRIGGER IF NOT EXISTS lots_strict_dates_update
+BEFORE UPDATE OF production_date, expiry_date ON lots
+WHEN NEW.production_date IS NOT date(NEW.production_date) OR NEW.expiry_date IS NOT date(NEW.expiry_date)
+BEGIN
+    SELECT RAISE(ABORT, 'lot dates must be valid YYYY-MM-DD dates');
+END;
+
+CREATE TRIGGER IF NOT EXISTS daily_sales_strict_dates_insert
+BEFORE INSERT ON daily_sales
+WHEN NEW.sale_date IS NOT date(NEW.sale_date)
+BEGIN
+    SELECT RAISE(ABORT, 'sale dates must be valid YYYY-MM-DD dates');
+END;
@@ -0,0 +1,6 @@
+-- Rules are defined in the rules file; this records runtime enable/disable switches
+CREATE TABLE IF NOT EXISTS alert_rule_state (
+    rule_id TEXT PRIMARY KEY,
+    enabled BOOLEAN NOT NULL,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_alert_notes_alert;
+DROP TABLE IF EXISTS alert_notes;
+DROP INDEX IF EXISTS idx_alerts_status;
+DROP TABLE IF EXISTS alerts;
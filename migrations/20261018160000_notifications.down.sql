@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS notification_digests;
+DROP INDEX IF EXISTS idx_alert_deliveries_channel;
+DROP TABLE IF EXISTS alert_deliveries;
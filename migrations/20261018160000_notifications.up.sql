@@ -0,0 +1,18 @@
+-- One row per alert sent on a channel, so an alert is only sent again once it
+-- escalates or reopens
+CREATE TABLE IF NOT EXISTS alert_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    alert_id INTEGER NOT NULL REFERENCES alerts(id),
+    channel TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    digest BOOLEAN NOT NULL DEFAULT 0,
+    sent_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_alert_deliveries_channel ON alert_deliveries(channel, alert_id);
+
+-- Local date of the last daily digest of each digest channel
+CREATE TABLE IF NOT EXISTS notification_digests (
+    channel TEXT PRIMARY KEY,
+    sent_on TEXT NOT NULL
+);
@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS purchase_order_lines;
+DROP INDEX IF EXISTS idx_purchase_orders_status;
+DROP TABLE IF EXISTS purchase_orders;
+
+ALTER TABLE products DROP COLUMN case_pack;
@@ -0,0 +1,12 @@
+DROP INDEX IF EXISTS idx_purchase_orders_status;
+ALTER TABLE purchase_orders ADD COLUMN supplier TEXT NOT NULL DEFAULT '';
+UPDATE purchase_orders SET supplier = (SELECT name FROM suppliers WHERE id = purchase_orders.supplier_id);
+ALTER TABLE purchase_orders DROP COLUMN supplier_id;
+CREATE INDEX IF NOT EXISTS idx_purchase_orders_status ON purchase_orders(status, supplier);
+
+DROP INDEX IF EXISTS idx_products_supplier;
+ALTER TABLE products ADD COLUMN supplier TEXT NOT NULL DEFAULT '';
+UPDATE products SET supplier = (SELECT name FROM suppliers WHERE id = products.supplier_id);
+ALTER TABLE products DROP COLUMN supplier_id;
+
+DROP TABLE IF EXISTS suppliers;
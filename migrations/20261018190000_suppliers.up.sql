@@ -0,0 +1,74 @@
+CREATE TABLE IF NOT EXISTS suppliers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    -- Lowercased name without a trailing legal form, so "XYZ Import AB" and
+    -- "xyz import" are the same supplier
+    name_key TEXT NOT NULL UNIQUE,
+    contact_name TEXT,
+    email TEXT,
+    phone TEXT,
+    address TEXT,
+    country TEXT,
+    currency TEXT NOT NULL DEFAULT 'SEK',
+    -- Falls back to reorder.default_lead_time_days when not set
+    lead_time_days INTEGER CHECK (lead_time_days >= 0),
+    min_order_value REAL NOT NULL DEFAULT 0,
+    payment_terms TEXT,
+    vat_number TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Every supplier spelling in use, keyed the way the application keys names
+CREATE TEMP TABLE supplier_names AS
+SELECT name, SUM(products) AS products, rtrim(lower(trim(name)), ' .,') AS name_key
+FROM (
+    SELECT supplier AS name, COUNT(*) AS products FROM products GROUP BY supplier
+    UNION ALL
+    SELECT supplier, 0 FROM purchase_orders GROUP BY supplier
+)
+GROUP BY name;
+
+-- Collapse runs of spaces of any length: each space becomes char(1) char(2), the
+-- char(2) char(1) pairs between neighbouring spaces drop out, and what is left of
+-- each run turns back into one space
+UPDATE supplier_names SET name_key = replace(replace(replace(name_key, ' ', char(1) || char(2)), char(2) || char(1), ''), char(1) || char(2), ' ');
+UPDATE supplier_names SET name_key = rtrim(CASE
+    WHEN name_key LIKE '_% gmbh' OR name_key LIKE '_% corp' OR name_key LIKE '_% sarl'
+        THEN substr(name_key, 1, length(name_key) - 5)
+    WHEN name_key LIKE '_% ltd' OR name_key LIKE '_% inc' OR name_key LIKE '_% llc' OR name_key LIKE '_% sal'
+        OR name_key LIKE '_% plc' OR name_key LIKE '_% aps' OR name_key LIKE '_% srl'
+        THEN substr(name_key, 1, length(name_key) - 4)
+    WHEN name_key LIKE '_% ab' OR name_key LIKE '_% oy' OR name_key LIKE '_% as' OR name_key LIKE '_% sa'
+        OR name_key LIKE '_% bv' OR name_key LIKE '_% ag' OR name_key LIKE '_% hb' OR name_key LIKE '_% kb'
+        THEN substr(name_key, 1, length(name_key) - 3)
+    ELSE name_key
+END, ' .,');
+
+-- The spelling most products use names the supplier; ties go to the longer one
+INSERT INTO suppliers (name, name_key)
+SELECT (
+    SELECT n.name FROM supplier_names n
+    WHERE n.name_key = k.name_key
+    ORDER BY n.products DESC, length(n.name) DESC, n.name
+    LIMIT 1
+), k.name_key
+FROM supplier_names k
+GROUP BY k.name_key;
+
+ALTER TABLE products ADD COLUMN supplier_id INTEGER REFERENCES suppliers(id);
+UPDATE products SET supplier_id = (
+    SELECT s.id FROM suppliers s JOIN supplier_names n ON n.name_key = s.name_key WHERE n.name = products.supplier
+);
+ALTER TABLE products DROP COLUMN supplier;
+CREATE INDEX IF NOT EXISTS idx_products_supplier ON products(supplier_id);
+
+ALTER TABLE purchase_orders ADD COLUMN supplier_id INTEGER REFERENCES suppliers(id);
+UPDATE purchase_orders SET supplier_id = (
+    SELECT s.id FROM suppliers s JOIN supplier_names n ON n.name_key = s.name_key WHERE n.name = purchase_orders.supplier
+);
+DROP INDEX IF EXISTS idx_purchase_orders_status;
+ALTER TABLE purchase_orders DROP COLUMN supplier;
+CREATE INDEX IF NOT EXISTS idx_purchase_orders_status ON purchase_orders(status, supplier_id);
+
+DROP TABLE supplier_names;
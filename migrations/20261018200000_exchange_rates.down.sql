@@ -0,0 +1,10 @@
+ALTER TABLE purchase_orders DROP COLUMN currency;
+
+ALTER TABLE products DROP COLUMN handling_cost;
+ALTER TABLE products DROP COLUMN duty_percent;
+ALTER TABLE products DROP COLUMN freight_cost;
+ALTER TABLE products DROP COLUMN retail_currency;
+ALTER TABLE products DROP COLUMN wholesale_currency;
+ALTER TABLE products DROP COLUMN purchase_currency;
+
+DROP TABLE IF EXISTS exchange_rates;
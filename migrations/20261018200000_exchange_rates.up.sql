@@ -0,0 +1,28 @@
+-- Units of the base currency per unit of `currency`, from `valid_from` until
+-- the currency's next rate
+CREATE TABLE IF NOT EXISTS exchange_rates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    currency TEXT NOT NULL CHECK (length(currency) = 3 AND currency = upper(currency)),
+    rate REAL NOT NULL CHECK (rate > 0),
+    valid_from TEXT NOT NULL CHECK (valid_from IS date(valid_from)),
+    source TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (currency, valid_from)
+);
+
+-- Prices so far were entered in SEK, or in the supplier's currency for
+-- purchase prices
+ALTER TABLE products ADD COLUMN purchase_currency TEXT NOT NULL DEFAULT 'SEK';
+ALTER TABLE products ADD COLUMN wholesale_currency TEXT NOT NULL DEFAULT 'SEK';
+ALTER TABLE products ADD COLUMN retail_currency TEXT NOT NULL DEFAULT 'SEK';
+UPDATE products SET purchase_currency = (SELECT currency FROM suppliers WHERE id = products.supplier_id);
+
+-- Landed cost per unit: freight and handling in the purchase currency, duty as
+-- a percentage of purchase price plus freight
+ALTER TABLE products ADD COLUMN freight_cost REAL NOT NULL DEFAULT 0;
+ALTER TABLE products ADD COLUMN duty_percent REAL NOT NULL DEFAULT 0;
+ALTER TABLE products ADD COLUMN handling_cost REAL NOT NULL DEFAULT 0;
+
+-- Orders keep the currency they were placed in if the supplier's changes
+ALTER TABLE purchase_orders ADD COLUMN currency TEXT NOT NULL DEFAULT 'SEK';
+UPDATE purchase_orders SET currency = (SELECT currency FROM suppliers WHERE id = purchase_orders.supplier_id);
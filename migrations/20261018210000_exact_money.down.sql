@@ -0,0 +1,55 @@
+CREATE TABLE exchange_rates_real (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    currency TEXT NOT NULL CHECK (length(currency) = 3 AND currency = upper(currency)),
+    rate REAL NOT NULL CHECK (rate > 0),
+    valid_from TEXT NOT NULL CHECK (valid_from IS date(valid_from)),
+    source TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (currency, valid_from)
+);
+INSERT INTO exchange_rates_real (id, currency, rate, valid_from, source, created_at)
+SELECT id, currency, CAST(rate AS REAL), valid_from, source, created_at FROM exchange_rates;
+DROP TABLE exchange_rates;
+ALTER TABLE exchange_rates_real RENAME TO exchange_rates;
+
+ALTER TABLE products ADD COLUMN duty_percent_real REAL NOT NULL DEFAULT 0;
+UPDATE products SET duty_percent_real = CAST(duty_percent AS REAL);
+ALTER TABLE products DROP COLUMN duty_percent;
+ALTER TABLE products RENAME COLUMN duty_percent_real TO duty_percent;
+
+ALTER TABLE purchase_order_lines ADD COLUMN unit_cost_real REAL NOT NULL DEFAULT 0;
+UPDATE purchase_order_lines SET unit_cost_real = unit_cost / 10000.0;
+ALTER TABLE purchase_order_lines DROP COLUMN unit_cost;
+ALTER TABLE purchase_order_lines RENAME COLUMN unit_cost_real TO unit_cost;
+
+ALTER TABLE suppliers ADD COLUMN min_order_value_real REAL NOT NULL DEFAULT 0;
+UPDATE suppliers SET min_order_value_real = min_order_value / 10000.0;
+ALTER TABLE suppliers DROP COLUMN min_order_value;
+ALTER TABLE suppliers RENAME COLUMN min_order_value_real TO min_order_value;
+
+ALTER TABLE lots ADD COLUMN landed_cost_real REAL NOT NULL DEFAULT 0;
+UPDATE lots SET landed_cost_real = landed_cost / 10000.0;
+ALTER TABLE lots DROP COLUMN landed_cost;
+ALTER TABLE lots RENAME COLUMN landed_cost_real TO landed_cost;
+
+ALTER TABLE products ADD COLUMN purchase_price_real REAL NOT NULL DEFAULT 0;
+ALTER TABLE products ADD COLUMN wholesale_price_real REAL NOT NULL DEFAULT 0;
+ALTER TABLE products ADD COLUMN retail_price_real REAL NOT NULL DEFAULT 0;
+ALTER TABLE products ADD COLUMN freight_cost_real REAL NOT NULL DEFAULT 0;
+ALTER TABLE products ADD COLUMN handling_cost_real REAL NOT NULL DEFAULT 0;
+UPDATE products SET
+    purchase_price_real = purchase_price / 10000.0,
+    wholesale_price_real = wholesale_price / 10000.0,
+    retail_price_real = retail_price / 10000.0,
+    freight_cost_real = freight_cost / 10000.0,
+    handling_cost_real = handling_cost / 10000.0;
+ALTER TABLE products DROP COLUMN purchase_price;
+ALTER TABLE products DROP COLUMN wholesale_price;
+ALTER TABLE products DROP COLUMN retail_price;
+ALTER TABLE products DROP COLUMN freight_cost;
+ALTER TABLE products DROP COLUMN handling_cost;
+ALTER TABLE products RENAME COLUMN purchase_price_real TO purchase_price;
+ALTER TABLE products RENAME COLUMN wholesale_price_real TO wholesale_price;
+ALTER TABLE products RENAME COLUMN retail_price_real TO retail_price;
+ALTER TABLE products RENAME COLUMN freight_cost_real TO freight_cost;
+ALTER TABLE products RENAME COLUMN handling_cost_real TO handling_cost;
@@ -0,0 +1,59 @@
+-- Amounts become whole numbers of ten-thousandths (1.5 is stored as 15000) so
+-- sums are exact. Rounding to four places only drops float noise: prices were
+-- entered with at most four decimals.
+ALTER TABLE products ADD COLUMN purchase_price_units INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE products ADD COLUMN wholesale_price_units INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE products ADD COLUMN retail_price_units INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE products ADD COLUMN freight_cost_units INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE products ADD COLUMN handling_cost_units INTEGER NOT NULL DEFAULT 0;
+UPDATE products SET
+    purchase_price_units = CAST(round(purchase_price * 10000) AS INTEGER),
+    wholesale_price_units = CAST(round(wholesale_price * 10000) AS INTEGER),
+    retail_price_units = CAST(round(retail_price * 10000) AS INTEGER),
+    freight_cost_units = CAST(round(freight_cost * 10000) AS INTEGER),
+    handling_cost_units = CAST(round(handling_cost * 10000) AS INTEGER);
+ALTER TABLE products DROP COLUMN purchase_price;
+ALTER TABLE products DROP COLUMN wholesale_price;
+ALTER TABLE products DROP COLUMN retail_price;
+ALTER TABLE products DROP COLUMN freight_cost;
+ALTER TABLE products DROP COLUMN handling_cost;
+ALTER TABLE products RENAME COLUMN purchase_price_units TO purchase_price;
+ALTER TABLE products RENAME COLUMN wholesale_price_units TO wholesale_price;
+ALTER TABLE products RENAME COLUMN retail_price_units TO retail_price;
+ALTER TABLE products RENAME COLUMN freight_cost_units TO freight_cost;
+ALTER TABLE products RENAME COLUMN handling_cost_units TO handling_cost;
+
+ALTER TABLE lots ADD COLUMN landed_cost_units INTEGER NOT NULL DEFAULT 0;
+UPDATE lots SET landed_cost_units = CAST(round(landed_cost * 10000) AS INTEGER);
+ALTER TABLE lots DROP COLUMN landed_cost;
+ALTER TABLE lots RENAME COLUMN landed_cost_units TO landed_cost;
+
+ALTER TABLE suppliers ADD COLUMN min_order_value_units INTEGER NOT NULL DEFAULT 0;
+UPDATE suppliers SET min_order_value_units = CAST(round(min_order_value * 10000) AS INTEGER);
+ALTER TABLE suppliers DROP COLUMN min_order_value;
+ALTER TABLE suppliers RENAME COLUMN min_order_value_units TO min_order_value;
+
+ALTER TABLE purchase_order_lines ADD COLUMN unit_cost_units INTEGER NOT NULL DEFAULT 0;
+UPDATE purchase_order_lines SET unit_cost_units = CAST(round(unit_cost * 10000) AS INTEGER);
+ALTER TABLE purchase_order_lines DROP COLUMN unit_cost;
+ALTER TABLE purchase_order_lines RENAME COLUMN unit_cost_units TO unit_cost;
+
+-- Exchange rates and duty percentages are decimal text, every digit kept
+ALTER TABLE products ADD COLUMN duty_percent_text TEXT NOT NULL DEFAULT '0';
+UPDATE products SET duty_percent_text = CAST(duty_percent AS TEXT);
+ALTER TABLE products DROP COLUMN duty_percent;
+ALTER TABLE products RENAME COLUMN duty_percent_text TO duty_percent;
+
+CREATE TABLE exchange_rates_text (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    currency TEXT NOT NULL CHECK (length(currency) = 3 AND currency = upper(currency)),
+    rate TEXT NOT NULL CHECK (CAST(rate AS REAL) > 0),
+    valid_from TEXT NOT NULL CHECK (valid_from IS date(valid_from)),
+    source TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (currency, valid_from)
+);
+INSERT INTO exchange_rates_text (id, currency, rate, valid_from, source, created_at)
+SELECT id, currency, CAST(rate AS TEXT), valid_from, source, created_at FROM exchange_rates;
+DROP TABLE exchange_rates;
+ALTER TABLE exchange_rates_text RENAME TO exchange_rates;
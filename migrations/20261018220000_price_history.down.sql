@@ -0,0 +1,2 @@
+DROP TRIGGER IF EXISTS price_history_no_update;
+DROP TABLE IF EXISTS price_history;
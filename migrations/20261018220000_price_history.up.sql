@@ -0,0 +1,25 @@
+-- Every price a product is given, with the one it replaced and who set it why.
+-- Amounts are ten-thousandths, like the prices themselves.
+CREATE TABLE IF NOT EXISTS price_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    product_id INTEGER NOT NULL REFERENCES products(id),
+    price TEXT NOT NULL CHECK (price IN ('purchase', 'wholesale', 'retail')),
+    old_amount INTEGER,
+    old_currency TEXT,
+    new_amount INTEGER NOT NULL,
+    new_currency TEXT NOT NULL,
+    source TEXT NOT NULL,
+    rule_id TEXT,
+    reason TEXT,
+    changed_by TEXT,
+    changed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_price_history_product ON price_history(product_id, id);
+
+-- History is append-only
+CREATE TRIGGER IF NOT EXISTS price_history_no_update
+BEFORE UPDATE ON price_history
+BEGIN
+    SELECT RAISE(ABORT, 'price_history is append-only');
+END;
@@ -0,0 +1,3 @@
+ALTER TABLE products DROP COLUMN code_sequence;
+ALTER TABLE products DROP COLUMN code_version;
+ALTER TABLE products DROP COLUMN code_scheme;
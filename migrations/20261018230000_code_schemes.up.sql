@@ -0,0 +1,9 @@
+-- The code scheme and version a product's codes were made with, and its
+-- sequence number. Existing codes are the standard scheme's first version.
+ALTER TABLE products ADD COLUMN code_scheme TEXT NOT NULL DEFAULT 'standard';
+ALTER TABLE products ADD COLUMN code_version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE products ADD COLUMN code_sequence INTEGER;
+
+-- Alternative codes were numbered by the count of rows before them, deleted
+-- ones included
+UPDATE products SET code_sequence = (SELECT COUNT(*) FROM products AS earlier WHERE earlier.id <= products.id);
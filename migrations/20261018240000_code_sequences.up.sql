@@ -0,0 +1,7 @@
+-- The last sequence number handed out per alternative code prefix (the code
+-- up to its sequence number). Numbers only go up, so a deleted product's code
+-- is never given to another one.
+CREATE TABLE code_sequences (
+    prefix TEXT PRIMARY KEY,
+    last_value INTEGER NOT NULL
+);
@@ -0,0 +1,24 @@
+-- Back to the digits as printed
+CREATE TEMP TABLE printed_barcodes AS
+SELECT barcode, substr(barcode, CASE barcode_symbology
+    WHEN 'ean8' THEN 7
+    WHEN 'upc_a' THEN 3
+    WHEN 'ean13' THEN 2
+    ELSE 1
+END) AS printed
+FROM products
+WHERE barcode_symbology IS NOT NULL;
+
+UPDATE products SET barcode = (SELECT printed FROM printed_barcodes WHERE printed_barcodes.barcode = products.barcode)
+WHERE barcode_symbology IS NOT NULL;
+
+-- Alerts keep matching the products they were raised for
+UPDATE alerts SET
+    fingerprint = alert_type || ':' || (SELECT printed FROM printed_barcodes WHERE printed_barcodes.barcode = alerts.barcode)
+        || ':' || COALESCE(lot_code, ''),
+    barcode = (SELECT printed FROM printed_barcodes WHERE printed_barcodes.barcode = alerts.barcode)
+WHERE barcode IN (SELECT barcode FROM printed_barcodes);
+
+DROP TABLE printed_barcodes;
+
+ALTER TABLE products DROP COLUMN barcode_symbology;
@@ -0,0 +1,48 @@
+-- Barcodes are stored as 14-digit GTINs; this keeps how each one is printed
+-- (ean8, upc_a, ean13 or gtin14). Barcodes that are not GTINs keep none.
+ALTER TABLE products ADD COLUMN barcode_symbology TEXT;
+
+-- Digit-only barcodes of GTIN length, without spaces or hyphens. EAN-8 and
+-- UPC-A barcodes that lost their leading zero count as their own length.
+CREATE TEMP TABLE gtin_barcodes AS
+SELECT id, barcode, length(digits) AS printed, substr('0000000' || digits, -14) AS gtin
+FROM (SELECT id, barcode, replace(replace(barcode, ' ', ''), '-', '') AS digits FROM products)
+WHERE digits <> '' AND digits NOT GLOB '*[^0-9]*' AND length(digits) IN (7, 8, 11, 12, 13, 14);
+
+-- Barcodes whose check digit is wrong are not GTINs: they keep their digits and
+-- no symbology, and `barcode audit` lists them. Counting from the left of the
+-- 14 digits, odd positions weigh 3 and even ones 1.
+DELETE FROM gtin_barcodes WHERE (10 - (
+    3 * (substr(gtin, 1, 1) + substr(gtin, 3, 1) + substr(gtin, 5, 1) + substr(gtin, 7, 1)
+        + substr(gtin, 9, 1) + substr(gtin, 11, 1) + substr(gtin, 13, 1))
+    + substr(gtin, 2, 1) + substr(gtin, 4, 1) + substr(gtin, 6, 1) + substr(gtin, 8, 1)
+    + substr(gtin, 10, 1) + substr(gtin, 12, 1)
+) % 10) % 10 <> CAST(substr(gtin, 14, 1) AS INTEGER);
+
+-- Barcodes that pad to the same GTIN are left as they are, for someone to sort
+-- out (`barcode audit` lists them)
+DELETE FROM gtin_barcodes WHERE gtin IN (SELECT gtin FROM gtin_barcodes GROUP BY gtin HAVING COUNT(*) > 1);
+
+UPDATE products SET
+    barcode = (SELECT gtin FROM gtin_barcodes WHERE gtin_barcodes.id = products.id),
+    barcode_symbology = (
+        SELECT CASE
+            WHEN printed <= 8 THEN 'ean8'
+            WHEN printed <= 12 THEN 'upc_a'
+            WHEN printed = 13 THEN 'ean13'
+            ELSE 'gtin14'
+        END
+        FROM gtin_barcodes WHERE gtin_barcodes.id = products.id
+    )
+WHERE id IN (SELECT id FROM gtin_barcodes);
+
+-- Alerts are matched on a fingerprint of type, barcode and lot code, so they move
+-- to the new barcodes too; otherwise the next evaluation would resolve each one
+-- and open a fresh alert without its status, assignee, notes or deliveries
+UPDATE alerts SET
+    fingerprint = alert_type || ':' || (SELECT gtin FROM gtin_barcodes WHERE gtin_barcodes.barcode = alerts.barcode)
+        || ':' || COALESCE(lot_code, ''),
+    barcode = (SELECT gtin FROM gtin_barcodes WHERE gtin_barcodes.barcode = alerts.barcode)
+WHERE barcode IN (SELECT barcode FROM gtin_barcodes);
+
+DROP TABLE gtin_barcodes;
@@ -0,0 +1,627 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use crate::models::{AppError, Supplier};
+
+/// Placeholders a message template may use. Lot placeholders are empty for product rules,
+/// sales placeholders are empty for products without recent sales.
+const PLACEHOLDERS: [&str; 12] = [
+    "product", "barcode", "stock", "min_threshold", "per_day", "cover_days",
+    "lot", "quantity", "expiry_date", "days_left", "days_ago", "shelf_life_left",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(Severity::Info),
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            "critical" => Ok(Severity::Critical),
+            other => Err(AppError::Validation(format!("unknown severity {:?}", other))),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "Info",
+            Severity::Low => "Low",
+            Severity::Medium => "Medium",
+            Severity::High => "High",
+            Severity::Critical => "Critical",
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertType {
+    Expired,
+    ExpiryWarning,
+    LowStock,
+    WasteRisk,
+}
+
+impl AlertType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertType::Expired => "expired",
+            AlertType::ExpiryWarning => "expiry_warning",
+            AlertType::LowStock => "low_stock",
+            AlertType::WasteRisk => "waste_risk",
+        }
+    }
+}
+
+impl std::str::FromStr for AlertType {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "expired" => Ok(AlertType::Expired),
+            "expiry_warning" => Ok(AlertType::ExpiryWarning),
+            "low_stock" => Ok(AlertType::LowStock),
+            "waste_risk" => Ok(AlertType::WasteRisk),
+            other => Err(AppError::Validation(format!("unknown alert type {:?}", other))),
+        }
+    }
+}
+
+impl fmt::Display for AlertType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AlertType::Expired => "Expired",
+            AlertType::ExpiryWarning => "Expiry Warning",
+            AlertType::LowStock => "Low Stock",
+            AlertType::WasteRisk => "Waste Risk",
+        })
+    }
+}
+
+/// What a rule checks. Lot conditions are evaluated for every lot still on the
+/// shelf, product conditions once per product.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    /// The lot is past its expiry date.
+    Expired,
+    /// The lot expires within `days` and, if given, has no more than
+    /// `shelf_life_share` (0-1) of its production-to-expiry shelf life left.
+    ExpiresWithin { days: u32, shelf_life_share: Option<f64> },
+    /// Stock is below the product's own minimum threshold.
+    BelowMinThreshold,
+    /// Stock is at or below a fixed number of units.
+    StockAtMost { units: i32 },
+    /// Stock lasts fewer than `days` at the current sales rate.
+    CoverBelow { days: f64 },
+    /// Stock lasts more than `days` at the current sales rate.
+    CoverAbove { days: f64 },
+}
+
+impl Condition {
+    pub fn is_lot_condition(&self) -> bool {
+        matches!(self, Condition::Expired | Condition::ExpiresWithin { .. })
+    }
+
+    fn check(&self) -> Result<(), String> {
+        match self {
+            Condition::ExpiresWithin { shelf_life_share: Some(share), .. } if !(*share > 0.0 && *share <= 1.0) => {
+                Err("shelf_life_share must be above 0 and at most 1".to_string())
+            }
+            Condition::CoverBelow { days } | Condition::CoverAbove { days } if !(days.is_finite() && *days > 0.0) => {
+                Err("days must be a positive number".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn matches(&self, facts: &Facts) -> bool {
+        match (self, &facts.lot) {
+            (Condition::Expired, Some(lot)) => lot.days_left < 0,
+            (Condition::ExpiresWithin { days, shelf_life_share }, Some(lot)) => {
+                lot.days_left >= 0
+                    && lot.days_left <= *days as i64
+                    && shelf_life_share.is_none_or(|share| lot.share_left <= share)
+            }
+            (Condition::BelowMinThreshold, None) => facts.stock < facts.min_threshold,
+            (Condition::StockAtMost { units }, None) => facts.stock <= *units,
+            (Condition::CoverBelow { days }, None) => facts.cover_days.is_some_and(|cover| cover < *days),
+            (Condition::CoverAbove { days }, None) => facts.cover_days.is_some_and(|cover| cover > *days),
+            _ => false,
+        }
+    }
+}
+
+/// Limits a rule to products of a category, a supplier or a single barcode.
+/// Every field that is set must match; names compare case-insensitively, and
+/// supplier names also without their legal form.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct RuleScope {
+    pub category: Option<String>,
+    pub supplier: Option<String>,
+    pub barcode: Option<String>,
+}
+
+impl RuleScope {
+    fn matches(&self, facts: &Facts) -> bool {
+        let same = |want: &Option<String>, have: &str| want.as_ref().is_none_or(|want| want.eq_ignore_ascii_case(have));
+        let same_supplier = self.supplier.as_ref().is_none_or(|want| Supplier::name_key(want) == Supplier::name_key(facts.supplier));
+        same(&self.category, facts.category) && same_supplier && same(&self.barcode, facts.barcode)
+    }
+
+    /// Narrower scopes rank higher: barcode over supplier over category over everything.
+    fn specificity(&self) -> u8 {
+        (self.barcode.is_some() as u8) << 2 | (self.supplier.is_some() as u8) << 1 | self.category.is_some() as u8
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlertRule {
+    /// Stable identifier, used to enable or disable the rule.
+    pub id: String,
+    pub alert_type: AlertType,
+    pub severity: Severity,
+    pub condition: Condition,
+    /// Message with `{placeholder}`s, e.g. `{product} has {stock} units`.
+    pub message: String,
+    #[serde(default)]
+    pub scope: RuleScope,
+    #[serde(default = "AlertRule::enabled_by_default")]
+    pub enabled: bool,
+}
+
+impl AlertRule {
+    fn enabled_by_default() -> bool {
+        true
+    }
+
+    fn new(id: &str, alert_type: AlertType, severity: Severity, condition: Condition, message: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            alert_type,
+            severity,
+            condition,
+            message: message.to_string(),
+            scope: RuleScope::default(),
+            enabled: true,
+        }
+    }
+}
+
+/// A rule that fired for a product, or for one of its lots.
+pub struct RuleMatch<'a> {
+    pub rule: &'a AlertRule,
+    pub lot_code: Option<String>,
+    pub message: String,
+}
+
+/// Everything a condition or message template can refer to.
+pub struct Facts<'a> {
+    pub product: &'a str,
+    pub barcode: &'a str,
+    pub category: &'a str,
+    pub supplier: &'a str,
+    pub stock: i32,
+    pub min_threshold: i32,
+    pub per_day: f64,
+    pub cover_days: Option<f64>,
+    pub lot: Option<LotFacts<'a>>,
+}
+
+pub struct LotFacts<'a> {
+    pub lot_code: &'a str,
+    pub quantity: i32,
+    pub expiry_date: String,
+    pub days_left: i64,
+    pub share_left: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RuleSet {
+    pub rules: Vec<AlertRule>,
+}
+
+impl Default for RuleSet {
+    /// The built-in rules, used when no rules file is configured.
+    fn default() -> Self {
+        use AlertType::*;
+        use Condition::*;
+
+        let expiring = "{product} lot {lot} expires in {days_left} days ({expiry_date}), {shelf_life_left}% of shelf life left: {quantity} units";
+        Self {
+            rules: vec![
+                AlertRule::new("expired", AlertType::Expired, Severity::Critical, Condition::Expired,
+                    "{product} lot {lot} expired {days_ago} days ago ({expiry_date}): {quantity} units"),
+                AlertRule::new("expiry_90_days", ExpiryWarning, Severity::Low,
+                    ExpiresWithin { days: 90, shelf_life_share: Some(0.5) }, expiring),
+                AlertRule::new("expiry_30_days", ExpiryWarning, Severity::Medium,
+                    ExpiresWithin { days: 30, shelf_life_share: Some(0.25) }, expiring),
+                AlertRule::new("expiry_7_days", ExpiryWarning, Severity::High,
+                    ExpiresWithin { days: 7, shelf_life_share: None }, expiring),
+                AlertRule::new("out_of_stock", LowStock, Severity::Critical, StockAtMost { units: 0 },
+                    "{product} is out of stock"),
+                AlertRule::new("below_min_threshold", LowStock, Severity::Medium, BelowMinThreshold,
+                    "{product} has low stock: {stock} units (min {min_threshold})"),
+                AlertRule::new("low_cover", LowStock, Severity::Medium, CoverBelow { days: 14.0 },
+                    "{product} has low stock: {stock} units, {cover_days} days of cover at {per_day}/day"),
+                AlertRule::new("waste_risk", WasteRisk, Severity::Medium, CoverAbove { days: 90.0 },
+                    "{product} has excess stock: {stock} units, {cover_days} days of cover at {per_day}/day"),
+            ],
+        }
+    }
+}
+
+impl RuleSet {
+    /// Reads a rules file, as JSON if it ends in `.json` and TOML otherwise.
+    pub fn from_file(path: &Path) -> Result<Self, AppError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| AppError::Config(format!("cannot read {}: {}", path.display(), e)))?;
+
+        let rules: Self = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&contents)
+                .map_err(|e| AppError::Config(format!("invalid {}: {}", path.display(), e)))?
+        } else {
+            toml::from_str(&contents)
+                .map_err(|e| AppError::Config(format!("invalid {}: {}", path.display(), e)))?
+        };
+        rules.check()?;
+        Ok(rules)
+    }
+
+    /// The configured rules file, or the built-in rules without one.
+    pub fn load(rules_file: Option<&str>) -> Result<Self, AppError> {
+        match rules_file {
+            Some(path) => Self::from_file(Path::new(path)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn check(&self) -> Result<(), AppError> {
+        let mut seen = Vec::new();
+        for rule in &self.rules {
+            if rule.id.trim().is_empty() {
+                return Err(AppError::Config("every alert rule needs an id".to_string()));
+            }
+            if seen.contains(&rule.id.as_str()) {
+                return Err(AppError::Config(format!("duplicate alert rule id {:?}", rule.id)));
+            }
+            seen.push(&rule.id);
+
+            rule.condition
+                .check()
+                .map_err(|e| AppError::Config(format!("alert rule {:?}: {}", rule.id, e)))?;
+            if let Some(name) = unknown_placeholder(&rule.message) {
+                return Err(AppError::Config(format!("alert rule {:?}: unknown placeholder {{{}}}", rule.id, name)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&AlertRule> {
+        self.rules.iter().find(|rule| rule.id == id)
+    }
+
+    /// Applies enable/disable switches stored at runtime, keyed by rule id.
+    pub fn apply_overrides(&mut self, overrides: &HashMap<String, bool>) {
+        for rule in &mut self.rules {
+            if let Some(enabled) = overrides.get(&rule.id) {
+                rule.enabled = *enabled;
+            }
+        }
+    }
+
+    /// Evaluates the enabled rules for one subject (a product, or one of its lots).
+    ///
+    /// For each alert type, only the most specific scope that has rules for the
+    /// product counts, so a dairy waste-risk rule replaces the general one for
+    /// dairy instead of adding to it. Of the rules that fire at that level, the
+    /// most severe one wins; on a tie, the first in the file.
+    pub fn evaluate<'a>(&'a self, facts: &Facts) -> Vec<RuleMatch<'a>> {
+        let is_lot = facts.lot.is_some();
+        let applicable: Vec<&AlertRule> = self.rules
+            .iter()
+            .filter(|rule| rule.enabled && rule.condition.is_lot_condition() == is_lot && rule.scope.matches(facts))
+            .collect();
+
+        let mut types: Vec<AlertType> = Vec::new();
+        for rule in &applicable {
+            if !types.contains(&rule.alert_type) {
+                types.push(rule.alert_type);
+            }
+        }
+
+        let mut matches = Vec::new();
+        for alert_type in types {
+            let of_type = applicable.iter().filter(|rule| rule.alert_type == alert_type);
+            let level = of_type.clone().map(|rule| rule.scope.specificity()).max().unwrap_or_default();
+
+            let fired = of_type
+                .filter(|rule| rule.scope.specificity() == level && rule.condition.matches(facts))
+                .fold(None::<&AlertRule>, |best, rule| match best {
+                    Some(best) if best.severity >= rule.severity => Some(best),
+                    _ => Some(rule),
+                });
+
+            if let Some(rule) = fired {
+                matches.push(RuleMatch {
+                    rule,
+                    lot_code: facts.lot.as_ref().map(|lot| lot.lot_code.to_string()),
+                    message: render(&rule.message, facts),
+                });
+            }
+        }
+        matches
+    }
+}
+
+fn unknown_placeholder(template: &str) -> Option<&str> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}')? + start;
+        let name = &rest[start + 1..end];
+        if !PLACEHOLDERS.contains(&name) {
+            return Some(name);
+        }
+        rest = &rest[end + 1..];
+    }
+    None
+}
+
+fn render(template: &str, facts: &Facts) -> String {
+    let lot = facts.lot.as_ref();
+    let value = |name: &str| -> String {
+        match name {
+            "product" => facts.product.to_string(),
+            "barcode" => facts.barcode.to_string(),
+            "stock" => facts.stock.to_string(),
+            "min_threshold" => facts.min_threshold.to_string(),
+            "per_day" => format!("{:.1}", facts.per_day),
+            "cover_days" => facts.cover_days.map(|days| format!("{:.0}", days)).unwrap_or_default(),
+            "lot" => lot.map(|lot| lot.lot_code.to_string()).unwrap_or_default(),
+            "quantity" => lot.map(|lot| lot.quantity.to_string()).unwrap_or_default(),
+            "expiry_date" => lot.map(|lot| lot.expiry_date.clone()).unwrap_or_default(),
+            "days_left" => lot.map(|lot| lot.days_left.max(0).to_string()).unwrap_or_default(),
+            "days_ago" => lot.map(|lot| (-lot.days_left).max(0).to_string()).unwrap_or_default(),
+            "shelf_life_left" => lot.map(|lot| format!("{:.0}", lot.share_left * 100.0)).unwrap_or_default(),
+            _ => String::new(),
+        }
+    };
+
+    let mut message = template.to_string();
+    for name in PLACEHOLDERS {
+        let placeholder = format!("{{{}}}", name);
+        if message.contains(&placeholder) {
+            message = message.replace(&placeholder, &value(name));
+        }
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product<'a>(stock: i32, cover_days: Option<f64>) -> Facts<'a> {
+        Facts {
+            product: "Chickpeas",
+            barcode: "05281234567896",
+            category: "Legumes",
+            supplier: "Beirut Foods SAL",
+            stock,
+            min_threshold: 10,
+            per_day: 2.0,
+            cover_days,
+            lot: None,
+        }
+    }
+
+    fn lot<'a>(days_left: i64, share_left: f64) -> Facts<'a> {
+        Facts {
+            lot: Some(LotFacts {
+                lot_code: "LB-LEG-ALW-900-001-202712",
+                quantity: 12,
+                expiry_date: "2027-12-31".to_string(),
+                days_left,
+                share_left,
+            }),
+            ..product(24, None)
+        }
+    }
+
+    fn scoped(id: &str, severity: Severity, condition: Condition, scope: RuleScope) -> AlertRule {
+        AlertRule { scope, ..AlertRule::new(id, AlertType::WasteRisk, severity, condition, "{product}: {cover_days} days") }
+    }
+
+    fn fired(rules: &RuleSet, facts: &Facts) -> Vec<String> {
+        rules.evaluate(facts).iter().map(|m| m.rule.id.clone()).collect()
+    }
+
+    fn from_file(name: &str, contents: &str) -> Result<RuleSet, AppError> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        std::fs::write(&path, contents).unwrap();
+        RuleSet::from_file(&path)
+    }
+
+    #[test]
+    fn scopes_match_case_and_legal_form_insensitively() {
+        let facts = product(24, None);
+        let scope = |category: Option<&str>, supplier: Option<&str>, barcode: Option<&str>| RuleScope {
+            category: category.map(String::from),
+            supplier: supplier.map(String::from),
+            barcode: barcode.map(String::from),
+        };
+
+        assert!(scope(None, None, None).matches(&facts));
+        assert!(scope(Some("legumes"), None, None).matches(&facts));
+        assert!(scope(None, Some("beirut  foods"), None).matches(&facts));
+        assert!(scope(Some("Legumes"), Some("Beirut Foods sal."), Some("05281234567896")).matches(&facts));
+        assert!(!scope(Some("Dairy"), None, None).matches(&facts));
+        assert!(!scope(Some("Legumes"), Some("Damascus Trading"), None).matches(&facts));
+        assert!(!scope(None, None, Some("5281234567896")).matches(&facts));
+
+        assert!(scope(None, None, Some("x")).specificity() > scope(Some("x"), Some("x"), None).specificity());
+        assert!(scope(None, Some("x"), None).specificity() > scope(Some("x"), None, None).specificity());
+        assert!(scope(Some("x"), None, None).specificity() > scope(None, None, None).specificity());
+    }
+
+    #[test]
+    fn the_most_specific_scope_replaces_broader_rules() {
+        let legumes = RuleScope { category: Some("legumes".to_string()), ..RuleScope::default() };
+        let rules = RuleSet {
+            rules: vec![
+                scoped("general", Severity::Medium, Condition::CoverAbove { days: 90.0 }, RuleScope::default()),
+                scoped("legumes", Severity::Low, Condition::CoverAbove { days: 365.0 }, legumes.clone()),
+                scoped("legumes_severe", Severity::High, Condition::CoverAbove { days: 730.0 }, legumes),
+            ],
+        };
+
+        // The general rule would fire at 200 days, but legumes have their own
+        assert!(fired(&rules, &product(400, Some(200.0))).is_empty());
+        assert_eq!(fired(&rules, &product(800, Some(400.0))), ["legumes"]);
+        assert_eq!(fired(&rules, &product(2000, Some(1000.0))), ["legumes_severe"]);
+
+        let dairy = Facts { category: "Dairy", ..product(400, Some(200.0)) };
+        assert_eq!(fired(&rules, &dairy), ["general"]);
+
+        let mut disabled = rules.clone();
+        disabled.apply_overrides(&HashMap::from([("legumes".to_string(), false), ("legumes_severe".to_string(), false)]));
+        assert_eq!(fired(&disabled, &product(400, Some(200.0))), ["general"]);
+    }
+
+    #[test]
+    fn the_most_severe_rule_of_each_type_fires_once() {
+        let rules = RuleSet::default();
+        assert_eq!(fired(&rules, &product(0, None)), ["out_of_stock"]);
+        assert_eq!(fired(&rules, &product(5, Some(2.5))), ["below_min_threshold"]);
+        assert_eq!(fired(&rules, &product(400, Some(200.0))), ["waste_risk"]);
+        assert!(fired(&rules, &product(24, Some(30.0))).is_empty());
+
+        // Lot conditions only apply to lots, product conditions only to products
+        assert_eq!(fired(&rules, &lot(-3, 0.0)), ["expired"]);
+        assert_eq!(fired(&rules, &lot(5, 0.01)), ["expiry_7_days"]);
+        assert_eq!(fired(&rules, &lot(20, 0.1)), ["expiry_30_days"]);
+        // Within 30 days, but with too much of a long shelf life left to matter yet
+        assert_eq!(fired(&rules, &lot(20, 0.4)), ["expiry_90_days"]);
+        assert!(fired(&rules, &lot(20, 0.6)).is_empty());
+
+        let tie = RuleSet {
+            rules: vec![
+                scoped("first", Severity::Medium, Condition::CoverAbove { days: 90.0 }, RuleScope::default()),
+                scoped("second", Severity::Medium, Condition::CoverAbove { days: 60.0 }, RuleScope::default()),
+            ],
+        };
+        assert_eq!(fired(&tie, &product(400, Some(200.0))), ["first"]);
+    }
+
+    #[test]
+    fn messages_fill_in_their_placeholders() {
+        let rules = RuleSet::default();
+        let messages = |facts: &Facts| rules.evaluate(facts).into_iter().map(|m| m.message).collect::<Vec<_>>();
+
+        assert_eq!(messages(&product(5, Some(2.5))), ["Chickpeas has low stock: 5 units (min 10)"]);
+        assert_eq!(
+            messages(&lot(-3, 0.0)),
+            ["Chickpeas lot LB-LEG-ALW-900-001-202712 expired 3 days ago (2027-12-31): 12 units"]
+        );
+        assert_eq!(
+            messages(&lot(20, 0.1)),
+            ["Chickpeas lot LB-LEG-ALW-900-001-202712 expires in 20 days (2027-12-31), 10% of shelf life left: 12 units"]
+        );
+        let matched = rules.evaluate(&lot(20, 0.1));
+        assert_eq!(matched[0].lot_code.as_deref(), Some("LB-LEG-ALW-900-001-202712"));
+    }
+
+    #[test]
+    fn rules_files_are_read_as_toml_or_json() {
+        let toml = r#"
+            [[rules]]
+            id = "dairy_waste"
+            alert_type = "waste_risk"
+            severity = "high"
+            message = "{product}: {cover_days} days of cover"
+            condition = { type = "cover_above", days = 21 }
+            scope = { category = "Dairy" }
+
+            [[rules]]
+            id = "dairy_expiry"
+            alert_type = "expiry_warning"
+            severity = "critical"
+            message = "{product} lot {lot} expires {expiry_date}"
+            condition = { type = "expires_within", days = 3 }
+            enabled = false
+        "#;
+        let rules = from_file("rules.toml", toml).unwrap();
+        let waste = rules.get("dairy_waste").unwrap();
+        assert_eq!((waste.severity, waste.alert_type), (Severity::High, AlertType::WasteRisk));
+        assert_eq!(waste.condition, Condition::CoverAbove { days: 21.0 });
+        assert_eq!(waste.scope.category.as_deref(), Some("Dairy"));
+        assert!(waste.enabled && !rules.get("dairy_expiry").unwrap().enabled);
+
+        let json = r#"{"rules": [{"id": "empty", "alert_type": "low_stock", "severity": "critical",
+            "condition": {"type": "stock_at_most", "units": 0}, "message": "{product} is gone"}]}"#;
+        let rules = from_file("rules.json", json).unwrap();
+        assert_eq!(rules.get("empty").unwrap().condition, Condition::StockAtMost { units: 0 });
+    }
+
+    #[test]
+    fn rules_files_with_mistakes_are_rejected() {
+        let rule = |id: &str, severity: &str, condition: &str, message: &str| format!(
+            "[[rules]]\nid = \"{}\"\nalert_type = \"low_stock\"\nseverity = \"{}\"\ncondition = {}\nmessage = \"{}\"\n",
+            id, severity, condition, message
+        );
+        let empty = r#"{ type = "stock_at_most", units = 0 }"#;
+        let error = |contents: String| from_file("rules.toml", &contents).unwrap_err().to_string();
+
+        assert!(error(rule("empty", "urgent", empty, "{product}")).contains("unknown variant `urgent`"));
+        assert!(error(rule("empty", "high", r#"{ type = "sold_out" }"#, "{product}")).contains("unknown variant `sold_out`"));
+        assert!(error(rule("empty", "high", empty, "{product} in {aisle}")).contains("unknown placeholder {aisle}"));
+        assert!(error(rule(" ", "high", empty, "{product}")).contains("every alert rule needs an id"));
+        assert!(error(rule("empty", "high", empty, "{product}") + &rule("empty", "low", empty, "{product}"))
+            .contains("duplicate alert rule id \"empty\""));
+        let share = r#"{ type = "expires_within", days = 30, shelf_life_share = 1.5 }"#;
+        assert!(error(rule("soon", "high", share, "{product}")).contains("shelf_life_share must be above 0 and at most 1"));
+        let cover = r#"{ type = "cover_below", days = 0 }"#;
+        assert!(error(rule("thin", "high", cover, "{product}")).contains("days must be a positive number"));
+    }
+
+    #[test]
+    fn severities_are_ordered_and_round_trip() {
+        assert!(Severity::Info < Severity::Low && Severity::Low < Severity::Medium);
+        assert!(Severity::Medium < Severity::High && Severity::High < Severity::Critical);
+        for severity in [Severity::Info, Severity::Low, Severity::Medium, Severity::High, Severity::Critical] {
+            assert_eq!(severity.as_str().parse::<Severity>().unwrap(), severity);
+        }
+        assert!("Critical".parse::<Severity>().is_err());
+        assert_eq!("expiry_warning".parse::<AlertType>().unwrap(), AlertType::ExpiryWarning);
+        assert_eq!(AlertType::ExpiryWarning.to_string(), "Expiry Warning");
+    }
+}
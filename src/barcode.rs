@@ -0,0 +1,431 @@
+use serde::{Serialize, Deserialize};
+use std::str::FromStr;
+use crate::models::AppError;
+
+/// How a barcode is printed. Every symbology is a GTIN padded on the left, so
+/// all of them are stored as 14 digits.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Symbology {
+    Ean8,
+    UpcA,
+    Ean13,
+    /// Outer cases (ITF-14 or GS1-128)
+    Gtin14,
+}
+
+impl Symbology {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Symbology::Ean8 => "ean8",
+            Symbology::UpcA => "upc_a",
+            Symbology::Ean13 => "ean13",
+            Symbology::Gtin14 => "gtin14",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Symbology::Ean8 => "EAN-8",
+            Symbology::UpcA => "UPC-A",
+            Symbology::Ean13 => "EAN-13",
+            Symbology::Gtin14 => "GTIN-14",
+        }
+    }
+
+    /// Digits the symbology prints, check digit included.
+    pub fn length(&self) -> usize {
+        match self {
+            Symbology::Ean8 => 8,
+            Symbology::UpcA => 12,
+            Symbology::Ean13 => 13,
+            Symbology::Gtin14 => 14,
+        }
+    }
+}
+
+impl FromStr for Symbology {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ean8" => Ok(Symbology::Ean8),
+            "upc_a" => Ok(Symbology::UpcA),
+            "ean13" => Ok(Symbology::Ean13),
+            "gtin14" => Ok(Symbology::Gtin14),
+            _ => Err(AppError::Validation(format!("unknown symbology {:?}", s))),
+        }
+    }
+}
+
+/// A barcode with a valid GS1 check digit.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Gtin {
+    /// Padded with zeros to 14 digits, as stored
+    pub gtin14: String,
+    pub symbology: Symbology,
+}
+
+impl Gtin {
+    /// Reads a barcode as typed or scanned. Spaces and hyphens are ignored, and
+    /// an EAN-8 or UPC-A that lost its leading zero, as spreadsheets drop it, is
+    /// taken as one.
+    pub fn parse(input: &str) -> Result<Gtin, AppError> {
+        let digits: String = input.chars().filter(|c| !c.is_whitespace() && *c != '-').collect();
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(AppError::Validation(format!("barcode {:?} must contain only digits", input)));
+        }
+        let symbology = match digits.len() {
+            7 | 8 => Symbology::Ean8,
+            11 | 12 => Symbology::UpcA,
+            13 => Symbology::Ean13,
+            14 => Symbology::Gtin14,
+            n => {
+                return Err(AppError::Validation(format!(
+                    "barcode {} has {} digits; EAN-8, UPC-A, EAN-13 and GTIN-14 have 8, 12, 13 and 14", digits, n
+                )));
+            }
+        };
+
+        let gtin14 = format!("{:0>14}", digits);
+        let expected = check_digit(&gtin14[..13]);
+        if !gtin14.ends_with(expected) {
+            return Err(AppError::Validation(format!(
+                "barcode {} has a wrong check digit (expected {}); it is probably mistyped", digits, expected
+            )));
+        }
+        Ok(Gtin { gtin14, symbology })
+    }
+
+    /// The digits as printed under the bars, e.g. 13 for an EAN-13.
+    pub fn printed(&self) -> &str {
+        &self.gtin14[14 - self.symbology.length()..]
+    }
+
+    /// The GS1 prefix the number starts with: three digits, past the packaging
+    /// indicator of a GTIN-14 and the leading zero of a UPC-A.
+    pub fn prefix(&self) -> &str {
+        match self.symbology {
+            Symbology::Ean8 => &self.gtin14[6..9],
+            _ => &self.gtin14[1..4],
+        }
+    }
+
+    /// The country of the GS1 member organisation that issued the prefix. That
+    /// is where the brand owner registered, which is usually but not always
+    /// where the product is made. None for restricted, in-store and book numbers.
+    pub fn country(&self) -> Option<&'static str> {
+        self.country_names().map(|names| names[0])
+    }
+
+    fn country_names(&self) -> Option<&'static [&'static str]> {
+        let prefix: u16 = self.prefix().parse().ok()?;
+        // EAN-8 numbers starting with 0 or 2 are for velocity and in-store codes
+        if self.symbology == Symbology::Ean8 && matches!(prefix / 100, 0 | 2) {
+            return None;
+        }
+        PREFIXES
+            .iter()
+            .find(|(from, to, _)| (*from..=*to).contains(&prefix))
+            .map(|(_, _, names)| *names)
+    }
+
+    /// A warning when the GS1 prefix names a different country than the
+    /// product's origin. Only a hint: brand owners often register abroad.
+    pub fn origin_warning(&self, origin_country: &str) -> Option<String> {
+        let names = self.country_names()?;
+        let origin = origin_country.trim();
+        if origin.is_empty() || names.iter().any(|name| name.eq_ignore_ascii_case(origin)) {
+            return None;
+        }
+        let country = names[0];
+        Some(format!(
+            "barcode prefix {} is registered in {}, but origin_country is {}", self.prefix(), country, origin
+        ))
+    }
+}
+
+/// `Gtin::origin_warning` for a barcode that reads as a GTIN.
+pub fn origin_warning(barcode: &str, origin_country: &str) -> Option<String> {
+    Gtin::parse(barcode).ok()?.origin_warning(origin_country)
+}
+
+/// The form a barcode is stored in when it reads as a GTIN, whether or not its
+/// check digit is right, so lookups find it however it is typed. Anything else
+/// is only trimmed.
+pub fn storage_key(barcode: &str) -> String {
+    let digits: String = barcode.chars().filter(|c| !c.is_whitespace() && *c != '-').collect();
+    if digits.chars().all(|c| c.is_ascii_digit()) && matches!(digits.len(), 7 | 8 | 11..=14) {
+        format!("{:0>14}", digits)
+    } else {
+        barcode.trim().to_string()
+    }
+}
+
+/// GS1 check digit over the digits before it: weights 3 and 1 alternate from
+/// the right, and the digit brings the sum up to a multiple of ten.
+pub fn check_digit(digits: &str) -> char {
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, digit)| if i % 2 == 0 { digit * 3 } else { digit })
+        .sum();
+    char::from_digit((10 - sum % 10) % 10, 10).unwrap_or('0')
+}
+
+/// GS1 prefix ranges and the country each was issued to, its usual name first
+/// and other spellings an `origin_country` may use after it. Ranges missing
+/// here are restricted, unassigned or not countries (ISBN, ISSN, coupons).
+const PREFIXES: &[(u16, u16, &[&str])] = &[
+    (0, 19, &["United States", "USA", "US", "Canada"]),
+    (30, 39, &["United States", "USA", "US", "Canada"]),
+    (60, 139, &["United States", "USA", "US", "Canada"]),
+    (300, 379, &["France", "Monaco"]),
+    (380, 380, &["Bulgaria"]),
+    (383, 383, &["Slovenia"]),
+    (385, 385, &["Croatia"]),
+    (387, 387, &["Bosnia and Herzegovina", "Bosnia"]),
+    (389, 389, &["Montenegro"]),
+    (400, 440, &["Germany"]),
+    (450, 459, &["Japan"]),
+    (460, 469, &["Russia", "Russian Federation"]),
+    (470, 470, &["Kyrgyzstan"]),
+    (471, 471, &["Taiwan"]),
+    (474, 474, &["Estonia"]),
+    (475, 475, &["Latvia"]),
+    (476, 476, &["Azerbaijan"]),
+    (477, 477, &["Lithuania"]),
+    (478, 478, &["Uzbekistan"]),
+    (479, 479, &["Sri Lanka"]),
+    (480, 480, &["Philippines"]),
+    (481, 481, &["Belarus"]),
+    (482, 482, &["Ukraine"]),
+    (483, 483, &["Turkmenistan"]),
+    (484, 484, &["Moldova"]),
+    (485, 485, &["Armenia"]),
+    (486, 486, &["Georgia"]),
+    (487, 487, &["Kazakhstan"]),
+    (488, 488, &["Tajikistan"]),
+    (489, 489, &["Hong Kong"]),
+    (490, 499, &["Japan"]),
+    (500, 509, &["United Kingdom", "UK", "Great Britain"]),
+    (520, 521, &["Greece"]),
+    (528, 528, &["Lebanon"]),
+    (529, 529, &["Cyprus"]),
+    (530, 530, &["Albania"]),
+    (531, 531, &["North Macedonia", "Macedonia"]),
+    (535, 535, &["Malta"]),
+    (539, 539, &["Ireland"]),
+    (540, 549, &["Belgium", "Luxembourg"]),
+    (560, 560, &["Portugal"]),
+    (569, 569, &["Iceland"]),
+    (570, 579, &["Denmark", "Faroe Islands", "Greenland"]),
+    (590, 590, &["Poland"]),
+    (594, 594, &["Romania"]),
+    (599, 599, &["Hungary"]),
+    (600, 601, &["South Africa"]),
+    (603, 603, &["Ghana"]),
+    (604, 604, &["Senegal"]),
+    (608, 608, &["Bahrain"]),
+    (609, 609, &["Mauritius"]),
+    (611, 611, &["Morocco"]),
+    (613, 613, &["Algeria"]),
+    (615, 615, &["Nigeria"]),
+    (616, 616, &["Kenya"]),
+    (618, 618, &["Ivory Coast", "Côte d'Ivoire"]),
+    (619, 619, &["Tunisia"]),
+    (620, 620, &["Tanzania"]),
+    (621, 621, &["Syria"]),
+    (622, 622, &["Egypt"]),
+    (623, 623, &["Brunei"]),
+    (624, 624, &["Libya"]),
+    (625, 625, &["Jordan"]),
+    (626, 626, &["Iran"]),
+    (627, 627, &["Kuwait"]),
+    (628, 628, &["Saudi Arabia"]),
+    (629, 629, &["United Arab Emirates", "UAE"]),
+    (640, 649, &["Finland"]),
+    (690, 699, &["China"]),
+    (700, 709, &["Norway"]),
+    (729, 729, &["Israel"]),
+    (730, 739, &["Sweden"]),
+    (740, 740, &["Guatemala"]),
+    (741, 741, &["El Salvador"]),
+    (742, 742, &["Honduras"]),
+    (743, 743, &["Nicaragua"]),
+    (744, 744, &["Costa Rica"]),
+    (745, 745, &["Panama"]),
+    (746, 746, &["Dominican Republic"]),
+    (750, 750, &["Mexico"]),
+    (754, 755, &["Canada"]),
+    (759, 759, &["Venezuela"]),
+    (760, 769, &["Switzerland", "Liechtenstein"]),
+    (770, 771, &["Colombia"]),
+    (773, 773, &["Uruguay"]),
+    (775, 775, &["Peru"]),
+    (777, 777, &["Bolivia"]),
+    (778, 779, &["Argentina"]),
+    (780, 780, &["Chile"]),
+    (784, 784, &["Paraguay"]),
+    (786, 786, &["Ecuador"]),
+    (789, 790, &["Brazil"]),
+    (800, 839, &["Italy", "San Marino", "Vatican City"]),
+    (840, 849, &["Spain", "Andorra"]),
+    (850, 850, &["Cuba"]),
+    (858, 858, &["Slovakia"]),
+    (859, 859, &["Czech Republic", "Czechia"]),
+    (860, 860, &["Serbia"]),
+    (865, 865, &["Mongolia"]),
+    (867, 867, &["North Korea"]),
+    (868, 869, &["Turkey", "Türkiye", "Turkiye"]),
+    (870, 879, &["Netherlands"]),
+    (880, 881, &["South Korea", "Korea"]),
+    (883, 883, &["Myanmar"]),
+    (884, 884, &["Cambodia"]),
+    (885, 885, &["Thailand"]),
+    (888, 888, &["Singapore"]),
+    (890, 890, &["India"]),
+    (893, 893, &["Vietnam"]),
+    (896, 896, &["Pakistan"]),
+    (899, 899, &["Indonesia"]),
+    (900, 919, &["Austria"]),
+    (930, 939, &["Australia"]),
+    (940, 949, &["New Zealand"]),
+    (955, 955, &["Malaysia"]),
+    (958, 958, &["Macau"]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn gtin(barcode: &str) -> Gtin {
+        Gtin::parse(barcode).unwrap()
+    }
+
+    fn error(barcode: &str) -> String {
+        Gtin::parse(barcode).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn check_digits_bring_the_weighted_sum_to_ten() {
+        assert_eq!(check_digit("528123456789"), '6');
+        assert_eq!(check_digit("869050400000"), '6');
+        assert_eq!(check_digit("03600029145"), '2');
+        assert_eq!(check_digit("9638507"), '4');
+        assert_eq!(check_digit("1528123456789"), '3');
+        assert_eq!(check_digit("0000000000000"), '0');
+    }
+
+    #[test]
+    fn reads_each_symbology() {
+        let ean13 = gtin("5281234567896");
+        assert_eq!((ean13.gtin14.as_str(), ean13.symbology, ean13.printed()), ("05281234567896", Symbology::Ean13, "5281234567896"));
+
+        let upc_a = gtin("036000291452");
+        assert_eq!((upc_a.gtin14.as_str(), upc_a.symbology, upc_a.printed()), ("00036000291452", Symbology::UpcA, "036000291452"));
+
+        let ean8 = gtin("96385074");
+        assert_eq!((ean8.gtin14.as_str(), ean8.symbology, ean8.printed()), ("00000096385074", Symbology::Ean8, "96385074"));
+
+        let case = gtin("15281234567893");
+        assert_eq!((case.gtin14.as_str(), case.symbology, case.printed()), ("15281234567893", Symbology::Gtin14, "15281234567893"));
+
+        assert_eq!(gtin("5 281234-567896"), ean13);
+        assert_eq!(gtin(" 05281234567896 ").symbology, Symbology::Gtin14);
+    }
+
+    #[test]
+    fn recovers_a_dropped_leading_zero() {
+        let upc_a = gtin("36000291452");
+        assert_eq!((upc_a.symbology, upc_a.printed()), (Symbology::UpcA, "036000291452"));
+
+        let ean8 = gtin("1234565");
+        assert_eq!((ean8.symbology, ean8.printed()), (Symbology::Ean8, "01234565"));
+    }
+
+    #[test]
+    fn rejects_mistyped_barcodes() {
+        assert!(error("5281234567890").contains("wrong check digit (expected 6)"));
+        assert!(error("5281234567869").contains("wrong check digit"));
+        assert!(error("036000291453").contains("wrong check digit (expected 2)"));
+        assert!(error("96385075").contains("wrong check digit (expected 4)"));
+        assert!(error("3600029145").contains("has 10 digits"));
+        assert!(error("052812345678960").contains("has 15 digits"));
+        assert!(error("52812345678A6").contains("must contain only digits"));
+        assert!(error("").contains("must contain only digits"));
+        assert!(error(" - ").contains("must contain only digits"));
+    }
+
+    #[test]
+    fn storage_keys_pad_gtin_lengths_whatever_the_check_digit() {
+        assert_eq!(storage_key("5281234567896"), "05281234567896");
+        assert_eq!(storage_key("5281234567890"), "05281234567890");
+        assert_eq!(storage_key("36000291452"), "00036000291452");
+        assert_eq!(storage_key("9638-5074"), "00000096385074");
+        assert_eq!(storage_key("3600029145"), "3600029145");
+        assert_eq!(storage_key(" SKU-42 "), "SKU-42");
+    }
+
+    #[test]
+    fn prefixes_map_to_the_issuing_country() {
+        let cases = [
+            ("5281234567896", "528", Some("Lebanon")),
+            ("8690504000006", "869", Some("Turkey")),
+            // A UPC-A's leading zero is not part of its prefix
+            ("036000291452", "003", Some("United States")),
+            // Nor is a GTIN-14's packaging indicator
+            ("15281234567893", "528", Some("Lebanon")),
+            ("73513537", "735", Some("Sweden")),
+            // GS1 Global Office numbers belong to no country
+            ("96385074", "963", None),
+            // EAN-8s starting with 0 or 2 are velocity and in-store codes
+            ("01234565", "012", None),
+            ("20123451", "201", None),
+        ];
+        for (barcode, prefix, country) in cases {
+            let gtin = gtin(barcode);
+            assert_eq!((gtin.prefix(), gtin.country()), (prefix, country), "{}", barcode);
+        }
+        assert_eq!(gtin("0012345678905").country(), Some("United States"));
+    }
+
+    #[test]
+    fn warns_when_the_prefix_names_another_origin() {
+        let lebanese = gtin("5281234567896");
+        assert_eq!(lebanese.origin_warning("Lebanon"), None);
+        assert_eq!(lebanese.origin_warning(" lebanon "), None);
+        assert_eq!(lebanese.origin_warning(""), None);
+        assert_eq!(
+            lebanese.origin_warning("Syria").as_deref(),
+            Some("barcode prefix 528 is registered in Lebanon, but origin_country is Syria")
+        );
+        assert_eq!(gtin("8690504000006").origin_warning("Türkiye"), None);
+        assert_eq!(gtin("01234565").origin_warning("Sweden"), None);
+        assert_eq!(origin_warning("5281234567890", "Syria"), None);
+        assert!(origin_warning("5281234567896", "Syria").is_some());
+    }
+
+    proptest! {
+        #[test]
+        fn parsing_never_panics(barcode in any::<String>()) {
+            let _ = storage_key(&barcode);
+            if let Ok(gtin) = Gtin::parse(&barcode) {
+                let _ = (gtin.printed(), gtin.prefix(), gtin.country());
+            }
+        }
+
+        #[test]
+        fn gtins_with_their_check_digit_parse(digits in "[0-9]{7}|[0-9]{11,13}") {
+            let barcode = format!("{}{}", digits, check_digit(&digits));
+            let gtin = Gtin::parse(&barcode).unwrap();
+            prop_assert_eq!(gtin.printed(), barcode.as_str());
+            prop_assert_eq!(gtin.symbology.length(), barcode.len());
+            prop_assert_eq!(storage_key(&barcode), gtin.gtin14.clone());
+            let _ = (gtin.prefix(), gtin.country());
+        }
+    }
+}
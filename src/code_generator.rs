@@ -1,67 +1,756 @@
-use crate::models::Product;
+use chrono::format::{Item, StrftimeItems};
+use chrono::NaiveDate;
+use deunicode::deunicode_with_tofu;
+use serde::{Serialize, Deserialize};
+use std::ops::Range;
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::models::{AppError, LotInput, Product, Supplier};
 
-pub fn generate_internal_code(product: &Product) -> String {
-    let country = extract_country_code(&product.origin_country);
-    let category = extract_category_code(&product.category);
-    let brand = extract_brand_code(&product.brand);
-    let weight = extract_weight_digits(&product.weight);
-    let expiry = extract_expiry_code(&product.expiry_date);
-    
-    format!("{}-{}-{}-{}-{:03}-{}", 
-        country, category, brand, weight, product.batch_id, expiry)
+/// The built-in scheme. Its version 1 is the layout codes had before schemes
+/// could be configured, and is always available so those codes stay valid;
+/// version 2 adds a check character to internal codes.
+pub const STANDARD_SCHEME: &str = "standard";
+
+/// Versions of the standard scheme that are built in.
+const STANDARD_VERSIONS: u32 = 2;
+
+/// Characters a check character is computed over and chosen from.
+const CHECK_ALPHABET: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// What a segment of a code is made from.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    /// Country of origin
+    Country,
+    Category,
+    Brand,
+    Weight,
+    /// Batch number of the product, or of the lot for lot codes
+    Batch,
+    /// Expiry date of the product, or of the lot for lot codes
+    Expiry,
+    /// The product's number among those sharing its alternative code prefix
+    Sequence,
+    /// A check character over the code before it
+    Check,
 }
 
-pub fn generate_alternative_code(product: &Product, counter: i32) -> String {
-    let country = extract_country_code(&product.origin_country);
-    let category = extract_category_code(&product.category);
-    let brand = extract_brand_code(&product.brand);
-    let weight = extract_weight_digits(&product.weight);
-    
-    format!("{}{}{}{}{:02}", 
-        country, 
-        &category[..2.min(category.len())], 
-        &brand[..3.min(brand.len())], 
-        format!("{:0>3}", weight), 
-        counter)
+impl Field {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Field::Country => "country",
+            Field::Category => "category",
+            Field::Brand => "brand",
+            Field::Weight => "weight",
+            Field::Batch => "batch",
+            Field::Expiry => "expiry",
+            Field::Sequence => "sequence",
+            Field::Check => "check",
+        }
+    }
+
+    /// Fields whose text can be transformed and is cut to the segment's width.
+    fn is_text(self) -> bool {
+        matches!(self, Field::Country | Field::Category | Field::Brand)
+    }
+
+    /// Fields padded with zeros on the left by default and never cut short.
+    fn is_number(self) -> bool {
+        matches!(self, Field::Weight | Field::Batch | Field::Sequence)
+    }
 }
 
-fn extract_country_code(country: &str) -> String {
-    match country.to_lowercase().as_str() {
-        "lebanon" => "LB".to_string(),
-        "turkey" => "TR".to_string(),
-        "india" => "IN".to_string(),
-        "pakistan" => "PK".to_string(),
-        "tunisia" => "TN".to_string(),
-        "morocco" => "MA".to_string(),
-        "egypt" => "EG".to_string(),
-        _ => country[..2.min(country.len())].to_uppercase(),
+/// How a name becomes the text of a segment. Names are first spelled in ASCII
+/// (see `ascii`), so codes can be typed on any keyboard.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    /// The usual abbreviation for countries and categories, in English, Turkish,
+    /// Swedish or Arabic (Lebanon, Lübnan or لبنان → LB; Legumes → LEG),
+    /// otherwise upper case
+    Code,
+    Upper,
+    Lower,
+    /// Only the digits, e.g. 900g → 900
+    Digits,
+    /// Case as written
+    Keep,
+}
+
+impl Transform {
+    fn apply(self, field: Field, value: &str) -> String {
+        let value = ascii(value);
+        match self {
+            Transform::Code => {
+                let code = match field {
+                    Field::Country => country_code(&value),
+                    Field::Category => category_code(&value),
+                    _ => None,
+                };
+                code.map_or_else(|| value.to_ascii_uppercase(), str::to_string)
+            }
+            Transform::Upper => value.to_ascii_uppercase(),
+            Transform::Lower => value.to_ascii_lowercase(),
+            Transform::Digits => value.chars().filter(|c| c.is_ascii_digit()).collect(),
+            Transform::Keep => value,
+        }
     }
 }
 
-fn extract_category_code(category: &str) -> String {
-    match category.to_lowercase().as_str() {
-        "legumes" | "beans" => "LEG".to_string(),
-        "grains" | "rice" => "GRA".to_string(),
-        "oils" => "OIL".to_string(),
-        "spices" => "SPI".to_string(),
-        "dairy" => "DAI".to_string(),
-        _ => category[..3.min(category.len())].to_uppercase(),
+/// Spells a name in ASCII for a code, one grapheme at a time so a letter keeps
+/// its accents and marks: Ülker → Ulker, Kikärtor → Kikartor, ı → i, شتورة →
+/// shtwr. Letters and digits of other scripts become Latin letters and digits;
+/// whitespace, symbols and emoji are dropped. ASCII is kept as it is.
+pub fn ascii(value: &str) -> String {
+    value
+        .graphemes(true)
+        .map(|grapheme| {
+            if grapheme.is_ascii() {
+                grapheme.to_string()
+            } else if grapheme.starts_with(char::is_alphanumeric) {
+                deunicode_with_tofu(grapheme, "").chars().filter(char::is_ascii_alphanumeric).collect()
+            } else {
+                String::new()
+            }
+        })
+        .flat_map(|spelled| spelled.chars().filter(char::is_ascii_graphic).collect::<Vec<_>>())
+        .collect()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Segment {
+    pub field: Field,
+    /// Text longer than this is cut short; shorter values are padded to it when
+    /// there is a padding character. Numbers are never cut.
+    #[serde(default)]
+    pub width: Option<usize>,
+    /// Numbers pad on the left, with 0 unless set; text pads on the right, and
+    /// only when set.
+    #[serde(default)]
+    pub pad: Option<char>,
+    /// Defaults to `code` for country and category, `upper` for brand and
+    /// `digits` for weight.
+    #[serde(default)]
+    pub transform: Option<Transform>,
+    /// strftime format of the expiry date, `%Y%m` by default
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+impl Segment {
+    fn check(&self) -> Result<(), String> {
+        let field = self.field.as_str();
+        if self.width == Some(0) {
+            return Err(format!("{} segment width must be at least 1", field));
+        }
+        if self.transform.is_some() && !(self.field.is_text() || self.field == Field::Weight) {
+            return Err(format!("{} segment cannot have a transform", field));
+        }
+        match (&self.format, self.field) {
+            (Some(_), field) if field != Field::Expiry => Err("only expiry segments have a format".to_string()),
+            (Some(format), _) if StrftimeItems::new(format).any(|item| item == Item::Error) => {
+                Err(format!("invalid expiry format {:?}", format))
+            }
+            _ => Ok(()),
+        }
     }
+
+    fn render(&self, values: &Values) -> String {
+        let product = values.product;
+        let text = match self.field {
+            Field::Country => self.transform(Transform::Code, &product.origin_country),
+            Field::Category => self.transform(Transform::Code, &product.category),
+            Field::Brand => self.transform(Transform::Upper, &product.brand),
+            Field::Weight => self.transform(Transform::Digits, &product.weight),
+            Field::Batch => values.batch_id.to_string(),
+            Field::Expiry => values.expiry_date.format(self.expiry_format()).to_string(),
+            Field::Sequence => product.code_sequence.unwrap_or_default().to_string(),
+            Field::Check => unreachable!("check characters are rendered by the template"),
+        };
+
+        let Some(width) = self.width else { return text };
+        let length = text.chars().count();
+        if self.field.is_number() {
+            let pad = self.pad.unwrap_or('0');
+            return std::iter::repeat_n(pad, width.saturating_sub(length)).chain(text.chars()).collect();
+        }
+        let mut text: String = text.chars().take(width).collect();
+        if let Some(pad) = self.pad {
+            text.extend(std::iter::repeat_n(pad, width.saturating_sub(length)));
+        }
+        text
+    }
+
+    fn transform(&self, default: Transform, value: &str) -> String {
+        self.transform.unwrap_or(default).apply(self.field, value)
+    }
+
+    fn expiry_format(&self) -> &str {
+        self.format.as_deref().unwrap_or("%Y%m")
+    }
+
+    /// Whether `text` could have been rendered by this segment.
+    fn accepts(&self, text: &str) -> bool {
+        let length = text.chars().count();
+        match self.field {
+            Field::Check => length == 1 && text.chars().all(|c| CHECK_ALPHABET.contains(c.to_ascii_uppercase())),
+            Field::Expiry => parse_date(text, self.expiry_format()).is_some(),
+            field if field.is_number() => {
+                !text.is_empty()
+                    && self.width.is_none_or(|width| length >= width)
+                    && self.number(text).is_some()
+            }
+            _ => {
+                let fits = match (self.width, self.pad) {
+                    (Some(width), Some(_)) => length == width,
+                    (Some(width), None) => length <= width,
+                    (None, _) => true,
+                };
+                let cased = match self.transform.unwrap_or(Transform::Upper) {
+                    Transform::Code | Transform::Upper => !text.chars().any(char::is_lowercase),
+                    Transform::Lower => !text.chars().any(char::is_uppercase),
+                    Transform::Digits | Transform::Keep => true,
+                };
+                fits && cased
+            }
+        }
+    }
+
+    /// The number in a number segment's text, past its padding.
+    fn number(&self, text: &str) -> Option<i64> {
+        let digits = text.trim_start_matches(self.pad.unwrap_or('0'));
+        if digits.is_empty() {
+            return Some(0);
+        }
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    }
+
+    /// A text segment's text without its padding.
+    fn text<'a>(&self, text: &'a str) -> &'a str {
+        match self.pad {
+            Some(pad) => text.trim_end_matches(pad),
+            None => text,
+        }
+    }
+}
+
+/// Reads a date written with `format`; formats without a day give the first of
+/// the month.
+fn parse_date(text: &str, format: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(text, format)
+        .or_else(|_| NaiveDate::parse_from_str(&format!("{}|01", text), &format!("{}|%d", format)))
+        .ok()
 }
 
-fn extract_brand_code(brand: &str) -> String {
-    brand.replace(' ', "")[..3.min(brand.len())].to_uppercase()
+/// What a code is rendered from: the product, with a lot's batch and expiry for
+/// lot codes.
+struct Values<'a> {
+    product: &'a Product,
+    batch_id: i32,
+    expiry_date: NaiveDate,
 }
 
-fn extract_weight_digits(weight: &str) -> String {
-    weight.chars().filter(|c| c.is_ascii_digit()).collect()
+/// The layout of one kind of code: its segments in order, joined by `separator`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CodeTemplate {
+    #[serde(default)]
+    pub separator: String,
+    pub segments: Vec<Segment>,
 }
 
-fn extract_expiry_code(expiry: &str) -> String {
-    // Assumes format YYYY-MM-DD, extracts YYYYMM
-    if expiry.len() >= 7 {
-        format!("{}{}", &expiry[..4], &expiry[5..7])
-    } else {
-        "202501".to_string()
+impl CodeTemplate {
+    fn check(&self) -> Result<(), String> {
+        if self.segments.is_empty() {
+            return Err("needs at least one segment".to_string());
+        }
+        for (i, segment) in self.segments.iter().enumerate() {
+            if segment.field == Field::Check && i + 1 != self.segments.len() {
+                return Err("the check segment must come last".to_string());
+            }
+            segment.check()?;
+        }
+        Ok(())
     }
-}
\ No newline at end of file
+
+    fn render(&self, values: &Values) -> String {
+        self.render_segments(values, self.segments.len())
+    }
+
+    /// The code up to where its sequence number starts, separator included, or
+    /// the whole code without a sequence segment.
+    fn prefix(&self, values: &Values) -> String {
+        let end = self.segments.iter().position(|segment| segment.field == Field::Sequence);
+        let mut code = self.render_segments(values, end.unwrap_or(self.segments.len()));
+        if end.is_some_and(|end| end > 0) {
+            code.push_str(&self.separator);
+        }
+        code
+    }
+
+    /// Splits `code` into the text of each segment, or `None` when it does not
+    /// have this layout. Where text could be split more than one way, earlier
+    /// segments take as much as they can.
+    fn parse(&self, code: &str) -> Option<Vec<Range<usize>>> {
+        let mut parts = Vec::with_capacity(self.segments.len());
+        self.parse_from(code, 0, &mut parts).then_some(parts)
+    }
+
+    fn parse_from(&self, code: &str, start: usize, parts: &mut Vec<Range<usize>>) -> bool {
+        let i = parts.len();
+        let Some(segment) = self.segments.get(i) else {
+            return start == code.len();
+        };
+        let mut start = start;
+        if i > 0 {
+            if !code[start..].starts_with(&self.separator) {
+                return false;
+            }
+            start += self.separator.len();
+        }
+
+        let ends: Vec<usize> = code[start..].char_indices().map(|(offset, _)| start + offset).chain([code.len()]).collect();
+        for &end in ends.iter().rev() {
+            if segment.accepts(&code[start..end]) {
+                parts.push(start..end);
+                if self.parse_from(code, end, parts) {
+                    return true;
+                }
+                parts.pop();
+            }
+        }
+        false
+    }
+
+    fn render_segments(&self, values: &Values, end: usize) -> String {
+        let mut code = String::new();
+        for (i, segment) in self.segments[..end].iter().enumerate() {
+            if i > 0 {
+                code.push_str(&self.separator);
+            }
+            let part = match segment.field {
+                Field::Check => check_character(&code).to_string(),
+                _ => segment.render(values),
+            };
+            code.push_str(&part);
+        }
+        code
+    }
+}
+
+/// Limits a scheme to products of a category and/or supplier. Names compare
+/// case-insensitively, supplier names also without their legal form.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CodeScope {
+    pub category: Option<String>,
+    pub supplier: Option<String>,
+}
+
+impl CodeScope {
+    fn matches(&self, product: &Product) -> bool {
+        self.category.as_ref().is_none_or(|want| want.eq_ignore_ascii_case(&product.category))
+            && self.supplier.as_ref().is_none_or(|want| Supplier::name_key(want) == Supplier::name_key(&product.supplier))
+    }
+
+    /// Supplier scopes rank over category scopes, and both over no scope.
+    fn specificity(&self) -> u8 {
+        (self.supplier.is_some() as u8) << 1 | self.category.is_some() as u8
+    }
+}
+
+/// One version of a named layout for a product's internal code (also used for
+/// its lot codes) and alternative code. Products keep the scheme and version
+/// their codes were made with, so editing a scheme means adding a version.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CodeScheme {
+    pub name: String,
+    pub version: u32,
+    /// Products new codes use this scheme for; only the latest version's counts
+    #[serde(default)]
+    pub scope: CodeScope,
+    pub internal: CodeTemplate,
+    pub alternative: CodeTemplate,
+}
+
+impl CodeScheme {
+    /// The standard scheme's built-in versions, latest first.
+    fn built_in() -> Vec<Self> {
+        let mut checked = Self::standard();
+        checked.version = 2;
+        checked.internal.segments.push(Segment { field: Field::Check, width: None, pad: None, transform: None, format: None });
+        vec![checked, Self::standard()]
+    }
+
+    /// The layout of the standard scheme's first version:
+    /// `CC-CAT-BRA-WEIGHT-BATCH-YYYYMM` and `CCCABRAWWWSS`.
+    fn standard() -> Self {
+        let segment = |field, width: Option<usize>| Segment { field, width, pad: None, transform: None, format: None };
+        Self {
+            name: STANDARD_SCHEME.to_string(),
+            version: 1,
+            scope: CodeScope::default(),
+            internal: CodeTemplate {
+                separator: "-".to_string(),
+                segments: vec![
+                    segment(Field::Country, Some(2)),
+                    segment(Field::Category, Some(3)),
+                    segment(Field::Brand, Some(3)),
+                    segment(Field::Weight, None),
+                    segment(Field::Batch, Some(3)),
+                    segment(Field::Expiry, None),
+                ],
+            },
+            alternative: CodeTemplate {
+                separator: String::new(),
+                segments: vec![
+                    segment(Field::Country, Some(2)),
+                    segment(Field::Category, Some(2)),
+                    segment(Field::Brand, Some(3)),
+                    segment(Field::Weight, Some(3)),
+                    segment(Field::Sequence, Some(2)),
+                ],
+            },
+        }
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.version == 0 {
+            return Err("version must be at least 1".to_string());
+        }
+        self.internal.check().map_err(|e| format!("internal code {}", e))?;
+        self.alternative.check().map_err(|e| format!("alternative code {}", e))
+    }
+
+    pub fn internal_code(&self, product: &Product) -> String {
+        self.internal.render(&Values { product, batch_id: product.batch_id, expiry_date: product.expiry_date })
+    }
+
+    /// Internal code for one lot of a product: same layout, with the lot's batch and expiry.
+    pub fn lot_code(&self, product: &Product, lot: &LotInput) -> String {
+        self.internal.render(&Values { product, batch_id: lot.batch_id, expiry_date: lot.expiry_date })
+    }
+
+    pub fn alternative_code(&self, product: &Product) -> String {
+        self.alternative.render(&Values { product, batch_id: product.batch_id, expiry_date: product.expiry_date })
+    }
+
+    /// Reads `code` as an internal or lot code of this scheme. `None` when it does
+    /// not have the layout; an error when its check character does not match.
+    fn decode(&self, code: &str) -> Option<Result<InternalCode, AppError>> {
+        let parts = self.internal.parse(code)?;
+        let mut decoded = InternalCode {
+            code: code.to_string(),
+            scheme: self.name.clone(),
+            version: self.version,
+            country: None,
+            category: None,
+            brand: None,
+            weight: None,
+            batch: None,
+            expiry: None,
+            sequence: None,
+            checked: false,
+        };
+        for (segment, range) in self.internal.segments.iter().zip(parts) {
+            let text = &code[range.clone()];
+            match segment.field {
+                Field::Country => decoded.country = Some(segment.text(text).to_string()),
+                Field::Category => decoded.category = Some(segment.text(text).to_string()),
+                Field::Brand => decoded.brand = Some(segment.text(text).to_string()),
+                Field::Weight => decoded.weight = segment.number(text),
+                Field::Batch => decoded.batch = segment.number(text).and_then(|batch| i32::try_from(batch).ok()),
+                Field::Expiry => decoded.expiry = parse_date(text, segment.expiry_format()),
+                Field::Sequence => decoded.sequence = segment.number(text),
+                Field::Check => {
+                    if !text.eq_ignore_ascii_case(&check_character(&code[..range.start]).to_string()) {
+                        return Some(Err(AppError::Validation(format!(
+                            "check character of {} does not match; the code is probably mistyped", code
+                        ))));
+                    }
+                    decoded.checked = true;
+                }
+            }
+        }
+        Some(Ok(decoded))
+    }
+
+    /// The alternative code up to its sequence number. Each prefix counts on its
+    /// own, so sequence numbers stay short.
+    pub fn sequence_prefix(&self, product: &Product) -> String {
+        self.alternative.prefix(&Values { product, batch_id: product.batch_id, expiry_date: product.expiry_date })
+    }
+}
+
+/// Every code scheme version, the built-in standard one last.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CodeSchemes {
+    #[serde(default)]
+    pub schemes: Vec<CodeScheme>,
+}
+
+impl Default for CodeSchemes {
+    /// Only the built-in standard scheme.
+    fn default() -> Self {
+        Self { schemes: CodeScheme::built_in() }
+    }
+}
+
+impl CodeSchemes {
+    /// Reads a schemes file, as JSON if it ends in `.json` and TOML otherwise.
+    pub fn from_file(path: &Path) -> Result<Self, AppError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| AppError::Config(format!("cannot read {}: {}", path.display(), e)))?;
+
+        let mut schemes: Self = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&contents)
+                .map_err(|e| AppError::Config(format!("invalid {}: {}", path.display(), e)))?
+        } else {
+            toml::from_str(&contents)
+                .map_err(|e| AppError::Config(format!("invalid {}: {}", path.display(), e)))?
+        };
+        schemes.check()?;
+        schemes.schemes.extend(CodeScheme::built_in());
+        Ok(schemes)
+    }
+
+    /// The configured schemes file, or only the standard scheme without one.
+    pub fn load(schemes_file: Option<&str>) -> Result<Self, AppError> {
+        match schemes_file {
+            Some(path) => Self::from_file(Path::new(path)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn check(&self) -> Result<(), AppError> {
+        for (i, scheme) in self.schemes.iter().enumerate() {
+            if scheme.name.trim().is_empty() {
+                return Err(AppError::Config("every code scheme needs a name".to_string()));
+            }
+            if scheme.name == STANDARD_SCHEME && scheme.version <= STANDARD_VERSIONS {
+                return Err(AppError::Config(format!(
+                    "{} versions up to {} are built in; add version {} to change it", STANDARD_SCHEME, STANDARD_VERSIONS, STANDARD_VERSIONS + 1
+                )));
+            }
+            if self.schemes[..i].iter().any(|other| other.name == scheme.name && other.version == scheme.version) {
+                return Err(AppError::Config(format!("code scheme {:?} version {} is defined twice", scheme.name, scheme.version)));
+            }
+            scheme.check()
+                .map_err(|e| AppError::Config(format!("code scheme {:?} version {}: {}", scheme.name, scheme.version, e)))?;
+        }
+        Ok(())
+    }
+
+    /// The scheme a product's codes were made with.
+    pub fn of(&self, product: &Product) -> Result<&CodeScheme, AppError> {
+        self.get(&product.code_scheme, product.code_version)
+    }
+
+    pub fn get(&self, name: &str, version: u32) -> Result<&CodeScheme, AppError> {
+        self.schemes
+            .iter()
+            .find(|scheme| scheme.name == name && scheme.version == version)
+            .ok_or_else(|| AppError::Config(format!("code scheme {:?} version {} is not defined", name, version)))
+    }
+
+    /// Reads an internal or lot code back into its fields, trying each scheme in
+    /// turn. Codes are matched as typed, then in upper case.
+    pub fn parse_internal_code(&self, code: &str) -> Result<InternalCode, AppError> {
+        let code = code.trim();
+        let mut mismatch = None;
+        for candidate in [code.to_string(), code.to_uppercase()] {
+            for scheme in &self.schemes {
+                match scheme.decode(&candidate) {
+                    Some(Ok(decoded)) => return Ok(decoded),
+                    Some(Err(e)) => mismatch = mismatch.or(Some(e)),
+                    None => {}
+                }
+            }
+        }
+        Err(mismatch.unwrap_or_else(|| AppError::Validation(format!("{:?} does not match any code scheme", code))))
+    }
+
+    /// The scheme new codes for `product` use: of the latest version of each
+    /// scheme, the one with the most specific matching scope, and of those the
+    /// first in the file.
+    pub fn for_new(&self, product: &Product) -> Result<&CodeScheme, AppError> {
+        self.schemes
+            .iter()
+            .filter(|scheme| !self.schemes.iter().any(|other| other.name == scheme.name && other.version > scheme.version))
+            .filter(|scheme| scheme.scope.matches(product))
+            .fold(None::<&CodeScheme>, |best, scheme| match best {
+                Some(best) if best.scope.specificity() >= scheme.scope.specificity() => Some(best),
+                _ => Some(scheme),
+            })
+            .ok_or_else(|| AppError::Config(format!("no code scheme applies to product {}", product.barcode)))
+    }
+}
+
+/// The fields of an internal or lot code. Fields its scheme's layout leaves out
+/// are empty; text fields hold only the letters the code kept, e.g. the first
+/// three of the brand.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct InternalCode {
+    pub code: String,
+    pub scheme: String,
+    pub version: u32,
+    pub country: Option<String>,
+    pub category: Option<String>,
+    pub brand: Option<String>,
+    /// Digits of the weight, without a unit
+    pub weight: Option<i64>,
+    pub batch: Option<i32>,
+    /// First of the month when the layout has no day
+    pub expiry: Option<NaiveDate>,
+    pub sequence: Option<i64>,
+    /// Whether a check character confirmed the code; codes without one cannot
+    /// catch typos
+    pub checked: bool,
+}
+
+/// ISO 7064 MOD 37,36 check character over the letters and digits of `code`;
+/// other characters, such as separators, are skipped. It catches every single
+/// mistyped character and most swapped neighbours.
+pub fn check_character(code: &str) -> char {
+    let modulus = CHECK_ALPHABET.len() as u32;
+    let mut product = modulus;
+    for value in code.chars().filter_map(|c| CHECK_ALPHABET.find(c.to_ascii_uppercase())) {
+        let mut sum = (product + value as u32) % modulus;
+        if sum == 0 {
+            sum = modulus;
+        }
+        product = (sum * 2) % (modulus + 1);
+    }
+    let check = (modulus + 1 - product) % modulus;
+    CHECK_ALPHABET.as_bytes()[check as usize] as char
+}
+
+/// Takes a name already spelled in ASCII, so Arabic names appear as `ascii`
+/// spells them (لبنان → lbnn).
+fn country_code(country: &str) -> Option<&'static str> {
+    match country.to_ascii_lowercase().as_str() {
+        "lebanon" | "lubnan" | "libanon" | "lbnn" => Some("LB"),
+        "turkey" | "turkiye" | "turkiet" | "trky" | "trkya" => Some("TR"),
+        "india" | "hindistan" | "indien" | "lhnd" => Some("IN"),
+        "pakistan" | "bkstn" => Some("PK"),
+        "tunisia" | "tunus" | "tunisien" | "twns" => Some("TN"),
+        "morocco" | "fas" | "marocko" | "lmgrb" => Some("MA"),
+        "egypt" | "misir" | "egypten" | "msr" => Some("EG"),
+        _ => None,
+    }
+}
+
+/// Takes a name already spelled in ASCII, like `country_code`.
+fn category_code(category: &str) -> Option<&'static str> {
+    match category.to_ascii_lowercase().as_str() {
+        "legumes" | "beans" | "baklagiller" | "bakliyat" | "baljvaxter" | "bqwlyt" => Some("LEG"),
+        "grains" | "rice" | "tahil" | "pirinc" | "spannmal" | "ris" | "hbwb" | "rz" => Some("GRA"),
+        "oils" | "yag" | "olja" | "zywt" => Some("OIL"),
+        "spices" | "baharat" | "kryddor" | "bhrt" => Some("SPI"),
+        "dairy" | "sut" | "mejeri" | "lbn" => Some("DAI"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ProductPatch;
+    use proptest::prelude::*;
+
+    fn product(brand: &str, category: &str, country: &str, weight: &str, batch_id: i32, expiry_date: NaiveDate) -> Product {
+        let mut product = ProductPatch {
+            brand: Some(brand.to_string()),
+            category: Some(category.to_string()),
+            origin_country: Some(country.to_string()),
+            weight: Some(weight.to_string()),
+            batch_id: Some(batch_id),
+            expiry_date: Some(expiry_date),
+            ..ProductPatch::default()
+        }
+        .into_product()
+        .unwrap();
+        product.code_sequence = Some(7);
+        product
+    }
+
+    fn expiry() -> impl Strategy<Value = NaiveDate> {
+        (2000i32..2100, 1u32..=12, 1u32..=28).prop_map(|(y, m, d)| NaiveDate::from_ymd_opt(y, m, d).unwrap())
+    }
+
+    #[test]
+    fn names_are_spelled_in_ascii() {
+        let expiry_date = NaiveDate::from_ymd_opt(2027, 12, 31).unwrap();
+        let ulker = product("Ülker", "Bakliyat", "Türkiye", "900g", 42, expiry_date);
+        let chtaura = product("شتورة", "بقوليات", "لبنان", "٩٠٠غ", 42, expiry_date);
+        let scheme = CodeScheme::standard();
+
+        assert_eq!(scheme.internal_code(&ulker), "TR-LEG-ULK-900-042-202712");
+        assert_eq!(scheme.internal_code(&chtaura), "LB-LEG-SHT-900-042-202712");
+        assert_eq!(scheme.alternative_code(&chtaura), "LBLESHT90007");
+        assert_eq!(ascii("Al-Wadi Al-Akhdar"), "Al-WadiAl-Akhdar");
+        assert_eq!(ascii("Spannmål 🌾"), "Spannmal");
+    }
+
+    proptest! {
+        #[test]
+        fn codes_never_panic_and_are_ascii(
+            brand in any::<String>(),
+            category in any::<String>(),
+            country in any::<String>(),
+            weight in any::<String>(),
+            batch_id in any::<i32>(),
+            expiry_date in expiry(),
+        ) {
+            let product = product(&brand, &category, &country, &weight, batch_id, expiry_date);
+            let lot = LotInput {
+                batch_id,
+                quantity: 1,
+                production_date: expiry_date,
+                expiry_date,
+                supplier_invoice: None,
+                landed_cost: crate::money::Money::ZERO,
+            };
+            for scheme in CodeScheme::built_in() {
+                for code in [
+                    scheme.internal_code(&product),
+                    scheme.lot_code(&product, &lot),
+                    scheme.alternative_code(&product),
+                    scheme.sequence_prefix(&product),
+                ] {
+                    prop_assert!(code.chars().all(|c| c.is_ascii_graphic()), "{:?}", code);
+                }
+            }
+        }
+
+        #[test]
+        fn ascii_is_printable_ascii(value in any::<String>()) {
+            prop_assert!(ascii(&value).chars().all(|c| c.is_ascii_graphic()));
+        }
+
+        #[test]
+        fn checked_codes_decode(
+            brand in "\\PC*",
+            category in "\\PC*",
+            country in "\\PC*",
+            weight in "[0-9]{1,9}\\PC{0,4}",
+            batch_id in 0i32..100_000,
+            expiry_date in expiry(),
+        ) {
+            let product = product(&brand, &category, &country, &weight, batch_id, expiry_date);
+            let code = CodeScheme::built_in()[0].internal_code(&product);
+            let decoded = CodeSchemes::default().parse_internal_code(&code);
+            prop_assert!(decoded.as_ref().is_ok_and(|decoded| decoded.checked), "{} {:?}", code, decoded);
+        }
+
+        #[test]
+        fn parsing_never_panics(code in any::<String>()) {
+            let _ = CodeSchemes::default().parse_internal_code(&code);
+            let _ = check_character(&code);
+        }
+    }
+}
@@ -0,0 +1,546 @@
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use std::str::FromStr;
+use chrono::NaiveTime;
+use croner::Cron;
+use crate::alert_rules::Severity;
+use crate::models::{is_currency_code, AppError};
+use crate::money::{self, Rounding, RoundingMode};
+use crate::scheduler::JobKind;
+
+const DEFAULT_CONFIG_FILE: &str = "food_imports.toml";
+const ENV_PREFIX: &str = "FOOD_IMPORTS_";
+
+/// Runtime configuration, layered as defaults -> TOML file -> environment -> CLI flags.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub server: ServerConfig,
+    pub lookup: LookupConfig,
+    pub inventory: InventoryConfig,
+    pub reorder: ReorderConfig,
+    pub currency: CurrencyConfig,
+    pub alerts: AlertsConfig,
+    pub pricing: PricingConfig,
+    pub codes: CodesConfig,
+    pub reports: ReportsConfig,
+    pub notifications: NotificationsConfig,
+    pub scheduler: SchedulerConfig,
+    pub log_level: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    pub wal: bool,
+    pub busy_timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// Allowed CORS origins; `*` allows any origin.
+    pub cors_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LookupConfig {
+    /// Providers to try in order: `fixtures`, `openfoodfacts`.
+    pub providers: Vec<String>,
+    pub openfoodfacts_url: String,
+    /// Directory of saved OpenFoodFacts responses named `<barcode>.json`.
+    pub fixture_dir: String,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InventoryConfig {
+    /// Sales velocity windows in days; the trend compares the shortest with the longest.
+    pub velocity_windows: Vec<u32>,
+    /// Window whose daily rate the days-of-cover alert conditions use.
+    pub rule_window_days: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReorderConfig {
+    /// Days from order to delivery for suppliers without their own lead time.
+    pub default_lead_time_days: u32,
+    /// Days of sales held back as safety stock.
+    pub safety_stock_days: u32,
+    /// Days between orders to the same supplier; each order covers this period.
+    pub review_period_days: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CurrencyConfig {
+    /// Currency lot costs, valuations and margins are kept in; exchange rates are
+    /// quoted as units of it per unit of the other currency.
+    pub base: String,
+    /// Decimal places (at most 4) for computed per-unit amounts: converted prices,
+    /// duty and landed cost.
+    pub unit_decimals: u32,
+    /// Decimal places (at most 4) for order totals and report values.
+    pub total_decimals: u32,
+    /// How computed amounts are rounded: half_even, half_up, down or up.
+    pub rounding: RoundingMode,
+}
+
+impl CurrencyConfig {
+    pub fn rounding(&self) -> Rounding {
+        Rounding {
+            unit_decimals: self.unit_decimals,
+            total_decimals: self.total_decimals,
+            mode: self.rounding,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertsConfig {
+    /// TOML or JSON file of alert rules; the built-in rules apply without one.
+    pub rules_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PricingConfig {
+    /// TOML or JSON file of pricing rules; without one recalculating changes nothing.
+    pub rules_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CodesConfig {
+    /// TOML or JSON file of code schemes; only the built-in standard scheme without one.
+    pub schemes_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportsConfig {
+    /// Days a report covers when no start date is given, ending on its last day.
+    pub period_days: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    pub channels: Vec<ChannelConfig>,
+    /// Timeout for SMTP and webhook deliveries.
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelConfig {
+    pub name: String,
+    /// Least severe alert sent on this channel.
+    #[serde(default = "default_min_severity")]
+    pub min_severity: Severity,
+    /// Local time (HH:MM) of a daily digest; without one, alerts go out as they open.
+    #[serde(default)]
+    pub digest_at: Option<String>,
+    #[serde(flatten)]
+    pub kind: ChannelKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChannelKind {
+    Smtp {
+        host: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        /// Upgrade the connection with STARTTLS; off for local relays.
+        #[serde(default)]
+        starttls: bool,
+        username: Option<String>,
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+    },
+    /// POSTs each batch as JSON.
+    Webhook {
+        url: String,
+    },
+    /// Appends each batch to a file as one JSON line.
+    File {
+        path: String,
+    },
+    /// Sends each alert to the local syslog socket.
+    Syslog {
+        #[serde(default = "default_syslog_socket")]
+        socket: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    /// Run the jobs inside `web` as well; `daemon` always runs them.
+    pub in_web: bool,
+    pub jobs: Vec<JobConfig>,
+    /// Directory the daily report is written to.
+    pub report_dir: String,
+    /// Directory database backups are written to.
+    pub backup_dir: String,
+    /// Backups to keep; older ones are deleted after each backup.
+    pub backup_keep: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobConfig {
+    pub job: JobKind,
+    /// Cron expression in local time, e.g. `30 6 * * *` for 06:30 every day.
+    pub schedule: String,
+}
+
+impl JobConfig {
+    fn new(job: JobKind, schedule: &str) -> Self {
+        Self { job, schedule: schedule.to_string() }
+    }
+
+    pub fn cron(&self) -> Result<Cron, AppError> {
+        Cron::new(&self.schedule).parse().map_err(|e| {
+            AppError::Config(format!("invalid schedule {:?} for {}: {}", self.schedule, self.job, e))
+        })
+    }
+}
+
+fn default_min_severity() -> Severity {
+    Severity::Info
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+fn default_syslog_socket() -> String {
+    "/dev/log".to_string()
+}
+
+impl ChannelConfig {
+    pub fn digest_time(&self) -> Result<Option<NaiveTime>, AppError> {
+        self.digest_at
+            .as_deref()
+            .map(|at| {
+                NaiveTime::parse_from_str(at, "%H:%M").map_err(|_| {
+                    AppError::Config(format!("channel {:?}: digest_at must be HH:MM, got {:?}", self.name, at))
+                })
+            })
+            .transpose()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database: DatabaseConfig::default(),
+            server: ServerConfig::default(),
+            lookup: LookupConfig::default(),
+            inventory: InventoryConfig::default(),
+            reorder: ReorderConfig::default(),
+            currency: CurrencyConfig::default(),
+            alerts: AlertsConfig::default(),
+            pricing: PricingConfig::default(),
+            codes: CodesConfig::default(),
+            reports: ReportsConfig::default(),
+            notifications: NotificationsConfig::default(),
+            scheduler: SchedulerConfig::default(),
+            log_level: "info".to_string(),
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: "sqlite:products.db".to_string(),
+            max_connections: 5,
+            wal: true,
+            busy_timeout_ms: 5000,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+            cors_origins: vec!["*".to_string()],
+        }
+    }
+}
+
+impl Default for LookupConfig {
+    fn default() -> Self {
+        Self {
+            providers: vec!["fixtures".to_string(), "openfoodfacts".to_string()],
+            openfoodfacts_url: "https://world.openfoodfacts.org".to_string(),
+            fixture_dir: "fixtures/lookup".to_string(),
+            timeout_secs: 10,
+        }
+    }
+}
+
+impl Default for InventoryConfig {
+    fn default() -> Self {
+        Self {
+            velocity_windows: vec![7, 30, 90],
+            rule_window_days: 30,
+        }
+    }
+}
+
+impl Default for ReorderConfig {
+    fn default() -> Self {
+        Self {
+            default_lead_time_days: 14,
+            safety_stock_days: 7,
+            review_period_days: 7,
+        }
+    }
+}
+
+impl Default for CurrencyConfig {
+    fn default() -> Self {
+        Self {
+            base: "SEK".to_string(),
+            unit_decimals: 4,
+            total_decimals: 2,
+            rounding: RoundingMode::HalfEven,
+        }
+    }
+}
+
+impl Default for ReportsConfig {
+    fn default() -> Self {
+        Self { period_days: 30 }
+    }
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            channels: Vec::new(),
+            timeout_secs: 10,
+        }
+    }
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            in_web: true,
+            jobs: vec![
+                JobConfig::new(JobKind::CheckAlerts, "*/15 * * * *"),
+                JobConfig::new(JobKind::DailyReport, "30 6 * * *"),
+                JobConfig::new(JobKind::Backup, "0 2 * * *"),
+                JobConfig::new(JobKind::Reorder, "0 5 * * 1"),
+            ],
+            report_dir: "reports".to_string(),
+            backup_dir: "backups".to_string(),
+            backup_keep: 14,
+        }
+    }
+}
+
+impl InventoryConfig {
+    /// Days of sales history the velocity windows and stock rules look at.
+    pub fn history_days(&self) -> u32 {
+        self.velocity_windows.iter().copied().chain([self.rule_window_days]).max().unwrap_or_default()
+    }
+}
+
+impl Config {
+    /// Loads the config file (explicit path, `FOOD_IMPORTS_CONFIG`, or `food_imports.toml`
+    /// if present) and applies environment overrides on top.
+    pub fn load(path: Option<&Path>) -> Result<Self, AppError> {
+        let env_path = std::env::var(format!("{}CONFIG", ENV_PREFIX)).ok();
+
+        let mut config = match path.map(Path::to_path_buf).or(env_path.map(Into::into)) {
+            Some(path) => Self::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => Self::default(),
+        };
+
+        config.apply_env()?;
+        config.check()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self, AppError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| AppError::Config(format!("cannot read {}: {}", path.display(), e)))?;
+
+        toml::from_str(&contents)
+            .map_err(|e| AppError::Config(format!("invalid {}: {}", path.display(), e)))
+    }
+
+    fn check(&self) -> Result<(), AppError> {
+        if self.database.max_connections == 0 {
+            return Err(AppError::Config("database max_connections must be at least 1".to_string()));
+        }
+        let windows = &self.inventory.velocity_windows;
+        if windows.is_empty() || windows.contains(&0) {
+            return Err(AppError::Config("velocity_windows must list at least one window of 1 day or more".to_string()));
+        }
+        if self.inventory.rule_window_days == 0 {
+            return Err(AppError::Config("rule_window_days must be at least 1".to_string()));
+        }
+        if self.reports.period_days == 0 {
+            return Err(AppError::Config("report period_days must be at least 1".to_string()));
+        }
+        if !is_currency_code(&self.currency.base) {
+            return Err(AppError::Config(format!("base currency must be a three-letter ISO code, got {:?}", self.currency.base)));
+        }
+        if self.currency.unit_decimals > money::SCALE || self.currency.total_decimals > money::SCALE {
+            return Err(AppError::Config(format!("currency decimals must be at most {}", money::SCALE)));
+        }
+        for (i, channel) in self.notifications.channels.iter().enumerate() {
+            if self.notifications.channels[..i].iter().any(|c| c.name == channel.name) {
+                return Err(AppError::Config(format!("duplicate notification channel {:?}", channel.name)));
+            }
+            channel.digest_time()?;
+        }
+        for job in &self.scheduler.jobs {
+            job.cron()?;
+        }
+        Ok(())
+    }
+
+    fn apply_env(&mut self) -> Result<(), AppError> {
+        if let Some(url) = env_var("DATABASE_URL") {
+            self.database.url = url;
+        }
+        if let Some(size) = env_parse("DB_POOL_SIZE")? {
+            self.database.max_connections = size;
+        }
+        if let Some(wal) = env_parse("DB_WAL")? {
+            self.database.wal = wal;
+        }
+        if let Some(timeout) = env_parse("DB_BUSY_TIMEOUT_MS")? {
+            self.database.busy_timeout_ms = timeout;
+        }
+        if let Some(host) = env_var("HOST") {
+            self.server.host = host;
+        }
+        if let Some(port) = env_parse("PORT")? {
+            self.server.port = port;
+        }
+        if let Some(origins) = env_var("CORS_ORIGINS") {
+            self.server.cors_origins = split_list(&origins);
+        }
+        if let Some(providers) = env_var("LOOKUP_PROVIDERS") {
+            self.lookup.providers = split_list(&providers);
+        }
+        if let Some(url) = env_var("LOOKUP_URL") {
+            self.lookup.openfoodfacts_url = url;
+        }
+        if let Some(dir) = env_var("LOOKUP_FIXTURES") {
+            self.lookup.fixture_dir = dir;
+        }
+        if let Some(windows) = env_var("VELOCITY_WINDOWS") {
+            self.inventory.velocity_windows = split_list(&windows)
+                .iter()
+                .map(|days| days.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| AppError::Config(format!("invalid value for {}VELOCITY_WINDOWS: {}", ENV_PREFIX, windows)))?;
+        }
+        if let Some(base) = env_var("BASE_CURRENCY") {
+            self.currency.base = base;
+        }
+        if let Some(mode) = env_parse("ROUNDING")? {
+            self.currency.rounding = mode;
+        }
+        if let Some(path) = env_var("ALERT_RULES") {
+            self.alerts.rules_file = Some(path);
+        }
+        if let Some(path) = env_var("PRICING_RULES") {
+            self.pricing.rules_file = Some(path);
+        }
+        if let Some(path) = env_var("CODE_SCHEMES") {
+            self.codes.schemes_file = Some(path);
+        }
+        if let Some(in_web) = env_parse("SCHEDULER_IN_WEB")? {
+            self.scheduler.in_web = in_web;
+        }
+        if let Some(level) = env_var("LOG_LEVEL") {
+            self.log_level = level;
+        }
+
+        Ok(())
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(format!("{}{}", ENV_PREFIX, name)).ok()
+}
+
+fn env_parse<T: FromStr>(name: &str) -> Result<Option<T>, AppError> {
+    env_var(name)
+        .map(|value| {
+            value.parse().map_err(|_| {
+                AppError::Config(format!("invalid value for {}{}: {}", ENV_PREFIX, name, value))
+            })
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_toml(name: &str, contents: &str) -> Result<Config, AppError> {
+        let path = std::env::temp_dir().join(format!("food_imports_{}_{}.toml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let config = Config::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let config = config?;
+        config.check()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn defaults_pass_their_own_checks() {
+        let config = Config::default();
+        config.check().unwrap();
+        assert_eq!(config.database.max_connections, 5);
+        assert_eq!(config.database.url, "sqlite:products.db");
+    }
+
+    #[test]
+    fn files_override_only_what_they_set() {
+        let config = from_toml("partial", "[database]\nmax_connections = 2\n\n[server]\nport = 9000\n").unwrap();
+        assert_eq!(config.database.max_connections, 2);
+        assert_eq!(config.server.port, 9000);
+        assert!(config.database.wal);
+        assert_eq!(config.server.host, ServerConfig::default().host);
+    }
+
+    #[test]
+    fn an_empty_pool_is_rejected() {
+        let error = from_toml("empty_pool", "[database]\nmax_connections = 0\n").unwrap_err().to_string();
+        assert!(error.contains("max_connections must be at least 1"), "{}", error);
+        assert!(from_toml("bad_windows", "[inventory]\nvelocity_windows = []\n").is_err());
+        assert!(from_toml("bad_currency", "[currency]\nbase = \"kronor\"\n").is_err());
+    }
+}
@@ -0,0 +1,581 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use crate::database::Database;
+use crate::models::{AppError, ChangeNote, ExchangeRateInput, Product, ProductPatch, PurchaseOrder};
+use crate::money::Rate;
+use crate::reports::Table;
+
+/// Product fields that can be set from an import file, in export column order.
+const IMPORT_FIELDS: [&str; 24] = [
+    "barcode", "original_name", "imported_name", "local_name", "brand", "category",
+    "weight", "origin_country", "supplier", "purchase_price", "purchase_currency",
+    "wholesale_price", "wholesale_currency", "retail_price", "retail_currency",
+    "freight_cost", "duty_percent", "handling_cost", "production_date", "expiry_date",
+    "batch_id", "stock_quantity", "min_threshold", "case_pack",
+];
+
+/// Generated fields; exported, but ignored on import.
+const GENERATED_FIELDS: [&str; 2] = ["internal_code", "alternative_code"];
+
+/// Maps spreadsheet column headers onto product field names. Headers that are not
+/// listed are matched against the field names themselves (case-insensitive).
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct HeaderMapping {
+    #[serde(default)]
+    pub columns: HashMap<String, String>,
+}
+
+impl HeaderMapping {
+    pub fn from_file(path: &Path) -> Result<Self, AppError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| AppError::Config(format!("cannot read {}: {}", path.display(), e)))?;
+
+        let mapping: Self = toml::from_str(&contents)
+            .map_err(|e| AppError::Config(format!("invalid {}: {}", path.display(), e)))?;
+        mapping.check()?;
+        Ok(mapping)
+    }
+
+    pub fn check(&self) -> Result<(), AppError> {
+        for (header, field) in &self.columns {
+            if !IMPORT_FIELDS.contains(&field.as_str()) && !GENERATED_FIELDS.contains(&field.as_str()) {
+                return Err(AppError::Config(format!(
+                    "column {:?} is mapped to unknown product field {:?}", header, field
+                )));
+            }
+            // Exports name each field's column after its mapping, so it must be one
+            let mut headers: Vec<&str> = self.columns.iter().filter(|(_, f)| *f == field).map(|(h, _)| h.as_str()).collect();
+            if headers.len() > 1 {
+                headers.sort();
+                return Err(AppError::Config(format!(
+                    "columns {:?} are all mapped to product field {:?}; map only one", headers, field
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn field_for(&self, header: &str) -> Option<&str> {
+        if let Some(field) = self.columns.get(header) {
+            return Some(field);
+        }
+
+        let normalized = header.trim().to_lowercase().replace([' ', '-'], "_");
+        IMPORT_FIELDS
+            .iter()
+            .chain(GENERATED_FIELDS.iter())
+            .find(|field| **field == normalized)
+            .copied()
+    }
+
+    fn header_for<'a>(&'a self, field: &'a str) -> &'a str {
+        self.columns
+            .iter()
+            .find(|(_, f)| f.as_str() == field)
+            .map(|(header, _)| header.as_str())
+            .unwrap_or(field)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub struct ImportOptions {
+    /// Validate and preview the import without committing it.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Update products whose barcode already exists instead of rejecting the row.
+    #[serde(default)]
+    pub upsert: bool,
+}
+
+/// One parsed data row, with its line number in the source file.
+pub struct ImportRow {
+    pub line: u64,
+    pub patch: ProductPatch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportAction {
+    Insert,
+    Update,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RowPreview {
+    pub line: u64,
+    pub action: ImportAction,
+    pub barcode: String,
+    pub imported_name: String,
+    /// Set when the barcode's GS1 prefix disagrees with the origin country
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RowError {
+    pub line: u64,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub committed: bool,
+    pub rows: Vec<RowPreview>,
+    pub errors: Vec<RowError>,
+    pub ignored_columns: Vec<String>,
+}
+
+impl ImportReport {
+    pub fn count(&self, action: ImportAction) -> usize {
+        self.rows.iter().filter(|row| row.action == action).count()
+    }
+}
+
+/// Imports a CSV file in a single transaction. If any row fails, nothing is written.
+pub async fn import_csv<R: Read>(
+    db: &Database,
+    reader: R,
+    mapping: &HeaderMapping,
+    options: ImportOptions,
+    note: &ChangeNote,
+) -> Result<ImportReport, AppError> {
+    let mut csv = csv::Reader::from_reader(reader);
+    let headers = csv.headers()?.clone();
+
+    let mut ignored_columns = Vec::new();
+    let columns: Vec<Option<&str>> = headers
+        .iter()
+        .map(|header| {
+            let field = mapping.field_for(header).filter(|f| !GENERATED_FIELDS.contains(f));
+            if field.is_none() {
+                ignored_columns.push(header.to_string());
+            }
+            field
+        })
+        .collect();
+
+    let mut rows = Vec::new();
+    let mut parse_errors = Vec::new();
+    for record in csv.records() {
+        let record = record?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+
+        let mut patch = ProductPatch::default();
+        let mut problems = Vec::new();
+        for (field, value) in columns.iter().zip(record.iter()) {
+            if let Some(field) = field {
+                if let Err(e) = set_field(&mut patch, field, value.trim()) {
+                    problems.push(e);
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            rows.push(ImportRow { line, patch });
+        } else {
+            parse_errors.push(RowError { line, message: problems.join("; ") });
+        }
+    }
+
+    let commit = !options.dry_run && parse_errors.is_empty();
+    let mut report = db.import_products(rows, options.upsert, commit, note).await?;
+    report.dry_run = options.dry_run;
+    report.ignored_columns = ignored_columns;
+    report.errors.extend(parse_errors);
+    report.errors.sort_by_key(|e| e.line);
+    Ok(report)
+}
+
+fn set_field(patch: &mut ProductPatch, field: &str, value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    fn number<T: std::str::FromStr>(field: &str, value: &str) -> Result<Option<T>, String> {
+        value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("{}: invalid number {:?}", field, value))
+    }
+
+    fn date(field: &str, value: &str) -> Result<Option<chrono::NaiveDate>, String> {
+        chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| format!("{}: expected a YYYY-MM-DD date, got {:?}", field, value))
+    }
+
+    let text = Some(value.to_string());
+    match field {
+        "barcode" => patch.barcode = text,
+        "original_name" => patch.original_name = text,
+        "imported_name" => patch.imported_name = text,
+        "local_name" => patch.local_name = text,
+        "brand" => patch.brand = text,
+        "category" => patch.category = text,
+        "weight" => patch.weight = text,
+        "origin_country" => patch.origin_country = text,
+        "supplier" => patch.supplier = text,
+        "purchase_price" => patch.purchase_price = number(field, value)?,
+        "purchase_currency" => patch.purchase_currency = text,
+        "wholesale_price" => patch.wholesale_price = number(field, value)?,
+        "wholesale_currency" => patch.wholesale_currency = text,
+        "retail_price" => patch.retail_price = number(field, value)?,
+        "retail_currency" => patch.retail_currency = text,
+        "freight_cost" => patch.freight_cost = number(field, value)?,
+        "duty_percent" => patch.duty_percent = number(field, value)?,
+        "handling_cost" => patch.handling_cost = number(field, value)?,
+        "production_date" => patch.production_date = date(field, value)?,
+        "expiry_date" => patch.expiry_date = date(field, value)?,
+        "batch_id" => patch.batch_id = number(field, value)?,
+        "stock_quantity" => patch.stock_quantity = number(field, value)?,
+        "min_threshold" => patch.min_threshold = number(field, value)?,
+        "case_pack" => patch.case_pack = number(field, value)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+pub fn export_csv<W: Write>(
+    products: &[Product],
+    mapping: &HeaderMapping,
+    writer: W,
+) -> Result<(), AppError> {
+    let fields: Vec<&str> = IMPORT_FIELDS.iter().chain(GENERATED_FIELDS.iter()).copied().collect();
+
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(fields.iter().map(|field| mapping.header_for(field)))?;
+    for product in products {
+        csv.write_record(fields.iter().map(|field| field_value(product, field)))?;
+    }
+    csv.flush().map_err(csv::Error::from)?;
+    Ok(())
+}
+
+/// Writes a purchase order's lines in the layout suppliers are sent.
+pub fn export_order_csv<W: Write>(order: &PurchaseOrder, writer: W) -> Result<(), AppError> {
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(["barcode", "product", "quantity", "case_pack", "cases", "unit_cost", "total", "currency"])?;
+    for line in &order.lines {
+        csv.write_record([
+            line.barcode.clone(),
+            line.product_name.clone(),
+            line.quantity.to_string(),
+            line.case_pack.to_string(),
+            format!("{:.2}", line.cases()),
+            line.unit_cost.to_string(),
+            line.total()?.to_string(),
+            order.currency.clone(),
+        ])?;
+    }
+    csv.flush().map_err(csv::Error::from)?;
+    Ok(())
+}
+
+/// Writes a report's table with its header row; a total row comes last.
+pub fn export_report_csv<W: Write>(table: &Table, writer: W) -> Result<(), AppError> {
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(&table.columns)?;
+    for row in table.rows.iter().chain(&table.total) {
+        csv.write_record(row)?;
+    }
+    csv.flush().map_err(csv::Error::from)?;
+    Ok(())
+}
+
+fn field_value(product: &Product, field: &str) -> String {
+    match field {
+        "barcode" => product.barcode.clone(),
+        "original_name" => product.original_name.clone(),
+        "imported_name" => product.imported_name.clone(),
+        "local_name" => product.local_name.clone().unwrap_or_default(),
+        "brand" => product.brand.clone(),
+        "category" => product.category.clone(),
+        "weight" => product.weight.clone(),
+        "origin_country" => product.origin_country.clone(),
+        "supplier" => product.supplier.clone(),
+        "purchase_price" => product.purchase_price.to_string(),
+        "purchase_currency" => product.purchase_currency.clone(),
+        "wholesale_price" => product.wholesale_price.to_string(),
+        "wholesale_currency" => product.wholesale_currency.clone(),
+        "retail_price" => product.retail_price.to_string(),
+        "retail_currency" => product.retail_currency.clone(),
+        "freight_cost" => product.freight_cost.to_string(),
+        "duty_percent" => product.duty_percent.to_string(),
+        "handling_cost" => product.handling_cost.to_string(),
+        "production_date" => product.production_date.to_string(),
+        "expiry_date" => product.expiry_date.to_string(),
+        "batch_id" => product.batch_id.to_string(),
+        "stock_quantity" => product.stock_quantity.to_string(),
+        "min_threshold" => product.min_threshold.to_string(),
+        "case_pack" => product.case_pack.to_string(),
+        "internal_code" => product.internal_code.clone(),
+        "alternative_code" => product.alternative_code.clone(),
+        _ => String::new(),
+    }
+}
+
+/// One day's sales of one product from a POS export.
+pub struct SalesRow {
+    pub line: u64,
+    pub barcode: String,
+    pub sale_date: chrono::NaiveDate,
+    pub quantity: i32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SalesImportReport {
+    pub dry_run: bool,
+    pub committed: bool,
+    pub rows: usize,
+    pub errors: Vec<RowError>,
+}
+
+/// Imports daily sales totals from a POS export with `barcode`, `date` and `quantity`
+/// columns, in a single transaction. If any row fails, nothing is written.
+pub async fn import_sales_csv<R: Read>(db: &Database, reader: R, dry_run: bool) -> Result<SalesImportReport, AppError> {
+    let mut csv = csv::Reader::from_reader(reader);
+    let headers = csv.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name))
+            .ok_or_else(|| AppError::Validation(format!("sales file has no {:?} column", name)))
+    };
+    let (barcode, date, quantity) = (column("barcode")?, column("date")?, column("quantity")?);
+
+    let mut rows = Vec::new();
+    let mut parse_errors = Vec::new();
+    for record in csv.records() {
+        let record = record?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let value = |index: usize| record.get(index).unwrap_or("").trim();
+
+        let mut problems = Vec::new();
+        if value(barcode).is_empty() {
+            problems.push("barcode is required".to_string());
+        }
+        let sale_date = chrono::NaiveDate::parse_from_str(value(date), "%Y-%m-%d");
+        if sale_date.is_err() {
+            problems.push(format!("date: expected a YYYY-MM-DD date, got {:?}", value(date)));
+        }
+        let parsed_quantity = value(quantity).parse::<i32>();
+        if parsed_quantity.is_err() {
+            problems.push(format!("quantity: invalid number {:?}", value(quantity)));
+        }
+
+        match (sale_date, parsed_quantity) {
+            (Ok(sale_date), Ok(quantity)) if problems.is_empty() => rows.push(SalesRow {
+                line,
+                barcode: value(barcode).to_string(),
+                sale_date,
+                quantity,
+            }),
+            _ => parse_errors.push(RowError { line, message: problems.join("; ") }),
+        }
+    }
+
+    let commit = !dry_run && parse_errors.is_empty();
+    let mut report = db.import_sales(rows, commit).await?;
+    report.dry_run = dry_run;
+    report.errors.extend(parse_errors);
+    report.errors.sort_by_key(|e| e.line);
+    Ok(report)
+}
+
+/// One rate from an exchange-rate file.
+pub struct RateRow {
+    pub line: u64,
+    pub input: ExchangeRateInput,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RateImportReport {
+    pub dry_run: bool,
+    pub committed: bool,
+    pub rows: usize,
+    pub errors: Vec<RowError>,
+}
+
+/// Imports exchange rates from a file with `currency`, `rate` and `date` columns
+/// and an optional `source` column, in a single transaction. Rows without a source
+/// get `default_source`, e.g. the file name. If any row fails, nothing is written.
+pub async fn import_rates_csv<R: Read>(
+    db: &Database,
+    reader: R,
+    default_source: Option<&str>,
+    dry_run: bool,
+) -> Result<RateImportReport, AppError> {
+    let mut csv = csv::Reader::from_reader(reader);
+    let headers = csv.headers()?.clone();
+    let position = |name: &str| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name));
+    let column = |name: &str| {
+        position(name).ok_or_else(|| AppError::Validation(format!("rates file has no {:?} column", name)))
+    };
+    let (currency, rate, date) = (column("currency")?, column("rate")?, column("date")?);
+    let source = position("source");
+
+    let mut rows = Vec::new();
+    let mut parse_errors = Vec::new();
+    for record in csv.records() {
+        let record = record?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let value = |index: usize| record.get(index).unwrap_or("").trim();
+
+        let mut problems = Vec::new();
+        let valid_from = chrono::NaiveDate::parse_from_str(value(date), "%Y-%m-%d");
+        if valid_from.is_err() {
+            problems.push(format!("date: expected a YYYY-MM-DD date, got {:?}", value(date)));
+        }
+        let parsed_rate = value(rate).parse::<Rate>();
+        if parsed_rate.is_err() {
+            problems.push(format!("rate: invalid number {:?}", value(rate)));
+        }
+
+        match (valid_from, parsed_rate) {
+            (Ok(valid_from), Ok(rate)) if problems.is_empty() => rows.push(RateRow {
+                line,
+                input: ExchangeRateInput {
+                    currency: value(currency).to_ascii_uppercase(),
+                    rate,
+                    valid_from,
+                    source: source
+                        .map(value)
+                        .filter(|source| !source.is_empty())
+                        .or(default_source)
+                        .map(str::to_string),
+                },
+            }),
+            _ => parse_errors.push(RowError { line, message: problems.join("; ") }),
+        }
+    }
+
+    let commit = !dry_run && parse_errors.is_empty();
+    let mut report = db.import_exchange_rates(rows, commit).await?;
+    report.dry_run = dry_run;
+    report.errors.extend(parse_errors);
+    report.errors.sort_by_key(|e| e.line);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::{chickpeas, memory_db};
+
+    const HEADER: &str = "Artikelnummer,original_name,Imported Name,brand,category,weight,origin-country,supplier,retail_price,production_date,expiry_date,stock_quantity,internal_code,Notes";
+
+    fn row(barcode: &str, retail_price: &str, expiry_date: &str) -> String {
+        format!(
+            "{},حمص,Chickpeas,Al-Wadi,Legumes,900g,Lebanon,Beirut Foods,{},2026-01-15,{},24,LB-XXX,bring samples",
+            barcode, retail_price, expiry_date
+        )
+    }
+
+    fn mapping() -> HeaderMapping {
+        HeaderMapping { columns: HashMap::from([("Artikelnummer".to_string(), "barcode".to_string())]) }
+    }
+
+    async fn import(db: &Database, rows: &[String], dry_run: bool, upsert: bool) -> ImportReport {
+        let csv = format!("{}\n{}\n", HEADER, rows.join("\n"));
+        import_csv(db, csv.as_bytes(), &mapping(), ImportOptions { dry_run, upsert }, &ChangeNote::default()).await.unwrap()
+    }
+
+    #[test]
+    fn headers_map_through_the_mapping_or_by_field_name() {
+        let mapping = mapping();
+        assert_eq!(mapping.field_for("Artikelnummer"), Some("barcode"));
+        assert_eq!(mapping.field_for(" Imported-Name "), Some("imported_name"));
+        assert_eq!(mapping.field_for("ALTERNATIVE CODE"), Some("alternative_code"));
+        assert_eq!(mapping.field_for("Notes"), None);
+        assert_eq!(mapping.header_for("barcode"), "Artikelnummer");
+        assert_eq!(mapping.header_for("brand"), "brand");
+    }
+
+    #[test]
+    fn mappings_name_known_fields_once() {
+        mapping().check().unwrap();
+
+        let unknown = HeaderMapping { columns: HashMap::from([("Preis".to_string(), "price".to_string())]) };
+        assert!(unknown.check().unwrap_err().to_string().contains("unknown product field \"price\""));
+
+        let twice = HeaderMapping {
+            columns: HashMap::from([
+                ("EAN".to_string(), "barcode".to_string()),
+                ("Artikelnummer".to_string(), "barcode".to_string()),
+            ]),
+        };
+        let error = twice.check().unwrap_err().to_string();
+        assert!(error.contains(r#"columns ["Artikelnummer", "EAN"] are all mapped to product field "barcode""#), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn errors_name_their_line_and_nothing_is_written() {
+        let db = memory_db().await;
+        let rows = [
+            row("5281234567896", "29.90", "2027-12-31"),
+            row("8690504000006", "cheap", "31/12/2027"),
+            row("5281234567890", "29.90", "2027-12-31"),
+            row("", "29.90", "2027-12-31"),
+        ];
+        let report = import(&db, &rows, false, false).await;
+
+        let errors: Vec<(u64, &str)> = report.errors.iter().map(|e| (e.line, e.message.as_str())).collect();
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert_eq!(errors[0].0, 3);
+        assert!(errors[0].1.contains("retail_price: invalid number \"cheap\""), "{}", errors[0].1);
+        assert!(errors[0].1.contains("expiry_date: expected a YYYY-MM-DD date"), "{}", errors[0].1);
+        assert_eq!(errors[1].0, 4);
+        assert!(errors[1].1.contains("wrong check digit"), "{}", errors[1].1);
+        assert_eq!(errors[2], (5, "barcode is required"));
+        assert!(!report.committed);
+        assert!(db.get_all_products().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn dry_runs_preview_without_writing() {
+        let db = memory_db().await;
+        let rows = [row("5281234567896", "29.90", "2027-12-31"), row("8690504000006", "31.50", "2027-06-30")];
+
+        let preview = import(&db, &rows, true, false).await;
+        assert!(preview.errors.is_empty() && preview.dry_run && !preview.committed);
+        assert_eq!(preview.count(ImportAction::Insert), 2);
+        assert_eq!(preview.ignored_columns, ["internal_code", "Notes"]);
+        assert!(db.get_all_products().await.unwrap().is_empty());
+
+        let report = import(&db, &rows, false, false).await;
+        assert!(report.committed);
+        assert_eq!(db.get_all_products().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn existing_barcodes_are_updated_only_with_upsert() {
+        let db = memory_db().await;
+        db.add_product(chickpeas("5281234567896"), &ChangeNote::default()).await.unwrap();
+        let rows = [row("5281234567896", "31.50", "2027-12-31"), row("8690504000006", "31.50", "2027-06-30")];
+
+        let rejected = import(&db, &rows, false, false).await;
+        assert_eq!(rejected.errors.len(), 1);
+        assert_eq!(rejected.errors[0].line, 2);
+        assert!(rejected.errors[0].message.contains("use upsert"), "{}", rejected.errors[0].message);
+        assert!(!rejected.committed);
+        assert!(db.get_product_by_barcode("8690504000006").await.is_err());
+
+        let report = import(&db, &rows, false, true).await;
+        assert!(report.committed, "{:?}", report.errors);
+        assert_eq!((report.count(ImportAction::Update), report.count(ImportAction::Insert)), (1, 1));
+        let updated = db.get_product_by_barcode("5281234567896").await.unwrap();
+        assert_eq!(updated.retail_price, "31.50".parse().unwrap());
+    }
+
+    #[test]
+    fn exports_use_the_mapped_headers() {
+        let mut out = Vec::new();
+        export_csv(&[chickpeas("05281234567896")], &mapping(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let mut lines = out.lines();
+        assert!(lines.next().unwrap().starts_with("Artikelnummer,original_name,imported_name,"));
+        assert!(lines.next().unwrap().starts_with("05281234567896,حمص,Chickpeas,"));
+    }
+}
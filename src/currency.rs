@@ -0,0 +1,208 @@
+use chrono::NaiveDate;
+use serde::{Serialize, Deserialize};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use crate::models::{AppError, ExchangeRate, Product};
+use crate::money::{Money, Rate, Rounding};
+
+/// Exchange rates into the base currency, looked up by the day they are needed for,
+/// and how converted amounts are rounded.
+pub struct ExchangeRates {
+    base: String,
+    /// Rates per currency, oldest first
+    rates: HashMap<String, Vec<(NaiveDate, Rate)>>,
+    rounding: Rounding,
+}
+
+impl ExchangeRates {
+    pub fn new(base: &str, rates: &[ExchangeRate], rounding: Rounding) -> Self {
+        let mut by_currency: HashMap<String, Vec<(NaiveDate, Rate)>> = HashMap::new();
+        for rate in rates {
+            by_currency.entry(rate.currency.clone()).or_default().push((rate.valid_from, rate.rate));
+        }
+        for dated in by_currency.values_mut() {
+            dated.sort_by_key(|(valid_from, _)| *valid_from);
+        }
+        Self { base: base.to_string(), rates: by_currency, rounding }
+    }
+
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    pub fn rounding(&self) -> Rounding {
+        self.rounding
+    }
+
+    /// Units of the base currency per unit of `currency` on `date`: the latest rate
+    /// valid from that day or earlier.
+    pub fn rate(&self, currency: &str, date: NaiveDate) -> Result<Rate, AppError> {
+        if currency == self.base {
+            return Ok(Rate::ONE);
+        }
+        self.rates
+            .get(currency)
+            .and_then(|dated| dated.iter().rev().find(|(valid_from, _)| *valid_from <= date))
+            .map(|(_, rate)| *rate)
+            .ok_or_else(|| AppError::Validation(format!("no {} exchange rate on or before {}", currency, date)))
+    }
+
+    /// Converts a per-unit amount into the base currency, rounded to the places for
+    /// unit amounts.
+    pub fn to_base(&self, amount: Money, currency: &str, date: NaiveDate) -> Result<Money, AppError> {
+        self.convert(amount, currency, &self.base, date)
+    }
+
+    /// Converts a per-unit amount between two currencies through the base currency,
+    /// rounding once at the end.
+    pub fn convert(&self, amount: Money, from: &str, to: &str, date: NaiveDate) -> Result<Money, AppError> {
+        if from == to {
+            return Ok(amount);
+        }
+        let too_large = || AppError::Validation(format!("{} {} is too large to convert to {} on {}", amount, from, to, date));
+        let converted = self.rate(from, date)?
+            .to_decimal()
+            .checked_div(self.rate(to, date)?.to_decimal())
+            .and_then(|factor| amount.to_decimal().checked_mul(factor))
+            .ok_or_else(too_large)?;
+        self.rounding.unit(converted).map_err(|_| too_large())
+    }
+}
+
+/// What one unit costs delivered to the store, in the base currency.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LandedCost {
+    pub purchase: Money,
+    pub freight: Money,
+    pub duty: Money,
+    pub handling: Money,
+    pub total: Money,
+}
+
+/// A product's landed cost and selling prices in the base currency, and the gross
+/// margins between them.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Costing {
+    pub barcode: String,
+    pub currency: String,
+    /// Day whose exchange rates were used
+    pub date: NaiveDate,
+    pub landed_cost: LandedCost,
+    pub wholesale_price: Money,
+    pub retail_price: Money,
+    /// Share of the price left after landed cost, in percent to two places; none
+    /// without a price
+    pub wholesale_margin: Option<Rate>,
+    pub retail_margin: Option<Rate>,
+}
+
+/// Purchase price plus freight, duty on both, and handling, converted at the rate
+/// for `date`. Each part is rounded on its own, so the total is their exact sum.
+pub fn landed_cost(product: &Product, rates: &ExchangeRates, date: NaiveDate) -> Result<LandedCost, AppError> {
+    let currency = &product.purchase_currency;
+    let purchase = rates.to_base(product.purchase_price, currency, date)?;
+    let freight = rates.to_base(product.freight_cost, currency, date)?;
+    let dutiable = purchase.checked_add(freight)?;
+    let duty = dutiable
+        .to_decimal()
+        .checked_mul(product.duty_percent.to_decimal() / Decimal::ONE_HUNDRED)
+        .ok_or_else(|| AppError::Validation(format!("duty of {}% on {} is too large", product.duty_percent, dutiable)))?;
+    let duty = rates.rounding().unit(duty)?;
+    let handling = rates.to_base(product.handling_cost, currency, date)?;
+    let total = Money::checked_sum([purchase, freight, duty, handling])?;
+
+    Ok(LandedCost { purchase, freight, duty, handling, total })
+}
+
+pub fn costing(product: &Product, rates: &ExchangeRates, date: NaiveDate) -> Result<Costing, AppError> {
+    let landed_cost = landed_cost(product, rates, date)?;
+    let wholesale_price = rates.to_base(product.wholesale_price, &product.wholesale_currency, date)?;
+    let retail_price = rates.to_base(product.retail_price, &product.retail_currency, date)?;
+    let margin = |price: Money| {
+        (!price.is_zero()).then(|| {
+            let left = price.to_decimal() - landed_cost.total.to_decimal();
+            rates.rounding().percent(left / price.to_decimal() * Decimal::ONE_HUNDRED)
+        })
+    };
+
+    Ok(Costing {
+        barcode: product.barcode.clone(),
+        currency: rates.base().to_string(),
+        date,
+        wholesale_margin: margin(wholesale_price),
+        retail_margin: margin(retail_price),
+        landed_cost,
+        wholesale_price,
+        retail_price,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CurrencyConfig;
+    use crate::models::ProductPatch;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, day).unwrap()
+    }
+
+    fn rates(rates: &[(&str, u32, &str)]) -> ExchangeRates {
+        let rates: Vec<ExchangeRate> = rates
+            .iter()
+            .map(|(currency, day, rate)| ExchangeRate {
+                id: 0,
+                currency: currency.to_string(),
+                rate: rate.parse().unwrap(),
+                valid_from: date(*day),
+                source: None,
+                created_at: String::new(),
+            })
+            .collect();
+        ExchangeRates::new("SEK", &rates, CurrencyConfig::default().rounding())
+    }
+
+    fn money(s: &str) -> Money {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn converts_through_the_base_at_the_rate_for_the_day() {
+        let rates = rates(&[("USD", 1, "10.5"), ("USD", 15, "11"), ("EUR", 1, "11.5")]);
+
+        assert_eq!(rates.to_base(money("2"), "USD", date(14)).unwrap(), money("21"));
+        assert_eq!(rates.to_base(money("2"), "USD", date(15)).unwrap(), money("22"));
+        assert_eq!(rates.convert(money("10"), "USD", "EUR", date(15)).unwrap(), money("9.5652"));
+        assert!(rates.to_base(money("2"), "USD", NaiveDate::from_ymd_opt(2026, 9, 30).unwrap()).is_err());
+        assert!(rates.to_base(money("2"), "TRY", date(15)).is_err());
+    }
+
+    #[test]
+    fn amounts_too_large_to_convert_are_errors() {
+        let rates = rates(&[("USD", 1, "0.0000000000000000000001"), ("LBP", 1, "79228162514264337593543950335")]);
+
+        let error = rates.convert(money("900000000"), "SEK", "USD", date(18)).unwrap_err();
+        assert_eq!(error.to_string(), "Validation failed: 900000000.00 SEK is too large to convert to USD on 2026-10-18");
+        assert!(rates.convert(money("1"), "LBP", "USD", date(18)).is_err());
+        assert!(rates.to_base(money("2"), "LBP", date(18)).is_err());
+
+        let product = |duty_percent: &str| {
+            ProductPatch {
+                purchase_price: Some(money("900000000")),
+                purchase_currency: Some("SEK".to_string()),
+                duty_percent: Some(duty_percent.parse().unwrap()),
+                expiry_date: Some(date(18)),
+                ..ProductPatch::default()
+            }
+            .into_product()
+            .unwrap()
+        };
+        assert_eq!(landed_cost(&product("12.5"), &rates, date(18)).unwrap().total, money("1012500000"));
+        assert!(landed_cost(&product("79228162514264337593543950335"), &rates, date(18)).is_err());
+        assert!(costing(&product("1000000000000"), &rates, date(18)).is_err());
+
+        let freighted = Product { purchase_price: money("500000000000000"), freight_cost: money("500000000000000"), ..product("0") };
+        let error = landed_cost(&freighted, &rates, date(18)).unwrap_err();
+        assert!(error.to_string().contains("too large"), "{}", error);
+    }
+}
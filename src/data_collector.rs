@@ -1,68 +1,267 @@
+use async_trait::async_trait;
 use reqwest::Client;
 use serde_json::Value;
-use crate::models::{ProductInput, ModelError};
-use thiserror::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::config::LookupConfig;
+use crate::models::{ProductInput, AppError};
+use crate::money::Money;
 
-#[derive(Debug)]
-pub struct DataCollector {
+/// A source of product details by barcode. Providers return `AppError::NotFound`
+/// when they don't know a barcode, so a chain can fall through to the next one.
+#[async_trait]
+pub trait ProductLookupProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn lookup(&self, barcode: &str) -> Result<ProductInput, AppError>;
+}
+
+/// Queries an OpenFoodFacts-compatible API, e.g. world.openfoodfacts.org or a local stand-in.
+pub struct OpenFoodFactsProvider {
     client: Client,
+    base_url: String,
 }
 
-impl DataCollector {
-    pub fn new() -> Self {
-        Self {
-            client: Client::new(),
-        }
+impl OpenFoodFactsProvider {
+    pub fn new(base_url: &str, timeout: Duration) -> Result<Self, AppError> {
+        Ok(Self {
+            client: Client::builder().timeout(timeout).build()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl ProductLookupProvider for OpenFoodFactsProvider {
+    fn name(&self) -> &str {
+        "openfoodfacts"
     }
-    
-    pub async fn fetch_from_barcode(&self, barcode: &str) -> Result<ProductInput, ModelError> {
-        let url = format!("https://world.openfoodfacts.org/api/v0/product/{}.json", barcode);
+
+    async fn lookup(&self, barcode: &str) -> Result<ProductInput, AppError> {
+        let url = format!("{}/api/v0/product/{}.json", self.base_url, barcode);
         let response = self.client.get(&url).send().await?;
-        
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(AppError::NotFound);
+        }
         if !response.status().is_success() {
-            return Err(ModelError::BarcodeNotFound);
+            return Err(AppError::Lookup(format!("{} returned {}", url, response.status())));
         }
-        
+
         let json: Value = response.json().await?;
-        self.parse_openfoodfacts(json)
-    }
-    
-    fn parse_openfoodfacts(&self, json: Value) -> Result<ProductInput, ModelError> {
-        let product = json["product"].clone();
-        let today = chrono::Utc::now().date_naive();
-        
-        Ok(ProductInput {
-            original_name: product["product_name"].as_str().unwrap_or("").to_string(),
-            imported_name: product["product_name_en"]
-                .as_str()
-                .unwrap_or(product["product_name"].as_str().unwrap_or(""))
-                .to_string(),
-            local_name: None,
-            barcode: product["code"].as_str().unwrap_or("").to_string(),
-            brand: product["brands"].as_str().unwrap_or("Unknown").to_string(),
-            category: product["categories"].as_str()
-                .unwrap_or("Other")
-                .split(',')
-                .next()
-                .unwrap_or("Other")
-                .to_string(),
-            weight: product["quantity"].as_str().unwrap_or("0g").to_string(),
-            origin_country: product["countries"].as_str()
-                .unwrap_or("Unknown")
-                .split(',')
-                .next()
-                .unwrap_or("Unknown")
-                .to_string(),
-            supplier: "Imported".to_string(),
-            purchase_price: 0.0,
-            wholesale_price: 0.0,
-            retail_price: 0.0,
-            production_date: today.format("%Y-%m-%d").to_string(),
-            expiry_date: today.format("%Y-%m-%d").to_string(),
-            batch_id: 1,
-            stock_quantity: 0,
-            monthly_sales: 0,
-            min_threshold: 10,
-        })
+        parse_openfoodfacts(barcode, json)
+    }
+}
+
+/// Reads saved OpenFoodFacts responses from `<dir>/<barcode>.json`, for tests and offline shops.
+pub struct FixtureProvider {
+    dir: PathBuf,
+}
+
+impl FixtureProvider {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
     }
-}
\ No newline at end of file
+}
+
+#[async_trait]
+impl ProductLookupProvider for FixtureProvider {
+    fn name(&self) -> &str {
+        "fixtures"
+    }
+
+    async fn lookup(&self, barcode: &str) -> Result<ProductInput, AppError> {
+        if !barcode.chars().all(|c| c.is_ascii_digit()) {
+            return Err(AppError::NotFound);
+        }
+
+        let path = self.dir.join(format!("{}.json", barcode));
+        let contents = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(AppError::NotFound),
+            Err(e) => return Err(AppError::Lookup(format!("cannot read {}: {}", path.display(), e))),
+        };
+
+        parse_openfoodfacts(barcode, serde_json::from_str(&contents)?)
+    }
+}
+
+/// Tries each provider in order and returns the first match.
+pub struct ChainProvider {
+    providers: Vec<Box<dyn ProductLookupProvider>>,
+}
+
+impl ChainProvider {
+    pub fn new(providers: Vec<Box<dyn ProductLookupProvider>>) -> Self {
+        Self { providers }
+    }
+}
+
+#[async_trait]
+impl ProductLookupProvider for ChainProvider {
+    fn name(&self) -> &str {
+        "chain"
+    }
+
+    async fn lookup(&self, barcode: &str) -> Result<ProductInput, AppError> {
+        let mut last_error = AppError::NotFound;
+
+        for provider in &self.providers {
+            match provider.lookup(barcode).await {
+                Ok(input) => return Ok(input),
+                Err(AppError::NotFound) => continue,
+                Err(e) => {
+                    log::warn!("{} lookup for {} failed: {}", provider.name(), barcode, e);
+                    last_error = e;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+}
+
+pub struct DataCollector {
+    provider: Box<dyn ProductLookupProvider>,
+}
+
+impl DataCollector {
+    pub fn new(provider: Box<dyn ProductLookupProvider>) -> Self {
+        Self { provider }
+    }
+
+    /// Builds the provider chain listed in `[lookup] providers`.
+    pub fn from_config(config: &LookupConfig) -> Result<Self, AppError> {
+        let mut providers: Vec<Box<dyn ProductLookupProvider>> = Vec::new();
+
+        for name in &config.providers {
+            match name.as_str() {
+                "fixtures" => providers.push(Box::new(FixtureProvider::new(&config.fixture_dir))),
+                "openfoodfacts" => providers.push(Box::new(OpenFoodFactsProvider::new(
+                    &config.openfoodfacts_url,
+                    Duration::from_secs(config.timeout_secs),
+                )?)),
+                other => return Err(AppError::Config(format!("unknown lookup provider {:?}", other))),
+            }
+        }
+
+        Ok(Self::new(Box::new(ChainProvider::new(providers))))
+    }
+
+    pub async fn fetch_from_barcode(&self, barcode: &str) -> Result<ProductInput, AppError> {
+        self.provider.lookup(barcode).await
+    }
+}
+
+fn parse_openfoodfacts(barcode: &str, json: Value) -> Result<ProductInput, AppError> {
+    // OpenFoodFacts answers unknown barcodes with 200 and `"status": 0`
+    if json["status"].as_i64() == Some(0) || json["product"].is_null() {
+        return Err(AppError::NotFound);
+    }
+
+    let product = &json["product"];
+    let today = chrono::Local::now().date_naive();
+
+    Ok(ProductInput {
+        original_name: product["product_name"].as_str().unwrap_or("").to_string(),
+        imported_name: product["product_name_en"]
+            .as_str()
+            .filter(|name| !name.is_empty())
+            .unwrap_or(product["product_name"].as_str().unwrap_or(""))
+            .to_string(),
+        local_name: product["product_name_sv"].as_str().filter(|name| !name.is_empty()).map(str::to_string),
+        barcode: product["code"].as_str().unwrap_or(barcode).to_string(),
+        brand: first_entry(&product["brands"]).unwrap_or_else(|| "Unknown".to_string()),
+        category: first_entry(&product["categories"]).unwrap_or_else(|| "Other".to_string()),
+        weight: product["quantity"].as_str().unwrap_or("0g").replace(' ', ""),
+        origin_country: first_entry(&product["countries"]).unwrap_or_else(|| "Unknown".to_string()),
+        supplier: "Imported".to_string(),
+        purchase_price: Money::ZERO,
+        wholesale_price: Money::ZERO,
+        retail_price: Money::ZERO,
+        production_date: today,
+        expiry_date: today,
+        batch_id: 1,
+        stock_quantity: 0,
+        min_threshold: 10,
+        case_pack: 1,
+    })
+}
+
+/// First item of a comma-separated OpenFoodFacts list, without any `en:` style prefix.
+fn first_entry(value: &Value) -> Option<String> {
+    let first = value.as_str()?.split(',').next()?.trim();
+    let first = first.split_once(':').map_or(first, |(_, rest)| rest);
+    (!first.is_empty()).then(|| first.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use std::net::TcpListener;
+
+    const FIXTURE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/lookup");
+
+    /// An OpenFoodFacts stand-in on a free local port that knows the fixture
+    /// barcodes, answers `000…` with `"status": 0` and fails for anything else.
+    fn stand_in() -> String {
+        async fn product(path: web::Path<String>) -> HttpResponse {
+            let barcode = path.into_inner();
+            let barcode = barcode.trim_end_matches(".json");
+            if barcode.starts_with("000") {
+                return HttpResponse::Ok().json(serde_json::json!({ "code": barcode, "status": 0 }));
+            }
+            match std::fs::read_to_string(format!("{}/{}.json", FIXTURE_DIR, barcode)) {
+                Ok(contents) => HttpResponse::Ok().content_type("application/json").body(contents),
+                Err(_) => HttpResponse::ServiceUnavailable().finish(),
+            }
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = HttpServer::new(|| App::new().route("/api/v0/product/{barcode}", web::get().to(product)))
+            .workers(1)
+            .listen(listener)
+            .unwrap()
+            .run();
+        actix_web::rt::spawn(server);
+        url
+    }
+
+    fn openfoodfacts(url: &str) -> OpenFoodFactsProvider {
+        OpenFoodFactsProvider::new(url, Duration::from_secs(5)).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn reads_openfoodfacts_responses() {
+        let provider = openfoodfacts(&stand_in());
+
+        let input = provider.lookup("5281234567896").await.unwrap();
+        assert_eq!(input.original_name, "حمص حب");
+        assert_eq!(input.imported_name, "Chickpeas");
+        assert_eq!(input.local_name.as_deref(), Some("Kikärtor"));
+        assert_eq!(input.brand, "Al-Wadi");
+        assert_eq!(input.category, "legumes");
+        assert_eq!(input.weight, "900g");
+        assert_eq!(input.origin_country, "lebanon");
+
+        assert!(matches!(provider.lookup("0001234567895").await, Err(AppError::NotFound)));
+        assert!(matches!(provider.lookup("8690504000006").await, Err(AppError::Lookup(_))));
+    }
+
+    #[actix_web::test]
+    async fn chain_falls_through_to_the_next_provider() {
+        let url = stand_in();
+        let fixtures_first = ChainProvider::new(vec![Box::new(FixtureProvider::new(FIXTURE_DIR)), Box::new(openfoodfacts(&url))]);
+        assert_eq!(fixtures_first.lookup("5281234567896").await.unwrap().imported_name, "Chickpeas");
+        assert!(matches!(fixtures_first.lookup("0001234567895").await, Err(AppError::NotFound)));
+
+        // A provider that is down does not hide a later match, but is reported
+        // when nothing matches
+        let down = openfoodfacts(&format!("http://{}", TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()));
+        let down_first = ChainProvider::new(vec![Box::new(down), Box::new(FixtureProvider::new(FIXTURE_DIR))]);
+        assert_eq!(down_first.lookup("5281234567896").await.unwrap().brand, "Al-Wadi");
+        assert!(matches!(down_first.lookup("8690504000006").await, Err(AppError::Request(_))));
+    }
+}
@@ -1,165 +1,2802 @@
+use chrono::NaiveDate;
 use sqlx::{SqlitePool, Row};
-use crate::models::{Product, AppError};
-use crate::code_generator::{generate_internal_code, generate_alternative_code};
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteConnection, SqlitePoolOptions, SqliteRow};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::alert_rules::{RuleSet, Severity};
+use crate::barcode::{self, Gtin};
+use crate::config::{CodesConfig, CurrencyConfig, DatabaseConfig, InventoryConfig, ReorderConfig};
+use crate::currency::{self, Costing, ExchangeRates};
+use crate::csv_io::{ImportAction, ImportReport, ImportRow, RateImportReport, RateRow, RowError, RowPreview, SalesImportReport, SalesRow};
+use crate::inventory_manager::{InventoryAlert, InventoryManager, InventorySnapshot};
+use crate::models::{AlertNote, AlertStatus, AlertUpdate, ChangeNote, TrackedAlert, JobRun, JobStatus, DailySales, ExchangeRate, ExchangeRateInput, Lot, LotInput, MovementInput, MovementType, OrderStatus, PriceChange, PriceKind, PriceSource, Product, ProductPatch, PurchaseOrder, PurchaseOrderLine, PurchaseOrderUpdate, ReorderSuggestion, SalesSource, StockMovement, Supplier, SupplierPatch, SupplierSpend, AppError, PRODUCT_EDIT_REASON};
+use crate::money::{Money, Rounding};
+use crate::pricing::{self, PricingReport, PricingRules, PricingScope};
+use crate::reports::{ReportData, StockFlow};
+use crate::scheduler::JobKind;
+use crate::code_generator::CodeSchemes;
+
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Product rows with the supplier's name.
+const PRODUCT_SELECT: &str = "SELECT products.*, suppliers.name AS supplier FROM products JOIN suppliers ON suppliers.id = products.supplier_id";
+
+/// Purchase order rows with the supplier's name and terms.
+const ORDER_SELECT: &str = "SELECT purchase_orders.*, suppliers.name AS supplier, suppliers.min_order_value FROM purchase_orders JOIN suppliers ON suppliers.id = purchase_orders.supplier_id";
+
+/// How many taken alternative codes an insert skips before giving up.
+const CODE_ATTEMPTS: u32 = 100;
+
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub installed_on: Option<String>,
+}
+
+/// A date the strict dates migration rewrote, as it had a time part or could
+/// not be read at all.
+pub struct DateRepair {
+    pub table_name: String,
+    pub row_id: i64,
+    pub column_name: String,
+    pub original_value: Option<String>,
+    pub replaced_with: Option<String>,
+}
+
+pub struct LedgerMismatch {
+    pub barcode: String,
+    pub stock_quantity: i32,
+    pub lot_quantity: i32,
+    pub ledger_quantity: i32,
+}
 
 pub struct Database {
     pool: SqlitePool,
+    currency: CurrencyConfig,
+    codes: CodeSchemes,
 }
 
 impl Database {
-    pub async fn new() -> Result<Self, AppError> {
-        let pool = SqlitePool::connect("sqlite:products.db").await?;
+    pub async fn new(config: &DatabaseConfig, currency: &CurrencyConfig, codes: &CodesConfig) -> Result<Self, AppError> {
+        let db = Self::connect(config, currency, codes).await?;
+        db.migrate_up().await?;
+        Ok(db)
+    }
+    
+    /// Opens the pool without touching the schema; used by the `migrate` command.
+    pub async fn connect(config: &DatabaseConfig, currency: &CurrencyConfig, codes: &CodesConfig) -> Result<Self, AppError> {
+        let codes = CodeSchemes::load(codes.schemes_file.as_deref())?;
+        let journal_mode = if config.wal { SqliteJournalMode::Wal } else { SqliteJournalMode::Delete };
+        let options = SqliteConnectOptions::from_str(&config.url)?
+            .create_if_missing(true)
+            .journal_mode(journal_mode)
+            .busy_timeout(Duration::from_millis(config.busy_timeout_ms));
         
-        // Create table if not exists
-        sqlx::query(
+        let pool = SqlitePoolOptions::new()
+            .max_connections(config.max_connections)
+            .connect_with(options)
+            .await?;
+        Ok(Self { pool, currency: currency.clone(), codes })
+    }
+    
+    pub async fn migrate_up(&self) -> Result<(), AppError> {
+        self.reconcile_init_migration().await?;
+        MIGRATOR.run(&self.pool).await?;
+        Ok(())
+    }
+    
+    /// Databases set up with `sqlx migrate run` before the migrations were embedded
+    /// recorded a different checksum for the init migration. Its SQL only uses
+    /// `IF NOT EXISTS`, so re-apply it and adopt the current checksum.
+    async fn reconcile_init_migration(&self) -> Result<(), AppError> {
+        let Some(init) = MIGRATOR.iter().find(|m| m.migration_type.is_up_migration()) else {
+            return Ok(());
+        };
+        
+        let mut conn = self.pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        
+        let recorded: Option<Vec<u8>> = sqlx::query_scalar("SELECT checksum FROM _sqlx_migrations WHERE version = ?")
+            .bind(init.version)
+            .fetch_optional(&mut *conn)
+            .await?;
+        
+        if matches!(recorded, Some(checksum) if checksum != *init.checksum) {
+            sqlx::raw_sql(&init.sql).execute(&mut *conn).await?;
+            sqlx::query("UPDATE _sqlx_migrations SET checksum = ? WHERE version = ?")
+                .bind(&*init.checksum)
+                .bind(init.version)
+                .execute(&mut *conn)
+                .await?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Reverts the most recently applied migration. Returns its version, if any.
+    pub async fn migrate_down(&self) -> Result<Option<i64>, AppError> {
+        let applied: Vec<i64> = self
+            .migration_status()
+            .await?
+            .into_iter()
+            .filter(|m| m.installed_on.is_some())
+            .map(|m| m.version)
+            .collect();
+        
+        let Some(&latest) = applied.last() else {
+            return Ok(None);
+        };
+        let target = applied.iter().rev().nth(1).copied().unwrap_or(0);
+        
+        MIGRATOR.undo(&self.pool, target).await?;
+        Ok(Some(latest))
+    }
+    
+    pub async fn migration_status(&self) -> Result<Vec<MigrationStatus>, AppError> {
+        let mut conn = self.pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        
+        let rows = sqlx::query("SELECT version, installed_on FROM _sqlx_migrations WHERE success = 1")
+            .fetch_all(&mut *conn)
+            .await?;
+        let applied: HashMap<i64, String> = rows
+            .iter()
+            .map(|row| (row.get("version"), row.get("installed_on")))
+            .collect();
+        
+        Ok(MIGRATOR
+            .iter()
+            .filter(|m| m.migration_type.is_up_migration())
+            .map(|m| MigrationStatus {
+                version: m.version,
+                description: m.description.to_string(),
+                installed_on: applied.get(&m.version).cloned(),
+            })
+            .collect())
+    }
+    
+    /// Dates the strict dates migration rewrote, for someone to put the real
+    /// ones back. Empty before that migration has run.
+    pub async fn date_repairs(&self) -> Result<Vec<DateRepair>, AppError> {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'date_repairs')")
+            .fetch_one(&self.pool)
+            .await?;
+        if !exists {
+            return Ok(Vec::new());
+        }
+        
+        let rows = sqlx::query("SELECT * FROM date_repairs ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| DateRepair {
+                table_name: row.get("table_name"),
+                row_id: row.get("row_id"),
+                column_name: row.get("column_name"),
+                original_value: row.get("original_value"),
+                replaced_with: row.get("replaced_with"),
+            })
+            .collect())
+    }
+    
+    pub async fn add_product(&self, product: Product, note: &ChangeNote) -> Result<i64, AppError> {
+        // Taking the write lock up front lets concurrent inserts wait their turn
+        // for a sequence number instead of failing when they upgrade to write
+        let mut tx = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+        let id = insert_product(&mut tx, product, &self.currency, &self.codes, &PriceNote::new(PriceSource::Added, note)).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+    
+    /// Writes imported rows in one transaction. Existing barcodes are updated when
+    /// `upsert` is set and rejected otherwise. The transaction is only committed
+    /// when `commit` is set and every row succeeded.
+    pub async fn import_products(&self, rows: Vec<ImportRow>, upsert: bool, commit: bool, note: &ChangeNote) -> Result<ImportReport, AppError> {
+        let mut tx = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+        let mut report = ImportReport::default();
+        
+        for ImportRow { line, patch } in rows {
+            let Some(barcode) = patch.barcode.clone() else {
+                report.errors.push(RowError { line, message: "barcode is required".to_string() });
+                continue;
+            };
+            
+            let existing = sqlx::query(&format!("{} WHERE barcode IN (?, ?)", PRODUCT_SELECT))
+                .bind(barcode::storage_key(&barcode))
+                .bind(barcode.trim())
+                .fetch_optional(&mut *tx)
+                .await?;
+            
+            let result = match existing {
+                Some(row) if row.get::<Option<String>, _>("deleted_at").is_some() => {
+                    Err(AppError::Validation("barcode belongs to a deleted product; restore it first".to_string()))
+                }
+                Some(_) if !upsert => {
+                    Err(AppError::Validation("barcode already exists (use upsert to update it)".to_string()))
+                }
+                Some(row) => {
+                    let mut product = product_from_row(&row);
+                    patch.apply(&mut product);
+                    update_product_row(&mut tx, &mut product, false, &self.currency, &self.codes, &PriceNote::new(PriceSource::Import, note)).await.map(|_| (ImportAction::Update, product))
+                }
+                None => {
+                    match patch.into_product().and_then(|product| product.validate().map(|_| product)) {
+                        Ok(product) => insert_product(&mut tx, product.clone(), &self.currency, &self.codes, &PriceNote::new(PriceSource::Import, note)).await.map(|_| (ImportAction::Insert, product)),
+                        Err(e) => Err(e),
+                    }
+                }
+            };
+            
+            match result {
+                Ok((action, product)) => report.rows.push(RowPreview {
+                    line,
+                    action,
+                    warning: barcode::origin_warning(&product.barcode, &product.origin_country),
+                    barcode: product.barcode,
+                    imported_name: product.imported_name,
+                }),
+                Err(e) => report.errors.push(RowError { line, message: e.to_string() }),
+            }
+        }
+        
+        if commit && report.errors.is_empty() {
+            tx.commit().await?;
+            report.committed = true;
+        } else {
+            tx.rollback().await?;
+        }
+        
+        Ok(report)
+    }
+    
+    /// Finds the barcode however it is typed: with or without leading zeros,
+    /// spaces or hyphens.
+    pub async fn get_product_by_barcode(&self, barcode: &str) -> Result<Product, AppError> {
+        let mut conn = self.pool.acquire().await?;
+        fetch_product_by_barcode(&mut conn, barcode).await
+    }
+    
+    pub async fn get_product_by_internal_code(&self, code: &str) -> Result<Product, AppError> {
+        self.get_product_where("internal_code", code).await
+    }
+    
+    pub async fn get_product_by_alternative_code(&self, code: &str) -> Result<Product, AppError> {
+        self.get_product_where("alternative_code", code).await
+    }
+    
+    /// Looks a product up by barcode, internal code or alternative code, in that order.
+    pub async fn find_product(&self, key: &str) -> Result<Product, AppError> {
+        match self.get_product_by_barcode(key).await {
+            Err(AppError::NotFound) => {}
+            result => return result,
+        }
+        for column in ["internal_code", "alternative_code"] {
+            match self.get_product_where(column, key).await {
+                Err(AppError::NotFound) => continue,
+                result => return result,
+            }
+        }
+        Err(AppError::NotFound)
+    }
+    
+    async fn get_product_where(&self, column: &str, value: &str) -> Result<Product, AppError> {
+        let sql = format!("{} WHERE products.{} = ? AND deleted_at IS NULL", PRODUCT_SELECT, column);
+        let row = sqlx::query(&sql)
+            .bind(value)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        
+        Ok(product_from_row(&row))
+    }
+    
+    pub async fn get_all_products(&self) -> Result<Vec<Product>, AppError> {
+        let rows = sqlx::query(&format!("{} WHERE deleted_at IS NULL", PRODUCT_SELECT))
+            .fetch_all(&self.pool)
+            .await?;
+        
+        Ok(rows.iter().map(product_from_row).collect())
+    }
+    
+    /// Replaces every field of the product with `barcode`. The internal and
+    /// alternative codes are kept so printed labels stay valid; with
+    /// `regenerate_code` the internal code is rebuilt from the edited fields.
+    pub async fn update_product(&self, barcode: &str, product: Product, regenerate_code: bool, note: &ChangeNote) -> Result<Product, AppError> {
+        self.edit_product(barcode, |existing| Product { id: existing.id, ..product }, regenerate_code, note).await
+    }
+    
+    pub async fn patch_product(&self, barcode: &str, patch: ProductPatch, regenerate_code: bool, note: &ChangeNote) -> Result<Product, AppError> {
+        let apply = |mut product: Product| {
+            patch.apply(&mut product);
+            product
+        };
+        self.edit_product(barcode, apply, regenerate_code, note).await
+    }
+    
+    /// Reads the product with `barcode`, lets `edit` change it and writes it back,
+    /// all in one transaction.
+    async fn edit_product(
+        &self,
+        barcode: &str,
+        edit: impl FnOnce(Product) -> Product,
+        regenerate_code: bool,
+        note: &ChangeNote,
+    ) -> Result<Product, AppError> {
+        // The write lock is taken before reading, so concurrent edits wait their
+        // turn instead of one failing with SQLITE_BUSY or overwriting the other
+        let mut tx = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+        let mut product = edit(fetch_product_by_barcode(&mut tx, barcode).await?);
+        update_product_row(&mut tx, &mut product, regenerate_code, &self.currency, &self.codes, &PriceNote::new(PriceSource::Edit, note)).await?;
+        tx.commit().await?;
+        
+        Ok(product)
+    }
+    
+    /// Every price the product has been given, newest first.
+    pub async fn get_price_history(&self, barcode: &str) -> Result<Vec<PriceChange>, AppError> {
+        let product = self.get_product_by_barcode(barcode).await?;
+        let rows = sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS products (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                original_name TEXT NOT NULL,
-                imported_name TEXT NOT NULL,
-                local_name TEXT,
-                barcode TEXT NOT NULL UNIQUE,
-                internal_code TEXT NOT NULL UNIQUE,
-                alternative_code TEXT NOT NULL UNIQUE,
-                brand TEXT NOT NULL,
-                category TEXT NOT NULL,
-                weight TEXT NOT NULL,
-                origin_country TEXT NOT NULL,
-                supplier TEXT NOT NULL,
-                purchase_price REAL NOT NULL,
-                wholesale_price REAL NOT NULL,
-                retail_price REAL NOT NULL,
-                production_date TEXT NOT NULL,
-                expiry_date TEXT NOT NULL,
-                batch_id INTEGER NOT NULL,
-                stock_quantity INTEGER NOT NULL,
-                monthly_sales INTEGER NOT NULL,
-                min_threshold INTEGER NOT NULL,
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-            )
+            SELECT h.*, p.barcode FROM price_history h
+            JOIN products p ON p.id = h.product_id
+            WHERE h.product_id = ?
+            ORDER BY h.id DESC
             "#
         )
-        .execute(&pool)
+        .bind(product.id)
+        .fetch_all(&self.pool)
         .await?;
         
-        Ok(Self { pool })
+        rows.iter().map(price_change_from_row).collect()
     }
     
-    pub async fn add_product(&self, mut product: Product) -> Result<i64, AppError> {
-        let counter = self.count_products().await? + 1;
+    /// Works out the prices the pricing rules give the products in `scope` at
+    /// today's rates, and writes them when `apply` is set.
+    pub async fn recalculate_prices(
+        &self,
+        rules: &PricingRules,
+        scope: &PricingScope,
+        apply: bool,
+        note: &ChangeNote,
+    ) -> Result<PricingReport, AppError> {
+        // Prices are written from what this transaction read, so the write lock is
+        // taken before reading and no edit can land in between
+        let mut tx = if apply { self.pool.begin_with("BEGIN IMMEDIATE").await? } else { self.pool.begin().await? };
+        let rows = sqlx::query(&format!("{} WHERE deleted_at IS NULL", PRODUCT_SELECT))
+            .fetch_all(&mut *tx)
+            .await?;
+        let products: Vec<Product> = rows.iter().map(product_from_row).filter(|product| scope.matches(product)).collect();
+        let rates = fetch_exchange_rates(&mut tx, None).await?;
+        let rates = ExchangeRates::new(&self.currency.base, &rates, self.currency.rounding());
+        let mut report = pricing::recalculate(&products, rules, &rates, chrono::Local::now().date_naive());
+        if !apply {
+            return Ok(report);
+        }
         
-        // Generate codes
-        product.internal_code = generate_internal_code(&product);
-        product.alternative_code = generate_alternative_code(&product, counter);
+        for change in &report.changes {
+            let column = match change.price {
+                PriceKind::Purchase => "purchase_price",
+                PriceKind::Wholesale => "wholesale_price",
+                PriceKind::Retail => "retail_price",
+            };
+            sqlx::query(&format!("UPDATE products SET {} = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?", column))
+                .bind(change.proposed)
+                .bind(change.product_id)
+                .execute(&mut *tx)
+                .await?;
+            let why = PriceNote { rule_id: Some(&change.rule_id), ..PriceNote::new(PriceSource::Rule, note) };
+            let (old, new) = ((change.current, change.currency.as_str()), (change.proposed, change.currency.as_str()));
+            insert_price_change(&mut tx, change.product_id, change.price, Some(old), new, &why).await?;
+        }
+        tx.commit().await?;
+        report.applied = true;
         
+        Ok(report)
+    }
+    
+    /// Soft-deletes a product; it disappears from lookups until restored.
+    pub async fn delete_product(&self, barcode: &str) -> Result<(), AppError> {
         let result = sqlx::query(
+            "UPDATE products SET deleted_at = CURRENT_TIMESTAMP WHERE barcode IN (?, ?) AND deleted_at IS NULL"
+        )
+        .bind(barcode::storage_key(barcode))
+        .bind(barcode.trim())
+        .execute(&self.pool)
+        .await?;
+        
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+    
+    pub async fn get_lots(&self, barcode: &str) -> Result<Vec<Lot>, AppError> {
+        let product = self.get_product_by_barcode(barcode).await?;
+        let mut conn = self.pool.acquire().await?;
+        fetch_lots(&mut conn, product.id.ok_or(AppError::NotFound)?).await
+    }
+    
+    /// Lots of all products that are not deleted, earliest expiry first.
+    pub async fn get_all_lots(&self) -> Result<Vec<Lot>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT lots.* FROM lots
+            JOIN products ON products.id = lots.product_id
+            WHERE products.deleted_at IS NULL
+            ORDER BY lots.expiry_date, lots.id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows.iter().map(lot_from_row).collect())
+    }
+    
+    /// Receives a new lot of a product and adds its quantity to the product's stock.
+    pub async fn add_lot(&self, barcode: &str, lot: LotInput) -> Result<Lot, AppError> {
+        lot.validate()?;
+        let product = self.get_product_by_barcode(barcode).await?;
+        let product_id = product.id.ok_or(AppError::NotFound)?;
+        
+        let mut tx = self.pool.begin().await?;
+        let lot_code = self.codes.of(&product)?.lot_code(&product, &lot);
+        let id = insert_lot(&mut tx, &product, &lot, &lot_code).await?;
+        let receipt = Posting { reference: lot.supplier_invoice.as_deref(), ..Posting::new(MovementType::Receipt) };
+        post_movement(&mut tx, product_id, id, lot.quantity, &receipt).await?;
+        sync_stock(&mut tx, product_id).await?;
+        tx.commit().await?;
+        
+        Ok(Lot {
+            id: Some(id),
+            product_id,
+            batch_id: lot.batch_id,
+            lot_code,
+            quantity: lot.quantity,
+            production_date: lot.production_date,
+            expiry_date: lot.expiry_date,
+            supplier_invoice: lot.supplier_invoice,
+            landed_cost: lot.landed_cost,
+        })
+    }
+    
+    /// Records a stock operation in the ledger and applies it to the product's lots.
+    /// Sales and write-offs without a batch take the lots that expire first; sales
+    /// pass over expired lots.
+    pub async fn record_movement(&self, barcode: &str, input: MovementInput) -> Result<Vec<StockMovement>, AppError> {
+        input.validate()?;
+        // The lots are drawn down from what this transaction reads, so the write
+        // lock is taken first and a concurrent sale waits for it
+        let mut tx = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+        let product = fetch_product_by_barcode(&mut tx, barcode).await?;
+        let product_id = product.id.ok_or(AppError::NotFound)?;
+        let lots = fetch_lots(&mut tx, product_id).await?;
+        let find_lot = |batch_id: Option<i32>| {
+            let batch_id = batch_id.unwrap_or(product.batch_id);
+            lots.iter()
+                .find(|lot| lot.batch_id == batch_id)
+                .ok_or_else(|| AppError::Validation(format!("product has no lot with batch {}", batch_id)))
+        };
+        
+        let mut postings: Vec<(&Lot, i32)> = Vec::new();
+        match input.movement_type {
+            MovementType::Receipt | MovementType::Return => {
+                postings.push((find_lot(input.batch_id)?, input.quantity));
+            }
+            MovementType::Adjustment => {
+                let lot = find_lot(input.batch_id)?;
+                if lot.quantity + input.quantity < 0 {
+                    return Err(AppError::Validation(format!("lot {} only holds {} units", lot.lot_code, lot.quantity)));
+                }
+                postings.push((lot, input.quantity));
+            }
+            MovementType::Transfer => {
+                let from = find_lot(input.batch_id)?;
+                let to = find_lot(input.to_batch_id)?;
+                if from.quantity < input.quantity {
+                    return Err(AppError::Validation(format!("lot {} only holds {} units", from.lot_code, from.quantity)));
+                }
+                postings.push((from, -input.quantity));
+                postings.push((to, input.quantity));
+            }
+            MovementType::Sale | MovementType::WriteOff => {
+                // Expired stock is not for sale, but is what write-offs usually take
+                let today = chrono::Local::now().date_naive();
+                let unexpired_only = input.batch_id.is_none() && input.movement_type == MovementType::Sale;
+                let candidates: Vec<&Lot> = match input.batch_id {
+                    Some(batch_id) => vec![find_lot(Some(batch_id))?],
+                    None => lots.iter().filter(|lot| !unexpired_only || lot.expiry_date >= today).collect(),
+                };
+                
+                let mut remaining = input.quantity;
+                for lot in candidates {
+                    let taken = lot.quantity.min(remaining);
+                    if taken > 0 {
+                        postings.push((lot, -taken));
+                        remaining -= taken;
+                    }
+                }
+                if remaining > 0 {
+                    return Err(AppError::Validation(format!(
+                        "only {} of {} units in {}stock",
+                        input.quantity - remaining,
+                        input.quantity,
+                        if unexpired_only { "unexpired " } else { "" }
+                    )));
+                }
+            }
+            MovementType::Opening => unreachable!("rejected by MovementInput::validate"),
+        }
+        
+        let posting = Posting {
+            movement_type: input.movement_type,
+            reason: input.reason.as_deref(),
+            reference: input.reference.as_deref(),
+            note: input.note.as_deref(),
+        };
+        let mut ids = Vec::new();
+        for (lot, quantity) in postings {
+            let lot_id = lot.id.ok_or(AppError::NotFound)?;
+            ids.push(post_movement(&mut tx, product_id, lot_id, quantity, &posting).await?);
+        }
+        sync_stock(&mut tx, product_id).await?;
+        
+        let movements = fetch_movements(&mut tx, product_id).await?;
+        tx.commit().await?;
+        
+        Ok(movements.into_iter().filter(|m| ids.contains(&m.id)).collect())
+    }
+    
+    /// Ledger entries of one product, oldest first.
+    pub async fn get_movements(&self, barcode: &str) -> Result<Vec<StockMovement>, AppError> {
+        let product = self.get_product_by_barcode(barcode).await?;
+        let mut conn = self.pool.acquire().await?;
+        fetch_movements(&mut conn, product.id.ok_or(AppError::NotFound)?).await
+    }
+    
+    /// Products whose stock, lot total and ledger total disagree.
+    pub async fn check_ledger(&self) -> Result<Vec<LedgerMismatch>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                p.barcode,
+                p.stock_quantity,
+                (SELECT COALESCE(SUM(quantity), 0) FROM lots WHERE product_id = p.id) AS lot_quantity,
+                (SELECT COALESCE(SUM(quantity), 0) FROM stock_movements WHERE product_id = p.id) AS ledger_quantity
+            FROM products p
+            WHERE p.deleted_at IS NULL
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows
+            .iter()
+            .map(|row| LedgerMismatch {
+                barcode: row.get("barcode"),
+                stock_quantity: row.get("stock_quantity"),
+                lot_quantity: row.get("lot_quantity"),
+                ledger_quantity: row.get("ledger_quantity"),
+            })
+            .filter(|m| m.stock_quantity != m.lot_quantity || m.stock_quantity != m.ledger_quantity)
+            .collect())
+    }
+    
+    /// Daily sales of one product from every source, oldest first.
+    pub async fn get_sales(&self, barcode: &str) -> Result<Vec<DailySales>, AppError> {
+        let product = self.get_product_by_barcode(barcode).await?;
+        let rows = sqlx::query("SELECT * FROM daily_sales WHERE product_id = ? ORDER BY sale_date, source")
+            .bind(product.id)
+            .fetch_all(&self.pool)
+            .await?;
+        
+        rows.iter().map(daily_sales_from_row).collect()
+    }
+    
+    /// Daily sales of all products that are not deleted, back to `since`.
+    pub async fn get_all_sales(&self, since: NaiveDate) -> Result<Vec<DailySales>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT daily_sales.* FROM daily_sales
+            JOIN products ON products.id = daily_sales.product_id
+            WHERE products.deleted_at IS NULL AND daily_sales.sale_date >= ?
+            ORDER BY daily_sales.sale_date
+            "#
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.iter().map(daily_sales_from_row).collect()
+    }
+    
+    /// Products, lots, suppliers, exchange rates and the sales history reaching back
+    /// `history_days`.
+    pub async fn inventory_snapshot(&self, history_days: u32) -> Result<InventorySnapshot, AppError> {
+        let since = chrono::Local::now().date_naive() - chrono::Duration::days(history_days as i64);
+        let (products, lots, sales, suppliers, rates) = tokio::try_join!(
+            self.get_all_products(),
+            self.get_all_lots(),
+            self.get_all_sales(since),
+            self.get_suppliers(),
+            self.exchange_rates(),
+        )?;
+        
+        Ok(InventorySnapshot { products, lots, sales, suppliers, rates })
+    }
+    
+    /// Products, sales and ledger stock for reports over `from` to `to`, both
+    /// included, with every exchange rate. The ledger's opening balances count as
+    /// stock on hand before any period.
+    pub async fn report_data(&self, from: NaiveDate, to: NaiveDate) -> Result<ReportData, AppError> {
+        let (products, sales, rates) = tokio::try_join!(
+            self.get_all_products(),
+            self.get_all_sales(from),
+            self.exchange_rates(),
+        )?;
+        let sales = sales.into_iter().filter(|entry| entry.sale_date <= to).collect();
+        
+        let flows = sqlx::query(
             r#"
-            INSERT INTO products (
-                original_name, imported_name, local_name, barcode, internal_code, alternative_code,
-                brand, category, weight, origin_country, supplier, purchase_price, wholesale_price,
-                retail_price, production_date, expiry_date, batch_id, stock_quantity, monthly_sales,
-                min_threshold
+            SELECT
+                product_id,
+                COALESCE(SUM(CASE WHEN date(created_at) < ?1 OR movement_type = 'opening' THEN quantity END), 0) AS opening,
+                COALESCE(SUM(CASE WHEN movement_type = 'receipt' AND date(created_at) BETWEEN ?1 AND ?2
+                    THEN quantity END), 0) AS received,
+                COALESCE(SUM(CASE WHEN date(created_at) <= ?2 THEN quantity END), 0) AS closing
+            FROM stock_movements
+            GROUP BY product_id
+            "#
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| {
+            let flow = StockFlow { opening: row.get("opening"), received: row.get("received"), closing: row.get("closing") };
+            (row.get("product_id"), flow)
+        })
+        .collect();
+        
+        let last_sale = sqlx::query(
+            "SELECT product_id, MAX(sale_date) AS last_sale FROM daily_sales WHERE quantity > 0 AND sale_date <= ? GROUP BY product_id"
+        )
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| (row.get("product_id"), row.get("last_sale")))
+        .collect();
+        
+        Ok(ReportData { from, to, products, sales, rates, flows, last_sale })
+    }
+    
+    /// Writes daily totals exported from the POS in one transaction, replacing any
+    /// POS figure already stored for the same product and day. The transaction is
+    /// only committed when `commit` is set and every row succeeded.
+    pub async fn import_sales(&self, rows: Vec<SalesRow>, commit: bool) -> Result<SalesImportReport, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let mut report = SalesImportReport::default();
+        
+        for row in rows {
+            let product_id: Option<i64> = sqlx::query_scalar("SELECT id FROM products WHERE barcode IN (?, ?) AND deleted_at IS NULL")
+                .bind(barcode::storage_key(&row.barcode))
+                .bind(row.barcode.trim())
+                .fetch_optional(&mut *tx)
+                .await?;
+            
+            let Some(product_id) = product_id else {
+                report.errors.push(RowError { line: row.line, message: format!("unknown barcode {}", row.barcode) });
+                continue;
+            };
+            
+            sqlx::query(
+                r#"
+                INSERT INTO daily_sales (product_id, sale_date, source, quantity)
+                VALUES (?, ?, ?, ?)
+                ON CONFLICT (product_id, sale_date, source) DO UPDATE SET quantity = excluded.quantity
+                "#
             )
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            .bind(product_id)
+            .bind(row.sale_date)
+            .bind(SalesSource::Pos.as_str())
+            .bind(row.quantity)
+            .execute(&mut *tx)
+            .await?;
+            report.rows += 1;
+        }
+        
+        if commit && report.errors.is_empty() {
+            tx.commit().await?;
+            report.committed = true;
+        } else {
+            tx.rollback().await?;
+        }
+        
+        Ok(report)
+    }
+    
+    /// Alert rules switched on or off at runtime, by rule id.
+    pub async fn get_rule_overrides(&self) -> Result<HashMap<String, bool>, AppError> {
+        let rows = sqlx::query("SELECT rule_id, enabled FROM alert_rule_state")
+            .fetch_all(&self.pool)
+            .await?;
+        
+        Ok(rows.iter().map(|row| (row.get("rule_id"), row.get("enabled"))).collect())
+    }
+    
+    /// The configured rules with their runtime switches applied.
+    pub async fn load_rules(&self, rules: &RuleSet) -> Result<RuleSet, AppError> {
+        let mut rules = rules.clone();
+        rules.apply_overrides(&self.get_rule_overrides().await?);
+        Ok(rules)
+    }
+    
+    pub async fn set_rule_enabled(&self, rule_id: &str, enabled: bool) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO alert_rule_state (rule_id, enabled) VALUES (?, ?)
+            ON CONFLICT (rule_id) DO UPDATE SET enabled = excluded.enabled, updated_at = CURRENT_TIMESTAMP
             "#
         )
-        .bind(&product.original_name)
-        .bind(&product.imported_name)
-        .bind(&product.local_name)
-        .bind(&product.barcode)
-        .bind(&product.internal_code)
-        .bind(&product.alternative_code)
-        .bind(&product.brand)
-        .bind(&product.category)
-        .bind(&product.weight)
-        .bind(&product.origin_country)
-        .bind(&product.supplier)
-        .bind(product.purchase_price)
-        .bind(product.wholesale_price)
-        .bind(product.retail_price)
-        .bind(&product.production_date)
-        .bind(&product.expiry_date)
-        .bind(product.batch_id)
-        .bind(product.stock_quantity)
-        .bind(product.monthly_sales)
-        .bind(product.min_threshold)
+        .bind(rule_id)
+        .bind(enabled)
         .execute(&self.pool)
         .await?;
         
-        Ok(result.last_insert_rowid())
+        Ok(())
     }
     
-    pub async fn get_product_by_barcode(&self, barcode: &str) -> Result<Product, AppError> {
-        let row = sqlx::query("SELECT * FROM products WHERE barcode = ?")
-            .bind(barcode)
-            .fetch_one(&self.pool)
+    /// Evaluates the alert rules against current stock and records the outcome.
+    pub async fn refresh_alerts(&self, config: &InventoryConfig, rules: &RuleSet) -> Result<Vec<InventoryAlert>, AppError> {
+        let (snapshot, rules) = tokio::try_join!(self.inventory_snapshot(config.history_days()), self.load_rules(rules))?;
+        let alerts = InventoryManager::check_inventory(&snapshot, config, &rules);
+        self.sync_alerts(&alerts).await?;
+        Ok(alerts)
+    }
+    
+    /// Records the outcome of an alert evaluation. New problems are opened, known ones
+    /// refreshed (a resolved one reopens), expired snoozes reopen, and open problems
+    /// that were not raised this time are resolved.
+    pub async fn sync_alerts(&self, alerts: &[InventoryAlert]) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query(
+            "UPDATE alerts SET status = 'open', snoozed_until = NULL WHERE status = 'snoozed' AND snoozed_until <= date('now', 'localtime')"
+        )
+        .execute(&mut *tx)
+        .await?;
+        
+        for alert in alerts {
+            sqlx::query(
+                r#"
+                INSERT INTO alerts (fingerprint, rule_id, alert_type, severity, barcode, product_name, lot_code, message)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (fingerprint) DO UPDATE SET
+                    rule_id = excluded.rule_id,
+                    severity = excluded.severity,
+                    product_name = excluded.product_name,
+                    message = excluded.message,
+                    last_seen_at = CURRENT_TIMESTAMP,
+                    first_seen_at = CASE WHEN status = 'resolved' THEN CURRENT_TIMESTAMP ELSE first_seen_at END,
+                    resolved_at = CASE WHEN status = 'resolved' THEN NULL ELSE resolved_at END,
+                    status = CASE WHEN status = 'resolved' THEN 'open' ELSE status END
+                "#
+            )
+            .bind(alert.fingerprint())
+            .bind(&alert.rule_id)
+            .bind(alert.alert_type.as_str())
+            .bind(alert.severity.as_str())
+            .bind(&alert.barcode)
+            .bind(&alert.product_name)
+            .bind(&alert.lot_code)
+            .bind(&alert.message)
+            .execute(&mut *tx)
+            .await?;
+        }
+        
+        let raised: Vec<String> = alerts.iter().map(InventoryAlert::fingerprint).collect();
+        let unresolved: Vec<(i64, String)> = sqlx::query_as("SELECT id, fingerprint FROM alerts WHERE status != 'resolved'")
+            .fetch_all(&mut *tx)
             .await?;
+        for (id, fingerprint) in unresolved {
+            if !raised.contains(&fingerprint) {
+                sqlx::query("UPDATE alerts SET status = 'resolved', snoozed_until = NULL, resolved_at = CURRENT_TIMESTAMP WHERE id = ?")
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
         
-        Ok(Product {
-            id: Some(row.get("id")),
-            original_name: row.get("original_name"),
-            imported_name: row.get("imported_name"),
-            local_name: row.get("local_name"),
-            barcode: row.get("barcode"),
-            internal_code: row.get("internal_code"),
-            alternative_code: row.get("alternative_code"),
-            brand: row.get("brand"),
-            category: row.get("category"),
-            weight: row.get("weight"),
-            origin_country: row.get("origin_country"),
-            supplier: row.get("supplier"),
-            purchase_price: row.get("purchase_price"),
-            wholesale_price: row.get("wholesale_price"),
-            retail_price: row.get("retail_price"),
-            production_date: row.get("production_date"),
-            expiry_date: row.get("expiry_date"),
-            batch_id: row.get("batch_id"),
-            stock_quantity: row.get("stock_quantity"),
-            monthly_sales: row.get("monthly_sales"),
-            min_threshold: row.get("min_threshold"),
-        })
+        tx.commit().await?;
+        Ok(())
     }
     
-    pub async fn get_all_products(&self) -> Result<Vec<Product>, AppError> {
-        let rows = sqlx::query("SELECT * FROM products")
+    /// Tracked alerts, most severe first; all statuses when `status` is `None`.
+    pub async fn get_alerts(&self, status: Option<AlertStatus>) -> Result<Vec<TrackedAlert>, AppError> {
+        let rows = sqlx::query("SELECT * FROM alerts WHERE ? IS NULL OR status = ? ORDER BY first_seen_at, id")
+            .bind(status.map(|s| s.as_str()))
+            .bind(status.map(|s| s.as_str()))
+            .fetch_all(&self.pool)
+            .await?;
+        let note_rows = sqlx::query("SELECT * FROM alert_notes ORDER BY id")
             .fetch_all(&self.pool)
             .await?;
         
-        let mut products = Vec::new();
-        for row in rows {
-            products.push(Product {
-                id: Some(row.get("id")),
-                original_name: row.get("original_name"),
-                imported_name: row.get("imported_name"),
-                local_name: row.get("local_name"),
-                barcode: row.get("barcode"),
-                internal_code: row.get("internal_code"),
-                alternative_code: row.get("alternative_code"),
-                brand: row.get("brand"),
-                category: row.get("category"),
-                weight: row.get("weight"),
-                origin_country: row.get("origin_country"),
-                supplier: row.get("supplier"),
-                purchase_price: row.get("purchase_price"),
-                wholesale_price: row.get("wholesale_price"),
-                retail_price: row.get("retail_price"),
-                production_date: row.get("production_date"),
-                expiry_date: row.get("expiry_date"),
-                batch_id: row.get("batch_id"),
-                stock_quantity: row.get("stock_quantity"),
-                monthly_sales: row.get("monthly_sales"),
-                min_threshold: row.get("min_threshold"),
-            });
+        let mut alerts = rows.iter().map(tracked_alert_from_row).collect::<Result<Vec<_>, _>>()?;
+        for row in &note_rows {
+            let alert_id: i64 = row.get("alert_id");
+            if let Some(alert) = alerts.iter_mut().find(|alert| alert.id == alert_id) {
+                alert.notes.push(alert_note_from_row(row));
+            }
+        }
+        alerts.sort_by_key(|alert| std::cmp::Reverse(alert.severity));
+        Ok(alerts)
+    }
+    
+    pub async fn get_alert(&self, id: i64) -> Result<TrackedAlert, AppError> {
+        let row = sqlx::query("SELECT * FROM alerts WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        let note_rows = sqlx::query("SELECT * FROM alert_notes WHERE alert_id = ? ORDER BY id")
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await?;
+        
+        let mut alert = tracked_alert_from_row(&row)?;
+        alert.notes = note_rows.iter().map(alert_note_from_row).collect();
+        Ok(alert)
+    }
+    
+    /// Changes the status, assignee or notes of a tracked alert.
+    pub async fn update_alert(&self, id: i64, update: AlertUpdate) -> Result<TrackedAlert, AppError> {
+        update.validate()?;
+        let mut tx = self.pool.begin().await?;
+        
+        let exists: Option<i64> = sqlx::query_scalar("SELECT id FROM alerts WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if exists.is_none() {
+            return Err(AppError::NotFound);
+        }
+        
+        if let Some(status) = update.status {
+            sqlx::query(
+                r#"
+                UPDATE alerts SET
+                    status = ?,
+                    snoozed_until = ?,
+                    resolved_at = CASE WHEN ? = 'resolved' THEN CURRENT_TIMESTAMP END
+                WHERE id = ?
+                "#
+            )
+            .bind(status.as_str())
+            .bind(update.snoozed_until)
+            .bind(status.as_str())
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        if let Some(assignee) = &update.assignee {
+            sqlx::query("UPDATE alerts SET assignee = ? WHERE id = ?")
+                .bind(Some(assignee.trim()).filter(|a| !a.is_empty()))
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if let Some(note) = &update.note {
+            sqlx::query("INSERT INTO alert_notes (alert_id, note, author) VALUES (?, ?, ?)")
+                .bind(id)
+                .bind(note.trim())
+                .bind(&update.author)
+                .execute(&mut *tx)
+                .await?;
+        }
+        
+        tx.commit().await?;
+        self.get_alert(id).await
+    }
+    
+    /// Open alerts of at least `min_severity` that have not been sent on `channel`
+    /// since they were first seen, or only at a lower severity.
+    pub async fn undelivered_alerts(&self, channel: &str, min_severity: Severity) -> Result<Vec<TrackedAlert>, AppError> {
+        let delivered: Vec<(i64, String)> = sqlx::query_as(
+            r#"
+            SELECT d.alert_id, d.severity
+            FROM alert_deliveries d
+            JOIN alerts a ON a.id = d.alert_id
+            WHERE d.channel = ? AND d.sent_at >= a.first_seen_at
+            "#
+        )
+        .bind(channel)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let mut sent: HashMap<i64, Severity> = HashMap::new();
+        for (alert_id, severity) in delivered {
+            let severity = severity.parse()?;
+            let highest = sent.entry(alert_id).or_insert(severity);
+            *highest = (*highest).max(severity);
         }
         
-        Ok(products)
+        let alerts = self.get_alerts(Some(AlertStatus::Open)).await?;
+        Ok(alerts
+            .into_iter()
+            .filter(|alert| alert.severity >= min_severity)
+            .filter(|alert| sent.get(&alert.id).is_none_or(|severity| *severity < alert.severity))
+            .collect())
+    }
+    
+    pub async fn record_deliveries(&self, channel: &str, alerts: &[TrackedAlert], digest: bool) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        for alert in alerts {
+            sqlx::query("INSERT INTO alert_deliveries (alert_id, channel, severity, digest) VALUES (?, ?, ?, ?)")
+                .bind(alert.id)
+                .bind(channel)
+                .bind(alert.severity.as_str())
+                .bind(digest)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+    
+    pub async fn last_digest(&self, channel: &str) -> Result<Option<NaiveDate>, AppError> {
+        let sent_on = sqlx::query_scalar("SELECT sent_on FROM notification_digests WHERE channel = ?")
+            .bind(channel)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(sent_on)
+    }
+    
+    pub async fn record_digest(&self, channel: &str, sent_on: NaiveDate) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO notification_digests (channel, sent_on) VALUES (?, ?) ON CONFLICT (channel) DO UPDATE SET sent_on = excluded.sent_on"
+        )
+        .bind(channel)
+        .bind(sent_on)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+    
+    pub async fn start_job_run(&self, job: JobKind, triggered_by: &str) -> Result<i64, AppError> {
+        let result = sqlx::query("INSERT INTO job_runs (job, triggered_by) VALUES (?, ?)")
+            .bind(job.as_str())
+            .bind(triggered_by)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.last_insert_rowid())
     }
     
-    async fn count_products(&self) -> Result<i32, AppError> {
-        let row = sqlx::query("SELECT COUNT(*) as count FROM products")
+    pub async fn finish_job_run(&self, id: i64, outcome: &Result<String, AppError>) -> Result<JobRun, AppError> {
+        let (status, output, error) = match outcome {
+            Ok(output) => (JobStatus::Succeeded, Some(output.clone()), None),
+            Err(e) => (JobStatus::Failed, None, Some(e.to_string())),
+        };
+        sqlx::query("UPDATE job_runs SET status = ?, output = ?, error = ?, finished_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(status.as_str())
+            .bind(output)
+            .bind(error)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        
+        let row = sqlx::query("SELECT * FROM job_runs WHERE id = ?")
+            .bind(id)
             .fetch_one(&self.pool)
             .await?;
+        job_run_from_row(&row)
+    }
+    
+    /// Marks runs left `running` by a process that stopped mid-job as failed.
+    pub async fn fail_interrupted_job_runs(&self) -> Result<u64, AppError> {
+        let result = sqlx::query(
+            "UPDATE job_runs SET status = 'failed', error = 'interrupted', finished_at = CURRENT_TIMESTAMP WHERE status = 'running'"
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+    
+    /// Most recent job runs first, of one job or all of them.
+    pub async fn get_job_runs(&self, job: Option<JobKind>, limit: u32) -> Result<Vec<JobRun>, AppError> {
+        let rows = sqlx::query("SELECT * FROM job_runs WHERE ? IS NULL OR job = ? ORDER BY id DESC LIMIT ?")
+            .bind(job.map(|j| j.as_str()))
+            .bind(job.map(|j| j.as_str()))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(job_run_from_row).collect()
+    }
+    
+    /// Products due for reordering, counting approved orders as already on the way.
+    pub async fn reorder_suggestions(
+        &self,
+        inventory: &InventoryConfig,
+        reorder: &ReorderConfig,
+    ) -> Result<Vec<ReorderSuggestion>, AppError> {
+        let snapshot = self.inventory_snapshot(inventory.rule_window_days).await?;
+        let on_order: Vec<(i64, i32)> = sqlx::query_as(
+            r#"
+            SELECT l.product_id, SUM(l.quantity)
+            FROM purchase_order_lines l
+            JOIN purchase_orders o ON o.id = l.order_id
+            WHERE o.status = 'approved'
+            GROUP BY l.product_id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
         
-        Ok(row.get("count"))
+        let on_order: HashMap<i64, i32> = on_order.into_iter().collect();
+        InventoryManager::reorder_suggestions(&snapshot, &on_order, inventory, reorder)
     }
-}
\ No newline at end of file
+    
+    /// Adds the current suggestions to each supplier's draft order, opening a draft
+    /// for suppliers without one in their current currency. Products already on a
+    /// draft keep the quantity there. Returns the drafts that gained lines.
+    pub async fn draft_purchase_orders(
+        &self,
+        inventory: &InventoryConfig,
+        reorder: &ReorderConfig,
+    ) -> Result<Vec<PurchaseOrder>, AppError> {
+        let suggestions = self.reorder_suggestions(inventory, reorder).await?;
+        let mut tx = self.pool.begin().await?;
+        let mut drafted = Vec::new();
+        
+        for suggestion in &suggestions {
+            let on_draft: Option<i64> = sqlx::query_scalar(
+                r#"
+                SELECT l.id FROM purchase_order_lines l
+                JOIN purchase_orders o ON o.id = l.order_id
+                WHERE o.status = 'draft' AND l.product_id = ?
+                "#
+            )
+            .bind(suggestion.product_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+            if on_draft.is_some() {
+                continue;
+            }
+            
+            let draft: Option<i64> = sqlx::query_scalar(
+                "SELECT id FROM purchase_orders WHERE status = 'draft' AND supplier_id = ? AND currency = ? ORDER BY id LIMIT 1"
+            )
+            .bind(suggestion.supplier_id)
+            .bind(&suggestion.currency)
+            .fetch_optional(&mut *tx)
+            .await?;
+            let order_id = match draft {
+                Some(id) => id,
+                None => sqlx::query("INSERT INTO purchase_orders (supplier_id, currency) VALUES (?, ?)")
+                    .bind(suggestion.supplier_id)
+                    .bind(&suggestion.currency)
+                    .execute(&mut *tx)
+                    .await?
+                    .last_insert_rowid(),
+            };
+            
+            sqlx::query(
+                r#"
+                INSERT INTO purchase_order_lines (order_id, product_id, quantity, unit_cost, suggested_quantity)
+                VALUES (?, ?, ?, ?, ?)
+                "#
+            )
+            .bind(order_id)
+            .bind(suggestion.product_id)
+            .bind(suggestion.quantity)
+            .bind(suggestion.unit_cost)
+            .bind(suggestion.quantity)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query("UPDATE purchase_orders SET updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+                .bind(order_id)
+                .execute(&mut *tx)
+                .await?;
+            if !drafted.contains(&order_id) {
+                drafted.push(order_id);
+            }
+        }
+        
+        tx.commit().await?;
+        let mut orders = Vec::new();
+        for id in drafted {
+            orders.push(self.get_purchase_order(id).await?);
+        }
+        Ok(orders)
+    }
+    
+    /// Purchase orders, newest first; all statuses when `status` is `None`.
+    pub async fn get_purchase_orders(&self, status: Option<OrderStatus>) -> Result<Vec<PurchaseOrder>, AppError> {
+        let rows = sqlx::query(&format!("{} WHERE ? IS NULL OR status = ? ORDER BY purchase_orders.id DESC", ORDER_SELECT))
+            .bind(status.map(|s| s.as_str()))
+            .bind(status.map(|s| s.as_str()))
+            .fetch_all(&self.pool)
+            .await?;
+        let line_rows = sqlx::query(
+            r#"
+            SELECT l.*, p.barcode, p.imported_name, p.case_pack
+            FROM purchase_order_lines l
+            JOIN products p ON p.id = l.product_id
+            ORDER BY p.imported_name
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let mut orders = rows.iter().map(purchase_order_from_row).collect::<Result<Vec<_>, _>>()?;
+        for row in &line_rows {
+            let order_id: i64 = row.get("order_id");
+            if let Some(order) = orders.iter_mut().find(|order| order.id == order_id) {
+                order.lines.push(order_line_from_row(row));
+            }
+        }
+        for order in &mut orders {
+            total_order(order, self.currency.rounding())?;
+        }
+        Ok(orders)
+    }
+    
+    pub async fn get_purchase_order(&self, id: i64) -> Result<PurchaseOrder, AppError> {
+        let mut conn = self.pool.acquire().await?;
+        fetch_purchase_order(&mut conn, id, self.currency.rounding()).await
+    }
+    
+    /// Moves a purchase order along draft → approved → received, or cancels it, and
+    /// replaces its notes. Receiving only closes the order; the delivered stock is
+    /// booked as lots.
+    pub async fn update_purchase_order(&self, id: i64, update: PurchaseOrderUpdate) -> Result<PurchaseOrder, AppError> {
+        // The write lock is taken before the order is read, so a line edit cannot
+        // slip in between the status checks and the update.
+        let mut tx = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+        let order = fetch_purchase_order(&mut tx, id, self.currency.rounding()).await?;
+        
+        if let Some(status) = update.status {
+            if !order.status.can_become(status) {
+                return Err(AppError::Validation(format!(
+                    "cannot move an order from {} to {}", order.status.as_str(), status.as_str()
+                )));
+            }
+            if status == OrderStatus::Approved && order.lines.is_empty() {
+                return Err(AppError::Validation("cannot approve an order without lines".to_string()));
+            }
+            sqlx::query(
+                r#"
+                UPDATE purchase_orders SET
+                    status = ?,
+                    approved_at = CASE WHEN ? = 'approved' THEN CURRENT_TIMESTAMP ELSE approved_at END,
+                    updated_at = CURRENT_TIMESTAMP
+                WHERE id = ?
+                "#
+            )
+            .bind(status.as_str())
+            .bind(status.as_str())
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        if let Some(notes) = &update.notes {
+            sqlx::query("UPDATE purchase_orders SET notes = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+                .bind(Some(notes.trim()).filter(|n| !n.is_empty()))
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        
+        let order = fetch_purchase_order(&mut tx, id, self.currency.rounding()).await?;
+        tx.commit().await?;
+        Ok(order)
+    }
+    
+    /// Sets the quantity of a product on a draft order, adding the line if needed;
+    /// a quantity of 0 removes it.
+    pub async fn set_order_line(&self, id: i64, barcode: &str, quantity: i32) -> Result<PurchaseOrder, AppError> {
+        if quantity < 0 {
+            return Err(AppError::Validation("quantity must not be negative".to_string()));
+        }
+        // As in update_purchase_order, the order stays a draft until the line is written.
+        let mut tx = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+        let order = fetch_purchase_order(&mut tx, id, self.currency.rounding()).await?;
+        if order.status != OrderStatus::Draft {
+            return Err(AppError::Validation(format!("order {} is {} and can no longer be edited", id, order.status.as_str())));
+        }
+        let product = fetch_product_by_barcode(&mut tx, barcode).await?;
+        if product.supplier_id != Some(order.supplier_id) {
+            return Err(AppError::Validation(format!("{} is supplied by {}, not {}", barcode, product.supplier, order.supplier)));
+        }
+        let today = chrono::Local::now().date_naive();
+        let rates = fetch_exchange_rates(&mut tx, None).await?;
+        let unit_cost = ExchangeRates::new(&self.currency.base, &rates, self.currency.rounding())
+            .convert(product.purchase_price, &product.purchase_currency, &order.currency, today)?;
+        
+        if quantity == 0 {
+            sqlx::query("DELETE FROM purchase_order_lines WHERE order_id = ? AND product_id = ?")
+                .bind(id)
+                .bind(product.id)
+                .execute(&mut *tx)
+                .await?;
+        } else {
+            sqlx::query(
+                r#"
+                INSERT INTO purchase_order_lines (order_id, product_id, quantity, unit_cost)
+                VALUES (?, ?, ?, ?)
+                ON CONFLICT (order_id, product_id) DO UPDATE SET quantity = excluded.quantity
+                "#
+            )
+            .bind(id)
+            .bind(product.id)
+            .bind(quantity)
+            .bind(unit_cost)
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query("UPDATE purchase_orders SET updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        
+        let order = fetch_purchase_order(&mut tx, id, self.currency.rounding()).await?;
+        tx.commit().await?;
+        Ok(order)
+    }
+    
+    pub async fn get_suppliers(&self) -> Result<Vec<Supplier>, AppError> {
+        let rows = sqlx::query("SELECT * FROM suppliers ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        
+        Ok(rows.iter().map(supplier_from_row).collect())
+    }
+    
+    pub async fn get_supplier(&self, id: i64) -> Result<Supplier, AppError> {
+        let row = sqlx::query("SELECT * FROM suppliers WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        
+        Ok(supplier_from_row(&row))
+    }
+    
+    /// Looks a supplier up by id, or by name ignoring case and legal form.
+    pub async fn find_supplier(&self, key: &str) -> Result<Supplier, AppError> {
+        if let Ok(id) = key.parse::<i64>() {
+            match self.get_supplier(id).await {
+                Err(AppError::NotFound) => {}
+                result => return result,
+            }
+        }
+        self.get_supplier_by_name(key).await
+    }
+    
+    async fn get_supplier_by_name(&self, name: &str) -> Result<Supplier, AppError> {
+        let row = sqlx::query("SELECT * FROM suppliers WHERE name_key = ?")
+            .bind(Supplier::name_key(name))
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        
+        Ok(supplier_from_row(&row))
+    }
+    
+    pub async fn add_supplier(&self, supplier: Supplier) -> Result<Supplier, AppError> {
+        supplier.validate()?;
+        match self.get_supplier_by_name(&supplier.name).await {
+            Ok(existing) => return Err(AppError::Validation(format!(
+                "supplier {:?} already exists as {:?} (id {})", supplier.name, existing.name, existing.id.unwrap_or_default()
+            ))),
+            Err(AppError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        
+        let result = sqlx::query(
+            r#"
+            INSERT INTO suppliers (
+                name, name_key, contact_name, email, phone, address, country, currency,
+                lead_time_days, min_order_value, payment_terms, vat_number
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(&supplier.name)
+        .bind(Supplier::name_key(&supplier.name))
+        .bind(&supplier.contact_name)
+        .bind(&supplier.email)
+        .bind(&supplier.phone)
+        .bind(&supplier.address)
+        .bind(&supplier.country)
+        .bind(&supplier.currency)
+        .bind(supplier.lead_time_days)
+        .bind(supplier.min_order_value)
+        .bind(&supplier.payment_terms)
+        .bind(&supplier.vat_number)
+        .execute(&self.pool)
+        .await?;
+        
+        self.get_supplier(result.last_insert_rowid()).await
+    }
+    
+    pub async fn update_supplier(&self, id: i64, patch: SupplierPatch) -> Result<Supplier, AppError> {
+        let mut supplier = self.get_supplier(id).await?;
+        patch.apply(&mut supplier);
+        supplier.validate()?;
+        
+        let key = Supplier::name_key(&supplier.name);
+        let taken: Option<i64> = sqlx::query_scalar("SELECT id FROM suppliers WHERE name_key = ? AND id != ?")
+            .bind(&key)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        if let Some(other) = taken {
+            return Err(AppError::Validation(format!("name {:?} is already used by supplier {}", supplier.name, other)));
+        }
+        
+        sqlx::query(
+            r#"
+            UPDATE suppliers SET
+                name = ?, name_key = ?, contact_name = ?, email = ?, phone = ?, address = ?,
+                country = ?, currency = ?, lead_time_days = ?, min_order_value = ?,
+                payment_terms = ?, vat_number = ?, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#
+        )
+        .bind(&supplier.name)
+        .bind(&key)
+        .bind(&supplier.contact_name)
+        .bind(&supplier.email)
+        .bind(&supplier.phone)
+        .bind(&supplier.address)
+        .bind(&supplier.country)
+        .bind(&supplier.currency)
+        .bind(supplier.lead_time_days)
+        .bind(supplier.min_order_value)
+        .bind(&supplier.payment_terms)
+        .bind(&supplier.vat_number)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(supplier)
+    }
+    
+    /// Deletes a supplier that no product or purchase order refers to, including
+    /// deleted products.
+    pub async fn delete_supplier(&self, id: i64) -> Result<(), AppError> {
+        let (products, orders): (i64, i64) = sqlx::query_as(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM products WHERE supplier_id = ?),
+                (SELECT COUNT(*) FROM purchase_orders WHERE supplier_id = ?)
+            "#
+        )
+        .bind(id)
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        if products > 0 || orders > 0 {
+            return Err(AppError::Validation(format!(
+                "supplier {} still has {} products and {} purchase orders", id, products, orders
+            )));
+        }
+        
+        let result = sqlx::query("DELETE FROM suppliers WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+    
+    pub async fn get_supplier_products(&self, id: i64) -> Result<Vec<Product>, AppError> {
+        self.get_supplier(id).await?;
+        let rows = sqlx::query(&format!("{} WHERE supplier_id = ? AND deleted_at IS NULL ORDER BY imported_name", PRODUCT_SELECT))
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await?;
+        
+        Ok(rows.iter().map(product_from_row).collect())
+    }
+    
+    /// Stock value, receipts and orders per supplier. Receipts and orders count from
+    /// `since` when given; stock and open orders are current.
+    pub async fn supplier_spend(&self, since: Option<NaiveDate>) -> Result<Vec<SupplierSpend>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                s.id, s.name, s.currency,
+                (SELECT COUNT(*) FROM products p WHERE p.supplier_id = s.id AND p.deleted_at IS NULL) AS products,
+                (SELECT COALESCE(SUM(l.quantity * l.landed_cost), 0)
+                    FROM lots l JOIN products p ON p.id = l.product_id
+                    WHERE p.supplier_id = s.id AND p.deleted_at IS NULL) AS stock_value,
+                (SELECT COALESCE(SUM(m.quantity), 0)
+                    FROM stock_movements m JOIN products p ON p.id = m.product_id
+                    WHERE p.supplier_id = s.id AND m.movement_type = 'receipt'
+                        AND (?1 IS NULL OR date(m.created_at) >= ?1)) AS received_units,
+                (SELECT COALESCE(SUM(m.quantity * l.landed_cost), 0)
+                    FROM stock_movements m JOIN products p ON p.id = m.product_id JOIN lots l ON l.id = m.lot_id
+                    WHERE p.supplier_id = s.id AND m.movement_type = 'receipt'
+                        AND (?1 IS NULL OR date(m.created_at) >= ?1)) AS received_value,
+                (SELECT COUNT(*) FROM purchase_orders o
+                    WHERE o.supplier_id = s.id AND o.status IN ('approved', 'received')
+                        AND (?1 IS NULL OR date(o.approved_at) >= ?1)) AS orders,
+                (SELECT COALESCE(SUM(ol.quantity * ol.unit_cost), 0)
+                    FROM purchase_order_lines ol JOIN purchase_orders o ON o.id = ol.order_id
+                    WHERE o.supplier_id = s.id AND o.status IN ('approved', 'received')
+                        AND (?1 IS NULL OR date(o.approved_at) >= ?1)) AS ordered_value,
+                (SELECT COALESCE(SUM(ol.quantity * ol.unit_cost), 0)
+                    FROM purchase_order_lines ol JOIN purchase_orders o ON o.id = ol.order_id
+                    WHERE o.supplier_id = s.id AND o.status = 'approved') AS on_order_value
+            FROM suppliers s
+            ORDER BY s.name
+            "#
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let rounding = self.currency.rounding();
+        let value = |row: &SqliteRow, column: &str| rounding.total(row.get::<Money, _>(column).to_decimal());
+        rows
+            .iter()
+            .map(|row| Ok(SupplierSpend {
+                supplier_id: row.get("id"),
+                supplier: row.get("name"),
+                currency: row.get("currency"),
+                products: row.get("products"),
+                stock_value: value(row, "stock_value")?,
+                received_units: row.get("received_units"),
+                received_value: value(row, "received_value")?,
+                orders: row.get("orders"),
+                ordered_value: value(row, "ordered_value")?,
+                on_order_value: value(row, "on_order_value")?,
+            }))
+            .collect()
+    }
+    
+    /// Exchange rates by currency, newest first; all currencies when `currency` is `None`.
+    pub async fn get_exchange_rates(&self, currency: Option<&str>) -> Result<Vec<ExchangeRate>, AppError> {
+        let mut conn = self.pool.acquire().await?;
+        fetch_exchange_rates(&mut conn, currency).await
+    }
+    
+    /// Every stored rate, ready for converting into the base currency.
+    pub async fn exchange_rates(&self) -> Result<ExchangeRates, AppError> {
+        let rates = self.get_exchange_rates(None).await?;
+        Ok(ExchangeRates::new(&self.currency.base, &rates, self.currency.rounding()))
+    }
+    
+    /// Sets a currency's rate from a day on, replacing the rate already set for that day.
+    pub async fn set_exchange_rate(&self, input: ExchangeRateInput) -> Result<ExchangeRate, AppError> {
+        input.validate(&self.currency.base)?;
+        let mut conn = self.pool.acquire().await?;
+        upsert_exchange_rate(&mut conn, &input).await
+    }
+    
+    pub async fn delete_exchange_rate(&self, currency: &str, valid_from: NaiveDate) -> Result<(), AppError> {
+        let result = sqlx::query("DELETE FROM exchange_rates WHERE currency = ? AND valid_from = ?")
+            .bind(currency)
+            .bind(valid_from)
+            .execute(&self.pool)
+            .await?;
+        
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+    
+    /// Writes imported rates in one transaction, replacing rates already set for
+    /// the same currency and day. The transaction is only committed when `commit`
+    /// is set and every row succeeded.
+    pub async fn import_exchange_rates(&self, rows: Vec<RateRow>, commit: bool) -> Result<RateImportReport, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let mut report = RateImportReport::default();
+        
+        for row in rows {
+            if let Err(e) = row.input.validate(&self.currency.base) {
+                report.errors.push(RowError { line: row.line, message: e.to_string() });
+                continue;
+            }
+            upsert_exchange_rate(&mut tx, &row.input).await?;
+            report.rows += 1;
+        }
+        
+        if commit && report.errors.is_empty() {
+            tx.commit().await?;
+            report.committed = true;
+        } else {
+            tx.rollback().await?;
+        }
+        
+        Ok(report)
+    }
+    
+    /// Landed cost and margins of a product in the base currency, at today's rates.
+    pub async fn product_costing(&self, barcode: &str) -> Result<Costing, AppError> {
+        let product = self.get_product_by_barcode(barcode).await?;
+        let rates = self.exchange_rates().await?;
+        currency::costing(&product, &rates, chrono::Local::now().date_naive())
+    }
+    
+    /// Copies the database into a new file at `path`, consistently even while in use.
+    pub async fn backup(&self, path: &str) -> Result<(), AppError> {
+        sqlx::query("VACUUM INTO ?")
+            .bind(path)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    
+    pub async fn restore_product(&self, barcode: &str) -> Result<Product, AppError> {
+        let result = sqlx::query(
+            "UPDATE products SET deleted_at = NULL, updated_at = CURRENT_TIMESTAMP WHERE barcode IN (?, ?) AND deleted_at IS NOT NULL"
+        )
+        .bind(barcode::storage_key(barcode))
+        .bind(barcode.trim())
+        .execute(&self.pool)
+        .await?;
+        
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+        self.get_product_by_barcode(barcode).await
+    }
+}
+
+async fn insert_product(
+    conn: &mut SqliteConnection,
+    mut product: Product,
+    config: &CurrencyConfig,
+    codes: &CodeSchemes,
+    why: &PriceNote<'_>,
+) -> Result<i64, AppError> {
+    set_barcode(&mut product, None)?;
+    // Checked up front, as SQLite would otherwise report a clashing internal code first
+    let taken: Option<i64> = sqlx::query_scalar("SELECT id FROM products WHERE barcode = ?")
+        .bind(&product.barcode)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if taken.is_some() {
+        return Err(AppError::Validation(format!("barcode {} already exists, possibly as a deleted product", product.barcode)));
+    }
+    resolve_supplier(conn, &mut product, config).await?;
+    let scheme = codes.for_new(&product)?;
+    product.code_scheme = scheme.name.clone();
+    product.code_version = scheme.version;
+    let prefix = scheme.sequence_prefix(&product);
+    
+    // A code taken before sequences were kept, or by a product of another
+    // scheme, is skipped rather than reused
+    let mut attempts = 0;
+    let id = loop {
+        product.code_sequence = Some(next_code_sequence(conn, &prefix).await?);
+        product.internal_code = scheme.internal_code(&product);
+        product.alternative_code = scheme.alternative_code(&product);
+        
+        match insert_product_row(conn, &product).await {
+            Ok(id) => break id,
+            Err(e) if unique_violation(&e) == Some("products.alternative_code") && attempts < CODE_ATTEMPTS => attempts += 1,
+            Err(e) => return Err(insert_error(e, &product)),
+        }
+    };
+    product.id = Some(id);
+    record_price_changes(conn, None, &product, why).await?;
+    reconcile_lots(conn, &product, &Posting::new(MovementType::Receipt), config, codes).await?;
+    
+    Ok(id)
+}
+
+/// Hands out the next sequence number for an alternative code prefix. The first
+/// number for a prefix follows the highest one existing codes with it end in.
+async fn next_code_sequence(conn: &mut SqliteConnection, prefix: &str) -> Result<i64, AppError> {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO code_sequences (prefix, last_value)
+        VALUES (?1, 1 + (
+            SELECT COALESCE(MAX(CAST(substr(alternative_code, length(?1) + 1) AS INTEGER)), 0)
+            FROM products
+            WHERE substr(alternative_code, 1, length(?1)) = ?1
+        ))
+        ON CONFLICT (prefix) DO UPDATE SET last_value = last_value + 1
+        RETURNING last_value
+        "#
+    )
+    .bind(prefix)
+    .fetch_one(&mut *conn)
+    .await?;
+    
+    Ok(row.get("last_value"))
+}
+
+/// The column a UNIQUE constraint failed on, e.g. `products.barcode`.
+fn unique_violation(e: &sqlx::Error) -> Option<&str> {
+    match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => db.message().strip_prefix("UNIQUE constraint failed: "),
+        _ => None,
+    }
+}
+
+/// Turns a product's UNIQUE conflicts into messages naming the clashing value.
+fn insert_error(e: sqlx::Error, product: &Product) -> AppError {
+    match unique_violation(&e) {
+        Some("products.barcode") => AppError::Validation(format!("barcode {} already exists", product.barcode)),
+        Some("products.internal_code") => AppError::Validation(format!(
+            "internal code {} is already used by another product; change its batch or expiry", product.internal_code
+        )),
+        Some("products.alternative_code") => AppError::Validation(format!(
+            "no free alternative code after {} tries, last {}", CODE_ATTEMPTS + 1, product.alternative_code
+        )),
+        _ => e.into(),
+    }
+}
+
+async fn insert_product_row(conn: &mut SqliteConnection, product: &Product) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO products (
+            original_name, imported_name, local_name, barcode, barcode_symbology, internal_code, alternative_code,
+            brand, category, weight, origin_country, supplier_id, purchase_price, purchase_currency,
+            wholesale_price, wholesale_currency, retail_price, retail_currency, freight_cost,
+            duty_percent, handling_cost, production_date, expiry_date, batch_id, stock_quantity,
+            min_threshold, case_pack, code_scheme, code_version, code_sequence
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&product.original_name)
+    .bind(&product.imported_name)
+    .bind(&product.local_name)
+    .bind(&product.barcode)
+    .bind(product.barcode_symbology.map(|symbology| symbology.as_str()))
+    .bind(&product.internal_code)
+    .bind(&product.alternative_code)
+    .bind(&product.brand)
+    .bind(&product.category)
+    .bind(&product.weight)
+    .bind(&product.origin_country)
+    .bind(product.supplier_id)
+    .bind(product.purchase_price)
+    .bind(&product.purchase_currency)
+    .bind(product.wholesale_price)
+    .bind(&product.wholesale_currency)
+    .bind(product.retail_price)
+    .bind(&product.retail_currency)
+    .bind(product.freight_cost)
+    .bind(product.duty_percent)
+    .bind(product.handling_cost)
+    .bind(product.production_date)
+    .bind(product.expiry_date)
+    .bind(product.batch_id)
+    .bind(product.stock_quantity)
+    .bind(product.min_threshold)
+    .bind(product.case_pack)
+    .bind(&product.code_scheme)
+    .bind(product.code_version)
+    .bind(product.code_sequence)
+    .execute(&mut *conn)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+/// Writes every field of an existing product after validating it, as every edit
+/// path goes through here. The codes, code scheme and sequence stay as they were;
+/// with `regenerate_code` the internal code is rebuilt from the edited fields.
+async fn update_product_row(
+    conn: &mut SqliteConnection,
+    product: &mut Product,
+    regenerate_code: bool,
+    config: &CurrencyConfig,
+    codes: &CodeSchemes,
+    why: &PriceNote<'_>,
+) -> Result<(), AppError> {
+    product.validate()?;
+    resolve_supplier(conn, product, config).await?;
+    let previous = sqlx::query(&format!("{} WHERE products.id = ?", PRODUCT_SELECT))
+        .bind(product.id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let previous = product_from_row(&previous);
+    set_barcode(product, Some(&previous))?;
+    product.alternative_code = previous.alternative_code.clone();
+    product.code_scheme = previous.code_scheme.clone();
+    product.code_version = previous.code_version;
+    product.code_sequence = previous.code_sequence;
+    product.internal_code = if regenerate_code {
+        codes.of(product)?.internal_code(product)
+    } else {
+        previous.internal_code.clone()
+    };
+    
+    sqlx::query(
+        r#"
+        UPDATE products SET
+            original_name = ?, imported_name = ?, local_name = ?, barcode = ?, barcode_symbology = ?, internal_code = ?,
+            brand = ?, category = ?, weight = ?, origin_country = ?, supplier_id = ?, purchase_price = ?,
+            purchase_currency = ?, wholesale_price = ?, wholesale_currency = ?, retail_price = ?,
+            retail_currency = ?, freight_cost = ?, duty_percent = ?, handling_cost = ?, production_date = ?,
+            expiry_date = ?, batch_id = ?, stock_quantity = ?, min_threshold = ?, case_pack = ?,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE id = ?
+        "#
+    )
+    .bind(&product.original_name)
+    .bind(&product.imported_name)
+    .bind(&product.local_name)
+    .bind(&product.barcode)
+    .bind(product.barcode_symbology.map(|symbology| symbology.as_str()))
+    .bind(&product.internal_code)
+    .bind(&product.brand)
+    .bind(&product.category)
+    .bind(&product.weight)
+    .bind(&product.origin_country)
+    .bind(product.supplier_id)
+    .bind(product.purchase_price)
+    .bind(&product.purchase_currency)
+    .bind(product.wholesale_price)
+    .bind(&product.wholesale_currency)
+    .bind(product.retail_price)
+    .bind(&product.retail_currency)
+    .bind(product.freight_cost)
+    .bind(product.duty_percent)
+    .bind(product.handling_cost)
+    .bind(product.production_date)
+    .bind(product.expiry_date)
+    .bind(product.batch_id)
+    .bind(product.stock_quantity)
+    .bind(product.min_threshold)
+    .bind(product.case_pack)
+    .bind(product.id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| insert_error(e, product))?;
+    
+    record_price_changes(conn, Some(&previous), product, why).await?;
+    let edit = Posting { reason: Some(PRODUCT_EDIT_REASON), ..Posting::new(MovementType::Adjustment) };
+    reconcile_lots(conn, product, &edit, config, codes).await
+}
+
+/// Stores a new or changed barcode as a GTIN-14, refusing one with a wrong check
+/// digit. A barcode that is the same as `previous`'s, however it is typed, stays
+/// as stored, so products from before barcodes were checked can still be edited.
+fn set_barcode(product: &mut Product, previous: Option<&Product>) -> Result<(), AppError> {
+    if let Some(previous) = previous.filter(|previous| barcode::storage_key(&product.barcode) == barcode::storage_key(&previous.barcode)) {
+        product.barcode = previous.barcode.clone();
+        product.barcode_symbology = previous.barcode_symbology;
+        return Ok(());
+    }
+    let gtin = Gtin::parse(&product.barcode)?;
+    product.barcode = gtin.gtin14;
+    product.barcode_symbology = Some(gtin.symbology);
+    Ok(())
+}
+
+/// Points the product at its supplier: the one with `supplier_id` if set, otherwise
+/// the one whose name matches `supplier`, which is added if there is none yet.
+/// Either way `supplier` ends up as the supplier's own spelling of the name. Prices
+/// without a currency get the supplier's currency for the purchase price and the
+/// base currency for the selling prices.
+async fn resolve_supplier(conn: &mut SqliteConnection, product: &mut Product, config: &CurrencyConfig) -> Result<(), AppError> {
+    let row = match product.supplier_id {
+        Some(id) => sqlx::query("SELECT id, name, currency FROM suppliers WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .ok_or_else(|| AppError::Validation(format!("supplier {} does not exist", id)))?,
+        None => {
+            let key = Supplier::name_key(&product.supplier);
+            if key.is_empty() {
+                return Err(AppError::Validation("supplier is required".to_string()));
+            }
+            let existing = sqlx::query("SELECT id, name, currency FROM suppliers WHERE name_key = ?")
+                .bind(&key)
+                .fetch_optional(&mut *conn)
+                .await?;
+            match existing {
+                Some(row) => row,
+                None => {
+                    log::info!("adding supplier {:?}", product.supplier.trim());
+                    sqlx::query("INSERT INTO suppliers (name, name_key, currency) VALUES (?, ?, ?) RETURNING id, name, currency")
+                        .bind(product.supplier.trim())
+                        .bind(&key)
+                        .bind(&config.base)
+                        .fetch_one(&mut *conn)
+                        .await?
+                }
+            }
+        }
+    };
+    
+    product.supplier_id = Some(row.get("id"));
+    product.supplier = row.get("name");
+    if product.purchase_currency.is_empty() {
+        product.purchase_currency = row.get("currency");
+    }
+    for currency in [&mut product.wholesale_currency, &mut product.retail_currency] {
+        if currency.is_empty() {
+            *currency = config.base.clone();
+        }
+    }
+    Ok(())
+}
+
+/// Brings the product's lots in line with its `stock_quantity` through the ledger.
+/// Extra stock goes into the lot for the product's current batch, which is opened
+/// at today's landed cost if needed; missing stock is taken from the lots that
+/// expire first.
+async fn reconcile_lots(
+    conn: &mut SqliteConnection,
+    product: &Product,
+    posting: &Posting<'_>,
+    config: &CurrencyConfig,
+    codes: &CodeSchemes,
+) -> Result<(), AppError> {
+    let product_id = product.id.ok_or(AppError::NotFound)?;
+    let lots = fetch_lots(conn, product_id).await?;
+    let total: i32 = lots.iter().map(|lot| lot.quantity).sum();
+    let mut difference = product.stock_quantity - total;
+    
+    if difference > 0 {
+        let lot_id = match lots.iter().find(|lot| lot.batch_id == product.batch_id) {
+            Some(lot) => lot.id.ok_or(AppError::NotFound)?,
+            None => {
+                let rates = ExchangeRates::new(&config.base, &fetch_exchange_rates(conn, None).await?, config.rounding());
+                let landed_cost = currency::landed_cost(product, &rates, chrono::Local::now().date_naive())?;
+                let lot = LotInput {
+                    batch_id: product.batch_id,
+                    quantity: 0,
+                    production_date: product.production_date,
+                    expiry_date: product.expiry_date,
+                    supplier_invoice: None,
+                    landed_cost: landed_cost.total,
+                };
+                insert_lot(conn, product, &lot, &codes.of(product)?.lot_code(product, &lot)).await?
+            }
+        };
+        post_movement(conn, product_id, lot_id, difference, posting).await?;
+    }
+    
+    for lot in &lots {
+        if difference >= 0 {
+            break;
+        }
+        let taken = lot.quantity.min(-difference);
+        if taken > 0 {
+            post_movement(conn, product_id, lot.id.ok_or(AppError::NotFound)?, -taken, posting).await?;
+            difference += taken;
+        }
+    }
+    
+    Ok(())
+}
+
+/// Creates an empty lot; stock only enters it through `post_movement`.
+async fn insert_lot(conn: &mut SqliteConnection, product: &Product, lot: &LotInput, lot_code: &str) -> Result<i64, AppError> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO lots (
+            product_id, batch_id, lot_code, quantity, production_date, expiry_date,
+            supplier_invoice, landed_cost
+        )
+        VALUES (?, ?, ?, 0, ?, ?, ?, ?)
+        "#
+    )
+    .bind(product.id)
+    .bind(lot.batch_id)
+    .bind(lot_code)
+    .bind(lot.production_date)
+    .bind(lot.expiry_date)
+    .bind(&lot.supplier_invoice)
+    .bind(lot.landed_cost)
+    .execute(&mut *conn)
+    .await?;
+    
+    Ok(result.last_insert_rowid())
+}
+
+/// Why prices are being written, for the price history.
+struct PriceNote<'a> {
+    source: PriceSource,
+    rule_id: Option<&'a str>,
+    note: &'a ChangeNote,
+}
+
+impl<'a> PriceNote<'a> {
+    fn new(source: PriceSource, note: &'a ChangeNote) -> Self {
+        Self { source, rule_id: None, note }
+    }
+}
+
+/// Adds a history entry for each of `product`'s prices that differs from
+/// `previous`, or for every price when there is no previous version.
+async fn record_price_changes(
+    conn: &mut SqliteConnection,
+    previous: Option<&Product>,
+    product: &Product,
+    why: &PriceNote<'_>,
+) -> Result<(), AppError> {
+    let product_id = product.id.ok_or(AppError::NotFound)?;
+    for price in [PriceKind::Purchase, PriceKind::Wholesale, PriceKind::Retail] {
+        let old = previous.map(|previous| price.of(previous));
+        let new = price.of(product);
+        if old != Some(new) {
+            insert_price_change(conn, product_id, price, old, new, why).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn insert_price_change(
+    conn: &mut SqliteConnection,
+    product_id: i64,
+    price: PriceKind,
+    old: Option<(Money, &str)>,
+    new: (Money, &str),
+    why: &PriceNote<'_>,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO price_history (
+            product_id, price, old_amount, old_currency, new_amount, new_currency, source, rule_id,
+            reason, changed_by
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(product_id)
+    .bind(price.as_str())
+    .bind(old.map(|(amount, _)| amount))
+    .bind(old.map(|(_, currency)| currency))
+    .bind(new.0)
+    .bind(new.1)
+    .bind(why.source.as_str())
+    .bind(why.rule_id)
+    .bind(&why.note.reason)
+    .bind(&why.note.changed_by)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// What a ledger entry records besides the product, lot and quantity.
+struct Posting<'a> {
+    movement_type: MovementType,
+    reason: Option<&'a str>,
+    reference: Option<&'a str>,
+    note: Option<&'a str>,
+}
+
+impl Posting<'_> {
+    fn new(movement_type: MovementType) -> Self {
+        Self { movement_type, reason: None, reference: None, note: None }
+    }
+}
+
+/// Appends a ledger entry and applies its signed quantity to the lot.
+async fn post_movement(
+    conn: &mut SqliteConnection,
+    product_id: i64,
+    lot_id: i64,
+    quantity: i32,
+    posting: &Posting<'_>,
+) -> Result<i64, AppError> {
+    sqlx::query("UPDATE lots SET quantity = quantity + ? WHERE id = ?")
+        .bind(quantity)
+        .bind(lot_id)
+        .execute(&mut *conn)
+        .await?;
+    
+    let result = sqlx::query(
+        r#"
+        INSERT INTO stock_movements (product_id, lot_id, movement_type, quantity, reason, reference, note)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(product_id)
+    .bind(lot_id)
+    .bind(posting.movement_type.as_str())
+    .bind(quantity)
+    .bind(posting.reason)
+    .bind(posting.reference)
+    .bind(posting.note)
+    .execute(&mut *conn)
+    .await?;
+    
+    // Sales leave stock as negative quantities; returns bring it back
+    if matches!(posting.movement_type, MovementType::Sale | MovementType::Return) {
+        sqlx::query(
+            r#"
+            INSERT INTO daily_sales (product_id, sale_date, source, quantity)
+            VALUES (?, date('now', 'localtime'), ?, ?)
+            ON CONFLICT (product_id, sale_date, source) DO UPDATE SET quantity = quantity + excluded.quantity
+            "#
+        )
+        .bind(product_id)
+        .bind(SalesSource::Ledger.as_str())
+        .bind(-quantity)
+        .execute(&mut *conn)
+        .await?;
+    }
+    
+    Ok(result.last_insert_rowid())
+}
+
+async fn fetch_movements(conn: &mut SqliteConnection, product_id: i64) -> Result<Vec<StockMovement>, AppError> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.*, lots.lot_code FROM stock_movements m
+        LEFT JOIN lots ON lots.id = m.lot_id
+        WHERE m.product_id = ?
+        ORDER BY m.id
+        "#
+    )
+    .bind(product_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    
+    rows.iter()
+        .map(|row| {
+            Ok(StockMovement {
+                id: row.get("id"),
+                product_id: row.get("product_id"),
+                lot_id: row.get("lot_id"),
+                lot_code: row.get("lot_code"),
+                movement_type: row.get::<String, _>("movement_type").parse()?,
+                quantity: row.get("quantity"),
+                reason: row.get("reason"),
+                reference: row.get("reference"),
+                note: row.get("note"),
+                created_at: row.get("created_at"),
+            })
+        })
+        .collect()
+}
+
+/// Lots of one product, earliest expiry first.
+/// `Database::get_product_by_barcode` on a connection, e.g. inside a transaction.
+async fn fetch_product_by_barcode(conn: &mut SqliteConnection, barcode: &str) -> Result<Product, AppError> {
+    let sql = format!("{} WHERE products.barcode IN (?, ?) AND deleted_at IS NULL", PRODUCT_SELECT);
+    let row = sqlx::query(&sql)
+        .bind(barcode::storage_key(barcode))
+        .bind(barcode.trim())
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    
+    Ok(product_from_row(&row))
+}
+
+async fn fetch_purchase_order(conn: &mut SqliteConnection, id: i64, rounding: Rounding) -> Result<PurchaseOrder, AppError> {
+    let row = sqlx::query(&format!("{} WHERE purchase_orders.id = ?", ORDER_SELECT))
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let line_rows = sqlx::query(
+        r#"
+        SELECT l.*, p.barcode, p.imported_name, p.case_pack
+        FROM purchase_order_lines l
+        JOIN products p ON p.id = l.product_id
+        WHERE l.order_id = ?
+        ORDER BY p.imported_name
+        "#
+    )
+    .bind(id)
+    .fetch_all(&mut *conn)
+    .await?;
+    
+    let mut order = purchase_order_from_row(&row)?;
+    order.lines = line_rows.iter().map(order_line_from_row).collect();
+    total_order(&mut order, rounding)?;
+    Ok(order)
+}
+
+async fn fetch_lots(conn: &mut SqliteConnection, product_id: i64) -> Result<Vec<Lot>, AppError> {
+    let rows = sqlx::query("SELECT * FROM lots WHERE product_id = ? ORDER BY expiry_date, id")
+        .bind(product_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    
+    Ok(rows.iter().map(lot_from_row).collect())
+}
+
+async fn fetch_exchange_rates(conn: &mut SqliteConnection, currency: Option<&str>) -> Result<Vec<ExchangeRate>, AppError> {
+    let rows = sqlx::query("SELECT * FROM exchange_rates WHERE ?1 IS NULL OR currency = ?1 ORDER BY currency, valid_from DESC")
+        .bind(currency)
+        .fetch_all(&mut *conn)
+        .await?;
+    
+    Ok(rows.iter().map(exchange_rate_from_row).collect())
+}
+
+async fn upsert_exchange_rate(conn: &mut SqliteConnection, input: &ExchangeRateInput) -> Result<ExchangeRate, AppError> {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO exchange_rates (currency, rate, valid_from, source)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT (currency, valid_from) DO UPDATE SET
+            rate = excluded.rate, source = excluded.source, created_at = CURRENT_TIMESTAMP
+        RETURNING *
+        "#
+    )
+    .bind(&input.currency)
+    .bind(input.rate)
+    .bind(input.valid_from)
+    .bind(&input.source)
+    .fetch_one(&mut *conn)
+    .await?;
+    
+    Ok(exchange_rate_from_row(&row))
+}
+
+/// Recomputes `products.stock_quantity` as the sum of the product's lots.
+async fn sync_stock(conn: &mut SqliteConnection, product_id: i64) -> Result<(), AppError> {
+    sqlx::query(
+        "UPDATE products SET stock_quantity = (SELECT COALESCE(SUM(quantity), 0) FROM lots WHERE product_id = ?) WHERE id = ?"
+    )
+    .bind(product_id)
+    .bind(product_id)
+    .execute(&mut *conn)
+    .await?;
+    
+    Ok(())
+}
+
+fn exchange_rate_from_row(row: &SqliteRow) -> ExchangeRate {
+    ExchangeRate {
+        id: row.get("id"),
+        currency: row.get("currency"),
+        rate: row.get("rate"),
+        valid_from: row.get("valid_from"),
+        source: row.get("source"),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn lot_from_row(row: &SqliteRow) -> Lot {
+    Lot {
+        id: Some(row.get("id")),
+        product_id: row.get("product_id"),
+        batch_id: row.get("batch_id"),
+        lot_code: row.get("lot_code"),
+        quantity: row.get("quantity"),
+        production_date: row.get("production_date"),
+        expiry_date: row.get("expiry_date"),
+        supplier_invoice: row.get("supplier_invoice"),
+        landed_cost: row.get("landed_cost"),
+    }
+}
+
+fn daily_sales_from_row(row: &SqliteRow) -> Result<DailySales, AppError> {
+    Ok(DailySales {
+        product_id: row.get("product_id"),
+        sale_date: row.get("sale_date"),
+        source: row.get::<String, _>("source").parse()?,
+        quantity: row.get("quantity"),
+    })
+}
+
+fn tracked_alert_from_row(row: &SqliteRow) -> Result<TrackedAlert, AppError> {
+    Ok(TrackedAlert {
+        id: row.get("id"),
+        fingerprint: row.get("fingerprint"),
+        rule_id: row.get("rule_id"),
+        alert_type: row.get::<String, _>("alert_type").parse()?,
+        severity: row.get::<String, _>("severity").parse()?,
+        barcode: row.get("barcode"),
+        product_name: row.get("product_name"),
+        lot_code: row.get("lot_code"),
+        message: row.get("message"),
+        status: row.get::<String, _>("status").parse()?,
+        snoozed_until: row.get("snoozed_until"),
+        assignee: row.get("assignee"),
+        first_seen_at: row.get("first_seen_at"),
+        last_seen_at: row.get("last_seen_at"),
+        resolved_at: row.get("resolved_at"),
+        notes: Vec::new(),
+    })
+}
+
+fn job_run_from_row(row: &SqliteRow) -> Result<JobRun, AppError> {
+    Ok(JobRun {
+        id: row.get("id"),
+        job: row.get::<String, _>("job").parse()?,
+        triggered_by: row.get("triggered_by"),
+        status: row.get::<String, _>("status").parse()?,
+        output: row.get("output"),
+        error: row.get("error"),
+        started_at: row.get("started_at"),
+        finished_at: row.get("finished_at"),
+    })
+}
+
+fn supplier_from_row(row: &SqliteRow) -> Supplier {
+    Supplier {
+        id: Some(row.get("id")),
+        name: row.get("name"),
+        contact_name: row.get("contact_name"),
+        email: row.get("email"),
+        phone: row.get("phone"),
+        address: row.get("address"),
+        country: row.get("country"),
+        currency: row.get("currency"),
+        lead_time_days: row.get("lead_time_days"),
+        min_order_value: row.get("min_order_value"),
+        payment_terms: row.get("payment_terms"),
+        vat_number: row.get("vat_number"),
+    }
+}
+
+fn purchase_order_from_row(row: &SqliteRow) -> Result<PurchaseOrder, AppError> {
+    Ok(PurchaseOrder {
+        id: row.get("id"),
+        supplier_id: row.get("supplier_id"),
+        supplier: row.get("supplier"),
+        currency: row.get("currency"),
+        min_order_value: row.get("min_order_value"),
+        status: row.get::<String, _>("status").parse()?,
+        notes: row.get("notes"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+        approved_at: row.get("approved_at"),
+        lines: Vec::new(),
+        total: Money::ZERO,
+    })
+}
+
+fn order_line_from_row(row: &SqliteRow) -> PurchaseOrderLine {
+    PurchaseOrderLine {
+        barcode: row.get("barcode"),
+        product_name: row.get("imported_name"),
+        quantity: row.get("quantity"),
+        case_pack: row.get("case_pack"),
+        unit_cost: row.get("unit_cost"),
+        suggested_quantity: row.get("suggested_quantity"),
+    }
+}
+
+/// Sets the order total once its lines are loaded.
+fn total_order(order: &mut PurchaseOrder, rounding: Rounding) -> Result<(), AppError> {
+    let totals = order.lines.iter().map(PurchaseOrderLine::total).collect::<Result<Vec<_>, _>>()?;
+    order.total = rounding.total(Money::checked_sum(totals)?.to_decimal())?;
+    Ok(())
+}
+
+fn price_change_from_row(row: &SqliteRow) -> Result<PriceChange, AppError> {
+    Ok(PriceChange {
+        id: row.get("id"),
+        barcode: row.get("barcode"),
+        price: row.get::<String, _>("price").parse()?,
+        old_amount: row.get("old_amount"),
+        old_currency: row.get("old_currency"),
+        new_amount: row.get("new_amount"),
+        new_currency: row.get("new_currency"),
+        source: row.get::<String, _>("source").parse()?,
+        rule_id: row.get("rule_id"),
+        reason: row.get("reason"),
+        changed_by: row.get("changed_by"),
+        changed_at: row.get("changed_at"),
+    })
+}
+
+fn alert_note_from_row(row: &SqliteRow) -> AlertNote {
+    AlertNote {
+        id: row.get("id"),
+        note: row.get("note"),
+        author: row.get("author"),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn product_from_row(row: &SqliteRow) -> Product {
+    Product {
+        id: Some(row.get("id")),
+        original_name: row.get("original_name"),
+        imported_name: row.get("imported_name"),
+        local_name: row.get("local_name"),
+        barcode: row.get("barcode"),
+        barcode_symbology: row.get::<Option<String>, _>("barcode_symbology").and_then(|symbology| symbology.parse().ok()),
+        internal_code: row.get("internal_code"),
+        alternative_code: row.get("alternative_code"),
+        brand: row.get("brand"),
+        category: row.get("category"),
+        weight: row.get("weight"),
+        origin_country: row.get("origin_country"),
+        supplier: row.get("supplier"),
+        supplier_id: row.get("supplier_id"),
+        purchase_price: row.get("purchase_price"),
+        purchase_currency: row.get("purchase_currency"),
+        wholesale_price: row.get("wholesale_price"),
+        wholesale_currency: row.get("wholesale_currency"),
+        retail_price: row.get("retail_price"),
+        retail_currency: row.get("retail_currency"),
+        freight_cost: row.get("freight_cost"),
+        duty_percent: row.get("duty_percent"),
+        handling_cost: row.get("handling_cost"),
+        production_date: row.get("production_date"),
+        expiry_date: row.get("expiry_date"),
+        batch_id: row.get("batch_id"),
+        stock_quantity: row.get("stock_quantity"),
+        min_threshold: row.get("min_threshold"),
+        case_pack: row.get("case_pack"),
+        code_scheme: row.get("code_scheme"),
+        code_version: row.get("code_version"),
+        code_sequence: row.get("code_sequence"),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::alert_rules::AlertType;
+    
+    /// An empty, migrated database in memory. It has a single connection, as
+    /// every connection to `sqlite::memory:` opens a database of its own.
+    pub(crate) async fn memory_db() -> Database {
+        let config = DatabaseConfig {
+            url: "sqlite::memory:".to_string(),
+            max_connections: 1,
+            wal: false,
+            busy_timeout_ms: 5000,
+        };
+        Database::new(&config, &CurrencyConfig::default(), &CodesConfig::default()).await.unwrap()
+    }
+    
+    /// A database file in `dir` with WAL and several connections, so transactions
+    /// on different connections really compete for the write lock.
+    pub(crate) fn file_db_config(dir: &std::path::Path) -> DatabaseConfig {
+        DatabaseConfig {
+            url: format!("sqlite:{}", dir.join("products.db").display()),
+            max_connections: 4,
+            wal: true,
+            busy_timeout_ms: 5000,
+        }
+    }
+    
+    pub(crate) async fn file_db(dir: &std::path::Path) -> Database {
+        Database::new(&file_db_config(dir), &CurrencyConfig::default(), &CodesConfig::default()).await.unwrap()
+    }
+    
+    /// A valid product with 24 units in stock.
+    pub(crate) fn chickpeas(barcode: &str) -> Product {
+        ProductPatch {
+            original_name: Some("حمص".to_string()),
+            imported_name: Some("Chickpeas".to_string()),
+            barcode: Some(barcode.to_string()),
+            brand: Some("Al-Wadi".to_string()),
+            category: Some("Legumes".to_string()),
+            weight: Some("900g".to_string()),
+            origin_country: Some("Lebanon".to_string()),
+            supplier: Some("Beirut Foods".to_string()),
+            retail_price: Some("29.90".parse().unwrap()),
+            production_date: NaiveDate::from_ymd_opt(2026, 1, 15),
+            expiry_date: NaiveDate::from_ymd_opt(2027, 12, 31),
+            stock_quantity: Some(24),
+            ..ProductPatch::default()
+        }
+        .into_product()
+        .unwrap()
+    }
+    
+    fn note() -> ChangeNote {
+        ChangeNote::default()
+    }
+    
+    #[tokio::test]
+    async fn update_rejects_an_invalid_product() {
+        let db = memory_db().await;
+        db.add_product(chickpeas("5281234567896"), &note()).await.unwrap();
+        
+        let mut product = db.get_product_by_barcode("5281234567896").await.unwrap();
+        product.stock_quantity = -5;
+        product.case_pack = 0;
+        product.expiry_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let error = db.update_product("5281234567896", product, false, &note()).await.unwrap_err().to_string();
+        assert!(error.contains("stock_quantity must not be negative"), "{}", error);
+        assert!(error.contains("case_pack must be at least 1"), "{}", error);
+        assert!(error.contains("expiry_date must not be before production_date"), "{}", error);
+        
+        let stored = db.get_product_by_barcode("5281234567896").await.unwrap();
+        assert_eq!(stored.stock_quantity, 24);
+        assert_eq!(stored.case_pack, 1);
+        assert_eq!(db.get_lots("5281234567896").await.unwrap()[0].quantity, 24);
+    }
+    
+    #[tokio::test]
+    async fn patch_rejects_an_invalid_price_or_barcode() {
+        let db = memory_db().await;
+        db.add_product(chickpeas("5281234567896"), &note()).await.unwrap();
+        
+        let negative = ProductPatch { retail_price: Some("-3".parse().unwrap()), ..ProductPatch::default() };
+        let error = db.patch_product("5281234567896", negative, false, &note()).await.unwrap_err().to_string();
+        assert!(error.contains("retail_price must not be negative"), "{}", error);
+        
+        let mistyped = ProductPatch { barcode: Some("5281234567890".to_string()), ..ProductPatch::default() };
+        let error = db.patch_product("5281234567896", mistyped, false, &note()).await.unwrap_err().to_string();
+        assert!(error.contains("wrong check digit"), "{}", error);
+        
+        let stored = db.get_product_by_barcode("5281234567896").await.unwrap();
+        assert_eq!(stored.retail_price, "29.90".parse().unwrap());
+    }
+    
+    #[tokio::test]
+    async fn concurrent_edits_both_land() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = file_db(dir.path()).await;
+        db.add_product(chickpeas("5281234567896"), &note()).await.unwrap();
+        
+        let price = ProductPatch { retail_price: Some("31.50".parse().unwrap()), ..ProductPatch::default() };
+        let case = ProductPatch { case_pack: Some(6), ..ProductPatch::default() };
+        let note = note();
+        let (a, b) = tokio::join!(
+            db.patch_product("5281234567896", price, false, &note),
+            db.patch_product("5281234567896", case, false, &note),
+        );
+        a.unwrap();
+        b.unwrap();
+        
+        let stored = db.get_product_by_barcode("5281234567896").await.unwrap();
+        assert_eq!((stored.retail_price, stored.case_pack), ("31.50".parse().unwrap(), 6));
+    }
+    
+    #[tokio::test]
+    async fn edits_keep_the_internal_code_unless_asked_to_regenerate_it() {
+        let db = memory_db().await;
+        db.add_product(chickpeas("5281234567896"), &note()).await.unwrap();
+        let added = db.get_product_by_barcode("5281234567896").await.unwrap();
+        
+        let relabelled = || ProductPatch {
+            batch_id: Some(7),
+            expiry_date: NaiveDate::from_ymd_opt(2028, 6, 30),
+            ..ProductPatch::default()
+        };
+        let kept = db.patch_product("5281234567896", relabelled(), false, &note()).await.unwrap();
+        assert_eq!(kept.internal_code, added.internal_code);
+        assert_eq!(kept.batch_id, 7);
+        assert_eq!(db.get_product_by_internal_code(&added.internal_code).await.unwrap().id, added.id);
+        
+        let regenerated = db.patch_product("5281234567896", relabelled(), true, &note()).await.unwrap();
+        assert_ne!(regenerated.internal_code, added.internal_code);
+        assert!(regenerated.internal_code.contains("-007-202806-"), "{}", regenerated.internal_code);
+        assert_eq!(regenerated.alternative_code, added.alternative_code);
+        assert_eq!(db.get_product_by_internal_code(&regenerated.internal_code).await.unwrap().id, added.id);
+    }
+    
+    fn lot(batch_id: i32, quantity: i32, expiry_date: NaiveDate) -> LotInput {
+        LotInput {
+            batch_id,
+            quantity,
+            production_date: expiry_date - chrono::Duration::days(365),
+            expiry_date,
+            supplier_invoice: None,
+            landed_cost: Money::ZERO,
+        }
+    }
+    
+    fn movement(movement_type: MovementType, quantity: i32, reason: Option<&str>) -> MovementInput {
+        MovementInput {
+            movement_type,
+            quantity,
+            batch_id: None,
+            to_batch_id: None,
+            reason: reason.map(str::to_string),
+            reference: None,
+            note: None,
+        }
+    }
+    
+    /// Quantity left in each batch, earliest expiry first.
+    async fn lot_quantities(db: &Database, barcode: &str) -> Vec<(i32, i32)> {
+        db.get_lots(barcode).await.unwrap().iter().map(|lot| (lot.batch_id, lot.quantity)).collect()
+    }
+    
+    #[tokio::test]
+    async fn sales_take_the_earliest_unexpired_lot_first() {
+        let db = memory_db().await;
+        let today = chrono::Local::now().date_naive();
+        db.add_product(chickpeas("5281234567896"), &note()).await.unwrap();
+        db.add_lot("5281234567896", lot(2, 5, today - chrono::Duration::days(3))).await.unwrap();
+        db.add_lot("5281234567896", lot(3, 10, today + chrono::Duration::days(30))).await.unwrap();
+        db.add_lot("5281234567896", lot(4, 10, today)).await.unwrap();
+        
+        let sale = db.record_movement("5281234567896", movement(MovementType::Sale, 15, None)).await.unwrap();
+        assert_eq!(sale.iter().map(|m| m.quantity).collect::<Vec<_>>(), vec![-10, -5]);
+        assert_eq!(lot_quantities(&db, "5281234567896").await, vec![(2, 5), (4, 0), (3, 5), (1, 24)]);
+        
+        // Only 29 units have not expired; the expired lot is not sold from
+        let error = db.record_movement("5281234567896", movement(MovementType::Sale, 30, None)).await.unwrap_err();
+        assert!(error.to_string().contains("only 29 of 30 units in unexpired stock"), "{}", error);
+        
+        // Write-offs still take the expired lot first
+        db.record_movement("5281234567896", movement(MovementType::WriteOff, 7, Some("expired"))).await.unwrap();
+        assert_eq!(lot_quantities(&db, "5281234567896").await, vec![(2, 0), (4, 0), (3, 3), (1, 24)]);
+        
+        let product = db.get_product_by_barcode("5281234567896").await.unwrap();
+        assert_eq!(product.stock_quantity, 27);
+        assert!(db.check_ledger().await.unwrap().is_empty());
+        let sold: i32 = db.get_sales("5281234567896").await.unwrap().iter().map(|day| day.quantity).sum();
+        assert_eq!(sold, 15);
+    }
+    
+    #[tokio::test]
+    async fn concurrent_sales_wait_for_each_other() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = file_db(dir.path()).await;
+        db.add_product(chickpeas("5281234567896"), &note()).await.unwrap();
+        
+        let sale = || db.record_movement("5281234567896", movement(MovementType::Sale, 10, None));
+        let (a, b, c) = tokio::join!(sale(), sale(), sale());
+        let results = [a, b, c];
+        
+        let errors: Vec<String> = results.into_iter().filter_map(|result| result.err()).map(|e| e.to_string()).collect();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("only 4 of 10 units"), "{}", errors[0]);
+        assert_eq!(lot_quantities(&db, "5281234567896").await, vec![(1, 4)]);
+        assert!(db.check_ledger().await.unwrap().is_empty());
+    }
+    
+    /// An empty draft order from the product's supplier, in its purchase currency.
+    async fn draft_order(db: &Database, barcode: &str) -> i64 {
+        let product = db.get_product_by_barcode(barcode).await.unwrap();
+        sqlx::query("INSERT INTO purchase_orders (supplier_id, currency) VALUES (?, ?)")
+            .bind(product.supplier_id)
+            .bind(&product.purchase_currency)
+            .execute(&db.pool)
+            .await
+            .unwrap()
+            .last_insert_rowid()
+    }
+    
+    #[tokio::test]
+    async fn orders_are_not_approved_while_their_lines_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = file_db(dir.path()).await;
+        db.add_product(chickpeas("5281234567896"), &note()).await.unwrap();
+        let id = draft_order(&db, "5281234567896").await;
+        db.set_order_line(id, "5281234567896", 12).await.unwrap();
+        
+        let approve = PurchaseOrderUpdate { status: Some(OrderStatus::Approved), notes: None };
+        let (approved, emptied) = tokio::join!(
+            db.update_purchase_order(id, approve),
+            db.set_order_line(id, "5281234567896", 0),
+        );
+        assert!(approved.is_ok() != emptied.is_ok(), "{:?} {:?}", approved, emptied);
+        
+        let order = db.get_purchase_order(id).await.unwrap();
+        match order.status {
+            OrderStatus::Approved => assert_eq!(order.lines.len(), 1),
+            OrderStatus::Draft => assert!(order.lines.is_empty()),
+            status => panic!("order became {}", status.as_str()),
+        }
+        
+        let error = match order.status {
+            OrderStatus::Approved => db.set_order_line(id, "5281234567896", 24).await.unwrap_err(),
+            _ => db.update_purchase_order(id, PurchaseOrderUpdate { status: Some(OrderStatus::Approved), notes: None }).await.unwrap_err(),
+        };
+        assert!(matches!(error, AppError::Validation(_)), "{}", error);
+    }
+    
+    /// Chickpeas from another batch, so its internal code differs but its
+    /// alternative code prefix does not.
+    fn chickpeas_batch(barcode: &str, batch_id: i32) -> Product {
+        Product { batch_id, ..chickpeas(barcode) }
+    }
+    
+    async fn alternative_code(db: &Database, barcode: &str) -> String {
+        db.get_product_by_barcode(barcode).await.unwrap().alternative_code
+    }
+    
+    #[tokio::test]
+    async fn code_sequences_follow_existing_codes_and_count_per_prefix() {
+        let db = memory_db().await;
+        db.add_product(chickpeas_batch("5281234567896", 1), &note()).await.unwrap();
+        let first = alternative_code(&db, "5281234567896").await;
+        let prefix = first.strip_suffix("01").unwrap().to_string();
+        
+        // A database from before sequences were kept: the prefix is seeded from
+        // the highest code already ending in a number
+        sqlx::query("DELETE FROM code_sequences").execute(&db.pool).await.unwrap();
+        sqlx::query("UPDATE products SET alternative_code = ? WHERE alternative_code = ?")
+            .bind(format!("{}41", prefix))
+            .bind(&first)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        db.add_product(chickpeas_batch("8690504000006", 2), &note()).await.unwrap();
+        assert_eq!(alternative_code(&db, "8690504000006").await, format!("{}42", prefix));
+        
+        let beans = Product { brand: "Durra".to_string(), ..chickpeas_batch("5281234567803", 1) };
+        db.add_product(beans, &note()).await.unwrap();
+        let other = alternative_code(&db, "5281234567803").await;
+        assert!(!other.starts_with(&prefix) && other.ends_with("01"), "{}", other);
+        
+        db.add_product(chickpeas_batch("8690504000013", 3), &note()).await.unwrap();
+        assert_eq!(alternative_code(&db, "8690504000013").await, format!("{}43", prefix));
+    }
+    
+    #[tokio::test]
+    async fn code_sequences_skip_a_code_already_taken() {
+        let db = memory_db().await;
+        db.add_product(chickpeas_batch("5281234567896", 1), &note()).await.unwrap();
+        let first = alternative_code(&db, "5281234567896").await;
+        let prefix = first.strip_suffix("01").unwrap().to_string();
+        
+        // Taken outside the sequence, e.g. by a product of another scheme
+        sqlx::query("UPDATE products SET alternative_code = ? WHERE alternative_code = ?")
+            .bind(format!("{}02", prefix))
+            .bind(&first)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        db.add_product(chickpeas_batch("8690504000006", 2), &note()).await.unwrap();
+        assert_eq!(alternative_code(&db, "8690504000006").await, format!("{}03", prefix));
+    }
+    
+    #[tokio::test]
+    async fn concurrent_inserts_with_one_prefix_get_their_own_codes() {
+        // Several pooled connections, so the inserts really compete for the prefix
+        let dir = tempfile::tempdir().unwrap();
+        let db = file_db(dir.path()).await;
+        let note = note();
+        let barcodes = ["5281234567896", "8690504000006", "8690504000013", "5281234567803"];
+        let (a, b, c, d) = tokio::join!(
+            db.add_product(chickpeas_batch(barcodes[0], 1), &note),
+            db.add_product(chickpeas_batch(barcodes[1], 2), &note),
+            db.add_product(chickpeas_batch(barcodes[2], 3), &note),
+            db.add_product(chickpeas_batch(barcodes[3], 4), &note),
+        );
+        for result in [a, b, c, d] {
+            result.unwrap();
+        }
+        
+        let mut codes = Vec::new();
+        for barcode in barcodes {
+            codes.push(alternative_code(&db, barcode).await);
+        }
+        codes.sort();
+        let prefix = codes[0].strip_suffix("01").unwrap();
+        assert_eq!(codes, ["01", "02", "03", "04"].map(|n| format!("{}{}", prefix, n)));
+    }
+    
+    #[tokio::test]
+    async fn gtin_migration_leaves_barcodes_with_a_wrong_check_digit_alone() {
+        let db = memory_db().await;
+        for (barcode, batch_id) in [("5281234567896", 1), ("8690504000006", 2), ("8690504000013", 3)] {
+            db.add_product(chickpeas_batch(barcode, batch_id), &note()).await.unwrap();
+        }
+        assert_eq!(db.migrate_down().await.unwrap(), Some(20261018250000));
+        
+        // Legacy barcodes as typed: a UPC-A that lost its leading zero, and two
+        // with a wrong check digit
+        for (stored, legacy) in [("5281234567896", "36000291452"), ("8690504000006", "5281234567890"), ("8690504000013", "3600029145")] {
+            sqlx::query("UPDATE products SET barcode = ? WHERE barcode = ?").bind(legacy).bind(stored).execute(&db.pool).await.unwrap();
+        }
+        db.migrate_up().await.unwrap();
+        
+        let rows: Vec<(String, Option<String>)> = sqlx::query_as("SELECT barcode, barcode_symbology FROM products ORDER BY id")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, [
+            ("00036000291452".to_string(), Some("upc_a".to_string())),
+            ("5281234567890".to_string(), None),
+            ("3600029145".to_string(), None),
+        ]);
+    }
+    
+    #[tokio::test]
+    async fn gtin_migration_keeps_alerts_with_their_products() {
+        let db = memory_db().await;
+        db.add_product(Product { min_threshold: 100, ..chickpeas("5281234567896") }, &note()).await.unwrap();
+        let (inventory, rules) = (InventoryConfig::default(), RuleSet::default());
+        let low_stock = |alerts: Vec<TrackedAlert>| {
+            let mut alerts: Vec<_> = alerts.into_iter().filter(|alert| alert.alert_type == AlertType::LowStock).collect();
+            assert_eq!(alerts.len(), 1, "{:?}", alerts);
+            alerts.remove(0)
+        };
+        
+        // An alert raised, sent and acknowledged before the upgrade
+        db.refresh_alerts(&inventory, &rules).await.unwrap();
+        let alert = low_stock(db.get_alerts(None).await.unwrap());
+        db.record_deliveries("email", std::slice::from_ref(&alert), false).await.unwrap();
+        db.update_alert(alert.id, AlertUpdate {
+            status: Some(AlertStatus::Acknowledged),
+            assignee: Some("Rana".to_string()),
+            note: Some("Reorder placed".to_string()),
+            ..AlertUpdate::default()
+        }).await.unwrap();
+        assert_eq!(db.migrate_down().await.unwrap(), Some(20261018250000));
+        let legacy = db.get_alert(alert.id).await.unwrap();
+        assert_eq!((legacy.barcode.as_str(), legacy.fingerprint.as_str()), ("5281234567896", "low_stock:5281234567896:"));
+        
+        db.migrate_up().await.unwrap();
+        db.refresh_alerts(&inventory, &rules).await.unwrap();
+        let kept = low_stock(db.get_alerts(None).await.unwrap());
+        assert_eq!(kept.id, alert.id);
+        assert_eq!((kept.barcode.as_str(), kept.fingerprint.as_str()), ("05281234567896", "low_stock:05281234567896:"));
+        assert_eq!((kept.status, kept.assignee.as_deref(), kept.notes.len()), (AlertStatus::Acknowledged, Some("Rana"), 1));
+        
+        // Reopened, it is not sent again at the severity already sent
+        db.update_alert(alert.id, AlertUpdate { status: Some(AlertStatus::Open), ..AlertUpdate::default() }).await.unwrap();
+        let unsent = db.undelivered_alerts("email", Severity::Info).await.unwrap();
+        assert!(unsent.iter().all(|unsent| unsent.id != alert.id), "{:?}", unsent);
+    }
+    
+    #[tokio::test]
+    async fn strict_dates_migration_keeps_the_dates_it_replaces() {
+        let db = memory_db().await;
+        db.add_product(chickpeas("5281234567896"), &note()).await.unwrap();
+        db.add_product(Product { batch_id: 2, ..chickpeas("8690504000006") }, &note()).await.unwrap();
+        while db.migrate_down().await.unwrap().unwrap() != 20261018130000 {}
+        
+        sqlx::query("UPDATE products SET production_date = '15/01/2026', expiry_date = 'end of 2027' WHERE id = 1")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE lots SET expiry_date = '2027-12-31 00:00:00' WHERE product_id = 2")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        db.migrate_up().await.unwrap();
+        
+        // date('now') is in UTC
+        let today = chrono::Utc::now().date_naive().to_string();
+        let repairs: Vec<_> = db.date_repairs().await.unwrap().into_iter()
+            .map(|r| (r.table_name, r.row_id, r.column_name, r.original_value.unwrap(), r.replaced_with.unwrap()))
+            .collect();
+        assert_eq!(repairs, [
+            ("products".to_string(), 1, "production_date".to_string(), "15/01/2026".to_string(), today.clone()),
+            ("products".to_string(), 1, "expiry_date".to_string(), "end of 2027".to_string(), today),
+            ("lots".to_string(), 2, "expiry_date".to_string(), "2027-12-31 00:00:00".to_string(), "2027-12-31".to_string()),
+        ]);
+    }
+    
+    #[tokio::test]
+    async fn suppliers_migration_keys_names_as_the_application_does() {
+        let db = memory_db().await;
+        db.add_product(chickpeas("5281234567896"), &note()).await.unwrap();
+        db.add_product(Product { batch_id: 2, ..chickpeas("8690504000006") }, &note()).await.unwrap();
+        while db.migrate_down().await.unwrap().unwrap() != 20261018190000 {}
+        
+        sqlx::query("UPDATE products SET supplier = 'BEIRUT     Foods   SAL' WHERE id = 2")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        db.migrate_up().await.unwrap();
+        
+        let keys: Vec<String> = sqlx::query_scalar("SELECT name_key FROM suppliers")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(keys, [Supplier::name_key("BEIRUT     Foods   SAL")]);
+        let first = db.get_product_by_barcode("5281234567896").await.unwrap();
+        let second = db.get_product_by_barcode("8690504000006").await.unwrap();
+        assert_eq!(first.supplier_id, second.supplier_id);
+    }
+    
+    #[tokio::test]
+    async fn supplier_spend_leaves_out_deleted_products() {
+        let db = memory_db().await;
+        let purchase_price: Money = "18.00".parse().unwrap();
+        db.add_product(Product { purchase_price, ..chickpeas("5281234567896") }, &note()).await.unwrap();
+        db.add_product(Product { batch_id: 2, purchase_price, ..chickpeas("8690504000006") }, &note()).await.unwrap();
+        let before = db.supplier_spend(None).await.unwrap();
+        
+        db.delete_product("8690504000006").await.unwrap();
+        let after = db.supplier_spend(None).await.unwrap();
+        assert_eq!((before[0].products, after[0].products), (2, 1));
+        assert!(after[0].stock_value > Money::ZERO);
+        assert_eq!(after[0].stock_value.checked_mul(2).unwrap(), before[0].stock_value);
+    }
+}
@@ -1,76 +1,432 @@
-use crate::models::Product;
+use crate::alert_rules::{AlertType, Facts, LotFacts, RuleSet, Severity};
+use crate::config::{InventoryConfig, ReorderConfig};
+use crate::currency::{self, ExchangeRates};
+use crate::models::{AppError, DailySales, Lot, Product, ReorderSuggestion, SalesVelocity, Supplier, Trend, VelocityWindow};
+use crate::money::{Money, Rate};
+use chrono::{Duration, NaiveDate};
+use rust_decimal::Decimal;
 use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+/// How far the short-window rate may drift from the long-window rate and still count as stable.
+const TREND_TOLERANCE: f64 = 0.2;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InventoryAlert {
+    /// Id of the rule that raised the alert
+    pub rule_id: String,
     pub product_name: String,
-    pub alert_type: String,
+    pub barcode: String,
+    pub lot_code: Option<String>,
+    pub alert_type: AlertType,
     pub message: String,
-    pub severity: String,
+    pub severity: Severity,
+}
+
+impl InventoryAlert {
+    /// Identifies the same problem across evaluations, whichever rule or severity raised it.
+    pub fn fingerprint(&self) -> String {
+        format!("{}:{}:{}", self.alert_type.as_str(), self.barcode, self.lot_code.as_deref().unwrap_or(""))
+    }
+}
+
+/// Everything the inventory rules look at, loaded in one go.
+pub struct InventorySnapshot {
+    pub products: Vec<Product>,
+    pub lots: Vec<Lot>,
+    pub sales: Vec<DailySales>,
+    pub suppliers: Vec<Supplier>,
+    pub rates: ExchangeRates,
 }
 
 pub struct InventoryManager;
 
 impl InventoryManager {
-    pub fn check_inventory(products: &[Product]) -> Vec<InventoryAlert> {
+    /// Evaluates the alert rules for every product and each of its lots on the shelf,
+    /// most severe alerts first.
+    pub fn check_inventory(snapshot: &InventorySnapshot, config: &InventoryConfig, rules: &RuleSet) -> Vec<InventoryAlert> {
         let mut alerts = Vec::new();
+        let today = chrono::Local::now().date_naive();
         
-        for product in products {
-            // Check expiry (simplified - just check if expiry is in near future)
-            if Self::is_expiring_soon(&product.expiry_date) {
-                alerts.push(InventoryAlert {
-                    product_name: product.imported_name.clone(),
-                    alert_type: "Expiry Warning".to_string(),
-                    message: format!("Product {} expires soon ({})", product.imported_name, product.expiry_date),
-                    severity: "High".to_string(),
+        for product in &snapshot.products {
+            let sales: Vec<&DailySales> = snapshot.sales.iter().filter(|s| Some(s.product_id) == product.id).collect();
+            let per_day = Self::daily_rate(&sales, today, config.rule_window_days);
+            let mut facts = Facts {
+                product: &product.imported_name,
+                barcode: &product.barcode,
+                category: &product.category,
+                supplier: &product.supplier,
+                stock: product.stock_quantity,
+                min_threshold: product.min_threshold,
+                per_day,
+                cover_days: (per_day > 0.0).then(|| product.stock_quantity as f64 / per_day),
+                lot: None,
+            };
+            
+            let mut raise = |facts: &Facts| {
+                for matched in rules.evaluate(facts) {
+                    alerts.push(InventoryAlert {
+                        rule_id: matched.rule.id.clone(),
+                        product_name: product.imported_name.clone(),
+                        barcode: product.barcode.clone(),
+                        lot_code: matched.lot_code,
+                        alert_type: matched.rule.alert_type,
+                        message: matched.message,
+                        severity: matched.rule.severity,
+                    });
+                }
+            };
+            
+            for lot in snapshot.lots.iter().filter(|lot| Some(lot.product_id) == product.id && lot.quantity > 0) {
+                let (days_left, share_left) = Self::shelf_life_left(lot, today);
+                facts.lot = Some(LotFacts {
+                    lot_code: &lot.lot_code,
+                    quantity: lot.quantity,
+                    expiry_date: lot.expiry_date.to_string(),
+                    days_left,
+                    share_left,
                 });
+                raise(&facts);
             }
             
-            // Check low stock
-            if product.stock_quantity < product.min_threshold {
-                alerts.push(InventoryAlert {
-                    product_name: product.imported_name.clone(),
-                    alert_type: "Low Stock".to_string(),
-                    message: format!("Product {} has low stock: {} units", product.imported_name, product.stock_quantity),
-                    severity: if product.stock_quantity == 0 { "Critical".to_string() } else { "Medium".to_string() },
-                });
+            facts.lot = None;
+            raise(&facts);
+        }
+        
+        alerts.sort_by_key(|alert| std::cmp::Reverse(alert.severity));
+        alerts
+    }
+    
+    /// Products whose unexpired stock plus open orders has fallen below their reorder
+    /// point, with the quantity that tops them up to cover lead time, safety stock and
+    /// the review period, rounded up to whole cases. The product's `min_threshold`
+    /// is the lowest reorder point, and the only one without sales history. Unit
+    /// costs are converted into the supplier's currency at today's rates.
+    pub fn reorder_suggestions(
+        snapshot: &InventorySnapshot,
+        on_order: &HashMap<i64, i32>,
+        config: &InventoryConfig,
+        reorder: &ReorderConfig,
+    ) -> Result<Vec<ReorderSuggestion>, AppError> {
+        let today = chrono::Local::now().date_naive();
+        let mut suggestions = Vec::new();
+        
+        for product in &snapshot.products {
+            let Some(product_id) = product.id else { continue };
+            let sales: Vec<&DailySales> = snapshot.sales.iter().filter(|s| s.product_id == product_id).collect();
+            let per_day = Self::daily_rate(&sales, today, config.rule_window_days);
+            let stock: i32 = snapshot.lots
+                .iter()
+                .filter(|lot| lot.product_id == product_id && lot.expiry_date >= today)
+                .map(|lot| lot.quantity)
+                .sum();
+            let on_order = on_order.get(&product_id).copied().unwrap_or(0);
+            
+            let supplier = snapshot.suppliers
+                .iter()
+                .find(|supplier| supplier.id.is_some() && supplier.id == product.supplier_id);
+            let lead_time_days = supplier
+                .and_then(|supplier| supplier.lead_time_days)
+                .unwrap_or(reorder.default_lead_time_days);
+            let cover = |days: u32| (per_day * days as f64).ceil() as i32;
+            let reorder_point = cover(lead_time_days + reorder.safety_stock_days).max(product.min_threshold);
+            let target_stock = reorder_point + cover(reorder.review_period_days);
+            
+            let position = stock + on_order;
+            if position >= reorder_point {
+                continue;
             }
             
-            // Check waste risk (simplified calculation)
-            if product.monthly_sales > 0 && product.stock_quantity > (product.monthly_sales * 3) {
-                alerts.push(InventoryAlert {
-                    product_name: product.imported_name.clone(),
-                    alert_type: "Waste Risk".to_string(),
-                    message: format!("Product {} has excess stock: {} units", product.imported_name, product.stock_quantity),
-                    severity: "Medium".to_string(),
-                });
+            let case_pack = product.case_pack.max(1);
+            let cases = (target_stock - position + case_pack - 1) / case_pack;
+            let currency = supplier.map_or(product.purchase_currency.clone(), |supplier| supplier.currency.clone());
+            let unit_cost = snapshot.rates.convert(product.purchase_price, &product.purchase_currency, &currency, today)?;
+            suggestions.push(ReorderSuggestion {
+                product_id,
+                barcode: product.barcode.clone(),
+                product_name: product.imported_name.clone(),
+                supplier_id: product.supplier_id.unwrap_or_default(),
+                supplier: product.supplier.clone(),
+                per_day,
+                lead_time_days,
+                stock,
+                on_order,
+                reorder_point,
+                target_stock,
+                case_pack,
+                quantity: cases * case_pack,
+                unit_cost,
+                currency,
+            });
+        }
+        
+        suggestions.sort_by(|a, b| a.supplier.cmp(&b.supplier).then_with(|| a.product_name.cmp(&b.product_name)));
+        Ok(suggestions)
+    }
+    
+    /// Velocity of one product over each window, ending today.
+    pub fn velocity(product: &Product, sales: &[DailySales], windows: &[u32]) -> SalesVelocity {
+        let today = chrono::Local::now().date_naive();
+        let sales: Vec<&DailySales> = sales.iter().filter(|s| Some(s.product_id) == product.id).collect();
+        
+        let mut windows: Vec<VelocityWindow> = windows
+            .iter()
+            .map(|&days| {
+                let units = Self::units_sold(&sales, today, days);
+                VelocityWindow { days, units, per_day: units as f64 / days as f64 }
+            })
+            .collect();
+        windows.sort_by_key(|w| w.days);
+        
+        // Only compare against windows the recorded history fully covers
+        let history_days = sales
+            .iter()
+            .map(|s| s.sale_date)
+            .min()
+            .map_or(0, |first| (today - first).num_days() + 1);
+        let long = windows.iter().rev().find(|w| w.days as i64 <= history_days);
+        
+        let trend = match (windows.first(), long) {
+            (Some(short), Some(long)) if long.units > 0 && long.days > short.days => {
+                let ratio = short.per_day / long.per_day;
+                if ratio > 1.0 + TREND_TOLERANCE {
+                    Trend::Rising
+                } else if ratio < 1.0 - TREND_TOLERANCE {
+                    Trend::Falling
+                } else {
+                    Trend::Stable
+                }
+            }
+            _ => Trend::Insufficient,
+        };
+        
+        SalesVelocity { barcode: product.barcode.clone(), windows, trend }
+    }
+    
+    fn daily_rate(sales: &[&DailySales], today: NaiveDate, days: u32) -> f64 {
+        Self::units_sold(sales, today, days) as f64 / days as f64
+    }
+    
+    /// Units sold in the `days` days up to and including today.
+    fn units_sold(sales: &[&DailySales], today: NaiveDate, days: u32) -> i32 {
+        Self::units_sold_between(sales, today - Duration::days(days as i64 - 1), today)
+    }
+    
+    /// Units sold from `from` to `to`, both included. Where several sources report
+    /// the same day, only the most authoritative one counts.
+    pub fn units_sold_between(sales: &[&DailySales], from: NaiveDate, to: NaiveDate) -> i32 {
+        let mut by_day: HashMap<NaiveDate, &DailySales> = HashMap::new();
+        
+        for entry in sales {
+            if entry.sale_date < from || entry.sale_date > to {
+                continue;
+            }
+            let best = by_day.entry(entry.sale_date).or_insert(entry);
+            if entry.source < best.source {
+                *best = entry;
             }
         }
         
-        alerts
+        by_day.values().map(|entry| entry.quantity).sum()
     }
     
-    fn is_expiring_soon(expiry_date: &str) -> bool {
-        // Simplified check - in real implementation, you'd parse dates properly
-        // For now, just check if the year is 2025 (assuming current year is 2025)
-        expiry_date.starts_with("2025")
+    /// Days until the lot expires (negative once expired) and the share of its
+    /// production-to-expiry shelf life that is left, between 0 and 1.
+    pub fn shelf_life_left(lot: &Lot, today: NaiveDate) -> (i64, f64) {
+        let days_left = (lot.expiry_date - today).num_days();
+        let shelf_life = (lot.expiry_date - lot.production_date).num_days();
+        let share_left = if shelf_life > 0 {
+            (days_left as f64 / shelf_life as f64).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        (days_left, share_left)
     }
+
     
-    pub fn generate_report(products: &[Product]) -> String {
+    /// Stock totals, and the stock's value and retail margin in the base currency at
+    /// today's rates. Products in a currency without a rate are left out of the value
+    /// and counted separately. An error when a value is too large to hold.
+    pub fn generate_report(products: &[Product], rates: &ExchangeRates) -> Result<String, AppError> {
+        let today = chrono::Local::now().date_naive();
         let total_products = products.len();
         let total_stock: i32 = products.iter().map(|p| p.stock_quantity).sum();
         let low_stock_count = products.iter().filter(|p| p.stock_quantity < p.min_threshold).count();
         
-        format!(
+        let (mut cost_value, mut retail_value, mut without_rate) = (Money::ZERO, Money::ZERO, 0);
+        for product in products {
+            match currency::costing(product, rates, today) {
+                Ok(costing) => {
+                    cost_value = cost_value.checked_add(costing.landed_cost.total.checked_mul(product.stock_quantity)?)?;
+                    retail_value = retail_value.checked_add(costing.retail_price.checked_mul(product.stock_quantity)?)?;
+                }
+                Err(_) => without_rate += 1,
+            }
+        }
+        let rounding = rates.rounding();
+        let margin = if retail_value.is_zero() {
+            Rate::ZERO
+        } else {
+            rounding.percent((retail_value.to_decimal() - cost_value.to_decimal()) / retail_value.to_decimal() * Decimal::ONE_HUNDRED)
+        };
+        
+        let mut report = format!(
             "Inventory Report:\n\
             - Total Products: {}\n\
             - Total Stock Units: {}\n\
             - Products with Low Stock: {}\n\
-            - Average Stock per Product: {:.1}",
+            - Average Stock per Product: {:.1}\n\
+            - Stock Value at Landed Cost: {} {}\n\
+            - Stock Value at Retail: {} {}\n\
+            - Retail Margin: {:.1}%",
             total_products,
             total_stock,
             low_stock_count,
-            if total_products > 0 { total_stock as f64 / total_products as f64 } else { 0.0 }
-        )
+            if total_products > 0 { total_stock as f64 / total_products as f64 } else { 0.0 },
+            rounding.total(cost_value.to_decimal())?,
+            rates.base(),
+            rounding.total(retail_value.to_decimal())?,
+            rates.base(),
+            margin
+        );
+        if without_rate > 0 {
+            report.push_str(&format!("\n- Products Without Exchange Rate: {}", without_rate));
+        }
+        Ok(report)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CurrencyConfig;
+    use crate::models::{ProductPatch, SalesSource};
+    
+    fn today() -> NaiveDate {
+        chrono::Local::now().date_naive()
+    }
+    
+    fn product(min_threshold: i32, case_pack: i32) -> Product {
+        let mut product = ProductPatch {
+            imported_name: Some("Chickpeas".to_string()),
+            barcode: Some("05281234567896".to_string()),
+            supplier: Some("Beirut Foods".to_string()),
+            purchase_price: Some("12.50".parse().unwrap()),
+            purchase_currency: Some("SEK".to_string()),
+            expiry_date: Some(today() + Duration::days(365)),
+            min_threshold: Some(min_threshold),
+            case_pack: Some(case_pack),
+            ..ProductPatch::default()
+        }
+        .into_product()
+        .unwrap();
+        product.id = Some(1);
+        product
+    }
+    
+    fn sale(days_ago: i64, source: SalesSource, quantity: i32) -> DailySales {
+        DailySales { product_id: 1, sale_date: today() - Duration::days(days_ago), source, quantity }
+    }
+    
+    /// `base` units a day from `days` days ago until a week ago, then `recent`
+    /// units over the last seven days, spread as evenly as whole units allow.
+    fn history(days: i64, base: i32, recent: i32) -> Vec<DailySales> {
+        let mut sales: Vec<DailySales> = (7..days).map(|days_ago| sale(days_ago, SalesSource::Pos, base)).collect();
+        sales.extend((0..7).map(|days_ago| sale(days_ago, SalesSource::Pos, recent / 7 + i32::from(days_ago < (recent % 7) as i64))));
+        sales
+    }
+    
+    fn lot(quantity: i32, expiry_date: NaiveDate) -> Lot {
+        Lot {
+            id: None,
+            product_id: 1,
+            batch_id: 1,
+            lot_code: String::new(),
+            quantity,
+            production_date: expiry_date - Duration::days(365),
+            expiry_date,
+            supplier_invoice: None,
+            landed_cost: Money::ZERO,
+        }
+    }
+    
+    #[test]
+    fn reports_refuse_stock_values_too_large_to_hold() {
+        let rates = ExchangeRates::new("SEK", &[], CurrencyConfig::default().rounding());
+        let stocked = |purchase_price: &str, stock_quantity: i32| Product {
+            purchase_price: purchase_price.parse().unwrap(),
+            stock_quantity,
+            wholesale_currency: "SEK".to_string(),
+            retail_currency: "SEK".to_string(),
+            ..product(0, 1)
+        };
+        
+        let report = InventoryManager::generate_report(&[stocked("12.50", 10)], &rates).unwrap();
+        assert!(report.contains("Stock Value at Landed Cost: 125.00 SEK"), "{}", report);
+        
+        let error = InventoryManager::generate_report(&[stocked("500000000000000", 2)], &rates).unwrap_err();
+        assert!(error.to_string().contains("too large"), "{}", error);
+        assert!(InventoryManager::generate_report(&[stocked("500000000000000", 1), stocked("500000000000000", 1)], &rates).is_err());
+    }
+    
+    #[test]
+    fn the_most_authoritative_source_counts_for_each_day() {
+        let sales = [
+            sale(1, SalesSource::Estimate, 9),
+            sale(1, SalesSource::Pos, 3),
+            sale(1, SalesSource::Ledger, 5),
+            sale(2, SalesSource::Estimate, 9),
+            sale(2, SalesSource::Ledger, 5),
+            sale(3, SalesSource::Estimate, 9),
+            sale(10, SalesSource::Pos, 100),
+        ];
+        let sales: Vec<&DailySales> = sales.iter().collect();
+        
+        assert_eq!(InventoryManager::units_sold_between(&sales, today() - Duration::days(3), today()), 3 + 5 + 9);
+        assert_eq!(InventoryManager::units_sold_between(&sales, today() - Duration::days(2), today() - Duration::days(2)), 5);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn trend_compares_the_last_week_with_the_longest_covered_window() {
+        let trend = |sales: Vec<DailySales>| InventoryManager::velocity(&product(10, 1), &sales, &[28, 7]).trend;
+        
+        // 21 units over the three weeks before the last, so the ratio is 4 × recent / (21 + recent)
+        assert_eq!(trend(history(28, 1, 7)), Trend::Stable);
+        assert_eq!(trend(history(28, 1, 8)), Trend::Stable);
+        assert_eq!(trend(history(28, 1, 10)), Trend::Rising);
+        assert_eq!(trend(history(28, 1, 6)), Trend::Stable);
+        assert_eq!(trend(history(28, 1, 5)), Trend::Falling);
+        assert_eq!(trend(history(28, 0, 0)), Trend::Insufficient);
+        assert_eq!(trend(history(20, 1, 10)), Trend::Insufficient);
+        
+        let velocity = InventoryManager::velocity(&product(10, 1), &history(28, 1, 14), &[28, 7]);
+        assert_eq!(velocity.windows.iter().map(|w| (w.days, w.units)).collect::<Vec<_>>(), vec![(7, 14), (28, 35)]);
+    }
+    
+    #[test]
+    fn suggestions_round_up_to_whole_cases() {
+        let snapshot = |min_threshold, case_pack, sales| InventorySnapshot {
+            products: vec![product(min_threshold, case_pack)],
+            lots: vec![lot(4, today() + Duration::days(90)), lot(100, today() - Duration::days(1))],
+            sales,
+            suppliers: Vec::new(),
+            rates: ExchangeRates::new("SEK", &[], CurrencyConfig::default().rounding()),
+        };
+        let suggest = |snapshot: &InventorySnapshot, on_order: i32| {
+            let on_order = HashMap::from([(1, on_order)]);
+            InventoryManager::reorder_suggestions(snapshot, &on_order, &InventoryConfig::default(), &ReorderConfig::default()).unwrap()
+        };
+        
+        // Without sales the minimum threshold is the reorder point; expired stock does not count
+        let suggestions = suggest(&snapshot(10, 12, Vec::new()), 0);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!((suggestions[0].stock, suggestions[0].reorder_point, suggestions[0].quantity), (4, 10, 12));
+        assert_eq!(suggestions[0].unit_cost, "12.50".parse().unwrap());
+        
+        // A unit a day covers 14 days' lead time and 7 of safety stock, then a 7-day review period
+        let selling = snapshot(10, 12, history(30, 1, 7));
+        let suggestions = suggest(&selling, 6);
+        assert_eq!((suggestions[0].reorder_point, suggestions[0].target_stock), (21, 28));
+        assert_eq!(suggestions[0].quantity, 24);
+        assert!(suggest(&selling, 17).is_empty());
+    }
+}
@@ -1,19 +1,60 @@
 mod models;
+mod config;
+mod currency;
+mod money;
+mod alert_rules;
+mod barcode;
 mod code_generator;
 mod database;
+mod csv_io;
+mod data_collector;
 mod inventory_manager;
+mod notifications;
+mod order_pdf;
+mod pricing;
+mod report_pdf;
+mod reports;
+mod scheduler;
 mod web;
 
-use models::Product;
+use models::{AlertStatus, AlertUpdate, AppError, ChangeNote, ExchangeRateInput, TrackedAlert, JobRun, LotInput, MovementInput, MovementType, OrderStatus, PriceChange, Product, ProductPatch, PurchaseOrder, PurchaseOrderUpdate, SalesVelocity, Supplier, SupplierPatch, ADJUSTMENT_REASONS, WRITE_OFF_REASONS};
+use alert_rules::RuleSet;
+use barcode::Gtin;
+use code_generator::{CodeScheme, CodeSchemes, InternalCode};
+use config::Config;
+use currency::Costing;
+use money::{Money, Rate};
 use database::Database;
+use csv_io::{HeaderMapping, ImportAction, ImportOptions};
 use inventory_manager::InventoryManager;
-use clap::{Parser, Subcommand};
+use data_collector::DataCollector;
+use notifications::Notifier;
+use pricing::{Guard, PricingRule, PricingRules, PricingScope, Target};
+use reports::{ReportFilter, ReportFormat, ReportKind, ReportParams};
+use scheduler::{JobKind, Scheduler};
+use chrono::NaiveDate;
+use clap::builder::PossibleValuesParser;
+use clap::{Args, Parser, Subcommand};
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
 
 #[derive(Parser)]
 #[command(name = "Food Imports DB")]
 #[command(version = "0.1.0")]
 #[command(about = "Food Import Products Management System")]
 struct Cli {
+    /// Path to a TOML config file (defaults to ./food_imports.toml if present)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Override the database URL, e.g. sqlite:/srv/store1/products.db
+    #[arg(long, global = true)]
+    database_url: Option<String>,
+    /// Override the log level (error, warn, info, debug, trace)
+    #[arg(long, global = true)]
+    log_level: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -21,84 +62,1533 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Start the web server
-    Web,
+    Web {
+        /// Address to bind to
+        #[arg(long)]
+        host: Option<String>,
+        /// Port to listen on
+        #[arg(long)]
+        port: Option<u16>,
+    },
     /// Add a new product via CLI
-    Add,
-    /// Show inventory alerts via CLI
-    Alerts,
+    Add {
+        #[command(flatten)]
+        fields: Box<ProductFieldArgs>,
+        /// Prompt for each field, using flag/file values as defaults
+        #[arg(long)]
+        interactive: bool,
+        /// Read product fields from a JSON file
+        #[arg(long, conflicts_with = "from_toml")]
+        from_json: Option<PathBuf>,
+        /// Read product fields from a TOML file
+        #[arg(long)]
+        from_toml: Option<PathBuf>,
+        #[command(flatten)]
+        note: ChangeNoteArgs,
+    },
+    /// Show open inventory alerts, or change an alert's status
+    Alerts {
+        /// List alerts with this status instead of open ones
+        #[arg(long, value_parser = PossibleValuesParser::new(["open", "acknowledged", "snoozed", "resolved", "all"]))]
+        status: Option<String>,
+        #[command(subcommand)]
+        action: Option<AlertAction>,
+    },
+    /// Send new alerts to the configured notification channels
+    Notify {
+        /// Show what each channel would be sent without sending it
+        #[arg(long)]
+        dry_run: bool,
+        /// Send a test message on this channel instead
+        #[arg(long, conflicts_with = "dry_run")]
+        test: Option<String>,
+    },
+    /// Run the background jobs on their schedules until stopped
+    Daemon,
+    /// Show scheduled jobs and their run history, or run a job now
+    Jobs {
+        #[command(subcommand)]
+        action: JobAction,
+    },
+    /// Manage suppliers and view their products and spend
+    Suppliers {
+        #[command(subcommand)]
+        action: SupplierAction,
+    },
+    /// Show reorder suggestions and manage supplier purchase orders
+    Reorder {
+        #[command(subcommand)]
+        action: ReorderAction,
+    },
+    /// Manage exchange rates into the base currency and convert amounts
+    Rates {
+        #[command(subcommand)]
+        action: RateAction,
+    },
+    /// Recalculate prices from the pricing rules and view price history
+    Pricing {
+        #[command(subcommand)]
+        action: PricingAction,
+    },
+    /// Read an internal or lot code back into its fields, checking it for typos
+    Decode {
+        code: String,
+    },
+    /// Check GS1 barcodes and find stored ones that fail the check
+    Barcode {
+        #[command(subcommand)]
+        action: BarcodeAction,
+    },
+    /// Show the code schemes internal and alternative codes are made with
+    Codes {
+        #[command(subcommand)]
+        action: CodeAction,
+    },
     /// List all products via CLI
     List,
-    /// Generate inventory report via CLI
-    Report,
+    /// Show a product by barcode, internal code or alternative code
+    Show {
+        key: String,
+    },
+    /// Update fields of an existing product
+    Update {
+        barcode: String,
+        #[command(flatten)]
+        fields: Box<ProductFieldArgs>,
+        /// Rebuild the internal code from the edited fields; labels already
+        /// printed with the old code stop matching
+        #[arg(long)]
+        regenerate_code: bool,
+        #[command(flatten)]
+        note: ChangeNoteArgs,
+    },
+    /// Delete a product (it can be restored later)
+    Delete {
+        barcode: String,
+    },
+    /// Restore a deleted product
+    Restore {
+        barcode: String,
+    },
+    /// List or receive inventory lots of a product
+    Lots {
+        #[command(subcommand)]
+        action: LotAction,
+    },
+    /// Record stock movements and view the stock ledger
+    Stock {
+        #[command(subcommand)]
+        action: StockAction,
+    },
+    /// List alert rules or switch them on and off
+    Rules {
+        #[command(subcommand)]
+        action: RuleAction,
+    },
+    /// Import POS sales and view sales history and velocity
+    Sales {
+        #[command(subcommand)]
+        action: SalesAction,
+    },
+    /// Look up product details for a barcode from the configured providers
+    Lookup {
+        barcode: String,
+        /// Print the result as JSON, ready for `add --from-json`
+        #[arg(long)]
+        json: bool,
+    },
+    /// Import products from a CSV file
+    Import {
+        file: PathBuf,
+        /// TOML file mapping CSV column headers to product fields
+        #[arg(long)]
+        mapping: Option<PathBuf>,
+        /// Validate and preview the import without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Update products whose barcode already exists
+        #[arg(long)]
+        upsert: bool,
+        #[command(flatten)]
+        note: ChangeNoteArgs,
+    },
+    /// Export all products to CSV
+    Export {
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// TOML file mapping CSV column headers to product fields
+        #[arg(long)]
+        mapping: Option<PathBuf>,
+    },
+    /// Print the inventory summary, or a detailed report over a period
+    Report {
+        /// valuation, margin, sell_through, cover or dead_stock; the summary when left out
+        #[arg(value_parser = PossibleValuesParser::new(ReportKind::ALL))]
+        kind: Option<String>,
+        #[command(flatten)]
+        params: ReportArgs,
+        #[arg(long, value_parser = PossibleValuesParser::new(["text", "json", "csv", "html", "pdf"]), default_value = "text")]
+        format: String,
+        /// Write to this file instead of stdout (required for PDF)
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Manage database schema migrations
+    Migrate {
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrateAction {
+    /// Show applied and pending migrations
+    Status,
+    /// Apply all pending migrations
+    Up,
+    /// Revert the most recently applied migration
+    Down,
+    /// Revert and re-apply the most recently applied migration
+    Redo,
+}
+
+#[derive(Subcommand)]
+enum LotAction {
+    /// List the lots of a product, earliest expiry first
+    List {
+        barcode: String,
+    },
+    /// Receive a new lot of a product
+    Add {
+        barcode: String,
+        #[arg(long)]
+        batch_id: i32,
+        #[arg(long)]
+        quantity: i32,
+        /// YYYY-MM-DD, defaults to today
+        #[arg(long)]
+        production_date: Option<NaiveDate>,
+        /// YYYY-MM-DD
+        #[arg(long)]
+        expiry_date: NaiveDate,
+        /// Supplier invoice reference
+        #[arg(long)]
+        invoice: Option<String>,
+        /// Landed cost per unit
+        #[arg(long, default_value = "0")]
+        landed_cost: Money,
+    },
+}
+
+#[derive(Subcommand)]
+enum StockAction {
+    /// Receive more units into an existing lot (use `lots add` for a new lot)
+    Receive {
+        #[command(flatten)]
+        movement: MovementArgs,
+    },
+    /// Record a sale; without --batch-id the earliest-expiring lots are used
+    Sell {
+        #[command(flatten)]
+        movement: MovementArgs,
+    },
+    /// Record a customer return
+    Return {
+        #[command(flatten)]
+        movement: MovementArgs,
+    },
+    /// Correct stock by a signed quantity
+    Adjust {
+        #[command(flatten)]
+        movement: MovementArgs,
+        #[arg(long, value_parser = PossibleValuesParser::new(ADJUSTMENT_REASONS))]
+        reason: String,
+    },
+    /// Move units from --batch-id to --to-batch
+    Transfer {
+        #[command(flatten)]
+        movement: MovementArgs,
+        #[arg(long)]
+        to_batch: i32,
+    },
+    /// Write off wasted or expired units
+    WriteOff {
+        #[command(flatten)]
+        movement: MovementArgs,
+        #[arg(long, value_parser = PossibleValuesParser::new(WRITE_OFF_REASONS))]
+        reason: String,
+    },
+    /// Show the movement history of a product
+    History {
+        barcode: String,
+    },
+    /// Check that stock, lot totals and the ledger agree
+    Reconcile,
+}
+
+#[derive(Subcommand)]
+enum AlertAction {
+    /// Show an alert with its history and notes
+    Show {
+        id: i64,
+    },
+    /// Mark an alert as seen; it stays listed under acknowledged until resolved
+    Ack {
+        id: i64,
+    },
+    /// Hide an alert until a date (YYYY-MM-DD)
+    Snooze {
+        id: i64,
+        #[arg(long)]
+        until: NaiveDate,
+    },
+    /// Mark an alert as resolved; it reopens if the problem is detected again
+    Resolve {
+        id: i64,
+    },
+    /// Open an acknowledged, snoozed or resolved alert again
+    Reopen {
+        id: i64,
+    },
+    /// Assign an alert to someone (an empty name clears it)
+    Assign {
+        id: i64,
+        assignee: String,
+    },
+    /// Add a note to an alert
+    Note {
+        id: i64,
+        note: String,
+        #[arg(long)]
+        author: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum JobAction {
+    /// Show the configured jobs with their next and last runs
+    List,
+    /// Show recent job runs, newest first
+    History {
+        #[arg(long, value_parser = PossibleValuesParser::new(JobKind::ALL))]
+        job: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+    /// Run a job now
+    Run {
+        #[arg(value_parser = PossibleValuesParser::new(JobKind::ALL))]
+        job: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SupplierAction {
+    /// List all suppliers
+    List,
+    /// Show a supplier by id or name
+    Show {
+        key: String,
+    },
+    /// Add a supplier
+    Add {
+        name: String,
+        #[command(flatten)]
+        fields: Box<SupplierFieldArgs>,
+    },
+    /// Update fields of a supplier (an empty text clears a field)
+    Update {
+        key: String,
+        #[arg(long)]
+        name: Option<String>,
+        #[command(flatten)]
+        fields: Box<SupplierFieldArgs>,
+    },
+    /// Delete a supplier that has no products or purchase orders
+    Delete {
+        key: String,
+    },
+    /// List the products bought from a supplier
+    Products {
+        key: String,
+    },
+    /// Show stock value, receipts and orders per supplier
+    Spend {
+        /// Count receipts and orders from this date (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<NaiveDate>,
+    },
+}
+
+#[derive(Args)]
+struct SupplierFieldArgs {
+    #[arg(long)]
+    contact_name: Option<String>,
+    #[arg(long)]
+    email: Option<String>,
+    #[arg(long)]
+    phone: Option<String>,
+    #[arg(long)]
+    address: Option<String>,
+    #[arg(long)]
+    country: Option<String>,
+    /// ISO 4217 code, e.g. SEK
+    #[arg(long)]
+    currency: Option<String>,
+    /// Days from order to delivery
+    #[arg(long)]
+    lead_time_days: Option<u32>,
+    #[arg(long)]
+    min_order_value: Option<Money>,
+    #[arg(long)]
+    payment_terms: Option<String>,
+    #[arg(long)]
+    vat_number: Option<String>,
+}
+
+impl SupplierFieldArgs {
+    fn into_patch(self, name: Option<String>) -> SupplierPatch {
+        SupplierPatch {
+            name,
+            contact_name: self.contact_name,
+            email: self.email,
+            phone: self.phone,
+            address: self.address,
+            country: self.country,
+            currency: self.currency,
+            lead_time_days: self.lead_time_days,
+            min_order_value: self.min_order_value,
+            payment_terms: self.payment_terms,
+            vat_number: self.vat_number,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum RateAction {
+    /// List exchange rates, newest first per currency
+    List {
+        #[arg(long)]
+        currency: Option<String>,
+    },
+    /// Set the rate of a currency: units of the base currency per unit
+    Set {
+        currency: String,
+        rate: Rate,
+        /// First day the rate applies (YYYY-MM-DD); defaults to today
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Where the rate came from, e.g. a bank
+        #[arg(long)]
+        source: Option<String>,
+    },
+    /// Import rates from a CSV file (columns: currency, rate, date, optional source)
+    Import {
+        file: PathBuf,
+        /// Validate the file without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Delete the rate a currency got on a day
+    Delete {
+        currency: String,
+        valid_from: NaiveDate,
+    },
+    /// Convert an amount between currencies
+    Convert {
+        amount: Money,
+        from: String,
+        /// Defaults to the base currency
+        #[arg(long)]
+        to: Option<String>,
+        /// Day whose rates to use (YYYY-MM-DD); defaults to today
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+}
+
+#[derive(Subcommand)]
+enum PricingAction {
+    /// List the pricing rules
+    Rules,
+    /// Preview the prices the rules give products, optionally only some of them
+    Recalculate {
+        #[arg(long)]
+        category: Option<String>,
+        #[arg(long)]
+        brand: Option<String>,
+        #[arg(long)]
+        supplier: Option<String>,
+        /// Write the new prices instead of only showing them
+        #[arg(long)]
+        apply: bool,
+        #[command(flatten)]
+        note: ChangeNoteArgs,
+    },
+    /// Show every price a product has had, newest first
+    History {
+        barcode: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum BarcodeAction {
+    /// Validate a barcode and show its GTIN-14, symbology and GS1 country
+    Check {
+        barcode: String,
+        /// Warn when the GS1 country differs from this origin
+        #[arg(long)]
+        origin_country: Option<String>,
+    },
+    /// List products whose barcode is not a valid GTIN or whose GS1 country
+    /// differs from their origin
+    Audit,
+}
+
+#[derive(Subcommand)]
+enum CodeAction {
+    /// List every code scheme version and the products it applies to
+    Schemes,
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    /// First day of the period (YYYY-MM-DD); [reports] period_days before --to by default
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Last day of the period (YYYY-MM-DD); today by default
+    #[arg(long)]
+    to: Option<NaiveDate>,
+    /// Rows per product, category, supplier or country (valuation, margin, sell_through)
+    #[arg(long, value_parser = PossibleValuesParser::new(["product", "category", "supplier", "country"]))]
+    group_by: Option<String>,
+    #[arg(long)]
+    category: Option<String>,
+    #[arg(long)]
+    brand: Option<String>,
+    #[arg(long)]
+    supplier: Option<String>,
+    /// Country of origin
+    #[arg(long)]
+    country: Option<String>,
+}
+
+impl TryFrom<ReportArgs> for ReportParams {
+    type Error = AppError;
+
+    fn try_from(args: ReportArgs) -> Result<Self, AppError> {
+        Ok(ReportParams {
+            from: args.from,
+            to: args.to,
+            group_by: args.group_by.as_deref().map(str::parse).transpose()?,
+            filter: ReportFilter { category: args.category, brand: args.brand, supplier: args.supplier, country: args.country },
+        })
+    }
+}
+
+/// Who is making a change and why, kept with the price changes it causes.
+#[derive(Args)]
+struct ChangeNoteArgs {
+    /// Who is making the change; defaults to $USER
+    #[arg(long)]
+    changed_by: Option<String>,
+    /// Why, e.g. "new supplier price list"
+    #[arg(long)]
+    reason: Option<String>,
+}
+
+impl From<ChangeNoteArgs> for ChangeNote {
+    fn from(args: ChangeNoteArgs) -> Self {
+        ChangeNote {
+            changed_by: args.changed_by.or_else(|| std::env::var("USER").ok()),
+            reason: args.reason,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum ReorderAction {
+    /// Show products due for reordering with suggested quantities
+    Suggest,
+    /// Add the current suggestions to draft orders, one per supplier
+    Draft,
+    /// List purchase orders, newest first
+    Orders {
+        #[arg(long, value_parser = PossibleValuesParser::new(["draft", "approved", "received", "cancelled"]))]
+        status: Option<String>,
+    },
+    /// Show a purchase order with its lines
+    Show {
+        id: i64,
+    },
+    /// Set the quantity of a product on a draft order (0 removes it)
+    Set {
+        id: i64,
+        barcode: String,
+        quantity: i32,
+    },
+    /// Approve a draft order; its quantities then count as on order
+    Approve {
+        id: i64,
+    },
+    /// Mark an approved order as delivered
+    Receive {
+        id: i64,
+    },
+    /// Cancel a draft or approved order
+    Cancel {
+        id: i64,
+    },
+    /// Set the notes of an order (an empty text clears them)
+    Note {
+        id: i64,
+        notes: String,
+    },
+    /// Export an order for the supplier
+    Export {
+        id: i64,
+        #[arg(long, value_parser = PossibleValuesParser::new(["csv", "pdf"]), default_value = "csv")]
+        format: String,
+        /// Write to this file instead of stdout (required for PDF)
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RuleAction {
+    /// Show the configured alert rules and whether they are enabled
+    List,
+    /// Enable a rule by id
+    Enable {
+        id: String,
+    },
+    /// Disable a rule by id
+    Disable {
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SalesAction {
+    /// Show the daily sales of a product
+    History {
+        barcode: String,
+    },
+    /// Import daily sales totals from a POS export (columns: barcode, date, quantity)
+    Import {
+        file: PathBuf,
+        /// Validate the file without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show sales velocity and trend of a product
+    Velocity {
+        barcode: String,
+    },
+}
+
+#[derive(Args)]
+struct MovementArgs {
+    barcode: String,
+    #[arg(long, allow_negative_numbers = true)]
+    quantity: i32,
+    /// Lot to act on (defaults to the product's current batch)
+    #[arg(long)]
+    batch_id: Option<i32>,
+    /// Invoice, receipt or other document reference
+    #[arg(long)]
+    reference: Option<String>,
+    #[arg(long)]
+    note: Option<String>,
+}
+
+#[derive(Args)]
+struct ProductFieldArgs {
+    #[arg(long)]
+    original_name: Option<String>,
+    #[arg(long)]
+    imported_name: Option<String>,
+    #[arg(long)]
+    local_name: Option<String>,
+    #[arg(long = "barcode", id = "new_barcode", value_name = "BARCODE")]
+    barcode: Option<String>,
+    #[arg(long)]
+    brand: Option<String>,
+    #[arg(long)]
+    category: Option<String>,
+    #[arg(long)]
+    weight: Option<String>,
+    #[arg(long)]
+    origin_country: Option<String>,
+    /// Supplier name; an unknown name adds a supplier
+    #[arg(long)]
+    supplier: Option<String>,
+    #[arg(long, conflicts_with = "supplier")]
+    supplier_id: Option<i64>,
+    #[arg(long)]
+    purchase_price: Option<Money>,
+    /// ISO 4217 code; defaults to the supplier's currency
+    #[arg(long)]
+    purchase_currency: Option<String>,
+    #[arg(long)]
+    wholesale_price: Option<Money>,
+    /// ISO 4217 code; defaults to the base currency
+    #[arg(long)]
+    wholesale_currency: Option<String>,
+    #[arg(long)]
+    retail_price: Option<Money>,
+    /// ISO 4217 code; defaults to the base currency
+    #[arg(long)]
+    retail_currency: Option<String>,
+    /// Freight per unit, in the purchase currency
+    #[arg(long)]
+    freight_cost: Option<Money>,
+    /// Import duty as a percentage of purchase price plus freight
+    #[arg(long)]
+    duty_percent: Option<Rate>,
+    /// Handling per unit, in the purchase currency
+    #[arg(long)]
+    handling_cost: Option<Money>,
+    /// YYYY-MM-DD
+    #[arg(long)]
+    production_date: Option<NaiveDate>,
+    /// YYYY-MM-DD
+    #[arg(long)]
+    expiry_date: Option<NaiveDate>,
+    #[arg(long)]
+    batch_id: Option<i32>,
+    #[arg(long)]
+    stock_quantity: Option<i32>,
+    #[arg(long)]
+    min_threshold: Option<i32>,
+    /// Units per case the supplier ships
+    #[arg(long)]
+    case_pack: Option<i32>,
+}
+
+impl From<ProductFieldArgs> for ProductPatch {
+    fn from(args: ProductFieldArgs) -> Self {
+        ProductPatch {
+            original_name: args.original_name,
+            imported_name: args.imported_name,
+            local_name: args.local_name,
+            barcode: args.barcode,
+            brand: args.brand,
+            category: args.category,
+            weight: args.weight,
+            origin_country: args.origin_country,
+            supplier: args.supplier,
+            supplier_id: args.supplier_id,
+            purchase_price: args.purchase_price,
+            purchase_currency: args.purchase_currency,
+            wholesale_price: args.wholesale_price,
+            wholesale_currency: args.wholesale_currency,
+            retail_price: args.retail_price,
+            retail_currency: args.retail_currency,
+            freight_cost: args.freight_cost,
+            duty_percent: args.duty_percent,
+            handling_cost: args.handling_cost,
+            production_date: args.production_date,
+            expiry_date: args.expiry_date,
+            batch_id: args.batch_id,
+            stock_quantity: args.stock_quantity,
+            min_threshold: args.min_threshold,
+            case_pack: args.case_pack,
+        }
+    }
+}
+
+/// Asks for every product field on stdin, keeping the current value on empty input.
+fn prompt_product(product: &mut Product) -> io::Result<()> {
+    prompt_field("Original name", &mut product.original_name)?;
+    prompt_field("Imported name", &mut product.imported_name)?;
+    let mut local_name = product.local_name.clone().unwrap_or_default();
+    prompt_field("Local name", &mut local_name)?;
+    product.local_name = Some(local_name).filter(|name| !name.is_empty());
+    prompt_field("Barcode", &mut product.barcode)?;
+    prompt_field("Brand", &mut product.brand)?;
+    prompt_field("Category", &mut product.category)?;
+    prompt_field("Weight", &mut product.weight)?;
+    prompt_field("Origin country", &mut product.origin_country)?;
+    prompt_field("Supplier", &mut product.supplier)?;
+    prompt_field("Purchase price", &mut product.purchase_price)?;
+    prompt_field("Purchase currency (empty for the supplier's)", &mut product.purchase_currency)?;
+    prompt_field("Freight per unit", &mut product.freight_cost)?;
+    prompt_field("Duty %", &mut product.duty_percent)?;
+    prompt_field("Handling per unit", &mut product.handling_cost)?;
+    prompt_field("Wholesale price", &mut product.wholesale_price)?;
+    prompt_field("Retail price", &mut product.retail_price)?;
+    prompt_field("Production date (YYYY-MM-DD)", &mut product.production_date)?;
+    prompt_field("Expiry date (YYYY-MM-DD)", &mut product.expiry_date)?;
+    prompt_field("Batch ID", &mut product.batch_id)?;
+    prompt_field("Stock quantity", &mut product.stock_quantity)?;
+    prompt_field("Minimum threshold", &mut product.min_threshold)?;
+    prompt_field("Case pack", &mut product.case_pack)?;
+    Ok(())
+}
+
+fn prompt_field<T: FromStr + Display>(label: &str, value: &mut T) -> io::Result<()> {
+    loop {
+        print!("{} [{}]: ", label, value);
+        io::stdout().flush()?;
+        
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Ok(());
+        }
+        
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(());
+        }
+        match input.parse() {
+            Ok(parsed) => {
+                *value = parsed;
+                return Ok(());
+            }
+            Err(_) => println!("Invalid value for {}, try again", label),
+        }
+    }
+}
+
+fn print_product(product: &Product) {
+    println!("{} ({})", product.imported_name, product.original_name);
+    if let Some(local_name) = &product.local_name {
+        println!("  Local name:       {}", local_name);
+    }
+    match product.barcode_symbology {
+        Some(symbology) => println!("  Barcode:          {} ({} {})", product.barcode, symbology.label(),
+            &product.barcode[product.barcode.len().saturating_sub(symbology.length())..]),
+        None => println!("  Barcode:          {} (not a GTIN; see `barcode audit`)", product.barcode),
+    }
+    println!("  Internal code:    {}", product.internal_code);
+    println!("  Alternative code: {}", product.alternative_code);
+    println!("  Code scheme:      {} v{}", product.code_scheme, product.code_version);
+    println!("  Brand:            {}", product.brand);
+    println!("  Category:         {}", product.category);
+    println!("  Weight:           {}", product.weight);
+    println!("  Origin:           {}", product.origin_country);
+    println!("  Supplier:         {} (#{})", product.supplier, product.supplier_id.unwrap_or_default());
+    println!("  Prices:           purchase {} {} / wholesale {} {} / retail {} {}",
+        product.purchase_price, product.purchase_currency, product.wholesale_price, product.wholesale_currency,
+        product.retail_price, product.retail_currency);
+    println!("  Import costs:     freight {} / duty {}% / handling {}",
+        product.freight_cost, product.duty_percent, product.handling_cost);
+    println!("  Batch:            {} (produced {}, expires {})",
+        product.batch_id, product.production_date, product.expiry_date);
+    println!("  Stock:            {} (min {})", product.stock_quantity, product.min_threshold);
+    println!("  Case pack:        {}", product.case_pack);
+}
+
+fn print_costing(costing: &Costing) {
+    let landed = &costing.landed_cost;
+    let margin = |margin: Option<Rate>| margin.map(|m| format!("{:.1}%", m)).unwrap_or_else(|| "-".to_string());
+    println!("  Landed cost:      {} {} (purchase {} + freight {} + duty {} + handling {})",
+        landed.total, costing.currency, landed.purchase, landed.freight, landed.duty, landed.handling);
+    println!("  Margins:          wholesale {} / retail {} (at rates of {})",
+        margin(costing.wholesale_margin), margin(costing.retail_margin), costing.date);
+}
+
+fn print_internal_code(decoded: &InternalCode) {
+    println!("Code:     {}", decoded.code);
+    println!("  Scheme:   {} v{}", decoded.scheme, decoded.version);
+    let fields = [
+        ("Country", decoded.country.clone()),
+        ("Category", decoded.category.clone()),
+        ("Brand", decoded.brand.clone()),
+        ("Weight", decoded.weight.map(|weight| weight.to_string())),
+        ("Batch", decoded.batch.map(|batch| batch.to_string())),
+        ("Expiry", decoded.expiry.map(|expiry| expiry.to_string())),
+        ("Sequence", decoded.sequence.map(|sequence| sequence.to_string())),
+    ];
+    for (label, value) in fields {
+        if let Some(value) = value {
+            println!("  {:<10}{}", format!("{}:", label), value);
+        }
+    }
+    println!("  Check:    {}", if decoded.checked { "ok" } else { "none (this code has no check character)" });
+}
+
+fn print_code_scheme(scheme: &CodeScheme) {
+    let scope = [("category", &scheme.scope.category), ("supplier", &scheme.scope.supplier)]
+        .into_iter()
+        .filter_map(|(label, value)| value.as_ref().map(|value| format!("{} {}", label, value)))
+        .collect::<Vec<_>>();
+    println!("{} v{}{}", scheme.name, scheme.version,
+        if scope.is_empty() { String::new() } else { format!(" for {}", scope.join(", ")) });
+    for (label, template) in [("internal", &scheme.internal), ("alternative", &scheme.alternative)] {
+        let segments = template.segments
+            .iter()
+            .map(|segment| match segment.width {
+                Some(width) => format!("{}({})", segment.field.as_str(), width),
+                None => segment.field.as_str().to_string(),
+            })
+            .collect::<Vec<_>>();
+        let separator = if template.separator.is_empty() { String::new() } else { format!(", joined by {:?}", template.separator) };
+        println!("  {:<12}{}{}", label, segments.join(" "), separator);
+    }
+}
+
+fn print_pricing_rule(rule: &PricingRule) {
+    let target = match rule.target {
+        Target::Margin { percent } => format!("{}% margin", percent),
+        Target::Markup { percent } => format!("{}% markup", percent),
+    };
+    let scope = [("category", &rule.scope.category), ("brand", &rule.scope.brand), ("supplier", &rule.scope.supplier)]
+        .into_iter()
+        .filter_map(|(label, value)| value.as_ref().map(|value| format!("{} {}", label, value)))
+        .collect::<Vec<_>>();
+    println!("{}: {} at {}{}", rule.id, rule.price.as_str(), target,
+        if scope.is_empty() { String::new() } else { format!(" for {}", scope.join(", ")) });
+    for (label, value) in [("ending", rule.ending), ("floor", rule.min_price), ("ceiling", rule.max_price)] {
+        if let Some(value) = value {
+            println!("  {:<8}{}", label, value);
+        }
+    }
+}
+
+fn print_price_change(change: &PriceChange) {
+    let old = match (&change.old_amount, &change.old_currency) {
+        (Some(amount), Some(currency)) => format!("{} {} -> ", amount, currency),
+        _ => String::new(),
+    };
+    let by = change.changed_by.as_ref().map(|by| format!(" by {}", by)).unwrap_or_default();
+    let why = [change.rule_id.as_ref().map(|id| format!("rule {}", id)), change.reason.clone()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    println!("{} {} {}{} {} ({}{}){}", change.changed_at, change.price.as_str(), old, change.new_amount,
+        change.new_currency, change.source.as_str(), by,
+        if why.is_empty() { String::new() } else { format!(": {}", why.join("; ")) });
+}
+
+fn print_alert(alert: &TrackedAlert) {
+    println!("#{} [{}] {}: {}", alert.id, alert.severity, alert.alert_type, alert.message);
+    println!("  Rule:       {}", alert.rule_id);
+    println!("  Status:     {}{}", alert.status.as_str(),
+        alert.snoozed_until.map(|until| format!(" until {}", until)).unwrap_or_default());
+    if let Some(assignee) = &alert.assignee {
+        println!("  Assignee:   {}", assignee);
+    }
+    println!("  First seen: {}", alert.first_seen_at);
+    println!("  Last seen:  {}", alert.last_seen_at);
+    if let Some(resolved_at) = &alert.resolved_at {
+        println!("  Resolved:   {}", resolved_at);
+    }
+    for note in &alert.notes {
+        println!("  Note ({}{}): {}", note.created_at,
+            note.author.as_ref().map(|a| format!(", {}", a)).unwrap_or_default(), note.note);
+    }
+}
+
+fn print_job_run(run: &JobRun) {
+    let outcome = run.error.as_ref().or(run.output.as_ref()).map(|o| format!(": {}", o)).unwrap_or_default();
+    println!("#{} {} {} ({}, {}){}", run.id, run.started_at, run.job, run.triggered_by, run.status.as_str(), outcome);
+}
+
+fn print_supplier(supplier: &Supplier) {
+    println!("#{} {}", supplier.id.unwrap_or_default(), supplier.name);
+    for (label, value) in [
+        ("Contact", &supplier.contact_name),
+        ("Email", &supplier.email),
+        ("Phone", &supplier.phone),
+        ("Address", &supplier.address),
+        ("Country", &supplier.country),
+        ("Payment terms", &supplier.payment_terms),
+        ("VAT number", &supplier.vat_number),
+    ] {
+        if let Some(value) = value {
+            println!("  {:<15}{}", format!("{}:", label), value);
+        }
+    }
+    println!("  Currency:      {}", supplier.currency);
+    println!("  Lead time:     {}", supplier.lead_time_days.map(|days| format!("{} days", days)).unwrap_or_else(|| "default".to_string()));
+    println!("  Minimum order: {} {}", supplier.min_order_value, supplier.currency);
+}
+
+/// Finds the supplier a command names by id or name, saying so when there is none.
+async fn lookup_supplier(db: &Database, key: &str) -> Result<Option<Supplier>, AppError> {
+    match db.find_supplier(key).await {
+        Ok(supplier) => Ok(Some(supplier)),
+        Err(AppError::NotFound) => {
+            println!("No supplier matches {:?}", key);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn print_order(order: &PurchaseOrder) -> Result<(), AppError> {
+    println!("#{} {} [{}] {} lines, total {} {}", order.id, order.supplier, order.status.as_str(),
+        order.lines.len(), order.total, order.currency);
+    if order.below_minimum() {
+        println!("  Below the supplier's minimum order value of {} {}", order.min_order_value, order.currency);
+    }
+    println!("  Created:  {}", order.created_at);
+    if let Some(approved_at) = &order.approved_at {
+        println!("  Approved: {}", approved_at);
+    }
+    if let Some(notes) = &order.notes {
+        println!("  Notes:    {}", notes);
+    }
+    for line in &order.lines {
+        let suggested = match line.suggested_quantity {
+            Some(suggested) if suggested != line.quantity => format!(" (suggested {})", suggested),
+            _ => String::new(),
+        };
+        println!("  {} {:<30} {:>6} x {} = {:>9}  {:.1} cases of {}{}", line.barcode, line.product_name,
+            line.quantity, line.unit_cost, line.total()?, line.cases(), line.case_pack, suggested);
+    }
+    Ok(())
+}
+
+fn print_velocity(velocity: &SalesVelocity) {
+    let windows: Vec<String> = velocity.windows
+        .iter()
+        .map(|w| format!("{}d {} ({:.1}/day)", w.days, w.units, w.per_day))
+        .collect();
+    println!("  Sales:            {} - trend {:?}", windows.join(", "), velocity.trend);
+}
+
+/// The config file and environment with the global flags on top.
+fn load_config(cli: &Cli) -> Result<Config, AppError> {
+    let mut config = Config::load(cli.config.as_deref())?;
+    if let Some(url) = &cli.database_url {
+        config.database.url = url.clone();
+    }
+    if let Some(level) = &cli.log_level {
+        config.log_level = level.clone();
+    }
+    Ok(config)
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
+    
+    let config = load_config(&cli)?;
+    env_logger::Builder::new().parse_filters(&config.log_level).init();
+    
+    run(cli.command, config).await
+}
 
-    match cli.command {
-        Commands::Web => {
+/// Carries out one command with the loaded config.
+async fn run(command: Commands, mut config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Commands::Web { host, port } => {
+            if let Some(host) = host {
+                config.server.host = host;
+            }
+            if let Some(port) = port {
+                config.server.port = port;
+            }
             println!("🌐 Starting web interface...");
-            web::start_web_server().await?;
-        }
-        
-        Commands::Add => {
-            let db = Database::new().await?;
-            println!("Adding sample product...");
-            
-            let product = Product {
-                id: None,
-                original_name: "حمص حب".to_string(),
-                imported_name: "Chickpeas".to_string(),
-                local_name: Some("Kikärtor".to_string()),
-                barcode: "5281234567890".to_string(),
-                internal_code: String::new(), // Will be generated
-                alternative_code: String::new(), // Will be generated
-                brand: "Shatoura".to_string(),
-                category: "Legumes".to_string(),
-                weight: "900g".to_string(),
-                origin_country: "Lebanon".to_string(),
-                supplier: "XYZ Import AB".to_string(),
-                purchase_price: 1.50,
-                wholesale_price: 2.20,
-                retail_price: 3.00,
-                production_date: "2025-01-01".to_string(),
-                expiry_date: "2025-08-01".to_string(),
-                batch_id: 12,
-                stock_quantity: 1000,
-                monthly_sales: 200,
-                min_threshold: 200,
+            web::start_web_server(config).await?;
+        }
+        
+        Commands::Add { fields, interactive, from_json, from_toml, note } => {
+            let mut patch = match (from_json, from_toml) {
+                (Some(path), _) => serde_json::from_str::<ProductPatch>(&std::fs::read_to_string(path)?)?,
+                (_, Some(path)) => toml::from_str::<ProductPatch>(&std::fs::read_to_string(path)?)?,
+                (None, None) => ProductPatch::default(),
+            };
+            patch.merge((*fields).into());
+            
+            // The expiry prompt needs a starting value to offer
+            if interactive && patch.expiry_date.is_none() {
+                patch.expiry_date = Some(patch.production_date.unwrap_or_else(|| chrono::Local::now().date_naive()));
+            }
+            let mut product = match patch.into_product() {
+                Ok(product) => product,
+                Err(e) => {
+                    println!("Error adding product: {}", e);
+                    return Ok(());
+                }
             };
             
-            match db.add_product(product).await {
-                Ok(id) => println!("Product added with ID: {}", id),
+            if interactive {
+                prompt_product(&mut product)?;
+            }
+            
+            let gtin = match product.validate().and_then(|_| Gtin::parse(&product.barcode)) {
+                Ok(gtin) => gtin,
+                Err(e) => {
+                    println!("Error adding product: {}", e);
+                    return Ok(());
+                }
+            };
+            let warning = gtin.origin_warning(&product.origin_country);
+            
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            match db.add_product(product, &note.into()).await {
+                Ok(id) => {
+                    println!("Product added with ID: {}", id);
+                    if let Some(warning) = warning {
+                        println!("Warning: {}", warning);
+                    }
+                }
                 Err(e) => println!("Error adding product: {}", e),
             }
         }
         
-        Commands::Alerts => {
-            let db = Database::new().await?;
-            println!("Checking inventory alerts...");
+        Commands::Alerts { status, action } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
             
-            match db.get_all_products().await {
-                Ok(products) => {
-                    let alerts = InventoryManager::check_inventory(&products);
+            let (id, update) = match action {
+                None => {
+                    println!("Checking inventory alerts...");
+                    let rules = RuleSet::load(config.alerts.rules_file.as_deref())?;
+                    db.refresh_alerts(&config.inventory, &rules).await?;
+                    
+                    let status = match status.as_deref() {
+                        Some("all") => None,
+                        Some(status) => Some(status.parse()?),
+                        None => Some(AlertStatus::Open),
+                    };
+                    let alerts = db.get_alerts(status).await?;
                     
                     if alerts.is_empty() {
                         println!("No alerts found!");
                     } else {
                         println!("Found {} alerts:", alerts.len());
                         for alert in alerts {
-                            println!("[{}] {}: {}", alert.severity, alert.alert_type, alert.message);
+                            println!("#{} [{}] {}: {}{}", alert.id, alert.severity, alert.alert_type, alert.message,
+                                alert.assignee.map(|a| format!(" (assigned to {})", a)).unwrap_or_default());
                         }
                     }
+                    return Ok(());
+                }
+                Some(AlertAction::Show { id }) => {
+                    match db.get_alert(id).await {
+                        Ok(alert) => print_alert(&alert),
+                        Err(e) => println!("Error fetching alert: {}", e),
+                    }
+                    return Ok(());
+                }
+                Some(AlertAction::Ack { id }) => (id, AlertUpdate { status: Some(AlertStatus::Acknowledged), ..Default::default() }),
+                Some(AlertAction::Snooze { id, until }) => (id, AlertUpdate {
+                    status: Some(AlertStatus::Snoozed),
+                    snoozed_until: Some(until),
+                    ..Default::default()
+                }),
+                Some(AlertAction::Resolve { id }) => (id, AlertUpdate { status: Some(AlertStatus::Resolved), ..Default::default() }),
+                Some(AlertAction::Reopen { id }) => (id, AlertUpdate { status: Some(AlertStatus::Open), ..Default::default() }),
+                Some(AlertAction::Assign { id, assignee }) => (id, AlertUpdate { assignee: Some(assignee), ..Default::default() }),
+                Some(AlertAction::Note { id, note, author }) => (id, AlertUpdate { note: Some(note), author, ..Default::default() }),
+            };
+            
+            match db.update_alert(id, update).await {
+                Ok(alert) => print_alert(&alert),
+                Err(e) => println!("Error updating alert: {}", e),
+            }
+        }
+        
+        Commands::Notify { dry_run, test } => {
+            let notifier = Notifier::from_config(&config.notifications)?;
+            if config.notifications.channels.is_empty() {
+                println!("No notification channels configured");
+                return Ok(());
+            }
+            if let Some(channel) = test {
+                notifier.send_test(&channel).await?;
+                println!("Sent a test notification on {}", channel);
+                return Ok(());
+            }
+            
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            let rules = RuleSet::load(config.alerts.rules_file.as_deref())?;
+            db.refresh_alerts(&config.inventory, &rules).await?;
+            
+            let reports = notifier.dispatch(&db, dry_run).await?;
+            if reports.is_empty() {
+                println!("No channel is due; digests go out once a day at their set time");
+            }
+            for report in reports {
+                let kind = if report.digest { "digest of " } else { "" };
+                match (&report.error, report.sent) {
+                    (Some(error), _) => println!("{}: failed to send {}{} alerts: {}", report.channel, kind, report.alerts, error),
+                    (None, true) => println!("{}: sent {}{} alerts", report.channel, kind, report.alerts),
+                    (None, false) if dry_run => println!("{}: would send {}{} alerts", report.channel, kind, report.alerts),
+                    (None, false) => println!("{}: nothing new to send", report.channel),
+                }
+            }
+        }
+        
+        Commands::Daemon => {
+            let db = Arc::new(Database::new(&config.database, &config.currency, &config.codes).await?);
+            let scheduler = Arc::new(Scheduler::new(db, &config)?);
+            
+            for job in scheduler.status().await? {
+                println!("{} [{}] next run {}", job.job, job.schedule,
+                    job.next_run.map(|at| at.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_else(|| "never".to_string()));
+            }
+            println!("⏰ Scheduler running, press Ctrl+C to stop");
+            scheduler.run().await;
+        }
+        
+        Commands::Jobs { action } => {
+            let db = Arc::new(Database::new(&config.database, &config.currency, &config.codes).await?);
+            let scheduler = Scheduler::new(db.clone(), &config)?;
+            
+            match action {
+                JobAction::List => {
+                    for job in scheduler.status().await? {
+                        println!("{} [{}]", job.job, job.schedule);
+                        if let Some(next_run) = job.next_run {
+                            println!("  Next run: {}", next_run.format("%Y-%m-%d %H:%M"));
+                        }
+                        match &job.last_run {
+                            Some(run) => {
+                                print!("  Last run: ");
+                                print_job_run(run);
+                            }
+                            None => println!("  Last run: never"),
+                        }
+                    }
+                }
+                JobAction::History { job, limit } => {
+                    let job = job.map(|job| job.parse()).transpose()?;
+                    let runs = db.get_job_runs(job, limit).await?;
+                    if runs.is_empty() {
+                        println!("No job runs recorded");
+                    }
+                    for run in &runs {
+                        print_job_run(run);
+                    }
+                }
+                JobAction::Run { job } => {
+                    let run = scheduler.run_job(job.parse()?, "manual").await?;
+                    print_job_run(&run);
+                }
+            }
+        }
+        
+        Commands::Suppliers { action } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            
+            match action {
+                SupplierAction::List => {
+                    let suppliers = db.get_suppliers().await?;
+                    if suppliers.is_empty() {
+                        println!("No suppliers found!");
+                    }
+                    for supplier in &suppliers {
+                        println!("#{} {} ({}{})", supplier.id.unwrap_or_default(), supplier.name, supplier.currency,
+                            supplier.country.as_ref().map(|c| format!(", {}", c)).unwrap_or_default());
+                    }
+                }
+                SupplierAction::Show { key } => {
+                    if let Some(supplier) = lookup_supplier(&db, &key).await? {
+                        print_supplier(&supplier);
+                    }
+                }
+                SupplierAction::Add { name, fields } => {
+                    let added = match fields.into_patch(Some(name)).into_supplier() {
+                        Ok(supplier) => db.add_supplier(supplier).await,
+                        Err(e) => Err(e),
+                    };
+                    match added {
+                        Ok(supplier) => print_supplier(&supplier),
+                        Err(e) => println!("Error adding supplier: {}", e),
+                    }
+                }
+                SupplierAction::Update { key, name, fields } => {
+                    let Some(supplier) = lookup_supplier(&db, &key).await? else { return Ok(()) };
+                    match db.update_supplier(supplier.id.unwrap_or_default(), fields.into_patch(name)).await {
+                        Ok(supplier) => print_supplier(&supplier),
+                        Err(e) => println!("Error updating supplier: {}", e),
+                    }
+                }
+                SupplierAction::Delete { key } => {
+                    let Some(supplier) = lookup_supplier(&db, &key).await? else { return Ok(()) };
+                    match db.delete_supplier(supplier.id.unwrap_or_default()).await {
+                        Ok(()) => println!("Supplier {} deleted", supplier.name),
+                        Err(e) => println!("Error deleting supplier: {}", e),
+                    }
+                }
+                SupplierAction::Products { key } => {
+                    let Some(supplier) = lookup_supplier(&db, &key).await? else { return Ok(()) };
+                    let products = db.get_supplier_products(supplier.id.unwrap_or_default()).await?;
+                    if products.is_empty() {
+                        println!("No products found!");
+                    }
+                    for product in products {
+                        println!("- {} ({}) - Stock: {} - Purchase price: {}",
+                            product.imported_name, product.barcode, product.stock_quantity, product.purchase_price);
+                    }
+                }
+                SupplierAction::Spend { since } => {
+                    for spend in db.supplier_spend(since).await? {
+                        println!("{} ({} products)", spend.supplier, spend.products);
+                        println!("  Stock value: {} {}", spend.stock_value, config.currency.base);
+                        println!("  Received:    {} units, {} {}", spend.received_units, spend.received_value, config.currency.base);
+                        println!("  Ordered:     {} orders, {} {} ({} still on order)",
+                            spend.orders, spend.ordered_value, spend.currency, spend.on_order_value);
+                    }
                 }
-                Err(e) => println!("Error fetching products: {}", e),
+            }
+        }
+        
+        Commands::Rates { action } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            let today = chrono::Local::now().date_naive();
+            
+            match action {
+                RateAction::List { currency } => {
+                    let currency = currency.map(|currency| currency.to_ascii_uppercase());
+                    let rates = db.get_exchange_rates(currency.as_deref()).await?;
+                    if rates.is_empty() {
+                        println!("No exchange rates found!");
+                    }
+                    for rate in rates {
+                        println!("{} {} {} {}{}", rate.currency, rate.valid_from, rate.rate, config.currency.base,
+                            rate.source.map(|source| format!(" ({})", source)).unwrap_or_default());
+                    }
+                }
+                RateAction::Set { currency, rate, from, source } => {
+                    let input = ExchangeRateInput {
+                        currency: currency.to_ascii_uppercase(),
+                        rate,
+                        valid_from: from.unwrap_or(today),
+                        source,
+                    };
+                    match db.set_exchange_rate(input).await {
+                        Ok(rate) => println!("1 {} = {} {} from {}", rate.currency, rate.rate, config.currency.base, rate.valid_from),
+                        Err(e) => println!("Error setting exchange rate: {}", e),
+                    }
+                }
+                RateAction::Import { file, dry_run } => {
+                    let source = file.file_name().map(|name| name.to_string_lossy().into_owned());
+                    let report = csv_io::import_rates_csv(&db, std::fs::File::open(&file)?, source.as_deref(), dry_run).await?;
+                    
+                    for error in &report.errors {
+                        println!("line {}: error: {}", error.line, error.message);
+                    }
+                    
+                    let summary = format!("{} rates, {} errors", report.rows, report.errors.len());
+                    if report.committed {
+                        println!("Rate import committed: {}", summary);
+                    } else if report.dry_run {
+                        println!("Dry run, nothing written: {}", summary);
+                    } else {
+                        println!("Rate import rolled back: {}", summary);
+                    }
+                }
+                RateAction::Delete { currency, valid_from } => {
+                    match db.delete_exchange_rate(&currency.to_ascii_uppercase(), valid_from).await {
+                        Ok(()) => println!("Exchange rate deleted"),
+                        Err(AppError::NotFound) => println!("No {} rate from {}", currency, valid_from),
+                        Err(e) => println!("Error deleting exchange rate: {}", e),
+                    }
+                }
+                RateAction::Convert { amount, from, to, date } => {
+                    let from = from.to_ascii_uppercase();
+                    let to = to.map_or_else(|| config.currency.base.clone(), |to| to.to_ascii_uppercase());
+                    let date = date.unwrap_or(today);
+                    match db.exchange_rates().await?.convert(amount, &from, &to, date) {
+                        Ok(converted) => println!("{} {} = {} {} on {}", amount, from, converted, to, date),
+                        Err(e) => println!("Error converting: {}", e),
+                    }
+                }
+            }
+        }
+        
+        Commands::Decode { code } => {
+            let schemes = CodeSchemes::load(config.codes.schemes_file.as_deref())?;
+            
+            match schemes.parse_internal_code(&code) {
+                Ok(decoded) => print_internal_code(&decoded),
+                Err(e) => println!("Error decoding code: {}", e),
+            }
+        }
+        
+        Commands::Barcode { action } => {
+            match action {
+                BarcodeAction::Check { barcode, origin_country } => match Gtin::parse(&barcode) {
+                    Ok(gtin) => {
+                        println!("{} {}", gtin.symbology.label(), gtin.printed());
+                        println!("  GTIN-14:  {}", gtin.gtin14);
+                        println!("  Prefix:   {} ({})", gtin.prefix(), gtin.country().unwrap_or("no country"));
+                        if let Some(warning) = origin_country.and_then(|origin| gtin.origin_warning(&origin)) {
+                            println!("Warning: {}", warning);
+                        }
+                    }
+                    Err(e) => println!("Invalid barcode: {}", e),
+                },
+                BarcodeAction::Audit => {
+                    let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+                    let mut problems = 0;
+                    for product in db.get_all_products().await? {
+                        let problem = match Gtin::parse(&product.barcode) {
+                            Err(e) => Some(e.to_string()),
+                            Ok(gtin) if gtin.gtin14 != product.barcode => {
+                                Some(format!("not stored as GTIN {}, as another barcode pads to the same number", gtin.gtin14))
+                            }
+                            Ok(gtin) => gtin.origin_warning(&product.origin_country),
+                        };
+                        if let Some(problem) = problem {
+                            println!("{} {}: {}", product.barcode, product.imported_name, problem);
+                            problems += 1;
+                        }
+                    }
+                    println!("{} products with barcode problems", problems);
+                }
+            }
+        }
+        
+        Commands::Codes { action } => {
+            let schemes = CodeSchemes::load(config.codes.schemes_file.as_deref())?;
+            
+            match action {
+                CodeAction::Schemes => {
+                    for scheme in &schemes.schemes {
+                        print_code_scheme(scheme);
+                    }
+                }
+            }
+        }
+        
+        Commands::Pricing { action } => {
+            let rules = PricingRules::load(config.pricing.rules_file.as_deref())?;
+            
+            match action {
+                PricingAction::Rules => {
+                    if rules.rules.is_empty() {
+                        println!("No pricing rules configured (see [pricing] rules_file)");
+                    }
+                    for rule in &rules.rules {
+                        print_pricing_rule(rule);
+                    }
+                }
+                PricingAction::Recalculate { category, brand, supplier, apply, note } => {
+                    let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+                    let scope = PricingScope { category, brand, supplier };
+                    let report = db.recalculate_prices(&rules, &scope, apply, &note.into()).await?;
+                    
+                    for change in &report.changes {
+                        let guard = match change.guard {
+                            Some(Guard::Floor) => " (raised to floor)",
+                            Some(Guard::Ceiling) => " (capped at ceiling)",
+                            None => "",
+                        };
+                        let margin = change.margin.map(|m| format!("{:.1}%", m)).unwrap_or_else(|| "-".to_string());
+                        println!("{} {} {}: {} -> {} {} (cost {}, margin {}, rule {}){}", change.barcode,
+                            change.product_name, change.price.as_str(), change.current, change.proposed,
+                            change.currency, change.landed_cost, margin, change.rule_id, guard);
+                    }
+                    for skip in &report.skipped {
+                        println!("{} {}: skipped ({})", skip.barcode, skip.price.as_str(), skip.reason);
+                    }
+                    
+                    let counts = format!("{} unchanged, {} skipped", report.unchanged, report.skipped.len());
+                    if report.applied {
+                        println!("Updated {} prices, {}", report.changes.len(), counts);
+                    } else {
+                        println!("Preview: {} prices would change, {} (use --apply to write them)", report.changes.len(), counts);
+                    }
+                }
+                PricingAction::History { barcode } => {
+                    let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+                    match db.get_price_history(&barcode).await {
+                        Ok(history) if history.is_empty() => println!("No price changes recorded"),
+                        Ok(history) => {
+                            for change in &history {
+                                print_price_change(change);
+                            }
+                        }
+                        Err(AppError::NotFound) => println!("Product not found"),
+                        Err(e) => println!("Error fetching price history: {}", e),
+                    }
+                }
+            }
+        }
+        
+        Commands::Reorder { action } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            
+            let (id, update) = match action {
+                ReorderAction::Suggest => {
+                    let suggestions = db.reorder_suggestions(&config.inventory, &config.reorder).await?;
+                    if suggestions.is_empty() {
+                        println!("Nothing needs reordering");
+                    }
+                    for s in &suggestions {
+                        println!("{} {} ({}): order {} ({} cases of {}) at {} {}", s.supplier, s.product_name, s.barcode,
+                            s.quantity, s.quantity / s.case_pack, s.case_pack, s.unit_cost, s.currency);
+                        println!("  {:.1}/day, stock {}, on order {}, reorder point {}, target {}, lead time {}d",
+                            s.per_day, s.stock, s.on_order, s.reorder_point, s.target_stock, s.lead_time_days);
+                    }
+                    return Ok(());
+                }
+                ReorderAction::Draft => {
+                    let orders = db.draft_purchase_orders(&config.inventory, &config.reorder).await?;
+                    if orders.is_empty() {
+                        println!("No new suggestions to draft");
+                    }
+                    for order in &orders {
+                        print_order(order)?;
+                    }
+                    return Ok(());
+                }
+                ReorderAction::Orders { status } => {
+                    let status = status.map(|status| status.parse()).transpose()?;
+                    let orders = db.get_purchase_orders(status).await?;
+                    if orders.is_empty() {
+                        println!("No purchase orders found!");
+                    }
+                    for order in &orders {
+                        println!("#{} {} [{}] {} lines, total {} {} ({})", order.id, order.supplier,
+                            order.status.as_str(), order.lines.len(), order.total, order.currency, order.updated_at);
+                    }
+                    return Ok(());
+                }
+                ReorderAction::Show { id } => {
+                    match db.get_purchase_order(id).await {
+                        Ok(order) => print_order(&order)?,
+                        Err(e) => println!("Error fetching purchase order: {}", e),
+                    }
+                    return Ok(());
+                }
+                ReorderAction::Set { id, barcode, quantity } => {
+                    match db.set_order_line(id, &barcode, quantity).await {
+                        Ok(order) => print_order(&order)?,
+                        Err(e) => println!("Error updating purchase order: {}", e),
+                    }
+                    return Ok(());
+                }
+                ReorderAction::Export { id, format, output } => {
+                    let order = db.get_purchase_order(id).await?;
+                    match (format.as_str(), output) {
+                        ("pdf", Some(path)) => std::fs::write(&path, order_pdf::render(&order)?)?,
+                        ("pdf", None) => {
+                            println!("PDF export needs --output");
+                            return Ok(());
+                        }
+                        (_, Some(path)) => csv_io::export_order_csv(&order, std::fs::File::create(&path)?)?,
+                        (_, None) => {
+                            csv_io::export_order_csv(&order, io::stdout())?;
+                            return Ok(());
+                        }
+                    }
+                    println!("Exported purchase order #{} with {} lines", order.id, order.lines.len());
+                    return Ok(());
+                }
+                ReorderAction::Approve { id } => (id, PurchaseOrderUpdate { status: Some(OrderStatus::Approved), ..Default::default() }),
+                ReorderAction::Receive { id } => (id, PurchaseOrderUpdate { status: Some(OrderStatus::Received), ..Default::default() }),
+                ReorderAction::Cancel { id } => (id, PurchaseOrderUpdate { status: Some(OrderStatus::Cancelled), ..Default::default() }),
+                ReorderAction::Note { id, notes } => (id, PurchaseOrderUpdate { notes: Some(notes), ..Default::default() }),
+            };
+            
+            match db.update_purchase_order(id, update).await {
+                Ok(order) => print_order(&order)?,
+                Err(e) => println!("Error updating purchase order: {}", e),
             }
         }
         
         Commands::List => {
-            let db = Database::new().await?;
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
             println!("Listing all products...");
             
             match db.get_all_products().await {
@@ -121,19 +1611,432 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
         
-        Commands::Report => {
-            let db = Database::new().await?;
-            println!("Generating inventory report...");
+        Commands::Show { key } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
             
-            match db.get_all_products().await {
-                Ok(products) => {
-                    let report = InventoryManager::generate_report(&products);
-                    println!("{}", report);
+            match db.find_product(&key).await {
+                Ok(product) => {
+                    print_product(&product);
+                    match db.product_costing(&product.barcode).await {
+                        Ok(costing) => print_costing(&costing),
+                        Err(e) => println!("  Landed cost:      {}", e),
+                    }
+                    let sales = db.get_sales(&product.barcode).await?;
+                    print_velocity(&InventoryManager::velocity(&product, &sales, &config.inventory.velocity_windows));
                 }
+                Err(e) => println!("Error fetching product: {}", e),
+            }
+        }
+        
+        Commands::Update { barcode, fields, regenerate_code, note } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            
+            match db.patch_product(&barcode, (*fields).into(), regenerate_code, &note.into()).await {
+                Ok(product) => {
+                    println!("Product updated:");
+                    print_product(&product);
+                }
+                Err(e) => println!("Error updating product: {}", e),
+            }
+        }
+        
+        Commands::Delete { barcode } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            
+            match db.delete_product(&barcode).await {
+                Ok(()) => println!("Product {} deleted (use `restore` to undo)", barcode),
+                Err(e) => println!("Error deleting product: {}", e),
+            }
+        }
+        
+        Commands::Restore { barcode } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            
+            match db.restore_product(&barcode).await {
+                Ok(product) => println!("Product {} restored", product.barcode),
+                Err(e) => println!("Error restoring product: {}", e),
+            }
+        }
+        
+        Commands::Lots { action } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            
+            match action {
+                LotAction::List { barcode } => match db.get_lots(&barcode).await {
+                    Ok(lots) if lots.is_empty() => println!("No lots found!"),
+                    Ok(lots) => {
+                        for lot in lots {
+                            println!("- {} batch {} - Qty: {} - Expires: {} - Landed cost: {}{}",
+                                lot.lot_code,
+                                lot.batch_id,
+                                lot.quantity,
+                                lot.expiry_date,
+                                lot.landed_cost,
+                                lot.supplier_invoice.map(|i| format!(" - Invoice: {}", i)).unwrap_or_default()
+                            );
+                        }
+                    }
+                    Err(e) => println!("Error fetching lots: {}", e),
+                },
+                LotAction::Add { barcode, batch_id, quantity, production_date, expiry_date, invoice, landed_cost } => {
+                    let lot = LotInput {
+                        batch_id,
+                        quantity,
+                        production_date: production_date.unwrap_or_else(|| chrono::Local::now().date_naive()),
+                        expiry_date,
+                        supplier_invoice: invoice,
+                        landed_cost,
+                    };
+                    
+                    match db.add_lot(&barcode, lot).await {
+                        Ok(lot) => println!("Lot {} received with {} units", lot.lot_code, lot.quantity),
+                        Err(e) => println!("Error adding lot: {}", e),
+                    }
+                }
+            }
+        }
+        
+        Commands::Stock { action } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            
+            let (movement, movement_type, reason, to_batch_id) = match action {
+                StockAction::Receive { movement } => (movement, MovementType::Receipt, None, None),
+                StockAction::Sell { movement } => (movement, MovementType::Sale, None, None),
+                StockAction::Return { movement } => (movement, MovementType::Return, None, None),
+                StockAction::Adjust { movement, reason } => (movement, MovementType::Adjustment, Some(reason), None),
+                StockAction::Transfer { movement, to_batch } => (movement, MovementType::Transfer, None, Some(to_batch)),
+                StockAction::WriteOff { movement, reason } => (movement, MovementType::WriteOff, Some(reason), None),
+                StockAction::History { barcode } => {
+                    match db.get_movements(&barcode).await {
+                        Ok(movements) if movements.is_empty() => println!("No movements found!"),
+                        Ok(movements) => {
+                            for m in movements {
+                                println!("{} {:>+6} {:<10} {} {}{}",
+                                    m.created_at,
+                                    m.quantity,
+                                    m.movement_type.as_str(),
+                                    m.lot_code.unwrap_or_default(),
+                                    m.reason.unwrap_or_default(),
+                                    m.reference.map(|r| format!(" ref {}", r)).unwrap_or_default()
+                                );
+                            }
+                        }
+                        Err(e) => println!("Error fetching movements: {}", e),
+                    }
+                    return Ok(());
+                }
+                StockAction::Reconcile => {
+                    let mismatches = db.check_ledger().await?;
+                    if mismatches.is_empty() {
+                        println!("Stock, lots and ledger agree for all products");
+                    }
+                    for m in mismatches {
+                        println!("{}: stock {} / lots {} / ledger {}",
+                            m.barcode, m.stock_quantity, m.lot_quantity, m.ledger_quantity);
+                    }
+                    return Ok(());
+                }
+            };
+            
+            let input = MovementInput {
+                movement_type,
+                quantity: movement.quantity,
+                batch_id: movement.batch_id,
+                to_batch_id,
+                reason,
+                reference: movement.reference,
+                note: movement.note,
+            };
+            match db.record_movement(&movement.barcode, input).await {
+                Ok(movements) => {
+                    for m in movements {
+                        println!("Recorded {} {:+} on lot {}", m.movement_type.as_str(), m.quantity, m.lot_code.unwrap_or_default());
+                    }
+                }
+                Err(e) => println!("Error recording movement: {}", e),
+            }
+        }
+        
+        Commands::Rules { action } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            let rules = db.load_rules(&RuleSet::load(config.alerts.rules_file.as_deref())?).await?;
+            
+            let (id, enabled) = match action {
+                RuleAction::List => {
+                    for rule in &rules.rules {
+                        println!("[{}] {} - {} / {}{}",
+                            if rule.enabled { "on " } else { "off" },
+                            rule.id,
+                            rule.alert_type.as_str(),
+                            rule.severity.as_str(),
+                            [("category", &rule.scope.category), ("supplier", &rule.scope.supplier), ("barcode", &rule.scope.barcode)]
+                                .iter()
+                                .filter_map(|(name, value)| value.as_ref().map(|value| format!(" {}={}", name, value)))
+                                .collect::<String>()
+                        );
+                    }
+                    return Ok(());
+                }
+                RuleAction::Enable { id } => (id, true),
+                RuleAction::Disable { id } => (id, false),
+            };
+            
+            if rules.get(&id).is_none() {
+                println!("No alert rule with id {:?}", id);
+                return Ok(());
+            }
+            db.set_rule_enabled(&id, enabled).await?;
+            println!("Rule {} {}", id, if enabled { "enabled" } else { "disabled" });
+        }
+        
+        Commands::Sales { action } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            
+            match action {
+                SalesAction::History { barcode } => match db.get_sales(&barcode).await {
+                    Ok(sales) if sales.is_empty() => println!("No sales found!"),
+                    Ok(sales) => {
+                        for day in sales {
+                            println!("{} {:>6} {}", day.sale_date, day.quantity, day.source.as_str());
+                        }
+                    }
+                    Err(e) => println!("Error fetching sales: {}", e),
+                },
+                SalesAction::Import { file, dry_run } => {
+                    let report = csv_io::import_sales_csv(&db, std::fs::File::open(&file)?, dry_run).await?;
+                    
+                    for error in &report.errors {
+                        println!("line {}: error: {}", error.line, error.message);
+                    }
+                    
+                    let summary = format!("{} days, {} errors", report.rows, report.errors.len());
+                    if report.committed {
+                        println!("Sales import committed: {}", summary);
+                    } else if report.dry_run {
+                        println!("Dry run, nothing written: {}", summary);
+                    } else {
+                        println!("Sales import rolled back: {}", summary);
+                    }
+                }
+                SalesAction::Velocity { barcode } => {
+                    match tokio::try_join!(db.get_product_by_barcode(&barcode), db.get_sales(&barcode)) {
+                        Ok((product, sales)) => {
+                            let velocity = InventoryManager::velocity(&product, &sales, &config.inventory.velocity_windows);
+                            println!("{} ({})", product.imported_name, product.barcode);
+                            print_velocity(&velocity);
+                        }
+                        Err(e) => println!("Error fetching sales: {}", e),
+                    }
+                }
+            }
+        }
+        
+        Commands::Lookup { barcode, json } => {
+            let collector = DataCollector::from_config(&config.lookup)?;
+            
+            match collector.fetch_from_barcode(&barcode).await {
+                Ok(input) if json => println!("{}", serde_json::to_string_pretty(&input)?),
+                Ok(input) => {
+                    println!("{} ({})", input.imported_name, input.original_name);
+                    println!("  Barcode:  {}", input.barcode);
+                    println!("  Brand:    {}", input.brand);
+                    println!("  Category: {}", input.category);
+                    println!("  Weight:   {}", input.weight);
+                    println!("  Origin:   {}", input.origin_country);
+                }
+                Err(e) => println!("Error looking up barcode: {}", e),
+            }
+        }
+        
+        Commands::Import { file, mapping, dry_run, upsert, note } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            let mapping = match mapping {
+                Some(path) => HeaderMapping::from_file(&path)?,
+                None => HeaderMapping::default(),
+            };
+            
+            let report = csv_io::import_csv(
+                &db,
+                std::fs::File::open(&file)?,
+                &mapping,
+                ImportOptions { dry_run, upsert },
+                &note.into(),
+            ).await?;
+            
+            for row in &report.rows {
+                println!("line {}: {:?} {} ({})", row.line, row.action, row.barcode, row.imported_name);
+                if let Some(warning) = &row.warning {
+                    println!("line {}: warning: {}", row.line, warning);
+                }
+            }
+            for column in &report.ignored_columns {
+                println!("Ignored column: {}", column);
+            }
+            for error in &report.errors {
+                println!("line {}: error: {}", error.line, error.message);
+            }
+            
+            let summary = format!("{} to insert, {} to update, {} errors",
+                report.count(ImportAction::Insert), report.count(ImportAction::Update), report.errors.len());
+            if report.committed {
+                println!("Import committed: {}", summary);
+            } else if report.dry_run {
+                println!("Dry run, nothing written: {}", summary);
+            } else {
+                println!("Import rolled back: {}", summary);
+            }
+        }
+        
+        Commands::Export { output, mapping } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            let mapping = match mapping {
+                Some(path) => HeaderMapping::from_file(&path)?,
+                None => HeaderMapping::default(),
+            };
+            let products = db.get_all_products().await?;
+            
+            match output {
+                Some(path) => {
+                    csv_io::export_csv(&products, &mapping, std::fs::File::create(&path)?)?;
+                    println!("Exported {} products to {}", products.len(), path.display());
+                }
+                None => csv_io::export_csv(&products, &mapping, io::stdout())?,
+            }
+        }
+        
+        Commands::Report { kind: Some(kind), params, format, output } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            let (kind, format): (ReportKind, ReportFormat) = (kind.parse()?, format.parse()?);
+            let params = ReportParams::try_from(params)?;
+            let (from, to) = params.period(config.reports.period_days, chrono::Local::now().date_naive())?;
+            
+            let report = reports::build(kind, &db.report_data(from, to).await?, &params)?;
+            let body = report.render(format)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, body)?;
+                    println!("Wrote {} report for {} to {} to {}", kind.as_str(), from, to, path.display());
+                }
+                None if format == ReportFormat::Pdf => println!("PDF export needs --output"),
+                None => io::stdout().write_all(&body)?,
+            }
+        }
+        
+        Commands::Report { kind: None, .. } => {
+            let db = Database::new(&config.database, &config.currency, &config.codes).await?;
+            println!("Generating inventory report...");
+            
+            let report = tokio::try_join!(db.get_all_products(), db.exchange_rates())
+                .and_then(|(products, rates)| InventoryManager::generate_report(&products, &rates));
+            match report {
+                Ok(report) => println!("{}", report),
                 Err(e) => println!("Error generating report: {}", e),
             }
         }
+        
+        Commands::Migrate { action } => {
+            let db = Database::connect(&config.database, &config.currency, &config.codes).await?;
+            
+            match action {
+                MigrateAction::Status => {
+                    for migration in db.migration_status().await? {
+                        match migration.installed_on {
+                            Some(installed_on) => println!("[applied {}] {} {}",
+                                installed_on, migration.version, migration.description),
+                            None => println!("[pending] {} {}",
+                                migration.version, migration.description),
+                        }
+                    }
+                    let repairs = db.date_repairs().await?;
+                    if !repairs.is_empty() {
+                        println!("\n{} dates were rewritten when dates became strict:", repairs.len());
+                        for repair in repairs {
+                            println!("  {} {} {}: {:?} -> {}", repair.table_name, repair.row_id, repair.column_name,
+                                repair.original_value.unwrap_or_default(), repair.replaced_with.unwrap_or_default());
+                        }
+                    }
+                }
+                MigrateAction::Up => {
+                    db.migrate_up().await?;
+                    println!("Database schema is up to date");
+                }
+                MigrateAction::Down => match db.migrate_down().await? {
+                    Some(version) => println!("Reverted migration {}", version),
+                    None => println!("No migrations to revert"),
+                },
+                MigrateAction::Redo => match db.migrate_down().await? {
+                    Some(version) => {
+                        db.migrate_up().await?;
+                        println!("Re-applied migration {}", version);
+                    }
+                    None => println!("No migrations to redo"),
+                },
+            }
+        }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::{chickpeas, file_db, file_db_config};
+    
+    #[tokio::test]
+    async fn update_command_rejects_an_invalid_product() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config { database: file_db_config(dir.path()), ..Config::default() };
+        let db = file_db(dir.path()).await;
+        db.add_product(chickpeas("5281234567896"), &ChangeNote::default()).await.unwrap();
+        let update = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["food_imports_db", "update", "5281234567896"].iter().chain(args)).unwrap();
+            run(cli.command, config.clone())
+        };
+        
+        update(&["--stock-quantity=-5", "--retail-price=-3", "--case-pack", "0", "--expiry-date", "2000-01-01"]).await.unwrap();
+        let stored = db.get_product_by_barcode("5281234567896").await.unwrap();
+        assert_eq!((stored.stock_quantity, stored.case_pack), (24, 1));
+        assert_eq!(stored.retail_price, "29.90".parse().unwrap());
+        assert_eq!(db.get_lots("5281234567896").await.unwrap()[0].quantity, 24);
+        
+        update(&["--retail-price", "31.50", "--case-pack", "6"]).await.unwrap();
+        let stored = db.get_product_by_barcode("5281234567896").await.unwrap();
+        assert_eq!((stored.retail_price, stored.case_pack), ("31.50".parse().unwrap(), 6));
+    }
+    
+    /// The only test that sets `FOOD_IMPORTS_*` variables, as they are shared by
+    /// every test in the process.
+    #[test]
+    fn config_layers_file_then_environment_then_flags() {
+        let path = std::env::temp_dir().join(format!("food_imports_layers_{}.toml", std::process::id()));
+        std::fs::write(&path, "log_level = \"warn\"\n\n[database]\nurl = \"sqlite:file.db\"\nmax_connections = 3\n").unwrap();
+        let config_arg = path.to_str().unwrap();
+        let load = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["food_imports_db", "--config", config_arg].iter().chain(args).chain(&["list"])).unwrap();
+            load_config(&cli)
+        };
+        
+        let config = load(&[]).unwrap();
+        assert_eq!((config.database.url.as_str(), config.database.max_connections), ("sqlite:file.db", 3));
+        assert_eq!(config.log_level, "warn");
+        assert_eq!(config.database.busy_timeout_ms, 5000);
+        
+        std::env::set_var("FOOD_IMPORTS_DATABASE_URL", "sqlite:env.db");
+        std::env::set_var("FOOD_IMPORTS_DB_POOL_SIZE", "7");
+        let config = load(&[]).unwrap();
+        assert_eq!((config.database.url.as_str(), config.database.max_connections), ("sqlite:env.db", 7));
+        
+        let config = load(&["--database-url", "sqlite:flag.db", "--log-level", "debug"]).unwrap();
+        assert_eq!((config.database.url.as_str(), config.database.max_connections), ("sqlite:flag.db", 7));
+        assert_eq!(config.log_level, "debug");
+        
+        std::env::set_var("FOOD_IMPORTS_DB_POOL_SIZE", "0");
+        let error = load(&[]).unwrap_err().to_string();
+        
+        std::env::remove_var("FOOD_IMPORTS_DATABASE_URL");
+        std::env::remove_var("FOOD_IMPORTS_DB_POOL_SIZE");
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.contains("max_connections must be at least 1"), "{}", error);
+    }
+}
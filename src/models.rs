@@ -1,5 +1,10 @@
+use chrono::NaiveDate;
 use serde::{Serialize, Deserialize};
 use thiserror::Error;
+use crate::alert_rules::{AlertType, Severity};
+use crate::barcode::Symbology;
+use crate::money::{Money, Rate};
+use crate::scheduler::JobKind;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Product {
@@ -7,33 +12,1164 @@ pub struct Product {
     pub original_name: String,
     pub imported_name: String,
     pub local_name: Option<String>,
+    /// Stored as a 14-digit GTIN; `barcode_symbology` says how it is printed
     pub barcode: String,
+    /// How the barcode is printed; none for barcodes stored before they were checked
+    #[serde(default)]
+    pub barcode_symbology: Option<Symbology>,
     pub internal_code: String,
     pub alternative_code: String,
     pub brand: String,
     pub category: String,
     pub weight: String,
     pub origin_country: String,
+    /// Supplier name; a name without a matching supplier adds one
     pub supplier: String,
-    pub purchase_price: f64,
-    pub wholesale_price: f64,
-    pub retail_price: f64,
-    pub production_date: String,  // Simplified as string
-    pub expiry_date: String,      // Simplified as string
+    /// Takes precedence over `supplier` when set
+    #[serde(default)]
+    pub supplier_id: Option<i64>,
+    pub purchase_price: Money,
+    /// ISO 4217 code of the purchase price; the supplier's currency when left empty
+    #[serde(default)]
+    pub purchase_currency: String,
+    pub wholesale_price: Money,
+    /// The base currency when left empty
+    #[serde(default)]
+    pub wholesale_currency: String,
+    pub retail_price: Money,
+    /// The base currency when left empty
+    #[serde(default)]
+    pub retail_currency: String,
+    /// Freight per unit, in the purchase currency
+    #[serde(default)]
+    pub freight_cost: Money,
+    /// Import duty as a percentage of purchase price plus freight
+    #[serde(default)]
+    pub duty_percent: Rate,
+    /// Handling per unit, in the purchase currency
+    #[serde(default)]
+    pub handling_cost: Money,
+    pub production_date: NaiveDate,
+    pub expiry_date: NaiveDate,
     pub batch_id: i32,
     pub stock_quantity: i32,
-    pub monthly_sales: i32,
     pub min_threshold: i32,
+    /// Units per case the supplier ships
+    #[serde(default = "default_case_pack")]
+    pub case_pack: i32,
+    /// Code scheme and version the codes were generated with; set on insert
+    #[serde(default)]
+    pub code_scheme: String,
+    #[serde(default)]
+    pub code_version: u32,
+    /// Number the product's codes count with, from the sequence of its
+    /// alternative code prefix; set on insert and never handed out again
+    #[serde(default)]
+    pub code_sequence: Option<i64>,
+}
+
+/// One delivery of a product on the shelf, with its own quantity and expiry.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Lot {
+    pub id: Option<i64>,
+    pub product_id: i64,
+    pub batch_id: i32,
+    pub lot_code: String,
+    pub quantity: i32,
+    pub production_date: NaiveDate,
+    pub expiry_date: NaiveDate,
+    pub supplier_invoice: Option<String>,
+    /// Cost per unit delivered, in the base currency
+    pub landed_cost: Money,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LotInput {
+    pub batch_id: i32,
+    pub quantity: i32,
+    pub production_date: NaiveDate,
+    pub expiry_date: NaiveDate,
+    pub supplier_invoice: Option<String>,
+    /// Cost per unit delivered, in the base currency
+    pub landed_cost: Money,
+}
+
+impl LotInput {
+    pub fn validate(&self) -> Result<(), AppError> {
+        let mut problems = Vec::new();
+        
+        if self.batch_id < 0 {
+            problems.push("batch_id must not be negative".to_string());
+        }
+        if self.quantity < 0 {
+            problems.push("quantity must not be negative".to_string());
+        }
+        if self.landed_cost.is_negative() {
+            problems.push("landed_cost must not be negative".to_string());
+        }
+        
+        if self.expiry_date < self.production_date {
+            problems.push("expiry_date must not be before production_date".to_string());
+        }
+        
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Validation(problems.join("; ")))
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MovementType {
+    /// Stock on hand when the ledger was introduced
+    Opening,
+    Receipt,
+    Sale,
+    Return,
+    Adjustment,
+    Transfer,
+    WriteOff,
+}
+
+impl MovementType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MovementType::Opening => "opening",
+            MovementType::Receipt => "receipt",
+            MovementType::Sale => "sale",
+            MovementType::Return => "return",
+            MovementType::Adjustment => "adjustment",
+            MovementType::Transfer => "transfer",
+            MovementType::WriteOff => "write_off",
+        }
+    }
+}
+
+impl std::str::FromStr for MovementType {
+    type Err = AppError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "opening" => Ok(MovementType::Opening),
+            "receipt" => Ok(MovementType::Receipt),
+            "sale" => Ok(MovementType::Sale),
+            "return" => Ok(MovementType::Return),
+            "adjustment" => Ok(MovementType::Adjustment),
+            "transfer" => Ok(MovementType::Transfer),
+            "write_off" => Ok(MovementType::WriteOff),
+            other => Err(AppError::Validation(format!("unknown movement type {:?}", other))),
+        }
+    }
+}
+
+/// Reason codes accepted for manual adjustments.
+pub const ADJUSTMENT_REASONS: [&str; 5] = ["count_correction", "damaged", "lost", "found", "data_entry"];
+/// Reason codes accepted for write-offs.
+pub const WRITE_OFF_REASONS: [&str; 4] = ["expired", "spoiled", "damaged", "recalled"];
+/// Reason recorded when stock changes because a product record was edited or imported.
+pub const PRODUCT_EDIT_REASON: &str = "product_edit";
+
+/// One entry in the append-only stock ledger. `quantity` is signed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StockMovement {
+    pub id: i64,
+    pub product_id: i64,
+    pub lot_id: Option<i64>,
+    pub lot_code: Option<String>,
+    pub movement_type: MovementType,
+    pub quantity: i32,
+    pub reason: Option<String>,
+    pub reference: Option<String>,
+    pub note: Option<String>,
+    pub created_at: String,
+}
+
+/// Where a day's sales figure came from. When several sources cover the same
+/// product and day, the most authoritative one wins: POS, then ledger, then estimate.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum SalesSource {
+    Pos,
+    Ledger,
+    /// Spread from the typed-in monthly figure the history replaced
+    Estimate,
+}
+
+impl SalesSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SalesSource::Pos => "pos",
+            SalesSource::Ledger => "ledger",
+            SalesSource::Estimate => "estimate",
+        }
+    }
+}
+
+impl std::str::FromStr for SalesSource {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pos" => Ok(SalesSource::Pos),
+            "ledger" => Ok(SalesSource::Ledger),
+            "estimate" => Ok(SalesSource::Estimate),
+            other => Err(AppError::Validation(format!("unknown sales source {:?}", other))),
+        }
+    }
+}
+
+/// Units of a product sold on one day, net of returns.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DailySales {
+    pub product_id: i64,
+    pub sale_date: NaiveDate,
+    pub source: SalesSource,
+    pub quantity: i32,
+}
+
+/// Units sold over the last `days` days.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VelocityWindow {
+    pub days: u32,
+    pub units: i32,
+    pub per_day: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Trend {
+    Rising,
+    Stable,
+    Falling,
+    /// Not enough sales history to compare a short window against a longer one
+    Insufficient,
+}
+
+/// Sales velocity of one product over the configured windows, shortest first.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SalesVelocity {
+    pub barcode: String,
+    pub windows: Vec<VelocityWindow>,
+    pub trend: Trend,
+}
+
+/// A requested stock operation. `quantity` is the number of units moved; only
+/// adjustments take a signed quantity.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MovementInput {
+    pub movement_type: MovementType,
+    pub quantity: i32,
+    /// Lot to act on; sales and write-offs without one take the earliest expiry
+    /// first, and sales skip expired lots
+    pub batch_id: Option<i32>,
+    /// Destination lot for transfers
+    pub to_batch_id: Option<i32>,
+    pub reason: Option<String>,
+    pub reference: Option<String>,
+    pub note: Option<String>,
+}
+
+impl MovementInput {
+    pub fn validate(&self) -> Result<(), AppError> {
+        let problem = match self.movement_type {
+            MovementType::Opening => Some("opening balances are only recorded by migrations".to_string()),
+            MovementType::Adjustment if self.quantity == 0 => Some("quantity must not be zero".to_string()),
+            MovementType::Adjustment => match self.reason.as_deref() {
+                Some(reason) if ADJUSTMENT_REASONS.contains(&reason) => None,
+                _ => Some(format!("adjustments need a reason: {}", ADJUSTMENT_REASONS.join(", "))),
+            },
+            _ if self.quantity <= 0 => Some("quantity must be positive".to_string()),
+            MovementType::WriteOff => match self.reason.as_deref() {
+                Some(reason) if WRITE_OFF_REASONS.contains(&reason) => None,
+                _ => Some(format!("write-offs need a reason: {}", WRITE_OFF_REASONS.join(", "))),
+            },
+            MovementType::Transfer => match (self.batch_id, self.to_batch_id) {
+                (Some(from), Some(to)) if from != to => None,
+                _ => Some("transfers need different batch_id and to_batch_id".to_string()),
+            },
+            _ => None,
+        };
+        
+        match problem {
+            Some(problem) => Err(AppError::Validation(problem)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertStatus {
+    Open,
+    Acknowledged,
+    /// Hidden until `snoozed_until`, then open again if the problem persists
+    Snoozed,
+    Resolved,
+}
+
+impl AlertStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertStatus::Open => "open",
+            AlertStatus::Acknowledged => "acknowledged",
+            AlertStatus::Snoozed => "snoozed",
+            AlertStatus::Resolved => "resolved",
+        }
+    }
+}
+
+impl std::str::FromStr for AlertStatus {
+    type Err = AppError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(AlertStatus::Open),
+            "acknowledged" => Ok(AlertStatus::Acknowledged),
+            "snoozed" => Ok(AlertStatus::Snoozed),
+            "resolved" => Ok(AlertStatus::Resolved),
+            other => Err(AppError::Validation(format!("unknown alert status {:?}", other))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for JobStatus {
+    type Err = AppError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "running" => Ok(JobStatus::Running),
+            "succeeded" => Ok(JobStatus::Succeeded),
+            "failed" => Ok(JobStatus::Failed),
+            other => Err(AppError::Validation(format!("unknown job status {:?}", other))),
+        }
+    }
+}
+
+/// One run of a background job, scheduled or started by hand.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobRun {
+    pub id: i64,
+    pub job: JobKind,
+    /// `schedule` or `manual`
+    pub triggered_by: String,
+    pub status: JobStatus,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+}
+
+/// An alert as stored, tracked across evaluations by its fingerprint.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrackedAlert {
+    pub id: i64,
+    pub fingerprint: String,
+    pub rule_id: String,
+    pub alert_type: AlertType,
+    pub severity: Severity,
+    pub barcode: String,
+    pub product_name: String,
+    pub lot_code: Option<String>,
+    pub message: String,
+    pub status: AlertStatus,
+    pub snoozed_until: Option<NaiveDate>,
+    pub assignee: Option<String>,
+    pub first_seen_at: String,
+    pub last_seen_at: String,
+    pub resolved_at: Option<String>,
+    pub notes: Vec<AlertNote>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlertNote {
+    pub id: i64,
+    pub note: String,
+    pub author: Option<String>,
+    pub created_at: String,
+}
+
+/// A change to a tracked alert; only the fields that are present are applied.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AlertUpdate {
+    pub status: Option<AlertStatus>,
+    /// Required when snoozing
+    pub snoozed_until: Option<NaiveDate>,
+    /// An empty string clears the assignee
+    pub assignee: Option<String>,
+    pub note: Option<String>,
+    pub author: Option<String>,
+}
+
+impl AlertUpdate {
+    pub fn validate(&self) -> Result<(), AppError> {
+        let today = chrono::Local::now().date_naive();
+        let problem = match (self.status, self.snoozed_until) {
+            (Some(AlertStatus::Snoozed), None) => Some("snoozing needs a snoozed_until date"),
+            (Some(AlertStatus::Snoozed), Some(until)) if until <= today => Some("snoozed_until must be in the future"),
+            (Some(AlertStatus::Snoozed), _) => None,
+            (_, Some(_)) => Some("snoozed_until is only used with status snoozed"),
+            _ if self.note.as_deref().is_some_and(|note| note.trim().is_empty()) => Some("note must not be empty"),
+            _ => None,
+        };
+        
+        match problem {
+            Some(problem) => Err(AppError::Validation(problem.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+fn default_case_pack() -> i32 {
+    1
+}
+
+/// Whether `code` looks like an ISO 4217 currency code: three uppercase letters.
+pub fn is_currency_code(code: &str) -> bool {
+    code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase())
+}
+
+fn currency_code(code: &str) -> String {
+    code.trim().to_ascii_uppercase()
+}
+
+/// Units of the base currency one unit of `currency` buys from `valid_from` until
+/// the next rate for that currency.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExchangeRate {
+    pub id: i64,
+    pub currency: String,
+    pub rate: Rate,
+    pub valid_from: NaiveDate,
+    /// Where the rate came from, e.g. a bank or the import file name
+    pub source: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExchangeRateInput {
+    pub currency: String,
+    pub rate: Rate,
+    pub valid_from: NaiveDate,
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+impl ExchangeRateInput {
+    pub fn validate(&self, base: &str) -> Result<(), AppError> {
+        let mut problems = Vec::new();
+        
+        if !is_currency_code(&self.currency) {
+            problems.push("currency must be a three-letter ISO code, e.g. USD".to_string());
+        } else if self.currency == base {
+            problems.push(format!("{} is the base currency and needs no rate", base));
+        }
+        if !self.rate.is_positive() {
+            problems.push("rate must be positive".to_string());
+        }
+        
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Validation(problems.join("; ")))
+        }
+    }
+}
+
+/// A company products are bought from. Reorder lead times, order currency and the
+/// minimum order value come from here.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Supplier {
+    pub id: Option<i64>,
+    pub name: String,
+    pub contact_name: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    pub country: Option<String>,
+    /// ISO 4217 code, e.g. SEK
+    pub currency: String,
+    /// Days from order to delivery; the reorder default applies when not set
+    pub lead_time_days: Option<u32>,
+    pub min_order_value: Money,
+    pub payment_terms: Option<String>,
+    pub vat_number: Option<String>,
+}
+
+impl Supplier {
+    /// Trailing legal forms ignored when matching supplier names.
+    const LEGAL_FORMS: [&'static str; 18] = [
+        "gmbh", "corp", "sarl", "ltd", "inc", "llc", "sal", "plc", "aps", "srl",
+        "ab", "oy", "as", "sa", "bv", "ag", "hb", "kb",
+    ];
+    
+    /// The key names are matched on: lowercase, single-spaced and without a trailing
+    /// legal form, so "XYZ Import AB" and "xyz import" are the same supplier. Only
+    /// ASCII letters are lowercased, as in the migration that introduced it.
+    pub fn name_key(name: &str) -> String {
+        let name = name.trim().to_ascii_lowercase();
+        let key = name
+            .trim_end_matches([' ', '.', ','])
+            .split(' ')
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let key = match key.rsplit_once(' ') {
+            Some((rest, form)) if Self::LEGAL_FORMS.contains(&form) => rest,
+            _ => &key,
+        };
+        key.trim_end_matches([' ', '.', ',']).to_string()
+    }
+    
+    pub fn validate(&self) -> Result<(), AppError> {
+        let mut problems = Vec::new();
+        
+        if Self::name_key(&self.name).is_empty() {
+            problems.push("name is required".to_string());
+        }
+        if !is_currency_code(&self.currency) {
+            problems.push("currency must be a three-letter ISO code, e.g. SEK".to_string());
+        }
+        if self.min_order_value.is_negative() {
+            problems.push("min_order_value must not be negative".to_string());
+        }
+        if self.email.as_ref().is_some_and(|email| !email.contains('@')) {
+            problems.push("email must be an email address".to_string());
+        }
+        
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Validation(problems.join("; ")))
+        }
+    }
+}
+
+/// Partial update for a supplier; only the fields that are present are changed.
+/// An empty string clears an optional field.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SupplierPatch {
+    pub name: Option<String>,
+    pub contact_name: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    pub country: Option<String>,
+    pub currency: Option<String>,
+    pub lead_time_days: Option<u32>,
+    pub min_order_value: Option<Money>,
+    pub payment_terms: Option<String>,
+    pub vat_number: Option<String>,
+}
+
+impl SupplierPatch {
+    /// Builds a new supplier from the patch; only the name is required. The result
+    /// still needs `validate`.
+    pub fn into_supplier(self) -> Result<Supplier, AppError> {
+        if self.name.is_none() {
+            return Err(AppError::Validation("name is required".to_string()));
+        }
+        
+        let mut supplier = Supplier {
+            id: None,
+            name: String::new(),
+            contact_name: None,
+            email: None,
+            phone: None,
+            address: None,
+            country: None,
+            currency: "SEK".to_string(),
+            lead_time_days: None,
+            min_order_value: Money::ZERO,
+            payment_terms: None,
+            vat_number: None,
+        };
+        self.apply(&mut supplier);
+        Ok(supplier)
+    }
+    
+    pub fn apply(self, supplier: &mut Supplier) {
+        fn text(field: &mut Option<String>, value: Option<String>) {
+            if let Some(value) = value {
+                *field = Some(value.trim().to_string()).filter(|v| !v.is_empty());
+            }
+        }
+        
+        if let Some(name) = self.name {
+            supplier.name = name.trim().to_string();
+        }
+        text(&mut supplier.contact_name, self.contact_name);
+        text(&mut supplier.email, self.email);
+        text(&mut supplier.phone, self.phone);
+        text(&mut supplier.address, self.address);
+        text(&mut supplier.country, self.country);
+        if let Some(currency) = self.currency {
+            supplier.currency = currency_code(&currency);
+        }
+        if self.lead_time_days.is_some() {
+            supplier.lead_time_days = self.lead_time_days;
+        }
+        if let Some(value) = self.min_order_value {
+            supplier.min_order_value = value;
+        }
+        text(&mut supplier.payment_terms, self.payment_terms);
+        text(&mut supplier.vat_number, self.vat_number);
+    }
+}
+
+/// What is held and has been bought from one supplier.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SupplierSpend {
+    pub supplier_id: i64,
+    pub supplier: String,
+    pub currency: String,
+    pub products: i64,
+    /// Lots on hand at landed cost, in the base currency
+    pub stock_value: Money,
+    /// Units received into lots during the period
+    pub received_units: i64,
+    /// Landed cost of the units received, in the base currency
+    pub received_value: Money,
+    /// Purchase orders approved during the period and not cancelled
+    pub orders: i64,
+    /// Order totals in `currency`
+    pub ordered_value: Money,
+    /// Approved purchase orders not yet received, whenever placed
+    pub on_order_value: Money,
+}
+
+/// Product fields as entered or looked up, before codes are generated.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProductInput {
+    pub original_name: String,
+    pub imported_name: String,
+    pub local_name: Option<String>,
+    pub barcode: String,
+    pub brand: String,
+    pub category: String,
+    pub weight: String,
+    pub origin_country: String,
+    pub supplier: String,
+    pub purchase_price: Money,
+    pub wholesale_price: Money,
+    pub retail_price: Money,
+    pub production_date: NaiveDate,
+    pub expiry_date: NaiveDate,
+    pub batch_id: i32,
+    pub stock_quantity: i32,
+    pub min_threshold: i32,
+    /// Units per case the supplier ships
+    #[serde(default = "default_case_pack")]
+    pub case_pack: i32,
+}
+
+impl Product {
+    /// Checks field formats before the product is written to the database.
+    pub fn validate(&self) -> Result<(), AppError> {
+        let mut problems = Vec::new();
+        
+        for (field, value) in [
+            ("original_name", &self.original_name),
+            ("imported_name", &self.imported_name),
+            ("barcode", &self.barcode),
+            ("brand", &self.brand),
+            ("category", &self.category),
+            ("weight", &self.weight),
+            ("origin_country", &self.origin_country),
+        ] {
+            if value.trim().is_empty() {
+                problems.push(format!("{} is required", field));
+            }
+        }
+        if self.supplier_id.is_none() && self.supplier.trim().is_empty() {
+            problems.push("supplier is required".to_string());
+        }
+        
+        if !self.weight.is_empty() && !self.weight.chars().any(|c| c.is_ascii_digit()) {
+            problems.push("weight must include an amount, e.g. 900g".to_string());
+        }
+        
+        for (field, value) in [
+            ("purchase_price", self.purchase_price),
+            ("wholesale_price", self.wholesale_price),
+            ("retail_price", self.retail_price),
+            ("freight_cost", self.freight_cost),
+            ("handling_cost", self.handling_cost),
+        ] {
+            if value.is_negative() {
+                problems.push(format!("{} must not be negative", field));
+            }
+        }
+        if self.duty_percent.is_negative() {
+            problems.push("duty_percent must not be negative".to_string());
+        }
+        
+        for (field, value) in [
+            ("purchase_currency", &self.purchase_currency),
+            ("wholesale_currency", &self.wholesale_currency),
+            ("retail_currency", &self.retail_currency),
+        ] {
+            if !value.is_empty() && !is_currency_code(value) {
+                problems.push(format!("{} must be a three-letter ISO code, e.g. SEK", field));
+            }
+        }
+        
+        for (field, value) in [
+            ("batch_id", self.batch_id),
+            ("stock_quantity", self.stock_quantity),
+            ("min_threshold", self.min_threshold),
+        ] {
+            if value < 0 {
+                problems.push(format!("{} must not be negative", field));
+            }
+        }
+        
+        if self.case_pack < 1 {
+            problems.push("case_pack must be at least 1".to_string());
+        }
+        
+        if self.expiry_date < self.production_date {
+            problems.push("expiry_date must not be before production_date".to_string());
+        }
+        
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Validation(problems.join("; ")))
+        }
+    }
+}
+
+/// Partial update for a product; only the fields that are present are changed.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ProductPatch {
+    pub original_name: Option<String>,
+    pub imported_name: Option<String>,
+    pub local_name: Option<String>,
+    pub barcode: Option<String>,
+    pub brand: Option<String>,
+    pub category: Option<String>,
+    pub weight: Option<String>,
+    pub origin_country: Option<String>,
+    pub supplier: Option<String>,
+    pub supplier_id: Option<i64>,
+    pub purchase_price: Option<Money>,
+    pub purchase_currency: Option<String>,
+    pub wholesale_price: Option<Money>,
+    pub wholesale_currency: Option<String>,
+    pub retail_price: Option<Money>,
+    pub retail_currency: Option<String>,
+    pub freight_cost: Option<Money>,
+    pub duty_percent: Option<Rate>,
+    pub handling_cost: Option<Money>,
+    pub production_date: Option<NaiveDate>,
+    pub expiry_date: Option<NaiveDate>,
+    pub batch_id: Option<i32>,
+    pub stock_quantity: Option<i32>,
+    pub min_threshold: Option<i32>,
+    pub case_pack: Option<i32>,
+}
+
+impl ProductPatch {
+    /// Builds a new product from the patch, filling optional fields with the
+    /// same defaults as the dashboard form. Only the expiry date has no default.
+    /// The result still needs `validate`.
+    pub fn into_product(self) -> Result<Product, AppError> {
+        let expiry_date = self.expiry_date
+            .ok_or_else(|| AppError::Validation("expiry_date is required".to_string()))?;
+        
+        Ok(Product {
+            id: None,
+            original_name: self.original_name.unwrap_or_default(),
+            imported_name: self.imported_name.unwrap_or_default(),
+            local_name: self.local_name.filter(|name| !name.is_empty()),
+            barcode: self.barcode.unwrap_or_default(),
+            barcode_symbology: None,
+            internal_code: String::new(),
+            alternative_code: String::new(),
+            code_scheme: String::new(),
+            code_version: 0,
+            code_sequence: None,
+            brand: self.brand.unwrap_or_default(),
+            category: self.category.unwrap_or_default(),
+            weight: self.weight.unwrap_or_default(),
+            origin_country: self.origin_country.unwrap_or_default(),
+            supplier: self.supplier.unwrap_or_default(),
+            supplier_id: self.supplier_id,
+            purchase_price: self.purchase_price.unwrap_or_default(),
+            purchase_currency: self.purchase_currency.as_deref().map(currency_code).unwrap_or_default(),
+            wholesale_price: self.wholesale_price.unwrap_or_default(),
+            wholesale_currency: self.wholesale_currency.as_deref().map(currency_code).unwrap_or_default(),
+            retail_price: self.retail_price.unwrap_or_default(),
+            retail_currency: self.retail_currency.as_deref().map(currency_code).unwrap_or_default(),
+            freight_cost: self.freight_cost.unwrap_or_default(),
+            duty_percent: self.duty_percent.unwrap_or_default(),
+            handling_cost: self.handling_cost.unwrap_or_default(),
+            production_date: self.production_date.unwrap_or_else(|| chrono::Local::now().date_naive()),
+            expiry_date,
+            batch_id: self.batch_id.unwrap_or(1),
+            stock_quantity: self.stock_quantity.unwrap_or(0),
+            min_threshold: self.min_threshold.unwrap_or(10),
+            case_pack: self.case_pack.unwrap_or(1),
+        })
+    }
+    
+    /// Overrides this patch with the fields present in `other`.
+    pub fn merge(&mut self, other: ProductPatch) {
+        fn take<T>(field: &mut Option<T>, value: Option<T>) {
+            if value.is_some() {
+                *field = value;
+            }
+        }
+        
+        take(&mut self.original_name, other.original_name);
+        take(&mut self.imported_name, other.imported_name);
+        take(&mut self.local_name, other.local_name);
+        take(&mut self.barcode, other.barcode);
+        take(&mut self.brand, other.brand);
+        take(&mut self.category, other.category);
+        take(&mut self.weight, other.weight);
+        take(&mut self.origin_country, other.origin_country);
+        take(&mut self.supplier, other.supplier);
+        take(&mut self.supplier_id, other.supplier_id);
+        take(&mut self.purchase_price, other.purchase_price);
+        take(&mut self.purchase_currency, other.purchase_currency);
+        take(&mut self.wholesale_price, other.wholesale_price);
+        take(&mut self.wholesale_currency, other.wholesale_currency);
+        take(&mut self.retail_price, other.retail_price);
+        take(&mut self.retail_currency, other.retail_currency);
+        take(&mut self.freight_cost, other.freight_cost);
+        take(&mut self.duty_percent, other.duty_percent);
+        take(&mut self.handling_cost, other.handling_cost);
+        take(&mut self.production_date, other.production_date);
+        take(&mut self.expiry_date, other.expiry_date);
+        take(&mut self.batch_id, other.batch_id);
+        take(&mut self.stock_quantity, other.stock_quantity);
+        take(&mut self.min_threshold, other.min_threshold);
+        take(&mut self.case_pack, other.case_pack);
+    }
+    
+    pub fn apply(self, product: &mut Product) {
+        fn set<T>(field: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+        
+        set(&mut product.original_name, self.original_name);
+        set(&mut product.imported_name, self.imported_name);
+        if self.local_name.is_some() {
+            product.local_name = self.local_name;
+        }
+        set(&mut product.barcode, self.barcode);
+        set(&mut product.brand, self.brand);
+        set(&mut product.category, self.category);
+        set(&mut product.weight, self.weight);
+        set(&mut product.origin_country, self.origin_country);
+        // A new supplier name replaces the supplier, unless an id is given as well
+        if self.supplier.is_some() || self.supplier_id.is_some() {
+            product.supplier_id = self.supplier_id;
+        }
+        set(&mut product.supplier, self.supplier);
+        set(&mut product.purchase_price, self.purchase_price);
+        set(&mut product.purchase_currency, self.purchase_currency.as_deref().map(currency_code));
+        set(&mut product.wholesale_price, self.wholesale_price);
+        set(&mut product.wholesale_currency, self.wholesale_currency.as_deref().map(currency_code));
+        set(&mut product.retail_price, self.retail_price);
+        set(&mut product.retail_currency, self.retail_currency.as_deref().map(currency_code));
+        set(&mut product.freight_cost, self.freight_cost);
+        set(&mut product.duty_percent, self.duty_percent);
+        set(&mut product.handling_cost, self.handling_cost);
+        set(&mut product.production_date, self.production_date);
+        set(&mut product.expiry_date, self.expiry_date);
+        set(&mut product.batch_id, self.batch_id);
+        set(&mut product.stock_quantity, self.stock_quantity);
+        set(&mut product.min_threshold, self.min_threshold);
+        set(&mut product.case_pack, self.case_pack);
+    }
+}
+
+/// One of a product's three prices.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceKind {
+    Purchase,
+    Wholesale,
+    Retail,
+}
+
+impl PriceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PriceKind::Purchase => "purchase",
+            PriceKind::Wholesale => "wholesale",
+            PriceKind::Retail => "retail",
+        }
+    }
+    
+    /// The price's amount and currency on `product`.
+    pub fn of<'a>(&self, product: &'a Product) -> (Money, &'a str) {
+        match self {
+            PriceKind::Purchase => (product.purchase_price, &product.purchase_currency),
+            PriceKind::Wholesale => (product.wholesale_price, &product.wholesale_currency),
+            PriceKind::Retail => (product.retail_price, &product.retail_currency),
+        }
+    }
+}
+
+impl std::str::FromStr for PriceKind {
+    type Err = AppError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "purchase" => Ok(PriceKind::Purchase),
+            "wholesale" => Ok(PriceKind::Wholesale),
+            "retail" => Ok(PriceKind::Retail),
+            other => Err(AppError::Validation(format!("unknown price {:?}", other))),
+        }
+    }
+}
+
+/// What wrote a price into the history.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    /// The price the product was added with
+    Added,
+    Edit,
+    Import,
+    /// A pricing rule, applied by a recalculation
+    Rule,
+}
+
+impl PriceSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PriceSource::Added => "added",
+            PriceSource::Edit => "edit",
+            PriceSource::Import => "import",
+            PriceSource::Rule => "rule",
+        }
+    }
+}
+
+impl std::str::FromStr for PriceSource {
+    type Err = AppError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "added" => Ok(PriceSource::Added),
+            "edit" => Ok(PriceSource::Edit),
+            "import" => Ok(PriceSource::Import),
+            "rule" => Ok(PriceSource::Rule),
+            other => Err(AppError::Validation(format!("unknown price source {:?}", other))),
+        }
+    }
+}
+
+/// Who is changing a product and why, kept with the price changes it causes.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ChangeNote {
+    pub changed_by: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// A price as it was set, with the one it replaced.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PriceChange {
+    pub id: i64,
+    pub barcode: String,
+    pub price: PriceKind,
+    /// None for the price the product was added with
+    pub old_amount: Option<Money>,
+    pub old_currency: Option<String>,
+    pub new_amount: Money,
+    pub new_currency: String,
+    pub source: PriceSource,
+    /// The pricing rule that set the price
+    pub rule_id: Option<String>,
+    pub reason: Option<String>,
+    pub changed_by: Option<String>,
+    pub changed_at: String,
+}
+
+/// How much of a product to reorder, with the figures behind it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReorderSuggestion {
+    pub product_id: i64,
+    pub barcode: String,
+    pub product_name: String,
+    pub supplier_id: i64,
+    pub supplier: String,
+    /// Daily sales rate over the rule window
+    pub per_day: f64,
+    pub lead_time_days: u32,
+    /// Units in lots that have not expired
+    pub stock: i32,
+    /// Units on approved purchase orders that have not been received
+    pub on_order: i32,
+    /// Stock plus orders below this level triggers a reorder
+    pub reorder_point: i32,
+    /// Level an order tops stock plus orders up to
+    pub target_stock: i32,
+    pub case_pack: i32,
+    /// Suggested units to order, a whole number of cases
+    pub quantity: i32,
+    /// Purchase price in `currency`
+    pub unit_cost: Money,
+    /// The supplier's currency
+    pub currency: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    /// Editable; reorder runs add newly suggested products to it
+    Draft,
+    /// Sent to the supplier; its quantities count as on order
+    Approved,
+    Received,
+    Cancelled,
+}
+
+impl OrderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Draft => "draft",
+            OrderStatus::Approved => "approved",
+            OrderStatus::Received => "received",
+            OrderStatus::Cancelled => "cancelled",
+        }
+    }
+    
+    /// Whether an order may move from this status to `next`.
+    pub fn can_become(&self, next: OrderStatus) -> bool {
+        matches!(
+            (self, next),
+            (OrderStatus::Draft, OrderStatus::Approved)
+                | (OrderStatus::Draft, OrderStatus::Cancelled)
+                | (OrderStatus::Approved, OrderStatus::Received)
+                | (OrderStatus::Approved, OrderStatus::Cancelled)
+        )
+    }
+}
+
+impl std::str::FromStr for OrderStatus {
+    type Err = AppError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "draft" => Ok(OrderStatus::Draft),
+            "approved" => Ok(OrderStatus::Approved),
+            "received" => Ok(OrderStatus::Received),
+            "cancelled" => Ok(OrderStatus::Cancelled),
+            other => Err(AppError::Validation(format!("unknown order status {:?}", other))),
+        }
+    }
+}
+
+/// An order to one supplier, drafted from reorder suggestions.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PurchaseOrder {
+    pub id: i64,
+    pub supplier_id: i64,
+    pub supplier: String,
+    /// The supplier's currency when the order was opened; line costs are in it
+    pub currency: String,
+    /// The supplier's minimum order value
+    pub min_order_value: Money,
+    pub status: OrderStatus,
+    pub notes: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub approved_at: Option<String>,
+    pub lines: Vec<PurchaseOrderLine>,
+    /// Sum of the line totals, rounded to the configured places for totals
+    pub total: Money,
+}
+
+impl PurchaseOrder {
+    pub fn below_minimum(&self) -> bool {
+        self.total < self.min_order_value
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PurchaseOrderLine {
+    pub barcode: String,
+    pub product_name: String,
+    pub quantity: i32,
+    pub case_pack: i32,
+    pub unit_cost: Money,
+    pub suggested_quantity: Option<i32>,
+}
+
+impl PurchaseOrderLine {
+    /// Whole and part cases, e.g. 2.5 for 30 units in cases of 12.
+    pub fn cases(&self) -> f64 {
+        self.quantity as f64 / self.case_pack.max(1) as f64
+    }
+    
+    pub fn total(&self) -> Result<Money, AppError> {
+        self.unit_cost.checked_mul(self.quantity)
+    }
+}
+
+/// Changes to a purchase order's status or notes.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PurchaseOrderUpdate {
+    pub status: Option<OrderStatus>,
+    pub notes: Option<String>,
 }
 
 #[derive(Debug, Error)]
 pub enum AppError {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
+    #[error("Migration error: {0}")]
+    Migration(#[from] sqlx::migrate::MigrateError),
     #[error("HTTP request error: {0}")]
     Request(#[from] reqwest::Error),
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
     #[error("JSON parsing error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("Validation failed: {0}")]
+    Validation(String),
+    #[error("Configuration error: {0}")]
+    Config(String),
+    #[error("Barcode lookup failed: {0}")]
+    Lookup(String),
+    #[error("Notification failed: {0}")]
+    Notification(String),
+    #[error("PDF export failed: {0}")]
+    Pdf(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
     #[error("Product not found")]
     NotFound,
 }
\ No newline at end of file
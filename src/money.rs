@@ -0,0 +1,389 @@
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Sqlite, Type};
+use std::fmt;
+use std::str::FromStr;
+use crate::models::AppError;
+
+/// Decimal places every amount is kept to.
+pub const SCALE: u32 = 4;
+
+/// An exact amount of money with up to four decimal places, stored as a whole
+/// number of ten-thousandths so that sums in Rust and in SQL never drift. Which
+/// currency it is in is kept alongside it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    /// Rounds `value` to the four places an amount holds. Callers round to fewer
+    /// places first through `Rounding`. Amounts beyond about ±922 trillion do not
+    /// fit and are an error.
+    pub fn from_decimal(value: Decimal) -> Result<Money, AppError> {
+        value
+            .round_dp_with_strategy(SCALE, RoundingStrategy::MidpointNearestEven)
+            .checked_mul(Decimal::from(10_i64.pow(SCALE)))
+            .and_then(|units| units.to_i64())
+            .map(Money)
+            .ok_or_else(|| AppError::Validation(format!("amount {} is too large", value)))
+    }
+
+    pub fn to_decimal(self) -> Decimal {
+        Decimal::new(self.0, SCALE)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+}
+
+impl Money {
+    pub fn checked_add(self, other: Money) -> Result<Money, AppError> {
+        self.0.checked_add(other.0).map(Money).ok_or_else(|| too_large(self, "+", other))
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, AppError> {
+        self.0.checked_sub(other.0).map(Money).ok_or_else(|| too_large(self, "-", other))
+    }
+
+    /// A unit amount times a quantity is exact, so needs no rounding.
+    pub fn checked_mul(self, quantity: i32) -> Result<Money, AppError> {
+        self.0.checked_mul(quantity as i64).map(Money).ok_or_else(|| too_large(self, "×", quantity))
+    }
+
+    /// Adds up amounts, failing on the first sum too large to hold.
+    pub fn checked_sum<I: IntoIterator<Item = Money>>(amounts: I) -> Result<Money, AppError> {
+        amounts.into_iter().try_fold(Money::ZERO, Money::checked_add)
+    }
+}
+
+fn too_large(left: Money, op: &str, right: impl fmt::Display) -> AppError {
+    AppError::Validation(format!("amount {} {} {} is too large", left, op, right))
+}
+
+impl FromStr for Money {
+    type Err = AppError;
+
+    /// Parses a plain decimal such as `12`, `-0.5` or `3.1415`; more than four
+    /// decimal places is an error rather than a silent rounding.
+    fn from_str(s: &str) -> Result<Money, AppError> {
+        let value = Decimal::from_str(s.trim())
+            .map_err(|_| AppError::Validation(format!("invalid amount {:?}", s)))?;
+        if value.normalize().scale() > SCALE {
+            return Err(AppError::Validation(format!("amount {:?} has more than {} decimal places", s, SCALE)));
+        }
+        Money::from_decimal(value)
+    }
+}
+
+/// Prints at least two decimal places, or exactly as many as a format precision
+/// asks for, rounding half to even.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut value = self.to_decimal();
+        match f.precision() {
+            Some(places) => {
+                value = value.round_dp_with_strategy(places as u32, RoundingStrategy::MidpointNearestEven);
+                value.rescale(places as u32);
+            }
+            None => {
+                value = value.normalize();
+                if value.scale() < 2 {
+                    value.rescale(2);
+                }
+            }
+        }
+        f.pad_integral(!value.is_sign_negative(), "", &value.abs().to_string())
+    }
+}
+
+/// Amounts go out as strings, `"12.50"`, so JSON clients never see a float.
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts a string or a JSON number, with the same limit on decimal places as
+/// parsing.
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+        let value = deserializer.deserialize_any(DecimalVisitor)?;
+        if value.normalize().scale() > SCALE {
+            return Err(de::Error::custom(format!("amount {} has more than {} decimal places", value, SCALE)));
+        }
+        Money::from_decimal(value).map_err(de::Error::custom)
+    }
+}
+
+/// An exact decimal factor: exchange rates and percentages. Stored as text so
+/// SQLite keeps every digit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rate(Decimal);
+
+impl Rate {
+    pub const ZERO: Rate = Rate(Decimal::ZERO);
+    pub const ONE: Rate = Rate(Decimal::ONE);
+
+    pub fn new(value: Decimal) -> Rate {
+        Rate(value.normalize())
+    }
+
+    pub fn to_decimal(self) -> Decimal {
+        self.0
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > Decimal::ZERO
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < Decimal::ZERO
+    }
+}
+
+impl FromStr for Rate {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Rate, AppError> {
+        Decimal::from_str(s.trim())
+            .map(Rate::new)
+            .map_err(|_| AppError::Validation(format!("invalid number {:?}", s)))
+    }
+}
+
+/// Prints every significant digit, or as many places as a format precision asks for.
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = match f.precision() {
+            Some(places) => {
+                let mut value = self.0.round_dp_with_strategy(places as u32, RoundingStrategy::MidpointNearestEven);
+                value.rescale(places as u32);
+                value
+            }
+            None => self.0,
+        };
+        f.pad_integral(!value.is_sign_negative(), "", &value.abs().to_string())
+    }
+}
+
+impl Serialize for Rate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Rate, D::Error> {
+        deserializer.deserialize_any(DecimalVisitor).map(Rate::new)
+    }
+}
+
+impl Type<Sqlite> for Rate {
+    fn type_info() -> SqliteTypeInfo {
+        <String as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <String as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for Rate {
+    fn encode_by_ref(&self, buf: &mut <Sqlite as sqlx::Database>::ArgumentBuffer<'q>) -> Result<IsNull, BoxDynError> {
+        <String as Encode<Sqlite>>::encode(self.0.to_string(), buf)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for Rate {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Rate, BoxDynError> {
+        let text = <&str as Decode<Sqlite>>::decode(value)?;
+        Ok(Rate::new(Decimal::from_str(text)?))
+    }
+}
+
+struct DecimalVisitor;
+
+impl<'de> Visitor<'de> for DecimalVisitor {
+    type Value = Decimal;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a decimal number or a string holding one")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Decimal, E> {
+        Decimal::from_str(value.trim()).map_err(|_| E::custom(format!("invalid number {:?}", value)))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Decimal, E> {
+        Ok(Decimal::from(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Decimal, E> {
+        Ok(Decimal::from(value))
+    }
+
+    /// Goes through the shortest string that round-trips, so 0.1 stays 0.1.
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Decimal, E> {
+        self.visit_str(&value.to_string())
+    }
+}
+
+/// How ties and remainders are settled when a computed amount is rounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    /// Ties go to the even digit ("banker's rounding")
+    #[default]
+    HalfEven,
+    /// Ties go away from zero
+    HalfUp,
+    /// Towards zero
+    Down,
+    /// Away from zero
+    Up,
+}
+
+impl RoundingMode {
+    fn strategy(self) -> RoundingStrategy {
+        match self {
+            RoundingMode::HalfEven => RoundingStrategy::MidpointNearestEven,
+            RoundingMode::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            RoundingMode::Down => RoundingStrategy::ToZero,
+            RoundingMode::Up => RoundingStrategy::AwayFromZero,
+        }
+    }
+}
+
+impl FromStr for RoundingMode {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half_even" => Ok(RoundingMode::HalfEven),
+            "half_up" => Ok(RoundingMode::HalfUp),
+            "down" => Ok(RoundingMode::Down),
+            "up" => Ok(RoundingMode::Up),
+            _ => Err(AppError::Validation(format!("unknown rounding mode {:?}", s))),
+        }
+    }
+}
+
+/// Where computed amounts are rounded. Entered amounts keep the places they were
+/// entered with; anything derived by converting currencies or applying a
+/// percentage is rounded here, once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rounding {
+    /// Places for per-unit amounts: converted prices, duty, landed cost
+    pub unit_decimals: u32,
+    /// Places for totals: order totals and report values
+    pub total_decimals: u32,
+    pub mode: RoundingMode,
+}
+
+impl Rounding {
+    /// An error when the amount is too large to hold, e.g. after converting at an
+    /// extreme exchange rate.
+    pub fn unit(&self, value: Decimal) -> Result<Money, AppError> {
+        self.round(value, self.unit_decimals)
+    }
+
+    /// An error when the total is too large to hold, as for `unit`.
+    pub fn total(&self, value: Decimal) -> Result<Money, AppError> {
+        self.round(value, self.total_decimals)
+    }
+
+    /// A percentage rounded to two places.
+    pub fn percent(&self, value: Decimal) -> Rate {
+        Rate::new(value.round_dp_with_strategy(2, self.mode.strategy()))
+    }
+
+    fn round(&self, value: Decimal, decimals: u32) -> Result<Money, AppError> {
+        Money::from_decimal(value.round_dp_with_strategy(decimals.min(SCALE), self.mode.strategy()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(s: &str) -> Money {
+        s.parse().unwrap()
+    }
+
+    fn rounding(mode: RoundingMode) -> Rounding {
+        Rounding { unit_decimals: 2, total_decimals: 0, mode }
+    }
+
+    #[test]
+    fn amounts_keep_four_places_exactly() {
+        assert_eq!(money("12.5").to_decimal(), Decimal::new(125_000, SCALE));
+        assert_eq!(money(" -0.0001 ").to_string(), "-0.0001");
+        assert_eq!(money("3").to_string(), "3.00");
+        assert_eq!(format!("{:.1}", money("0.25")), "0.2");
+        assert_eq!(format!("{:.1}", money("0.35")), "0.4");
+        assert!("0.00001".parse::<Money>().is_err());
+        assert!("12,50".parse::<Money>().is_err());
+        assert_eq!(money("0.1").checked_add(money("0.2")).unwrap(), money("0.3"));
+        assert_eq!(money("0.1").checked_sub(money("0.25")).unwrap(), money("-0.15"));
+        assert_eq!(money("1.25").checked_mul(3).unwrap(), money("3.75"));
+        assert_eq!(Money::checked_sum([money("1.5"), money("2"), money("-0.25")]).unwrap(), money("3.25"));
+    }
+
+    #[test]
+    fn rounding_modes_settle_ties_and_remainders() {
+        let cases = [
+            ("2.345", [("2.34", RoundingMode::HalfEven), ("2.35", RoundingMode::HalfUp), ("2.34", RoundingMode::Down), ("2.35", RoundingMode::Up)]),
+            ("2.355", [("2.36", RoundingMode::HalfEven), ("2.36", RoundingMode::HalfUp), ("2.35", RoundingMode::Down), ("2.36", RoundingMode::Up)]),
+            ("-2.341", [("-2.34", RoundingMode::HalfEven), ("-2.34", RoundingMode::HalfUp), ("-2.34", RoundingMode::Down), ("-2.35", RoundingMode::Up)]),
+        ];
+        for (value, expected) in cases {
+            for (rounded, mode) in expected {
+                let value = Decimal::from_str(value).unwrap();
+                assert_eq!(rounding(mode).unit(value).unwrap(), money(rounded), "{} {:?}", value, mode);
+            }
+        }
+        assert_eq!(rounding(RoundingMode::HalfEven).total(Decimal::new(25, 1)).unwrap(), money("2"));
+        assert_eq!(rounding(RoundingMode::HalfUp).total(Decimal::new(25, 1)).unwrap(), money("3"));
+        assert_eq!(rounding(RoundingMode::HalfEven).percent(Decimal::new(123_456, 4)).to_string(), "12.35");
+    }
+
+    #[test]
+    fn amounts_too_large_to_hold_are_errors() {
+        let largest = "922337203685477.5807";
+        assert_eq!(money(largest).to_string(), largest);
+        assert_eq!(money("-922337203685477.5808").to_string(), "-922337203685477.5808");
+
+        for input in ["922337203685477.5808", "-922337203685477.5809", "100000000000000000000000000", "79228162514264337593543950335"] {
+            let error = input.parse::<Money>().unwrap_err();
+            assert!(matches!(error, AppError::Validation(_)), "{}: {}", input, error);
+        }
+        assert!(serde_json::from_str::<Money>("\"100000000000000000000000000\"").is_err());
+        assert!(serde_json::from_str::<Money>("1e30").is_err());
+        assert!(rounding(RoundingMode::HalfEven).unit(Decimal::MAX).is_err());
+        assert!(rounding(RoundingMode::HalfEven).total(Decimal::MIN).is_err());
+    }
+
+    #[test]
+    fn sums_too_large_to_hold_are_errors() {
+        let largest = Money(i64::MAX);
+        let error = largest.checked_add(money("0.0001")).unwrap_err();
+        assert!(error.to_string().contains("too large"), "{}", error);
+        assert!(Money(i64::MIN).checked_sub(money("0.0001")).is_err());
+        assert!(money("500000000000000").checked_mul(2).is_err());
+        assert!(money("-500000000000000").checked_mul(2).is_err());
+        assert!(Money::checked_sum([largest, money("-1"), money("2")]).is_err());
+        assert_eq!(Money::checked_sum([largest, money("-1")]).unwrap(), money("922337203685476.5807"));
+    }
+}
@@ -0,0 +1,611 @@
+use async_trait::async_trait;
+use chrono::{Local, NaiveTime};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use reqwest::Client;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use crate::alert_rules::Severity;
+use crate::config::{ChannelKind, NotificationsConfig};
+use crate::database::Database;
+use crate::models::{AppError, TrackedAlert};
+
+/// A batch of alerts going out on one channel.
+#[derive(Debug, Serialize)]
+pub struct Notification {
+    pub subject: String,
+    pub digest: bool,
+    pub alerts: Vec<TrackedAlert>,
+}
+
+impl Notification {
+    fn immediate(alerts: Vec<TrackedAlert>) -> Self {
+        let subject = match alerts.as_slice() {
+            [alert] => format!("[{}] {}: {}", alert.severity, alert.alert_type, alert.product_name),
+            alerts => format!("{} new inventory alerts", alerts.len()),
+        };
+        Self { subject, digest: false, alerts }
+    }
+
+    fn digest(alerts: Vec<TrackedAlert>) -> Self {
+        Self {
+            subject: format!("Inventory alert digest for {}: {} alerts", Local::now().date_naive(), alerts.len()),
+            digest: true,
+            alerts,
+        }
+    }
+
+    fn test() -> Self {
+        Self {
+            subject: "Test notification from Food Imports DB".to_string(),
+            digest: false,
+            alerts: Vec::new(),
+        }
+    }
+
+    /// Plain-text body, one line per alert.
+    pub fn text(&self) -> String {
+        if self.alerts.is_empty() {
+            return format!("{}\n", self.subject);
+        }
+        self.alerts
+            .iter()
+            .map(|alert| format!("#{} [{}] {}: {}\n", alert.id, alert.severity, alert.alert_type, alert.message))
+            .collect()
+    }
+}
+
+/// Somewhere alerts can be sent. Channels only deliver; which alerts are due,
+/// digest timing and deduplication are handled by `Notifier`.
+#[async_trait]
+pub trait NotificationChannel: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn send(&self, notification: &Notification) -> Result<(), AppError>;
+}
+
+pub struct SmtpChannel {
+    name: String,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+#[async_trait]
+impl NotificationChannel for SmtpChannel {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        let mut message = Message::builder().from(self.from.clone());
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let message = message
+            .subject(&notification.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(notification.text())
+            .map_err(|e| AppError::Notification(e.to_string()))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| AppError::Notification(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// POSTs the notification as JSON to a URL, e.g. a chat integration.
+pub struct WebhookChannel {
+    name: String,
+    client: Client,
+    url: String,
+}
+
+#[async_trait]
+impl NotificationChannel for WebhookChannel {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        let response = self.client.post(&self.url).json(notification).send().await?;
+        if !response.status().is_success() {
+            return Err(AppError::Notification(format!("{} returned {}", self.url, response.status())));
+        }
+        Ok(())
+    }
+}
+
+/// Appends each notification to a file as one JSON line.
+pub struct FileChannel {
+    name: String,
+    path: String,
+}
+
+#[async_trait]
+impl NotificationChannel for FileChannel {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        let mut line = serde_json::to_vec(&serde_json::json!({
+            "sent_at": Local::now().naive_local(),
+            "subject": notification.subject,
+            "digest": notification.digest,
+            "alerts": notification.alerts,
+        }))?;
+        line.push(b'\n');
+
+        let result = async {
+            let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&self.path).await?;
+            file.write_all(&line).await
+        };
+        result
+            .await
+            .map_err(|e| AppError::Notification(format!("cannot write {}: {}", self.path, e)))
+    }
+}
+
+/// Sends one message per alert to the local syslog daemon, with the alert severity
+/// mapped onto the syslog one.
+pub struct SyslogChannel {
+    name: String,
+    socket: String,
+}
+
+impl SyslogChannel {
+    fn priority(severity: Severity) -> u8 {
+        const FACILITY_USER: u8 = 1;
+        let level = match severity {
+            Severity::Critical => 2,
+            Severity::High => 3,
+            Severity::Medium => 4,
+            Severity::Low => 5,
+            Severity::Info => 6,
+        };
+        FACILITY_USER * 8 + level
+    }
+}
+
+#[async_trait]
+impl NotificationChannel for SyslogChannel {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), AppError> {
+        let mut lines: Vec<(Severity, String)> = notification.alerts
+            .iter()
+            .map(|alert| (alert.severity, format!("#{} {}: {}", alert.id, alert.alert_type, alert.message)))
+            .collect();
+        if lines.is_empty() {
+            lines.push((Severity::Info, notification.subject.clone()));
+        }
+
+        let error = |e: std::io::Error| AppError::Notification(format!("{}: {}", self.socket, e));
+        let socket = tokio::net::UnixDatagram::unbound().map_err(error)?;
+        for (severity, line) in lines {
+            let message = format!("<{}>food_imports_db[{}]: {}", Self::priority(severity), std::process::id(), line);
+            socket.send_to(message.as_bytes(), &self.socket).await.map_err(error)?;
+        }
+        Ok(())
+    }
+}
+
+struct Channel {
+    sink: Box<dyn NotificationChannel>,
+    min_severity: Severity,
+    digest_at: Option<NaiveTime>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChannelReport {
+    pub channel: String,
+    pub digest: bool,
+    /// Alerts that were due on the channel.
+    pub alerts: usize,
+    pub sent: bool,
+    pub error: Option<String>,
+}
+
+pub struct Notifier {
+    channels: Vec<Channel>,
+}
+
+impl Notifier {
+    /// Builds the channels listed under `[[notifications.channels]]`.
+    pub fn from_config(config: &NotificationsConfig) -> Result<Self, AppError> {
+        let timeout = Duration::from_secs(config.timeout_secs);
+        let mut channels = Vec::new();
+
+        for channel in &config.channels {
+            let name = channel.name.clone();
+            let sink: Box<dyn NotificationChannel> = match &channel.kind {
+                ChannelKind::Smtp { host, port, starttls, username, password, from, to } => {
+                    let mailbox = |address: &String| {
+                        address.parse::<Mailbox>().map_err(|e| {
+                            AppError::Config(format!("channel {:?}: invalid address {:?}: {}", name, address, e))
+                        })
+                    };
+                    if to.is_empty() {
+                        return Err(AppError::Config(format!("channel {:?} has no recipients", name)));
+                    }
+
+                    let mut transport = if *starttls {
+                        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                            .map_err(|e| AppError::Config(format!("channel {:?}: {}", name, e)))?
+                    } else {
+                        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+                    };
+                    transport = transport.port(*port).timeout(Some(timeout));
+                    if let (Some(username), Some(password)) = (username, password) {
+                        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+                    }
+
+                    Box::new(SmtpChannel {
+                        from: mailbox(from)?,
+                        to: to.iter().map(mailbox).collect::<Result<_, _>>()?,
+                        transport: transport.build(),
+                        name,
+                    })
+                }
+                ChannelKind::Webhook { url } => Box::new(WebhookChannel {
+                    name,
+                    client: Client::builder().timeout(timeout).build()?,
+                    url: url.clone(),
+                }),
+                ChannelKind::File { path } => Box::new(FileChannel { name, path: path.clone() }),
+                ChannelKind::Syslog { socket } => Box::new(SyslogChannel { name, socket: socket.clone() }),
+            };
+
+            channels.push(Channel {
+                sink,
+                min_severity: channel.min_severity,
+                digest_at: channel.digest_time()?,
+            });
+        }
+
+        Ok(Self { channels })
+    }
+
+    /// Sends every channel the open alerts it has not been sent yet. Digest channels
+    /// collect them until their time of day and then send one summary a day. A failed
+    /// delivery is reported and retried on the next run.
+    pub async fn dispatch(&self, db: &Database, dry_run: bool) -> Result<Vec<ChannelReport>, AppError> {
+        let now = Local::now().naive_local();
+        let mut reports = Vec::new();
+
+        for channel in &self.channels {
+            let name = channel.sink.name();
+            if let Some(digest_at) = channel.digest_at {
+                if now.time() < digest_at || db.last_digest(name).await? == Some(now.date()) {
+                    continue;
+                }
+            }
+
+            let alerts = db.undelivered_alerts(name, channel.min_severity).await?;
+            let mut report = ChannelReport {
+                channel: name.to_string(),
+                digest: channel.digest_at.is_some(),
+                alerts: alerts.len(),
+                sent: false,
+                error: None,
+            };
+            if dry_run {
+                reports.push(report);
+                continue;
+            }
+
+            if !alerts.is_empty() {
+                let notification = if report.digest {
+                    Notification::digest(alerts)
+                } else {
+                    Notification::immediate(alerts)
+                };
+                match channel.sink.send(&notification).await {
+                    Ok(()) => {
+                        db.record_deliveries(name, &notification.alerts, report.digest).await?;
+                        report.sent = true;
+                    }
+                    Err(e) => {
+                        log::warn!("notification on {} failed: {}", name, e);
+                        report.error = Some(e.to_string());
+                    }
+                }
+            }
+            if report.digest && report.error.is_none() {
+                db.record_digest(name, now.date()).await?;
+            }
+            reports.push(report);
+        }
+
+        Ok(reports)
+    }
+
+    /// Sends a test message on a channel, without touching any alerts.
+    pub async fn send_test(&self, name: &str) -> Result<(), AppError> {
+        let channel = self.channels
+            .iter()
+            .find(|channel| channel.sink.name() == name)
+            .ok_or_else(|| AppError::Config(format!("unknown notification channel {:?}", name)))?;
+        channel.sink.send(&Notification::test()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert_rules::AlertType;
+    use crate::config::ChannelConfig;
+    use crate::database::tests::memory_db;
+    use crate::inventory_manager::InventoryAlert;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    /// Keeps what it is sent, and fails while `down`.
+    #[derive(Clone, Default)]
+    struct FakeChannel {
+        sent: Arc<Mutex<Vec<Notification>>>,
+        down: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl NotificationChannel for FakeChannel {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        async fn send(&self, notification: &Notification) -> Result<(), AppError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(AppError::Notification("connection refused".to_string()));
+            }
+            self.sent.lock().unwrap().push(Notification {
+                subject: notification.subject.clone(),
+                digest: notification.digest,
+                alerts: notification.alerts.clone(),
+            });
+            Ok(())
+        }
+    }
+
+    impl FakeChannel {
+        fn notifier(&self, min_severity: Severity, digest_at: Option<NaiveTime>) -> Notifier {
+            Notifier { channels: vec![Channel { sink: Box::new(self.clone()), min_severity, digest_at }] }
+        }
+
+        /// Alert ids of each notification sent so far.
+        fn batches(&self) -> Vec<Vec<i64>> {
+            self.sent.lock().unwrap().iter().map(|sent| sent.alerts.iter().map(|alert| alert.id).collect()).collect()
+        }
+    }
+
+    fn alert(barcode: &str, alert_type: AlertType, severity: Severity) -> InventoryAlert {
+        InventoryAlert {
+            rule_id: alert_type.as_str().to_string(),
+            product_name: "Chickpeas".to_string(),
+            barcode: barcode.to_string(),
+            lot_code: None,
+            alert_type,
+            message: format!("{} for {}", alert_type.as_str(), barcode),
+            severity,
+        }
+    }
+
+    /// Opens a high and a low severity alert and returns their ids.
+    async fn open_alerts(db: &Database) -> (i64, i64) {
+        db.sync_alerts(&[
+            alert("05281234567896", AlertType::LowStock, Severity::High),
+            alert("08690504000006", AlertType::WasteRisk, Severity::Low),
+        ])
+        .await
+        .unwrap();
+        let alerts = db.get_alerts(None).await.unwrap();
+        let id = |barcode: &str| alerts.iter().find(|alert| alert.barcode == barcode).unwrap().id;
+        (id("05281234567896"), id("08690504000006"))
+    }
+
+    #[tokio::test]
+    async fn sends_each_alert_once_and_retries_failed_deliveries() {
+        let db = memory_db().await;
+        let (high, low) = open_alerts(&db).await;
+        let channel = FakeChannel::default();
+        let notifier = channel.notifier(Severity::Medium, None);
+
+        channel.down.store(true, Ordering::SeqCst);
+        let reports = notifier.dispatch(&db, false).await.unwrap();
+        assert_eq!((reports[0].alerts, reports[0].sent), (1, false));
+        assert_eq!(reports[0].error.as_deref(), Some("Notification failed: connection refused"));
+
+        channel.down.store(false, Ordering::SeqCst);
+        assert!(notifier.dispatch(&db, false).await.unwrap()[0].sent);
+        assert_eq!(channel.batches(), vec![vec![high]]);
+
+        // Nothing new: the high alert is not sent again
+        let reports = notifier.dispatch(&db, false).await.unwrap();
+        assert_eq!((reports[0].alerts, reports[0].sent), (0, false));
+
+        // Raising an alert above the channel's filter, or above what was sent, sends it again
+        db.sync_alerts(&[
+            alert("05281234567896", AlertType::LowStock, Severity::Critical),
+            alert("08690504000006", AlertType::WasteRisk, Severity::Medium),
+        ])
+        .await
+        .unwrap();
+        notifier.dispatch(&db, false).await.unwrap();
+        assert_eq!(channel.batches(), vec![vec![high], vec![high, low]]);
+        assert!(channel.sent.lock().unwrap()[1].subject.starts_with("2 new inventory alerts"));
+    }
+
+    #[tokio::test]
+    async fn dry_runs_send_nothing() {
+        let db = memory_db().await;
+        open_alerts(&db).await;
+        let channel = FakeChannel::default();
+        let notifier = channel.notifier(Severity::Info, None);
+
+        assert_eq!(notifier.dispatch(&db, true).await.unwrap()[0].alerts, 2);
+        assert!(channel.batches().is_empty());
+        assert_eq!(notifier.dispatch(&db, false).await.unwrap()[0].alerts, 2);
+    }
+
+    #[tokio::test]
+    async fn digests_go_out_once_a_day() {
+        let db = memory_db().await;
+        let (high, low) = open_alerts(&db).await;
+        let channel = FakeChannel::default();
+        let notifier = channel.notifier(Severity::Info, NaiveTime::from_hms_opt(0, 0, 0));
+
+        // A failed digest is retried on the next run the same day
+        channel.down.store(true, Ordering::SeqCst);
+        assert!(notifier.dispatch(&db, false).await.unwrap()[0].error.is_some());
+        channel.down.store(false, Ordering::SeqCst);
+        let reports = notifier.dispatch(&db, false).await.unwrap();
+        assert!(reports[0].digest && reports[0].sent);
+        assert!(channel.sent.lock().unwrap()[0].subject.starts_with("Inventory alert digest"));
+        assert_eq!(channel.batches(), vec![vec![high, low]]);
+
+        db.sync_alerts(&[
+            alert("05281234567896", AlertType::LowStock, Severity::High),
+            alert("08690504000006", AlertType::WasteRisk, Severity::Low),
+            alert("05281234567896", AlertType::Expired, Severity::Critical),
+        ])
+        .await
+        .unwrap();
+        assert!(notifier.dispatch(&db, false).await.unwrap().is_empty());
+        assert_eq!(channel.batches().len(), 1);
+    }
+
+    /// An SMTP server on a free local port that accepts every message and keeps
+    /// its headers and body.
+    async fn smtp_stand_in() -> (u16, Arc<Mutex<Vec<String>>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let messages: Arc<Mutex<Vec<String>>> = Arc::default();
+        let received = messages.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let received = received.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = socket.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    let mut data: Option<String> = None;
+                    writer.write_all(b"220 localhost ESMTP stand-in\r\n").await?;
+                    while let Some(line) = lines.next_line().await? {
+                        if let Some(message) = data.as_mut() {
+                            if line != "." {
+                                message.push_str(&line);
+                                message.push('\n');
+                                continue;
+                            }
+                            received.lock().unwrap().extend(data.take());
+                            writer.write_all(b"250 queued\r\n").await?;
+                            continue;
+                        }
+                        let verb = line.split(' ').next().unwrap_or_default().to_ascii_uppercase();
+                        let reply: &[u8] = match verb.as_str() {
+                            "DATA" => {
+                                data = Some(String::new());
+                                b"354 end with .\r\n"
+                            }
+                            "QUIT" => b"221 bye\r\n",
+                            _ => b"250 localhost\r\n",
+                        };
+                        writer.write_all(reply).await?;
+                    }
+                    std::io::Result::Ok(())
+                });
+            }
+        });
+        (port, messages)
+    }
+
+    fn channel_config(kind: ChannelKind) -> NotificationsConfig {
+        NotificationsConfig {
+            channels: vec![ChannelConfig { name: "stand-in".to_string(), min_severity: Severity::Info, digest_at: None, kind }],
+            timeout_secs: 5,
+        }
+    }
+
+    #[tokio::test]
+    async fn smtp_channel_delivers_to_the_relay() {
+        let db = memory_db().await;
+        open_alerts(&db).await;
+        let (port, messages) = smtp_stand_in().await;
+        let notifier = Notifier::from_config(&channel_config(ChannelKind::Smtp {
+            host: "127.0.0.1".to_string(),
+            port,
+            starttls: false,
+            username: None,
+            password: None,
+            from: "Food Imports <alerts@example.com>".to_string(),
+            to: vec!["buyer@example.com".to_string()],
+        }))
+        .unwrap();
+
+        notifier.send_test("stand-in").await.unwrap();
+        assert!(notifier.dispatch(&db, false).await.unwrap()[0].sent);
+
+        let messages = messages.lock().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("Subject: Test notification from Food Imports DB"), "{}", messages[0]);
+        assert!(messages[1].contains("Subject: 2 new inventory alerts"), "{}", messages[1]);
+        assert!(messages[1].contains("To: buyer@example.com"), "{}", messages[1]);
+        assert!(messages[1].contains("#1 [High] Low Stock: low_stock for 05281234567896"), "{}", messages[1]);
+    }
+
+    #[actix_web::test]
+    async fn webhook_channel_posts_json_and_retries_errors() {
+        use actix_web::{web, App, HttpResponse, HttpServer};
+
+        /// Whether the stand-in answers with an error, and the bodies it accepted.
+        #[derive(Default)]
+        struct Received {
+            down: AtomicBool,
+            bodies: Mutex<Vec<serde_json::Value>>,
+        }
+
+        async fn hook(body: web::Json<serde_json::Value>, received: web::Data<Received>) -> HttpResponse {
+            if received.down.load(Ordering::SeqCst) {
+                return HttpResponse::ServiceUnavailable().finish();
+            }
+            received.bodies.lock().unwrap().push(body.into_inner());
+            HttpResponse::NoContent().finish()
+        }
+
+        let received = web::Data::new(Received::default());
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let data = received.clone();
+        let server = HttpServer::new(move || App::new().app_data(data.clone()).route("/hook", web::post().to(hook)))
+            .workers(1)
+            .listen(listener)
+            .unwrap()
+            .run();
+        actix_web::rt::spawn(server);
+
+        let db = memory_db().await;
+        open_alerts(&db).await;
+        let notifier = Notifier::from_config(&channel_config(ChannelKind::Webhook { url: url.clone() })).unwrap();
+
+        received.down.store(true, Ordering::SeqCst);
+        let reports = notifier.dispatch(&db, false).await.unwrap();
+        assert_eq!(reports[0].error, Some(format!("Notification failed: {} returned 503 Service Unavailable", url)));
+
+        received.down.store(false, Ordering::SeqCst);
+        assert!(notifier.dispatch(&db, false).await.unwrap()[0].sent);
+        assert!(!notifier.dispatch(&db, false).await.unwrap()[0].sent);
+
+        let bodies = received.bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 1);
+        assert_eq!(bodies[0]["digest"], false);
+        assert_eq!(bodies[0]["alerts"].as_array().unwrap().len(), 2);
+        assert_eq!(bodies[0]["alerts"][0]["alert_type"], "low_stock");
+    }
+}
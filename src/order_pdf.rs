@@ -0,0 +1,89 @@
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfLayerReference};
+use crate::models::{AppError, PurchaseOrder};
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 15.0;
+const ROW_HEIGHT: f32 = 6.0;
+
+/// Column titles and their left edges in mm.
+const COLUMNS: [(&str, f32); 7] = [
+    ("Barcode", 15.0),
+    ("Product", 48.0),
+    ("Qty", 110.0),
+    ("Case", 125.0),
+    ("Cases", 140.0),
+    ("Unit cost", 157.0),
+    ("Total", 180.0),
+];
+
+/// Renders a purchase order as an A4 PDF for sending to the supplier, continuing
+/// the line table on new pages as needed.
+pub fn render(order: &PurchaseOrder) -> Result<Vec<u8>, AppError> {
+    let title = format!("Purchase order #{}", order.id);
+    let (doc, page, layer) = PdfDocument::new(&title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Order");
+    let font = doc.add_builtin_font(BuiltinFont::Helvetica).map_err(|e| AppError::Pdf(e.to_string()))?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold).map_err(|e| AppError::Pdf(e.to_string()))?;
+
+    let mut layer = doc.get_page(page).get_layer(layer);
+    let mut y = PAGE_HEIGHT - MARGIN;
+    layer.use_text(&title, 16.0, Mm(MARGIN), Mm(y), &bold);
+    y -= 9.0;
+    for line in [
+        format!("Supplier: {}", order.supplier),
+        format!("Status: {}", order.status.as_str()),
+        format!("Created: {}", order.created_at),
+    ]
+    .into_iter()
+    .chain(order.approved_at.as_ref().map(|at| format!("Approved: {}", at)))
+    .chain(order.notes.as_ref().map(|notes| format!("Notes: {}", notes)))
+    {
+        layer.use_text(line, 10.0, Mm(MARGIN), Mm(y), &font);
+        y -= ROW_HEIGHT;
+    }
+
+    y -= ROW_HEIGHT;
+    header_row(&layer, &bold, y);
+    for line in &order.lines {
+        y -= ROW_HEIGHT;
+        if y < MARGIN {
+            let (page, new_layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Order");
+            layer = doc.get_page(page).get_layer(new_layer);
+            y = PAGE_HEIGHT - MARGIN;
+            header_row(&layer, &bold, y);
+            y -= ROW_HEIGHT;
+        }
+
+        let name: String = line.product_name.chars().take(34).collect();
+        let cells = [
+            line.barcode.clone(),
+            name,
+            line.quantity.to_string(),
+            line.case_pack.to_string(),
+            format!("{:.1}", line.cases()),
+            line.unit_cost.to_string(),
+            line.total()?.to_string(),
+        ];
+        for (cell, (_, x)) in cells.into_iter().zip(COLUMNS) {
+            layer.use_text(cell, 9.0, Mm(x), Mm(y), &font);
+        }
+    }
+
+    y -= ROW_HEIGHT * 1.5;
+    if y < MARGIN {
+        let (page, new_layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Order");
+        layer = doc.get_page(page).get_layer(new_layer);
+        y = PAGE_HEIGHT - MARGIN;
+    }
+    let units: i32 = order.lines.iter().map(|line| line.quantity).sum();
+    layer.use_text(format!("{} lines, {} units", order.lines.len(), units), 10.0, Mm(MARGIN), Mm(y), &bold);
+    layer.use_text(format!("{} {}", order.total, order.currency), 10.0, Mm(COLUMNS[6].1), Mm(y), &bold);
+
+    doc.save_to_bytes().map_err(|e| AppError::Pdf(e.to_string()))
+}
+
+fn header_row(layer: &PdfLayerReference, font: &IndirectFontRef, y: f32) {
+    for (title, x) in COLUMNS {
+        layer.use_text(title, 9.0, Mm(x), Mm(y), font);
+    }
+}
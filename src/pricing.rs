@@ -0,0 +1,486 @@
+use chrono::NaiveDate;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use crate::currency::{self, ExchangeRates};
+use crate::models::{AppError, PriceKind, Product, Supplier};
+use crate::money::{Money, Rate};
+
+/// How a rule gets from landed cost to a price.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Target {
+    /// `percent` of the price is margin: price = cost / (1 - percent / 100).
+    Margin { percent: Rate },
+    /// `percent` of the cost is added: price = cost × (1 + percent / 100).
+    Markup { percent: Rate },
+}
+
+impl Target {
+    fn check(&self) -> Result<(), String> {
+        match self {
+            Target::Margin { percent } if percent.is_negative() || percent.to_decimal() >= Decimal::ONE_HUNDRED => {
+                Err("margin percent must be at least 0 and below 100".to_string())
+            }
+            Target::Markup { percent } if percent.is_negative() => Err("markup percent must not be negative".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// `None` when the price is too large for a decimal, e.g. a margin a hair
+    /// below 100%.
+    fn apply(&self, cost: Decimal) -> Option<Decimal> {
+        match self {
+            Target::Margin { percent } => cost.checked_div(Decimal::ONE - percent.to_decimal() / Decimal::ONE_HUNDRED),
+            Target::Markup { percent } => cost.checked_mul(Decimal::ONE + percent.to_decimal() / Decimal::ONE_HUNDRED),
+        }
+    }
+}
+
+/// Limits a rule to products of a category, a brand and/or a supplier. Every
+/// field that is set must match; names compare case-insensitively, and supplier
+/// names also without their legal form.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PricingScope {
+    pub category: Option<String>,
+    pub brand: Option<String>,
+    pub supplier: Option<String>,
+}
+
+impl PricingScope {
+    pub fn matches(&self, product: &Product) -> bool {
+        let same = |want: &Option<String>, have: &str| want.as_ref().is_none_or(|want| want.eq_ignore_ascii_case(have));
+        let same_supplier = self.supplier.as_ref().is_none_or(|want| Supplier::name_key(want) == Supplier::name_key(&product.supplier));
+        same(&self.category, &product.category) && same(&self.brand, &product.brand) && same_supplier
+    }
+
+    /// Narrower scopes rank higher: supplier over brand over category over everything.
+    fn specificity(&self) -> u8 {
+        (self.supplier.is_some() as u8) << 2 | (self.brand.is_some() as u8) << 1 | self.category.is_some() as u8
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PricingRule {
+    /// Stable identifier, recorded with the prices the rule sets.
+    pub id: String,
+    /// The selling price the rule sets: wholesale or retail.
+    pub price: PriceKind,
+    pub target: Target,
+    #[serde(default)]
+    pub scope: PricingScope,
+    /// Prices are raised to the next amount ending in this, e.g. 0.95 for x.95
+    /// or 9 for prices ending in 9. Without one they are rounded up to `decimals`.
+    #[serde(default)]
+    pub ending: Option<Money>,
+    /// Lowest price the rule may set, in the price's currency.
+    #[serde(default)]
+    pub min_price: Option<Money>,
+    /// Highest price the rule may set, in the price's currency.
+    #[serde(default)]
+    pub max_price: Option<Money>,
+}
+
+impl PricingRule {
+    fn check(&self) -> Result<(), String> {
+        if self.price == PriceKind::Purchase {
+            return Err("price must be wholesale or retail".to_string());
+        }
+        self.target.check()?;
+        if self.ending.is_some_and(|ending| ending.is_negative() || ending.is_zero()) {
+            return Err("ending must be above 0".to_string());
+        }
+        if self.min_price.is_some_and(Money::is_negative) || self.max_price.is_some_and(Money::is_negative) {
+            return Err("min_price and max_price must not be negative".to_string());
+        }
+        if let (Some(min), Some(max)) = (self.min_price, self.max_price) {
+            if min > max {
+                return Err("min_price must not be above max_price".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// The price for a landed cost given in the price's currency, and the guard
+    /// that capped it, if any.
+    fn price_for(&self, cost: Money, decimals: u32) -> Result<(Money, Option<Guard>), AppError> {
+        let target = self.target
+            .apply(cost.to_decimal())
+            .ok_or_else(|| AppError::Validation(format!("rule {} gives a price too large to hold", self.id)))?;
+        let price = match self.ending {
+            Some(ending) => with_ending(target, ending.to_decimal()),
+            None => target.round_dp_with_strategy(decimals, RoundingStrategy::AwayFromZero),
+        };
+        let price = Money::from_decimal(price)?;
+
+        Ok(match (self.min_price, self.max_price) {
+            (Some(min), _) if price < min => (min, Some(Guard::Floor)),
+            (_, Some(max)) if price > max => (max, Some(Guard::Ceiling)),
+            _ => (price, None),
+        })
+    }
+}
+
+/// The smallest amount of at least `price` that ends in `ending`, counting the
+/// ending's digits from the smallest power of ten above it: with 0.95 prices step
+/// by 1 (3.95, 4.95), with 9 by 10 (19, 29).
+fn with_ending(price: Decimal, ending: Decimal) -> Decimal {
+    let mut step = Decimal::new(1, ending.scale());
+    while step <= ending {
+        step *= Decimal::TEN;
+    }
+    let candidate = (price / step).floor() * step + ending;
+    if candidate < price {
+        candidate + step
+    } else {
+        candidate
+    }
+}
+
+/// Which guard replaced the price a rule worked out.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Guard {
+    Floor,
+    Ceiling,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PricingRules {
+    /// Decimal places prices are rounded up to when a rule has no ending.
+    #[serde(default = "PricingRules::default_decimals")]
+    pub decimals: u32,
+    #[serde(default)]
+    pub rules: Vec<PricingRule>,
+}
+
+impl Default for PricingRules {
+    /// No rules: recalculating leaves every price as it is.
+    fn default() -> Self {
+        Self { decimals: Self::default_decimals(), rules: Vec::new() }
+    }
+}
+
+impl PricingRules {
+    fn default_decimals() -> u32 {
+        2
+    }
+
+    /// Reads a rules file, as JSON if it ends in `.json` and TOML otherwise.
+    pub fn from_file(path: &Path) -> Result<Self, AppError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| AppError::Config(format!("cannot read {}: {}", path.display(), e)))?;
+
+        let rules: Self = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&contents)
+                .map_err(|e| AppError::Config(format!("invalid {}: {}", path.display(), e)))?
+        } else {
+            toml::from_str(&contents)
+                .map_err(|e| AppError::Config(format!("invalid {}: {}", path.display(), e)))?
+        };
+        rules.check()?;
+        Ok(rules)
+    }
+
+    /// The configured rules file, or no rules without one.
+    pub fn load(rules_file: Option<&str>) -> Result<Self, AppError> {
+        match rules_file {
+            Some(path) => Self::from_file(Path::new(path)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn check(&self) -> Result<(), AppError> {
+        if self.decimals > crate::money::SCALE {
+            return Err(AppError::Config(format!("pricing decimals must be at most {}", crate::money::SCALE)));
+        }
+        let mut seen = Vec::new();
+        for rule in &self.rules {
+            if rule.id.trim().is_empty() {
+                return Err(AppError::Config("every pricing rule needs an id".to_string()));
+            }
+            if seen.contains(&rule.id.as_str()) {
+                return Err(AppError::Config(format!("duplicate pricing rule id {:?}", rule.id)));
+            }
+            seen.push(&rule.id);
+
+            rule.check()
+                .map_err(|e| AppError::Config(format!("pricing rule {:?}: {}", rule.id, e)))?;
+        }
+        Ok(())
+    }
+
+    /// The rule that prices `product`'s `price`: the one with the most specific
+    /// matching scope, and of those the first in the file.
+    pub fn rule_for(&self, product: &Product, price: PriceKind) -> Option<&PricingRule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.price == price && rule.scope.matches(product))
+            .fold(None::<&PricingRule>, |best, rule| match best {
+                Some(best) if best.scope.specificity() >= rule.scope.specificity() => Some(best),
+                _ => Some(rule),
+            })
+    }
+}
+
+/// A price a rule would set, next to the current one.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PriceProposal {
+    pub product_id: i64,
+    pub barcode: String,
+    pub product_name: String,
+    pub price: PriceKind,
+    pub rule_id: String,
+    pub currency: String,
+    /// Landed cost converted into `currency`
+    pub landed_cost: Money,
+    pub current: Money,
+    pub proposed: Money,
+    /// Margin at the proposed price in percent, to two places; none for a zero price
+    pub margin: Option<Rate>,
+    /// Set when a floor or ceiling replaced the rule's price
+    pub guard: Option<Guard>,
+}
+
+/// A price a rule covers but could not work out, e.g. for want of an exchange rate.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PriceSkip {
+    pub barcode: String,
+    pub price: PriceKind,
+    pub rule_id: String,
+    pub reason: String,
+}
+
+/// What a recalculation changes, or changed once applied.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct PricingReport {
+    pub applied: bool,
+    pub changes: Vec<PriceProposal>,
+    /// Prices a rule covers that are already at the rule's price
+    pub unchanged: usize,
+    pub skipped: Vec<PriceSkip>,
+}
+
+/// Works out the wholesale and retail prices the rules give each product from its
+/// landed cost on `date`. Prices without a rule are left out.
+pub fn recalculate(products: &[Product], rules: &PricingRules, rates: &ExchangeRates, date: NaiveDate) -> PricingReport {
+    let mut report = PricingReport::default();
+    for product in products {
+        for price in [PriceKind::Wholesale, PriceKind::Retail] {
+            let Some(rule) = rules.rule_for(product, price) else {
+                continue;
+            };
+            let (current, currency) = price.of(product);
+            let priced = currency::landed_cost(product, rates, date)
+                .and_then(|landed| rates.convert(landed.total, rates.base(), currency, date))
+                .and_then(|landed_cost| Ok((landed_cost, rule.price_for(landed_cost, rules.decimals)?)));
+            let (landed_cost, (proposed, guard)) = match priced {
+                Ok(priced) => priced,
+                Err(e) => {
+                    report.skipped.push(PriceSkip {
+                        barcode: product.barcode.clone(),
+                        price,
+                        rule_id: rule.id.clone(),
+                        reason: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            if proposed == current {
+                report.unchanged += 1;
+                continue;
+            }
+            let margin = (!proposed.is_zero()).then(|| {
+                let left = proposed.to_decimal() - landed_cost.to_decimal();
+                rates.rounding().percent(left / proposed.to_decimal() * Decimal::ONE_HUNDRED)
+            });
+            report.changes.push(PriceProposal {
+                product_id: product.id.unwrap_or_default(),
+                barcode: product.barcode.clone(),
+                product_name: product.imported_name.clone(),
+                price,
+                rule_id: rule.id.clone(),
+                currency: currency.to_string(),
+                landed_cost,
+                current,
+                proposed,
+                margin,
+                guard,
+            });
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CurrencyConfig;
+    use crate::models::ProductPatch;
+    use std::str::FromStr;
+
+    fn money(s: &str) -> Money {
+        s.parse().unwrap()
+    }
+
+    fn decimal(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn margin(percent: &str) -> Target {
+        Target::Margin { percent: percent.parse().unwrap() }
+    }
+
+    fn markup(percent: &str) -> Target {
+        Target::Markup { percent: percent.parse().unwrap() }
+    }
+
+    fn rule(id: &str, price: PriceKind, target: Target, scope: PricingScope) -> PricingRule {
+        PricingRule { id: id.to_string(), price, target, scope, ending: None, min_price: None, max_price: None }
+    }
+
+    fn scope(category: Option<&str>, brand: Option<&str>, supplier: Option<&str>) -> PricingScope {
+        PricingScope {
+            category: category.map(str::to_string),
+            brand: brand.map(str::to_string),
+            supplier: supplier.map(str::to_string),
+        }
+    }
+
+    fn product(barcode: &str, supplier: &str, purchase_currency: &str) -> Product {
+        let mut product = ProductPatch {
+            imported_name: Some("Chickpeas".to_string()),
+            barcode: Some(barcode.to_string()),
+            category: Some("Legumes".to_string()),
+            brand: Some("Al-Wadi".to_string()),
+            supplier: Some(supplier.to_string()),
+            purchase_price: Some(money("10")),
+            purchase_currency: Some(purchase_currency.to_string()),
+            wholesale_currency: Some("SEK".to_string()),
+            retail_currency: Some("SEK".to_string()),
+            retail_price: Some(money("29.90")),
+            expiry_date: NaiveDate::from_ymd_opt(2027, 12, 31),
+            ..ProductPatch::default()
+        }
+        .into_product()
+        .unwrap();
+        product.id = Some(1);
+        product
+    }
+
+    #[test]
+    fn endings_raise_prices_to_the_next_amount_ending_in_them() {
+        let cases = [
+            ("3.20", "0.95", "3.95"),
+            ("3.96", "0.95", "4.95"),
+            ("3.95", "0.95", "3.95"),
+            ("12", "9", "19"),
+            ("19", "9", "19"),
+            ("19.01", "9", "29"),
+            ("0.40", "9", "9"),
+            ("150", "99", "199"),
+        ];
+        for (price, ending, expected) in cases {
+            assert_eq!(with_ending(decimal(price), decimal(ending)), decimal(expected), "{} ending in {}", price, ending);
+        }
+    }
+
+    #[test]
+    fn margins_are_shares_of_the_price_and_markups_of_the_cost() {
+        assert_eq!(margin("50").apply(decimal("10")), Some(decimal("20")));
+        assert_eq!(margin("25").apply(decimal("30")), Some(decimal("40")));
+        assert_eq!(margin("0").apply(decimal("10")), Some(decimal("10")));
+        assert_eq!(markup("50").apply(decimal("10")), Some(decimal("15")));
+        assert_eq!(markup("0").apply(decimal("10")), Some(decimal("10")));
+        assert_eq!(margin("99.9999999999999999999999999").apply(decimal("1000000")), None);
+
+        // Without an ending prices round up to the rules' decimals
+        let retail = rule("retail", PriceKind::Retail, markup("10"), PricingScope::default());
+        assert_eq!(retail.price_for(money("1.01"), 2).unwrap(), (money("1.12"), None));
+        assert_eq!(retail.price_for(money("1.01"), 0).unwrap(), (money("2"), None));
+        let ending = PricingRule { ending: Some(money("0.95")), ..retail };
+        assert_eq!(ending.price_for(money("1.01"), 2).unwrap(), (money("1.95"), None));
+
+        let extreme = rule("extreme", PriceKind::Retail, margin("99.9999999999999999999999999"), PricingScope::default());
+        let error = extreme.price_for(money("1000000"), 2).unwrap_err();
+        assert!(error.to_string().contains("rule extreme gives a price too large to hold"), "{}", error);
+    }
+
+    #[test]
+    fn floors_and_ceilings_replace_prices_outside_them() {
+        let guarded = PricingRule {
+            min_price: Some(money("25")),
+            max_price: Some(money("40")),
+            ..rule("guarded", PriceKind::Retail, margin("50"), PricingScope::default())
+        };
+        assert_eq!(guarded.price_for(money("10"), 2).unwrap(), (money("25"), Some(Guard::Floor)));
+        assert_eq!(guarded.price_for(money("15"), 2).unwrap(), (money("30"), None));
+        assert_eq!(guarded.price_for(money("20"), 2).unwrap(), (money("40"), None));
+        assert_eq!(guarded.price_for(money("30"), 2).unwrap(), (money("40"), Some(Guard::Ceiling)));
+
+        let rules = PricingRules {
+            decimals: 2,
+            rules: vec![PricingRule { min_price: Some(money("50")), max_price: Some(money("40")), ..guarded }],
+        };
+        let error = rules.check().unwrap_err();
+        assert!(error.to_string().contains("min_price must not be above max_price"), "{}", error);
+    }
+
+    #[test]
+    fn the_most_specific_rule_applies_and_ties_go_to_the_first() {
+        let rules = PricingRules {
+            decimals: 2,
+            rules: vec![
+                rule("everything", PriceKind::Retail, margin("30"), PricingScope::default()),
+                rule("legumes", PriceKind::Retail, margin("35"), scope(Some("legumes"), None, None)),
+                rule("al-wadi-legumes", PriceKind::Retail, margin("40"), scope(Some("Legumes"), Some("AL-WADI"), None)),
+                rule("beirut", PriceKind::Retail, margin("45"), scope(None, None, Some("beirut foods sal"))),
+                rule("beirut-again", PriceKind::Retail, margin("50"), scope(None, None, Some("Beirut Foods"))),
+                rule("wholesale", PriceKind::Wholesale, markup("20"), PricingScope::default()),
+            ],
+        };
+        let id = |product: &Product, price| rules.rule_for(product, price).map(|rule| rule.id.as_str());
+
+        let beirut = product("5281234567896", "Beirut Foods", "SEK");
+        assert_eq!(id(&beirut, PriceKind::Retail), Some("beirut"));
+        assert_eq!(id(&beirut, PriceKind::Wholesale), Some("wholesale"));
+        assert_eq!(id(&beirut, PriceKind::Purchase), None);
+
+        let other = product("5281234567896", "Istanbul Trading", "SEK");
+        assert_eq!(id(&other, PriceKind::Retail), Some("al-wadi-legumes"));
+        let unbranded = Product { brand: "Durra".to_string(), ..other.clone() };
+        assert_eq!(id(&unbranded, PriceKind::Retail), Some("legumes"));
+        let rice = Product { category: "Rice".to_string(), ..unbranded };
+        assert_eq!(id(&rice, PriceKind::Retail), Some("everything"));
+    }
+
+    #[test]
+    fn prices_that_cannot_be_converted_are_skipped() {
+        let rules = PricingRules {
+            decimals: 2,
+            rules: vec![rule("retail", PriceKind::Retail, margin("50"), PricingScope::default())],
+        };
+        let rates = ExchangeRates::new("SEK", &[], CurrencyConfig::default().rounding());
+        let date = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+        let products = [
+            product("5281234567896", "Beirut Foods", "SEK"),
+            Product { id: Some(2), ..product("8690504000006", "Istanbul Trading", "USD") },
+        ];
+
+        let report = recalculate(&products, &rules, &rates, date);
+        assert_eq!(report.changes.len(), 1);
+        let change = &report.changes[0];
+        assert_eq!((change.barcode.as_str(), change.rule_id.as_str()), ("5281234567896", "retail"));
+        assert_eq!((change.landed_cost, change.current, change.proposed), (money("10"), money("29.90"), money("20")));
+        assert_eq!(change.margin, Some("50".parse().unwrap()));
+        assert_eq!(change.guard, None);
+
+        assert_eq!(report.skipped.len(), 1);
+        let skipped = &report.skipped[0];
+        assert_eq!((skipped.barcode.as_str(), skipped.price, skipped.rule_id.as_str()), ("8690504000006", PriceKind::Retail, "retail"));
+        assert!(skipped.reason.contains("USD"), "{}", skipped.reason);
+        assert_eq!(report.unchanged, 0);
+    }
+}
@@ -0,0 +1,95 @@
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfLayerReference};
+use crate::models::AppError;
+use crate::reports::Table;
+
+/// A4 landscape, so wide reports fit.
+const PAGE_WIDTH: f32 = 297.0;
+const PAGE_HEIGHT: f32 = 210.0;
+const MARGIN: f32 = 15.0;
+const ROW_HEIGHT: f32 = 5.5;
+const FONT_SIZE: f32 = 8.0;
+/// Rough width of a character at `FONT_SIZE` in Helvetica, for sizing columns.
+const CHAR_WIDTH: f32 = 1.6;
+/// Text columns wider than this many characters are cut short.
+const MAX_TEXT_CHARS: usize = 48;
+
+/// Renders a report table as a PDF for printing, repeating the column titles on
+/// every page.
+pub fn render(table: &Table) -> Result<Vec<u8>, AppError> {
+    let (doc, page, layer) = PdfDocument::new(&table.title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Report");
+    let font = doc.add_builtin_font(BuiltinFont::Helvetica).map_err(|e| AppError::Pdf(e.to_string()))?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold).map_err(|e| AppError::Pdf(e.to_string()))?;
+    let columns = column_edges(table);
+
+    let mut layer = doc.get_page(page).get_layer(layer);
+    let mut y = PAGE_HEIGHT - MARGIN;
+    layer.use_text(&table.title, 14.0, Mm(MARGIN), Mm(y), &bold);
+    y -= 7.0;
+    layer.use_text(&table.subtitle, 9.0, Mm(MARGIN), Mm(y), &font);
+    y -= ROW_HEIGHT * 2.0;
+
+    let header: Vec<String> = table.columns.iter().map(|column| column.to_string()).collect();
+    write_row(&layer, &bold, &columns, &header, y);
+    let rows = table.rows.iter().map(|row| (row, &font)).chain(table.total.iter().map(|row| (row, &bold)));
+    for (row, row_font) in rows {
+        y -= ROW_HEIGHT;
+        if y < MARGIN {
+            let (page, new_layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Report");
+            layer = doc.get_page(page).get_layer(new_layer);
+            y = PAGE_HEIGHT - MARGIN;
+            write_row(&layer, &bold, &columns, &header, y);
+            y -= ROW_HEIGHT;
+        }
+        write_row(&layer, row_font, &columns, row, y);
+    }
+
+    for note in &table.notes {
+        y -= ROW_HEIGHT * 1.5;
+        if y < MARGIN {
+            let (page, new_layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Report");
+            layer = doc.get_page(page).get_layer(new_layer);
+            y = PAGE_HEIGHT - MARGIN;
+        }
+        layer.use_text(note, FONT_SIZE, Mm(MARGIN), Mm(y), &font);
+    }
+
+    doc.save_to_bytes().map_err(|e| AppError::Pdf(e.to_string()))
+}
+
+/// Left edge and width in characters of each column, sized to its longest cell
+/// and scaled down to fit the page.
+fn column_edges(table: &Table) -> Vec<(f32, usize)> {
+    let chars: Vec<usize> = table.columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let longest = table.rows
+                .iter()
+                .chain(&table.total)
+                .map(|row| row[i].chars().count())
+                .chain([column.len()])
+                .max()
+                .unwrap_or(0);
+            longest.min(MAX_TEXT_CHARS) + 2
+        })
+        .collect();
+    let wanted: f32 = chars.iter().map(|&n| n as f32 * CHAR_WIDTH).sum();
+    let scale = ((PAGE_WIDTH - 2.0 * MARGIN) / wanted).min(1.0);
+
+    let mut x = MARGIN;
+    chars
+        .into_iter()
+        .map(|n| {
+            let edge = (x, ((n - 2) as f32 * scale) as usize);
+            x += n as f32 * CHAR_WIDTH * scale;
+            edge
+        })
+        .collect()
+}
+
+fn write_row(layer: &PdfLayerReference, font: &IndirectFontRef, columns: &[(f32, usize)], cells: &[String], y: f32) {
+    for (cell, &(x, width)) in cells.iter().zip(columns) {
+        let text: String = cell.chars().take(width.max(1)).collect();
+        layer.use_text(text, FONT_SIZE, Mm(x), Mm(y), font);
+    }
+}
@@ -0,0 +1,992 @@
+use chrono::{Duration, NaiveDate};
+use rust_decimal::Decimal;
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use crate::currency::{self, ExchangeRates};
+use crate::inventory_manager::InventoryManager;
+use crate::models::{AppError, DailySales, Product, Supplier};
+use crate::money::{Money, Rate, Rounding};
+
+/// The detailed reports, each over a period of days.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportKind {
+    /// Stock at the end of the period at landed cost and at retail
+    Valuation,
+    /// Revenue, cost and gross margin of what sold in the period
+    Margin,
+    /// Share of the stock on hand or received in the period that sold
+    SellThrough,
+    /// How many days the stock lasts at the period's rate of sale
+    Cover,
+    /// Stock that did not sell at all in the period
+    DeadStock,
+}
+
+impl ReportKind {
+    pub const ALL: [&'static str; 5] = ["valuation", "margin", "sell_through", "cover", "dead_stock"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportKind::Valuation => "valuation",
+            ReportKind::Margin => "margin",
+            ReportKind::SellThrough => "sell_through",
+            ReportKind::Cover => "cover",
+            ReportKind::DeadStock => "dead_stock",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            ReportKind::Valuation => "Stock valuation",
+            ReportKind::Margin => "Gross margin",
+            ReportKind::SellThrough => "Sell-through",
+            ReportKind::Cover => "Days of cover",
+            ReportKind::DeadStock => "Dead stock",
+        }
+    }
+
+    /// Whether rows can be grouped; cover and dead stock are always per product.
+    fn groups(&self) -> bool {
+        matches!(self, ReportKind::Valuation | ReportKind::Margin | ReportKind::SellThrough)
+    }
+}
+
+impl FromStr for ReportKind {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "valuation" => Ok(ReportKind::Valuation),
+            "margin" => Ok(ReportKind::Margin),
+            "sell_through" => Ok(ReportKind::SellThrough),
+            "cover" => Ok(ReportKind::Cover),
+            "dead_stock" => Ok(ReportKind::DeadStock),
+            _ => Err(AppError::Validation(format!("unknown report {:?}", s))),
+        }
+    }
+}
+
+/// What the rows of a report add up.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    #[default]
+    Product,
+    Category,
+    Supplier,
+    Country,
+}
+
+impl GroupBy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupBy::Product => "product",
+            GroupBy::Category => "category",
+            GroupBy::Supplier => "supplier",
+            GroupBy::Country => "country",
+        }
+    }
+
+    /// The key a product is grouped under and the group's label.
+    fn key(&self, product: &Product) -> (String, String) {
+        let label = match self {
+            GroupBy::Product => return (product.barcode.clone(), product.imported_name.clone()),
+            GroupBy::Category => &product.category,
+            GroupBy::Supplier => &product.supplier,
+            GroupBy::Country => &product.origin_country,
+        };
+        let label = if label.trim().is_empty() { "(none)".to_string() } else { label.trim().to_string() };
+        (label.to_lowercase(), label)
+    }
+}
+
+impl FromStr for GroupBy {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "product" => Ok(GroupBy::Product),
+            "category" => Ok(GroupBy::Category),
+            "supplier" => Ok(GroupBy::Supplier),
+            "country" => Ok(GroupBy::Country),
+            _ => Err(AppError::Validation(format!("unknown grouping {:?}", s))),
+        }
+    }
+}
+
+/// How a report is written out.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    Text,
+    Json,
+    Csv,
+    /// A standalone page that prints cleanly
+    Html,
+    Pdf,
+}
+
+impl ReportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ReportFormat::Text => "text/plain; charset=utf-8",
+            ReportFormat::Json => "application/json",
+            ReportFormat::Csv => "text/csv; charset=utf-8",
+            ReportFormat::Html => "text/html; charset=utf-8",
+            ReportFormat::Pdf => "application/pdf",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Text => "txt",
+            ReportFormat::Json => "json",
+            ReportFormat::Csv => "csv",
+            ReportFormat::Html => "html",
+            ReportFormat::Pdf => "pdf",
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            "html" => Ok(ReportFormat::Html),
+            "pdf" => Ok(ReportFormat::Pdf),
+            _ => Err(AppError::Validation(format!("unknown format {:?}", s))),
+        }
+    }
+}
+
+/// Limits a report to products of a category, brand, supplier and/or country of
+/// origin. Names compare case-insensitively, supplier names also without their
+/// legal form.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ReportFilter {
+    pub category: Option<String>,
+    pub brand: Option<String>,
+    pub supplier: Option<String>,
+    pub country: Option<String>,
+}
+
+impl ReportFilter {
+    pub fn matches(&self, product: &Product) -> bool {
+        let same = |want: &Option<String>, have: &str| want.as_ref().is_none_or(|want| want.eq_ignore_ascii_case(have));
+        let same_supplier = self.supplier.as_ref().is_none_or(|want| Supplier::name_key(want) == Supplier::name_key(&product.supplier));
+        same(&self.category, &product.category)
+            && same(&self.brand, &product.brand)
+            && same(&self.country, &product.origin_country)
+            && same_supplier
+    }
+
+    fn describe(&self) -> Vec<String> {
+        [("category", &self.category), ("brand", &self.brand), ("supplier", &self.supplier), ("country", &self.country)]
+            .into_iter()
+            .filter_map(|(label, value)| value.as_ref().map(|value| format!("{} {}", label, value)))
+            .collect()
+    }
+}
+
+/// The period, grouping and filter a report is run with.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ReportParams {
+    /// First day of the period; `period_days` before `to` when left out
+    pub from: Option<NaiveDate>,
+    /// Last day of the period, today when left out
+    pub to: Option<NaiveDate>,
+    pub group_by: Option<GroupBy>,
+    #[serde(flatten)]
+    pub filter: ReportFilter,
+}
+
+impl ReportParams {
+    /// The first and last day of the period, both included.
+    pub fn period(&self, period_days: u32, today: NaiveDate) -> Result<(NaiveDate, NaiveDate), AppError> {
+        let to = self.to.unwrap_or(today);
+        let from = self.from.unwrap_or(to - Duration::days(period_days.max(1) as i64 - 1));
+        if from > to {
+            return Err(AppError::Validation(format!("report period starts on {} after it ends on {}", from, to)));
+        }
+        Ok((from, to))
+    }
+}
+
+/// Stock of one product around a period, from the stock ledger.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StockFlow {
+    /// Stock at the start of the first day
+    pub opening: i32,
+    /// Units received during the period
+    pub received: i32,
+    /// Stock at the end of the last day
+    pub closing: i32,
+}
+
+/// Everything the reports look at for one period, loaded in one go.
+pub struct ReportData {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub products: Vec<Product>,
+    /// Daily sales within the period
+    pub sales: Vec<DailySales>,
+    pub rates: ExchangeRates,
+    /// Ledger stock per product id; products without movements keep their current stock
+    pub flows: HashMap<i64, StockFlow>,
+    /// Last day each product sold, up to the end of the period
+    pub last_sale: HashMap<i64, NaiveDate>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ValuationRow {
+    pub group: String,
+    /// Set when grouped by product
+    pub barcode: Option<String>,
+    pub products: usize,
+    pub units: i32,
+    pub cost_value: Money,
+    pub retail_value: Money,
+    /// Share of the retail value left after landed cost, in percent
+    pub margin: Option<Rate>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MarginRow {
+    pub group: String,
+    pub barcode: Option<String>,
+    pub units_sold: i32,
+    /// Units sold at the current retail price
+    pub revenue: Money,
+    /// Units sold at the current landed cost
+    pub cost: Money,
+    pub gross_profit: Money,
+    pub margin: Option<Rate>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SellThroughRow {
+    pub group: String,
+    pub barcode: Option<String>,
+    pub opening: i32,
+    pub received: i32,
+    pub sold: i32,
+    pub closing: i32,
+    /// Units sold as a percentage of opening stock plus units received
+    pub sell_through: Option<Rate>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CoverRow {
+    pub barcode: String,
+    pub product: String,
+    pub stock: i32,
+    pub sold: i32,
+    pub per_day: f64,
+    /// None when nothing sold in the period
+    pub days_of_cover: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DeadStockRow {
+    pub barcode: String,
+    pub product: String,
+    pub stock: i32,
+    pub cost_value: Money,
+    /// None when the product has never sold
+    pub last_sale: Option<NaiveDate>,
+    /// Days from the last sale to the end of the period
+    pub days_since_sale: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+pub enum ReportBody {
+    Valuation { rows: Vec<ValuationRow>, total: ValuationRow },
+    Margin { rows: Vec<MarginRow>, total: MarginRow },
+    SellThrough { rows: Vec<SellThroughRow>, total: SellThroughRow },
+    Cover { rows: Vec<CoverRow> },
+    DeadStock { rows: Vec<DeadStockRow>, units: i32, cost_value: Money },
+}
+
+/// A report with the period and filter it covers. Amounts are in the base
+/// currency at the exchange rates of the period's last day.
+#[derive(Debug, Serialize, Clone)]
+pub struct Report {
+    pub kind: ReportKind,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub currency: String,
+    /// None for reports that are always per product
+    pub group_by: Option<GroupBy>,
+    pub filter: ReportFilter,
+    /// Barcodes of products left out because a price could not be converted
+    pub without_rate: Vec<String>,
+    #[serde(flatten)]
+    pub body: ReportBody,
+}
+
+/// One product's figures for the period, in the base currency.
+struct Figures<'a> {
+    product: &'a Product,
+    flow: StockFlow,
+    sold: i32,
+    unit_cost: Money,
+    unit_retail: Money,
+}
+
+/// Works out a report from the data for its period. An error when a value is too
+/// large to hold.
+pub fn build(kind: ReportKind, data: &ReportData, params: &ReportParams) -> Result<Report, AppError> {
+    let mut sales: HashMap<i64, Vec<&DailySales>> = HashMap::new();
+    for entry in &data.sales {
+        sales.entry(entry.product_id).or_default().push(entry);
+    }
+
+    let mut figures = Vec::new();
+    let mut without_rate = Vec::new();
+    for product in data.products.iter().filter(|product| params.filter.matches(product)) {
+        let Some(product_id) = product.id else { continue };
+        let costing = match currency::costing(product, &data.rates, data.to) {
+            Ok(costing) => costing,
+            Err(_) => {
+                without_rate.push(product.barcode.clone());
+                continue;
+            }
+        };
+        let stock = product.stock_quantity;
+        let sold = sales
+            .get(&product_id)
+            .map_or(0, |sales| InventoryManager::units_sold_between(sales, data.from, data.to));
+        figures.push(Figures {
+            product,
+            flow: data.flows.get(&product_id).copied().unwrap_or(StockFlow { opening: stock, received: 0, closing: stock }),
+            sold,
+            unit_cost: costing.landed_cost.total,
+            unit_retail: costing.retail_price,
+        });
+    }
+
+    let rounding = data.rates.rounding();
+    let group_by = kind.groups().then(|| params.group_by.unwrap_or_default());
+    let body = match kind {
+        ReportKind::Valuation => {
+            let row = |group: String, barcode: Option<String>, figures: &[&Figures]| {
+                let units = figures.iter().map(|f| f.flow.closing).sum();
+                let cost = value_of(figures, |f| (f.unit_cost, f.flow.closing))?;
+                let retail = value_of(figures, |f| (f.unit_retail, f.flow.closing))?;
+                Ok(ValuationRow {
+                    group,
+                    barcode,
+                    products: figures.len(),
+                    units,
+                    cost_value: rounding.total(cost.to_decimal())?,
+                    retail_value: rounding.total(retail.to_decimal())?,
+                    margin: share(retail.checked_sub(cost)?, retail, rounding),
+                })
+            };
+            let (rows, total) = grouped(&figures, group_by.unwrap_or_default(), row)?;
+            ReportBody::Valuation { rows, total }
+        }
+        ReportKind::Margin => {
+            let row = |group: String, barcode: Option<String>, figures: &[&Figures]| {
+                let revenue = value_of(figures, |f| (f.unit_retail, f.sold))?;
+                let cost = value_of(figures, |f| (f.unit_cost, f.sold))?;
+                let gross_profit = revenue.checked_sub(cost)?;
+                Ok(MarginRow {
+                    group,
+                    barcode,
+                    units_sold: figures.iter().map(|f| f.sold).sum(),
+                    revenue: rounding.total(revenue.to_decimal())?,
+                    cost: rounding.total(cost.to_decimal())?,
+                    gross_profit: rounding.total(gross_profit.to_decimal())?,
+                    margin: share(gross_profit, revenue, rounding),
+                })
+            };
+            let (rows, total) = grouped(&figures, group_by.unwrap_or_default(), row)?;
+            ReportBody::Margin { rows, total }
+        }
+        ReportKind::SellThrough => {
+            let row = |group: String, barcode: Option<String>, figures: &[&Figures]| {
+                let opening = figures.iter().map(|f| f.flow.opening).sum::<i32>();
+                let received = figures.iter().map(|f| f.flow.received).sum::<i32>();
+                let sold = figures.iter().map(|f| f.sold).sum::<i32>();
+                let available = Decimal::from(opening + received);
+                Ok(SellThroughRow {
+                    group,
+                    barcode,
+                    opening,
+                    received,
+                    sold,
+                    closing: figures.iter().map(|f| f.flow.closing).sum(),
+                    sell_through: (available > Decimal::ZERO)
+                        .then(|| rounding.percent(Decimal::from(sold) / available * Decimal::ONE_HUNDRED)),
+                })
+            };
+            let (rows, total) = grouped(&figures, group_by.unwrap_or_default(), row)?;
+            ReportBody::SellThrough { rows, total }
+        }
+        ReportKind::Cover => {
+            let days = (data.to - data.from).num_days() + 1;
+            let mut rows: Vec<CoverRow> = figures
+                .iter()
+                .map(|f| {
+                    let per_day = f.sold as f64 / days as f64;
+                    CoverRow {
+                        barcode: f.product.barcode.clone(),
+                        product: f.product.imported_name.clone(),
+                        stock: f.flow.closing,
+                        sold: f.sold,
+                        per_day,
+                        days_of_cover: (per_day > 0.0).then(|| f.flow.closing.max(0) as f64 / per_day),
+                    }
+                })
+                .collect();
+            // Shortest cover first; products that did not sell last
+            rows.sort_by(|a, b| match (a.days_of_cover, b.days_of_cover) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => a.is_none().cmp(&b.is_none()),
+            });
+            ReportBody::Cover { rows }
+        }
+        ReportKind::DeadStock => {
+            let mut rows: Vec<DeadStockRow> = figures
+                .iter()
+                .filter(|f| f.sold <= 0 && f.flow.closing > 0)
+                .map(|f| {
+                    let last_sale = f.product.id.and_then(|id| data.last_sale.get(&id)).copied();
+                    Ok(DeadStockRow {
+                        barcode: f.product.barcode.clone(),
+                        product: f.product.imported_name.clone(),
+                        stock: f.flow.closing,
+                        cost_value: rounding.total(f.unit_cost.checked_mul(f.flow.closing)?.to_decimal())?,
+                        last_sale,
+                        days_since_sale: last_sale.map(|day| (data.to - day).num_days()),
+                    })
+                })
+                .collect::<Result<_, AppError>>()?;
+            rows.sort_by(|a, b| b.cost_value.cmp(&a.cost_value).then_with(|| a.product.cmp(&b.product)));
+            ReportBody::DeadStock {
+                units: rows.iter().map(|row| row.stock).sum(),
+                cost_value: Money::checked_sum(rows.iter().map(|row| row.cost_value))?,
+                rows,
+            }
+        }
+    };
+
+    Ok(Report {
+        kind,
+        from: data.from,
+        to: data.to,
+        currency: data.rates.base().to_string(),
+        group_by,
+        filter: params.filter.clone(),
+        without_rate,
+        body,
+    })
+}
+
+/// One row per group, ordered by label, and a row for all of them.
+fn grouped<T>(
+    figures: &[Figures],
+    group_by: GroupBy,
+    row: impl Fn(String, Option<String>, &[&Figures]) -> Result<T, AppError>,
+) -> Result<(Vec<T>, T), AppError> {
+    let mut groups: BTreeMap<(String, String), Vec<&Figures>> = BTreeMap::new();
+    for f in figures {
+        let (key, label) = group_by.key(f.product);
+        groups.entry((label.to_lowercase(), key)).or_default().push(f);
+    }
+
+    let rows = groups
+        .into_values()
+        .map(|members| {
+            let (_, label) = group_by.key(members[0].product);
+            let barcode = (group_by == GroupBy::Product).then(|| members[0].product.barcode.clone());
+            row(label, barcode, &members)
+        })
+        .collect::<Result<_, _>>()?;
+    let all: Vec<&Figures> = figures.iter().collect();
+    Ok((rows, row("Total".to_string(), None, &all)?))
+}
+
+/// Each product's unit amount times its units, added up.
+fn value_of(figures: &[&Figures], amount: impl Fn(&Figures) -> (Money, i32)) -> Result<Money, AppError> {
+    figures.iter().try_fold(Money::ZERO, |sum, f| {
+        let (unit, units) = amount(f);
+        sum.checked_add(unit.checked_mul(units)?)
+    })
+}
+
+/// `part` as a percentage of `whole`; none when `whole` is zero.
+fn share(part: Money, whole: Money, rounding: Rounding) -> Option<Rate> {
+    (!whole.is_zero()).then(|| rounding.percent(part.to_decimal() / whole.to_decimal() * Decimal::ONE_HUNDRED))
+}
+
+/// A report laid out as rows of text, for every format but JSON.
+pub struct Table {
+    pub title: String,
+    /// Period, currency and filter
+    pub subtitle: String,
+    pub columns: Vec<&'static str>,
+    /// How many leading columns hold text; the rest are right-aligned numbers
+    pub text_columns: usize,
+    pub rows: Vec<Vec<String>>,
+    pub total: Option<Vec<String>>,
+    pub notes: Vec<String>,
+}
+
+impl Report {
+    pub fn table(&self) -> Table {
+        let percent = |rate: Option<Rate>| rate.map(|rate| format!("{:.1}%", rate)).unwrap_or_else(|| "-".to_string());
+        let label = |group: &str, barcode: &Option<String>| match barcode {
+            Some(barcode) => vec![barcode.clone(), group.to_string()],
+            None => vec![group.to_string()],
+        };
+        let by_product = self.group_by == Some(GroupBy::Product);
+        let key_columns: Vec<&'static str> = match self.group_by {
+            Some(GroupBy::Product) | None => vec!["Barcode", "Product"],
+            Some(GroupBy::Category) => vec!["Category"],
+            Some(GroupBy::Supplier) => vec!["Supplier"],
+            Some(GroupBy::Country) => vec!["Country"],
+        };
+        let total_label = |cells: Vec<String>| {
+            if by_product {
+                [vec![String::new()], cells].concat()
+            } else {
+                cells
+            }
+        };
+
+        let (columns, rows, total): (Vec<&'static str>, Vec<Vec<String>>, Option<Vec<String>>) = match &self.body {
+            ReportBody::Valuation { rows, total } => {
+                let cells = |row: &ValuationRow| {
+                    let mut cells = label(&row.group, &row.barcode);
+                    cells.extend([
+                        row.products.to_string(),
+                        row.units.to_string(),
+                        row.cost_value.to_string(),
+                        row.retail_value.to_string(),
+                        percent(row.margin),
+                    ]);
+                    cells
+                };
+                (
+                    ["Products", "Units", "At cost", "At retail", "Margin"].to_vec(),
+                    rows.iter().map(cells).collect(),
+                    Some(total_label(cells(total))),
+                )
+            }
+            ReportBody::Margin { rows, total } => {
+                let cells = |row: &MarginRow| {
+                    let mut cells = label(&row.group, &row.barcode);
+                    cells.extend([
+                        row.units_sold.to_string(),
+                        row.revenue.to_string(),
+                        row.cost.to_string(),
+                        row.gross_profit.to_string(),
+                        percent(row.margin),
+                    ]);
+                    cells
+                };
+                (
+                    ["Sold", "Revenue", "Cost", "Gross profit", "Margin"].to_vec(),
+                    rows.iter().map(cells).collect(),
+                    Some(total_label(cells(total))),
+                )
+            }
+            ReportBody::SellThrough { rows, total } => {
+                let cells = |row: &SellThroughRow| {
+                    let mut cells = label(&row.group, &row.barcode);
+                    cells.extend([
+                        row.opening.to_string(),
+                        row.received.to_string(),
+                        row.sold.to_string(),
+                        row.closing.to_string(),
+                        percent(row.sell_through),
+                    ]);
+                    cells
+                };
+                (
+                    ["Opening", "Received", "Sold", "Closing", "Sell-through"].to_vec(),
+                    rows.iter().map(cells).collect(),
+                    Some(total_label(cells(total))),
+                )
+            }
+            ReportBody::Cover { rows } => (
+                ["Stock", "Sold", "Per day", "Days of cover"].to_vec(),
+                rows.iter()
+                    .map(|row| vec![
+                        row.barcode.clone(),
+                        row.product.clone(),
+                        row.stock.to_string(),
+                        row.sold.to_string(),
+                        format!("{:.2}", row.per_day),
+                        row.days_of_cover.map(|days| format!("{:.1}", days)).unwrap_or_else(|| "no sales".to_string()),
+                    ])
+                    .collect(),
+                None,
+            ),
+            ReportBody::DeadStock { rows, units, cost_value } => (
+                ["Stock", "At cost", "Last sale", "Days since"].to_vec(),
+                rows.iter()
+                    .map(|row| vec![
+                        row.barcode.clone(),
+                        row.product.clone(),
+                        row.stock.to_string(),
+                        row.cost_value.to_string(),
+                        row.last_sale.map(|day| day.to_string()).unwrap_or_else(|| "never".to_string()),
+                        row.days_since_sale.map(|days| days.to_string()).unwrap_or_else(|| "-".to_string()),
+                    ])
+                    .collect(),
+                Some(vec![
+                    String::new(),
+                    "Total".to_string(),
+                    units.to_string(),
+                    cost_value.to_string(),
+                    String::new(),
+                    String::new(),
+                ]),
+            ),
+        };
+
+        let mut subtitle = format!("{} to {}, amounts in {}", self.from, self.to, self.currency);
+        let filter = self.filter.describe();
+        if !filter.is_empty() {
+            subtitle.push_str(&format!(", {}", filter.join(", ")));
+        }
+        let mut notes = Vec::new();
+        if !self.without_rate.is_empty() {
+            notes.push(format!("Left out for want of an exchange rate: {}", self.without_rate.join(", ")));
+        }
+        if matches!(self.body, ReportBody::Margin { .. }) {
+            notes.push("Revenue and cost are at current retail prices and landed costs.".to_string());
+        }
+
+        Table {
+            title: match self.group_by {
+                Some(group_by) if group_by != GroupBy::Product => format!("{} by {}", self.kind.title(), group_by.as_str()),
+                _ => self.kind.title().to_string(),
+            },
+            subtitle,
+            text_columns: key_columns.len(),
+            columns: [key_columns, columns].concat(),
+            rows,
+            total,
+            notes,
+        }
+    }
+
+    /// The report in `format`; PDF and HTML are laid out for printing.
+    pub fn render(&self, format: ReportFormat) -> Result<Vec<u8>, AppError> {
+        match format {
+            ReportFormat::Json => Ok(serde_json::to_vec_pretty(self)?),
+            ReportFormat::Text => Ok(self.table().to_text().into_bytes()),
+            ReportFormat::Csv => {
+                let mut body = Vec::new();
+                crate::csv_io::export_report_csv(&self.table(), &mut body)?;
+                Ok(body)
+            }
+            ReportFormat::Html => Ok(self.table().to_html().into_bytes()),
+            ReportFormat::Pdf => crate::report_pdf::render(&self.table()),
+        }
+    }
+}
+
+impl Table {
+    pub fn to_text(&self) -> String {
+        let all: Vec<&Vec<String>> = self.rows.iter().chain(&self.total).collect();
+        let widths: Vec<usize> = self.columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| all.iter().map(|row| row[i].chars().count()).chain([column.len()]).max().unwrap_or(0))
+            .collect();
+        let line = |cells: &[String]| {
+            cells.iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(i, (cell, &width))| {
+                    if i < self.text_columns {
+                        format!("{:<width$}", cell)
+                    } else {
+                        format!("{:>width$}", cell)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        };
+        let rule = "-".repeat(widths.iter().sum::<usize>() + 2 * widths.len().saturating_sub(1));
+
+        let mut text = format!("{}\n{}\n\n", self.title, self.subtitle);
+        let header: Vec<String> = self.columns.iter().map(|column| column.to_string()).collect();
+        text.push_str(&format!("{}\n{}\n", line(&header), rule));
+        if self.rows.is_empty() {
+            text.push_str("(no products)\n");
+        }
+        for row in &self.rows {
+            text.push_str(&format!("{}\n", line(row)));
+        }
+        if let Some(total) = &self.total {
+            text.push_str(&format!("{}\n{}\n", rule, line(total)));
+        }
+        for note in &self.notes {
+            text.push_str(&format!("\n{}", note));
+        }
+        if !self.notes.is_empty() {
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn to_html(&self) -> String {
+        let cell = |tag: &str, i: usize, value: &str| {
+            let align = if i < self.text_columns { "" } else { " class=\"num\"" };
+            format!("<{tag}{align}>{}</{tag}>", escape_html(value))
+        };
+        let row = |tag: &str, cells: &[String]| {
+            format!("<tr>{}</tr>\n", cells.iter().enumerate().map(|(i, value)| cell(tag, i, value)).collect::<String>())
+        };
+
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n\
+            body {{ font-family: sans-serif; font-size: 12px; margin: 2em; }}\n\
+            table {{ border-collapse: collapse; width: 100%; }}\n\
+            th, td {{ padding: 4px 8px; border-bottom: 1px solid #ccc; text-align: left; }}\n\
+            .num {{ text-align: right; }}\n\
+            tfoot td {{ font-weight: bold; border-top: 2px solid #333; }}\n\
+            @media print {{ body {{ margin: 0; }} }}\n\
+            </style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>{subtitle}</p>\n<table>\n<thead>\n",
+            title = escape_html(&self.title),
+            subtitle = escape_html(&self.subtitle),
+        );
+        let header: Vec<String> = self.columns.iter().map(|column| column.to_string()).collect();
+        html.push_str(&row("th", &header));
+        html.push_str("</thead>\n<tbody>\n");
+        for cells in &self.rows {
+            html.push_str(&row("td", cells));
+        }
+        html.push_str("</tbody>\n");
+        if let Some(total) = &self.total {
+            html.push_str(&format!("<tfoot>\n{}</tfoot>\n", row("td", total)));
+        }
+        html.push_str("</table>\n");
+        for note in &self.notes {
+            html.push_str(&format!("<p>{}</p>\n", escape_html(note)));
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CurrencyConfig;
+    use crate::models::{ProductPatch, SalesSource};
+
+    fn day(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, month, day).unwrap()
+    }
+
+    fn money(s: &str) -> Money {
+        s.parse().unwrap()
+    }
+
+    fn rate(s: &str) -> Option<Rate> {
+        Some(s.parse().unwrap())
+    }
+
+    /// A product priced in the base currency, sourced as (category, supplier,
+    /// country) and priced as (purchase, retail).
+    fn product(id: i64, barcode: &str, name: &str, source: (&str, &str, &str), prices: (&str, &str), stock: i32) -> Product {
+        let ((category, supplier, country), (purchase, retail)) = (source, prices);
+        let mut product = ProductPatch {
+            imported_name: Some(name.to_string()),
+            barcode: Some(barcode.to_string()),
+            category: Some(category.to_string()),
+            supplier: Some(supplier.to_string()),
+            origin_country: Some(country.to_string()),
+            purchase_price: Some(money(purchase)),
+            purchase_currency: Some("SEK".to_string()),
+            wholesale_currency: Some("SEK".to_string()),
+            retail_price: Some(money(retail)),
+            retail_currency: Some("SEK".to_string()),
+            stock_quantity: Some(stock),
+            expiry_date: Some(day(12, 31)),
+            ..ProductPatch::default()
+        }
+        .into_product()
+        .unwrap();
+        product.id = Some(id);
+        product
+    }
+
+    fn sale(product_id: i64, sale_date: NaiveDate, quantity: i32) -> DailySales {
+        DailySales { product_id, sale_date, source: SalesSource::Pos, quantity }
+    }
+
+    /// Ten days in October. Chickpeas and lentils sell, rice does not, and tahini
+    /// is bought in a currency without a rate.
+    fn data() -> ReportData {
+        let tahini = Product {
+            purchase_currency: "USD".to_string(),
+            ..product(4, "5281234567803", "Tahini", ("Spreads", "Beirut Foods", "Lebanon"), ("30", "45"), 5)
+        };
+        ReportData {
+            from: day(10, 1),
+            to: day(10, 10),
+            products: vec![
+                product(1, "5281234567896", "Chickpeas", ("Legumes", "Beirut Foods", "Lebanon"), ("10", "20"), 30),
+                product(2, "8690504000006", "Lentils", ("Legumes", "Istanbul Trading AB", "Turkey"), ("5", "8"), 40),
+                product(3, "8690504000013", "Rice", ("Rice", "Istanbul Trading AB", "Turkey"), ("12", "15"), 10),
+                tahini,
+            ],
+            sales: vec![sale(1, day(10, 2), 12), sale(1, day(10, 9), 8), sale(2, day(10, 5), 10), sale(4, day(10, 3), 2)],
+            rates: ExchangeRates::new("SEK", &[], CurrencyConfig::default().rounding()),
+            flows: HashMap::from([
+                (1, StockFlow { opening: 40, received: 10, closing: 30 }),
+                (2, StockFlow { opening: 50, received: 0, closing: 40 }),
+            ]),
+            last_sale: HashMap::from([(1, day(10, 9)), (2, day(10, 5)), (3, day(9, 20))]),
+        }
+    }
+
+    fn build_with(kind: ReportKind, params: &ReportParams) -> Report {
+        build(kind, &data(), params).unwrap()
+    }
+
+    fn grouped_by(group_by: GroupBy) -> ReportParams {
+        ReportParams { group_by: Some(group_by), ..ReportParams::default() }
+    }
+
+    #[test]
+    fn valuation_prices_closing_stock_at_cost_and_retail() {
+        let report = build_with(ReportKind::Valuation, &ReportParams::default());
+        assert_eq!(report.without_rate, ["5281234567803"]);
+        assert_eq!(report.group_by, Some(GroupBy::Product));
+        let ReportBody::Valuation { rows, total } = report.body else { panic!("not a valuation") };
+
+        let values: Vec<_> = rows.iter()
+            .map(|row| (row.barcode.as_deref().unwrap(), row.units, row.cost_value, row.retail_value, row.margin))
+            .collect();
+        assert_eq!(values, [
+            ("5281234567896", 30, money("300"), money("600"), rate("50")),
+            ("8690504000006", 40, money("200"), money("320"), rate("37.5")),
+            ("8690504000013", 10, money("120"), money("150"), rate("20")),
+        ]);
+        assert_eq!((total.group.as_str(), total.products, total.units), ("Total", 3, 80));
+        assert_eq!((total.cost_value, total.retail_value, total.margin), (money("620"), money("1070"), rate("42.06")));
+    }
+
+    #[test]
+    fn margin_counts_what_sold_in_the_period() {
+        let report = build_with(ReportKind::Margin, &ReportParams::default());
+        let ReportBody::Margin { rows, total } = report.body else { panic!("not a margin report") };
+
+        let values: Vec<_> = rows.iter().map(|row| (row.units_sold, row.revenue, row.cost, row.gross_profit, row.margin)).collect();
+        assert_eq!(values, [
+            (20, money("400"), money("200"), money("200"), rate("50")),
+            (10, money("80"), money("50"), money("30"), rate("37.5")),
+            (0, money("0"), money("0"), money("0"), None),
+        ]);
+        assert_eq!((total.units_sold, total.revenue, total.gross_profit, total.margin), (30, money("480"), money("230"), rate("47.92")));
+    }
+
+    #[test]
+    fn sell_through_is_sold_out_of_opening_and_received_stock() {
+        let report = build_with(ReportKind::SellThrough, &ReportParams::default());
+        let ReportBody::SellThrough { rows, total } = report.body else { panic!("not a sell-through report") };
+
+        let values: Vec<_> = rows.iter().map(|row| (row.opening, row.received, row.sold, row.closing, row.sell_through)).collect();
+        // Rice has no movements, so its current stock opens and closes the period
+        assert_eq!(values, [(40, 10, 20, 30, rate("40")), (50, 0, 10, 40, rate("20")), (10, 0, 0, 10, rate("0"))]);
+        assert_eq!((total.opening, total.received, total.sold, total.sell_through), (100, 10, 30, rate("27.27")));
+    }
+
+    #[test]
+    fn cover_lists_the_shortest_first_and_unsold_stock_last() {
+        let report = build_with(ReportKind::Cover, &grouped_by(GroupBy::Category));
+        assert_eq!(report.group_by, None);
+        let ReportBody::Cover { rows } = report.body else { panic!("not a cover report") };
+
+        let values: Vec<_> = rows.iter().map(|row| (row.product.as_str(), row.per_day, row.days_of_cover)).collect();
+        assert_eq!(values, [("Chickpeas", 2.0, Some(15.0)), ("Lentils", 1.0, Some(40.0)), ("Rice", 0.0, None)]);
+    }
+
+    #[test]
+    fn dead_stock_is_what_did_not_sell_at_all() {
+        let report = build_with(ReportKind::DeadStock, &ReportParams::default());
+        let ReportBody::DeadStock { rows, units, cost_value } = report.body else { panic!("not a dead stock report") };
+
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!((row.barcode.as_str(), row.stock, row.cost_value), ("8690504000013", 10, money("120")));
+        assert_eq!((row.last_sale, row.days_since_sale), (Some(day(9, 20)), Some(20)));
+        assert_eq!((units, cost_value), (10, money("120")));
+    }
+
+    #[test]
+    fn groups_add_up_their_products_in_label_order() {
+        let report = build_with(ReportKind::Valuation, &grouped_by(GroupBy::Category));
+        let ReportBody::Valuation { rows, total } = report.body else { panic!("not a valuation") };
+        let groups: Vec<_> = rows.iter().map(|row| (row.group.as_str(), row.barcode.is_none(), row.products, row.cost_value)).collect();
+        assert_eq!(groups, [("Legumes", true, 2, money("500")), ("Rice", true, 1, money("120"))]);
+        assert_eq!(total.cost_value, money("620"));
+
+        let report = build_with(ReportKind::Margin, &grouped_by(GroupBy::Supplier));
+        let ReportBody::Margin { rows, .. } = report.body else { panic!("not a margin report") };
+        let groups: Vec<_> = rows.iter().map(|row| (row.group.as_str(), row.units_sold, row.revenue)).collect();
+        assert_eq!(groups, [("Beirut Foods", 20, money("400")), ("Istanbul Trading AB", 10, money("80"))]);
+
+        let mut data = data();
+        data.products[2].origin_country = " ".to_string();
+        let report = build(ReportKind::SellThrough, &data, &grouped_by(GroupBy::Country)).unwrap();
+        let ReportBody::SellThrough { rows, .. } = report.body else { panic!("not a sell-through report") };
+        let groups: Vec<_> = rows.iter().map(|row| (row.group.as_str(), row.sold)).collect();
+        assert_eq!(groups, [("(none)", 0), ("Lebanon", 20), ("Turkey", 10)]);
+    }
+
+    #[test]
+    fn filters_leave_out_other_products() {
+        let params = ReportParams {
+            filter: ReportFilter { supplier: Some("istanbul trading".to_string()), ..ReportFilter::default() },
+            ..ReportParams::default()
+        };
+        let report = build_with(ReportKind::Valuation, &params);
+        assert!(report.without_rate.is_empty());
+        let ReportBody::Valuation { rows, total } = report.body else { panic!("not a valuation") };
+        assert_eq!(rows.iter().map(|row| row.group.as_str()).collect::<Vec<_>>(), ["Lentils", "Rice"]);
+        assert_eq!((total.products, total.cost_value), (2, money("320")));
+
+        let filter = ReportFilter { category: Some("LEGUMES".to_string()), country: Some("lebanon".to_string()), ..ReportFilter::default() };
+        let matching: Vec<_> = data().products.iter().filter(|p| filter.matches(p)).map(|p| p.imported_name.clone()).collect();
+        assert_eq!(matching, ["Chickpeas"]);
+        assert!(!ReportFilter { brand: Some("Durra".to_string()), ..ReportFilter::default() }.matches(&data().products[0]));
+    }
+
+    #[test]
+    fn periods_default_to_the_days_before_today() {
+        let today = day(10, 18);
+        let params = |from: Option<NaiveDate>, to: Option<NaiveDate>| ReportParams { from, to, ..ReportParams::default() };
+
+        assert_eq!(params(None, None).period(30, today).unwrap(), (day(9, 19), today));
+        assert_eq!(params(None, None).period(0, today).unwrap(), (today, today));
+        assert_eq!(params(None, Some(day(10, 10))).period(7, today).unwrap(), (day(10, 4), day(10, 10)));
+        assert_eq!(params(Some(day(10, 1)), None).period(7, today).unwrap(), (day(10, 1), today));
+        let error = params(Some(day(10, 11)), Some(day(10, 10))).period(7, today).unwrap_err();
+        assert!(error.to_string().contains("starts on 2026-10-11 after it ends on 2026-10-10"), "{}", error);
+    }
+}
@@ -0,0 +1,330 @@
+use chrono::{DateTime, Local};
+use croner::Cron;
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use crate::alert_rules::RuleSet;
+use crate::config::{Config, InventoryConfig, ReorderConfig, SchedulerConfig};
+use crate::database::Database;
+use crate::inventory_manager::InventoryManager;
+use crate::models::{AppError, JobRun};
+use crate::notifications::Notifier;
+
+/// Background jobs the scheduler knows how to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// Re-evaluates the alert rules and sends new alerts to the notification channels
+    CheckAlerts,
+    /// Writes the inventory report to `report_dir`
+    DailyReport,
+    /// Copies the database to `backup_dir`
+    Backup,
+    /// Drafts purchase orders from the current reorder suggestions
+    Reorder,
+}
+
+impl JobKind {
+    pub const ALL: [&'static str; 4] = ["check_alerts", "daily_report", "backup", "reorder"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::CheckAlerts => "check_alerts",
+            JobKind::DailyReport => "daily_report",
+            JobKind::Backup => "backup",
+            JobKind::Reorder => "reorder",
+        }
+    }
+}
+
+impl std::str::FromStr for JobKind {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "check_alerts" => Ok(JobKind::CheckAlerts),
+            "daily_report" => Ok(JobKind::DailyReport),
+            "backup" => Ok(JobKind::Backup),
+            "reorder" => Ok(JobKind::Reorder),
+            other => Err(AppError::Validation(format!("unknown job {:?}", other))),
+        }
+    }
+}
+
+impl fmt::Display for JobKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A configured job with its next run and the outcome of its last one.
+#[derive(Debug, Serialize)]
+pub struct ScheduledJob {
+    pub job: JobKind,
+    pub schedule: String,
+    pub next_run: Option<DateTime<Local>>,
+    pub last_run: Option<JobRun>,
+}
+
+pub struct Scheduler {
+    db: Arc<Database>,
+    inventory: InventoryConfig,
+    reorder: ReorderConfig,
+    rules: RuleSet,
+    notifier: Notifier,
+    config: SchedulerConfig,
+    jobs: Vec<(JobKind, Cron)>,
+}
+
+impl Scheduler {
+    pub fn new(db: Arc<Database>, config: &Config) -> Result<Self, AppError> {
+        let jobs = config.scheduler.jobs
+            .iter()
+            .map(|job| Ok((job.job, job.cron()?)))
+            .collect::<Result<_, AppError>>()?;
+
+        Ok(Self {
+            db,
+            inventory: config.inventory.clone(),
+            reorder: config.reorder.clone(),
+            rules: RuleSet::load(config.alerts.rules_file.as_deref())?,
+            notifier: Notifier::from_config(&config.notifications)?,
+            config: config.scheduler.clone(),
+            jobs,
+        })
+    }
+
+    pub async fn status(&self) -> Result<Vec<ScheduledJob>, AppError> {
+        let now = Local::now();
+        let mut jobs = Vec::new();
+        for (job, cron) in &self.jobs {
+            jobs.push(ScheduledJob {
+                job: *job,
+                schedule: cron.pattern.to_string(),
+                next_run: cron.find_next_occurrence(&now, false).ok(),
+                last_run: self.db.get_job_runs(Some(*job), 1).await?.pop(),
+            });
+        }
+        Ok(jobs)
+    }
+
+    /// Runs the jobs on their schedules until the process exits. Jobs run one at a
+    /// time; one that comes due while another is running starts right after it.
+    pub async fn run(self: Arc<Self>) {
+        match self.db.fail_interrupted_job_runs().await {
+            Ok(0) => {}
+            Ok(n) => log::warn!("marked {} interrupted job runs as failed", n),
+            Err(e) => log::warn!("cannot check for interrupted job runs: {}", e),
+        }
+
+        let now = Local::now();
+        let mut next: Vec<Option<DateTime<Local>>> = self.jobs
+            .iter()
+            .map(|(_, cron)| cron.find_next_occurrence(&now, false).ok())
+            .collect();
+
+        while let Some(due) = next.iter().flatten().min().copied() {
+            let wait = (due - Local::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+
+            for (i, (job, cron)) in self.jobs.iter().enumerate() {
+                if next[i].is_some_and(|at| at <= due) {
+                    if let Err(e) = self.run_job(*job, "schedule").await {
+                        log::error!("cannot record run of job {}: {}", job, e);
+                    }
+                    // Skip occurrences missed while the job ran
+                    next[i] = cron.find_next_occurrence(&due.max(Local::now()), false).ok();
+                }
+            }
+        }
+        log::info!("no jobs scheduled, scheduler stopped");
+    }
+
+    /// Runs a job now and records the run; the job's own failure is recorded, not returned.
+    pub async fn run_job(&self, job: JobKind, triggered_by: &str) -> Result<JobRun, AppError> {
+        let id = self.db.start_job_run(job, triggered_by).await?;
+        log::info!("running job {}", job);
+
+        let outcome = match job {
+            JobKind::CheckAlerts => self.check_alerts().await,
+            JobKind::DailyReport => self.daily_report().await,
+            JobKind::Backup => self.backup().await,
+            JobKind::Reorder => self.reorder().await,
+        };
+        if let Err(e) = &outcome {
+            log::warn!("job {} failed: {}", job, e);
+        }
+        self.db.finish_job_run(id, &outcome).await
+    }
+
+    async fn check_alerts(&self) -> Result<String, AppError> {
+        let alerts = self.db.refresh_alerts(&self.inventory, &self.rules).await?;
+        let reports = self.notifier.dispatch(&self.db, false).await?;
+
+        let mut output = format!("{} alerts raised", alerts.len());
+        let mut failures = Vec::new();
+        for report in reports {
+            match report.error {
+                Some(error) => failures.push(format!("{}: {}", report.channel, error)),
+                None if report.sent => output.push_str(&format!(", {} sent to {}", report.alerts, report.channel)),
+                None => {}
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(output)
+        } else {
+            Err(AppError::Notification(format!("{}; {}", output, failures.join("; "))))
+        }
+    }
+
+    async fn reorder(&self) -> Result<String, AppError> {
+        let orders = self.db.draft_purchase_orders(&self.inventory, &self.reorder).await?;
+        let lines: usize = orders.iter().map(|order| order.lines.len()).sum();
+        Ok(format!("{} draft purchase orders with {} lines", orders.len(), lines))
+    }
+
+    async fn daily_report(&self) -> Result<String, AppError> {
+        let (products, rates) = tokio::try_join!(self.db.get_all_products(), self.db.exchange_rates())?;
+        let report = InventoryManager::generate_report(&products, &rates)?;
+
+        let path = Path::new(&self.config.report_dir).join(format!("inventory-{}.txt", Local::now().date_naive()));
+        tokio::fs::create_dir_all(&self.config.report_dir).await?;
+        tokio::fs::write(&path, report).await?;
+        Ok(format!("wrote {}", path.display()))
+    }
+
+    async fn backup(&self) -> Result<String, AppError> {
+        let dir = Path::new(&self.config.backup_dir);
+        tokio::fs::create_dir_all(dir).await?;
+        let path = dir.join(format!("products-{}.db", Local::now().format("%Y%m%d-%H%M%S%.3f")));
+        self.db.backup(&path.to_string_lossy()).await?;
+
+        // Timestamped names sort oldest first
+        let mut backups = Vec::new();
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with("products-") && name.ends_with(".db") {
+                backups.push(entry.path());
+            }
+        }
+        backups.sort();
+
+        let excess = backups.len().saturating_sub(self.config.backup_keep.max(1));
+        for old in &backups[..excess] {
+            tokio::fs::remove_file(old).await?;
+        }
+        Ok(format!("wrote {}, removed {} old backups", path.display(), excess))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::{chickpeas, file_db, file_db_config};
+    use crate::models::{ChangeNote, JobStatus};
+
+    /// A scheduler on a database file, as `VACUUM INTO` writes nothing for an
+    /// in-memory one.
+    async fn scheduler(dir: &Path, backup_keep: usize) -> Scheduler {
+        let db = file_db(dir).await;
+        db.add_product(chickpeas("5281234567896"), &ChangeNote::default()).await.unwrap();
+        let mut config = Config::default();
+        config.scheduler.report_dir = dir.join("reports").to_string_lossy().into_owned();
+        config.scheduler.backup_dir = dir.join("backups").to_string_lossy().into_owned();
+        config.scheduler.backup_keep = backup_keep;
+        Scheduler::new(Arc::new(db), &config).unwrap()
+    }
+
+    fn backups(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir.join("backups"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn runs_are_recorded_with_their_outcome() {
+        let dir = tempfile::tempdir().unwrap();
+        let scheduler = scheduler(dir.path(), 14).await;
+
+        let run = scheduler.run_job(JobKind::DailyReport, "manual").await.unwrap();
+        assert_eq!((run.job, run.status, run.triggered_by.as_str()), (JobKind::DailyReport, JobStatus::Succeeded, "manual"));
+        assert!(run.output.as_deref().unwrap().starts_with("wrote "), "{:?}", run.output);
+        assert!(run.finished_at.is_some() && run.error.is_none());
+        let report = dir.path().join("reports").join(format!("inventory-{}.txt", Local::now().date_naive()));
+        assert!(std::fs::read_to_string(report).unwrap().contains("Total Products: 1"));
+
+        // A job's own failure is recorded as the run's outcome, not returned
+        std::fs::remove_dir_all(dir.path().join("reports")).unwrap();
+        std::fs::write(dir.path().join("reports"), "not a directory").unwrap();
+        let failed = scheduler.run_job(JobKind::DailyReport, "schedule").await.unwrap();
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert!(failed.error.is_some() && failed.output.is_none());
+
+        let runs = scheduler.db.get_job_runs(Some(JobKind::DailyReport), 10).await.unwrap();
+        assert_eq!(runs.iter().map(|run| run.id).collect::<Vec<_>>(), [failed.id, run.id]);
+        let status = scheduler.status().await.unwrap();
+        let daily = status.iter().find(|job| job.job == JobKind::DailyReport).unwrap();
+        assert_eq!(daily.last_run.as_ref().map(|run| run.id), Some(failed.id));
+        assert!(daily.next_run.is_some());
+    }
+
+    #[tokio::test]
+    async fn interrupted_runs_are_marked_failed() {
+        let dir = tempfile::tempdir().unwrap();
+        let scheduler = scheduler(dir.path(), 14).await;
+        let finished = scheduler.run_job(JobKind::Reorder, "manual").await.unwrap();
+        let interrupted = scheduler.db.start_job_run(JobKind::Backup, "schedule").await.unwrap();
+
+        assert_eq!(scheduler.db.fail_interrupted_job_runs().await.unwrap(), 1);
+        assert_eq!(scheduler.db.fail_interrupted_job_runs().await.unwrap(), 0);
+
+        let runs = scheduler.db.get_job_runs(None, 10).await.unwrap();
+        let run = |id: i64| runs.iter().find(|run| run.id == id).unwrap();
+        assert_eq!((run(interrupted).status, run(interrupted).error.as_deref()), (JobStatus::Failed, Some("interrupted")));
+        assert!(run(interrupted).finished_at.is_some());
+        assert_eq!(run(finished.id).status, JobStatus::Succeeded);
+    }
+
+    #[tokio::test]
+    async fn backups_beyond_backup_keep_are_pruned_oldest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let scheduler = scheduler(dir.path(), 2).await;
+        std::fs::create_dir_all(dir.path().join("backups")).unwrap();
+        std::fs::write(dir.path().join("backups").join("notes.txt"), "kept").unwrap();
+
+        let mut outputs = Vec::new();
+        for _ in 0..3 {
+            let run = scheduler.run_job(JobKind::Backup, "manual").await.unwrap();
+            assert_eq!(run.status, JobStatus::Succeeded, "{:?}", run.error);
+            outputs.push(run.output.unwrap());
+            // Backups are named to the millisecond
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        assert!(outputs[1].ends_with("removed 0 old backups"), "{}", outputs[1]);
+        assert!(outputs[2].ends_with("removed 1 old backups"), "{}", outputs[2]);
+
+        let names = backups(dir.path());
+        assert_eq!(names.len(), 3, "{:?}", names);
+        assert_eq!(names[0], "notes.txt");
+        assert!(outputs[1].contains(&names[1]) && outputs[2].contains(&names[2]));
+
+        // A backup is a complete database
+        let copy = Database::new(
+            &crate::config::DatabaseConfig {
+                url: format!("sqlite:{}", dir.path().join("backups").join(&names[2]).display()),
+                ..file_db_config(dir.path())
+            },
+            &Default::default(),
+            &Default::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(copy.get_product_by_barcode("5281234567896").await.unwrap().imported_name, "Chickpeas");
+    }
+}
@@ -1,40 +1,140 @@
-use actix_web::{web, App, HttpServer, Result, HttpResponse, middleware::Logger};
+use actix_web::{web, App, HttpServer, HttpRequest, Result, HttpResponse, middleware::Logger};
+use actix_web::error::{InternalError, JsonPayloadError};
 use actix_cors::Cors;
+use crate::alert_rules::{AlertType, RuleSet};
+use crate::config::{Config, InventoryConfig, ReorderConfig, ReportsConfig};
+use crate::csv_io::{self, HeaderMapping, ImportOptions};
+use crate::data_collector::DataCollector;
 use crate::database::Database;
-use crate::models::Product;
+use crate::models::{AlertStatus, AlertUpdate, AppError, ChangeNote, ExchangeRateInput, LotInput, MovementInput, OrderStatus, Product, ProductPatch, PurchaseOrderUpdate, SupplierPatch};
 use crate::inventory_manager::InventoryManager;
+use crate::notifications::Notifier;
+use crate::order_pdf;
+use crate::barcode::Gtin;
+use crate::code_generator::CodeSchemes;
+use crate::pricing::{PricingRules, PricingScope};
+use crate::reports::{self, ReportFormat, ReportKind, ReportParams};
+use crate::scheduler::{JobKind, Scheduler};
+use serde::Deserialize;
 use serde_json::json;
 use std::sync::Arc;
 
-pub async fn start_web_server() -> std::io::Result<()> {
-    env_logger::init();
-    
-    let db = Arc::new(Database::new().await.expect("Failed to connect to database"));
+pub async fn start_web_server(config: Config) -> std::io::Result<()> {
+    let db = Arc::new(Database::new(&config.database, &config.currency, &config.codes).await.expect("Failed to connect to database"));
+    let collector = Arc::new(DataCollector::from_config(&config.lookup).expect("Invalid lookup configuration"));
+    let bind_addr = (config.server.host.clone(), config.server.port);
+    let cors_origins = config.server.cors_origins.clone();
+    let inventory = web::Data::new(config.inventory.clone());
+    let reorder = web::Data::new(config.reorder.clone());
+    let report_config = web::Data::new(config.reports.clone());
+    let rules = web::Data::new(RuleSet::load(config.alerts.rules_file.as_deref()).expect("Invalid alert rules"));
+    let pricing = web::Data::new(PricingRules::load(config.pricing.rules_file.as_deref()).expect("Invalid pricing rules"));
+    let codes = web::Data::new(CodeSchemes::load(config.codes.schemes_file.as_deref()).expect("Invalid code schemes"));
+    let notifier = web::Data::new(Notifier::from_config(&config.notifications).expect("Invalid notification channels"));
+    let scheduler = Arc::new(Scheduler::new(db.clone(), &config).expect("Invalid scheduler configuration"));
+    if config.scheduler.in_web {
+        tokio::spawn(scheduler.clone().run());
+    }
+    let scheduler = web::Data::from(scheduler);
     
-    println!("🚀 Starting web server at http://localhost:8080");
+    println!("🚀 Starting web server at http://{}:{}", bind_addr.0, bind_addr.1);
     
     HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin()
+        let mut cors = Cors::default()
             .allow_any_method()
             .allow_any_header();
+        for origin in &cors_origins {
+            cors = if origin == "*" { cors.allow_any_origin() } else { cors.allowed_origin(origin) };
+        }
             
         App::new()
             .app_data(web::Data::new(db.clone()))
+            .app_data(web::Data::new(collector.clone()))
+            .app_data(inventory.clone())
+            .app_data(reorder.clone())
+            .app_data(report_config.clone())
+            .app_data(rules.clone())
+            .app_data(pricing.clone())
+            .app_data(codes.clone())
+            .app_data(notifier.clone())
+            .app_data(scheduler.clone())
+            .app_data(web::JsonConfig::default().error_handler(json_error))
             .wrap(cors)
             .wrap(Logger::default())
-            .route("/", web::get().to(dashboard))
-            .route("/api/products", web::get().to(get_products))
-            .route("/api/products", web::post().to(add_product))
-            .route("/api/products/{barcode}", web::get().to(get_product))
-            .route("/api/alerts", web::get().to(get_alerts))
-            .route("/api/stats", web::get().to(get_stats))
+            .configure(routes)
     })
-    .bind("127.0.0.1:8080")?
+    .bind(bind_addr)?
     .run()
     .await
 }
 
+/// Every page and API endpoint; the data they use is added by the caller.
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .route("/", web::get().to(dashboard))
+        .route("/api/products", web::get().to(get_products))
+        .route("/api/products", web::post().to(add_product))
+        .route("/api/products/import", web::post().to(import_products))
+        .route("/api/products/export", web::get().to(export_products))
+        .route("/api/products/code/{code}", web::get().to(get_product_by_code))
+        .route("/api/products/{barcode}", web::get().to(get_product))
+        .route("/api/products/{barcode}", web::put().to(update_product))
+        .route("/api/products/{barcode}", web::patch().to(patch_product))
+        .route("/api/products/{barcode}", web::delete().to(delete_product))
+        .route("/api/products/{barcode}/restore", web::post().to(restore_product))
+        .route("/api/products/{barcode}/lots", web::get().to(get_lots))
+        .route("/api/products/{barcode}/lots", web::post().to(add_lot))
+        .route("/api/products/{barcode}/movements", web::get().to(get_movements))
+        .route("/api/products/{barcode}/movements", web::post().to(record_movement))
+        .route("/api/products/{barcode}/sales", web::get().to(get_sales))
+        .route("/api/products/{barcode}/velocity", web::get().to(get_velocity))
+        .route("/api/products/{barcode}/costing", web::get().to(get_costing))
+        .route("/api/products/{barcode}/price-history", web::get().to(get_price_history))
+        .route("/api/sales/import", web::post().to(import_sales))
+        .route("/api/lookup/{barcode}", web::get().to(lookup_barcode))
+        .route("/api/alerts", web::get().to(get_alerts))
+        .route("/api/alerts/{id}", web::get().to(get_alert))
+        .route("/api/alerts/{id}", web::patch().to(update_alert))
+        .route("/api/notifications/send", web::post().to(send_notifications))
+        .route("/api/jobs", web::get().to(get_jobs))
+        .route("/api/jobs/runs", web::get().to(get_job_runs))
+        .route("/api/jobs/{job}/run", web::post().to(run_job))
+        .route("/api/suppliers", web::get().to(get_suppliers))
+        .route("/api/suppliers", web::post().to(add_supplier))
+        .route("/api/suppliers/spend", web::get().to(get_supplier_spend))
+        .route("/api/suppliers/{id}", web::get().to(get_supplier))
+        .route("/api/suppliers/{id}", web::patch().to(update_supplier))
+        .route("/api/suppliers/{id}", web::delete().to(delete_supplier))
+        .route("/api/suppliers/{id}/products", web::get().to(get_supplier_products))
+        .route("/api/exchange-rates", web::get().to(get_exchange_rates))
+        .route("/api/exchange-rates", web::post().to(set_exchange_rate))
+        .route("/api/exchange-rates/import", web::post().to(import_exchange_rates))
+        .route("/api/exchange-rates/{currency}/{valid_from}", web::delete().to(delete_exchange_rate))
+        .route("/api/pricing/rules", web::get().to(get_pricing_rules))
+        .route("/api/codes/schemes", web::get().to(get_code_schemes))
+        .route("/api/codes/decode", web::get().to(decode_code))
+        .route("/api/pricing/recalculate", web::post().to(recalculate_prices))
+        .route("/api/reorder/suggestions", web::get().to(get_reorder_suggestions))
+        .route("/api/reorder/drafts", web::post().to(draft_purchase_orders))
+        .route("/api/reorder/orders", web::get().to(get_purchase_orders))
+        .route("/api/reorder/orders/{id}", web::get().to(get_purchase_order))
+        .route("/api/reorder/orders/{id}", web::patch().to(update_purchase_order))
+        .route("/api/reorder/orders/{id}/lines/{barcode}", web::put().to(set_order_line))
+        .route("/api/reorder/orders/{id}/export", web::get().to(export_purchase_order))
+        .route("/api/reports/{kind}", web::get().to(get_report))
+        .route("/api/rules", web::get().to(get_rules))
+        .route("/api/rules/{id}", web::patch().to(update_rule))
+        .route("/api/stats", web::get().to(get_stats));
+}
+
+/// Rejects malformed request bodies, e.g. dates that are not YYYY-MM-DD, with a JSON error.
+fn json_error(error: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let response = HttpResponse::BadRequest().json(json!({
+        "error": format!("Invalid request body: {}", error)
+    }));
+    InternalError::from_response(error, response).into()
+}
+
 async fn dashboard() -> Result<HttpResponse> {
     let html = include_str!("dashboard.html");
     Ok(HttpResponse::Ok().content_type("text/html; charset=utf-8").body(html))
@@ -49,23 +149,41 @@ async fn get_products(db: web::Data<Arc<Database>>) -> Result<HttpResponse> {
     }
 }
 
+/// A request body with optional `changed_by` and `reason` fields alongside its
+/// own, recorded with any price changes it makes.
+#[derive(Deserialize)]
+struct WithNote<T> {
+    #[serde(flatten)]
+    body: T,
+    #[serde(flatten)]
+    note: ChangeNote,
+}
+
 async fn add_product(
-    product: web::Json<Product>,
+    patch: web::Json<WithNote<ProductPatch>>,
     db: web::Data<Arc<Database>>
 ) -> Result<HttpResponse> {
-    let mut new_product = product.into_inner();
+    let WithNote { body: patch, note } = patch.into_inner();
+    let new_product = match patch.into_product() {
+        Ok(product) => product,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to add product: {}", e)
+        }))),
+    };
     
-    // Set default values
-    if new_product.production_date.is_empty() {
-        new_product.production_date = chrono::Utc::now().format("%Y-%m-%d").to_string();
-    }
-    if new_product.expiry_date.is_empty() {
-        new_product.expiry_date = chrono::Utc::now().format("%Y-%m-%d").to_string();
-    }
+    let gtin = match new_product.validate().and_then(|_| Gtin::parse(&new_product.barcode)) {
+        Ok(gtin) => gtin,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to add product: {}", e)
+        }))),
+    };
+    let warning = gtin.origin_warning(&new_product.origin_country);
     
-    match db.add_product(new_product).await {
+    match db.add_product(new_product, &note).await {
         Ok(id) => Ok(HttpResponse::Ok().json(json!({
             "id": id,
+            "barcode": gtin.gtin14,
+            "warning": warning,
             "message": "Product added successfully"
         }))),
         Err(e) => Ok(HttpResponse::BadRequest().json(json!({
@@ -88,26 +206,863 @@ async fn get_product(
     }
 }
 
-async fn get_alerts(db: web::Data<Arc<Database>>) -> Result<HttpResponse> {
-    match db.get_all_products().await {
-        Ok(products) => {
-            let alerts = InventoryManager::check_inventory(&products);
-            Ok(HttpResponse::Ok().json(alerts))
+#[derive(Deserialize)]
+struct ImportRequest {
+    csv: String,
+    #[serde(default)]
+    mapping: HeaderMapping,
+    #[serde(flatten)]
+    options: ImportOptions,
+    #[serde(flatten)]
+    note: ChangeNote,
+}
+
+async fn import_products(
+    request: web::Json<ImportRequest>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    if let Err(e) = request.mapping.check() {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to import products: {}", e)
+        })));
+    }
+    
+    match csv_io::import_csv(&db, request.csv.as_bytes(), &request.mapping, request.options, &request.note).await {
+        Ok(report) if report.errors.is_empty() => Ok(HttpResponse::Ok().json(report)),
+        Ok(report) => Ok(HttpResponse::UnprocessableEntity().json(report)),
+        Err(e) => Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to import products: {}", e)
+        })))
+    }
+}
+
+async fn export_products(db: web::Data<Arc<Database>>) -> Result<HttpResponse> {
+    let mut body = Vec::new();
+    let exported = match db.get_all_products().await {
+        Ok(products) => csv_io::export_csv(&products, &HeaderMapping::default(), &mut body),
+        Err(e) => Err(e),
+    };
+    
+    match exported {
+        Ok(()) => Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header(("Content-Disposition", "attachment; filename=\"products.csv\""))
+            .body(body)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to export products: {}", e)
+        })))
+    }
+}
+
+async fn get_product_by_code(
+    path: web::Path<String>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    let code = path.into_inner();
+    
+    let product = match db.get_product_by_internal_code(&code).await {
+        Err(AppError::NotFound) => db.get_product_by_alternative_code(&code).await,
+        result => result,
+    };
+    
+    match product {
+        Ok(product) => Ok(HttpResponse::Ok().json(product)),
+        Err(e) => Ok(product_error_response(e, "look up product")),
+    }
+}
+
+/// `?regenerate_code=true` rebuilds an edited product's internal code, which is
+/// otherwise kept as printed on its labels.
+#[derive(Deserialize)]
+struct EditQuery {
+    #[serde(default)]
+    regenerate_code: bool,
+}
+
+async fn update_product(
+    path: web::Path<String>,
+    query: web::Query<EditQuery>,
+    product: web::Json<WithNote<Product>>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    let WithNote { body: product, note } = product.into_inner();
+    match db.update_product(&path.into_inner(), product, query.regenerate_code, &note).await {
+        Ok(product) => Ok(HttpResponse::Ok().json(product)),
+        Err(e) => Ok(product_error_response(e, "update product")),
+    }
+}
+
+async fn patch_product(
+    path: web::Path<String>,
+    query: web::Query<EditQuery>,
+    patch: web::Json<WithNote<ProductPatch>>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    let WithNote { body: patch, note } = patch.into_inner();
+    match db.patch_product(&path.into_inner(), patch, query.regenerate_code, &note).await {
+        Ok(product) => Ok(HttpResponse::Ok().json(product)),
+        Err(e) => Ok(product_error_response(e, "update product")),
+    }
+}
+
+async fn delete_product(
+    path: web::Path<String>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.delete_product(&path.into_inner()).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({
+            "message": "Product deleted successfully"
+        }))),
+        Err(e) => Ok(product_error_response(e, "delete product")),
+    }
+}
+
+async fn restore_product(
+    path: web::Path<String>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.restore_product(&path.into_inner()).await {
+        Ok(product) => Ok(HttpResponse::Ok().json(product)),
+        Err(e) => Ok(product_error_response(e, "restore product")),
+    }
+}
+
+async fn get_lots(
+    path: web::Path<String>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.get_lots(&path.into_inner()).await {
+        Ok(lots) => Ok(HttpResponse::Ok().json(lots)),
+        Err(e) => Ok(product_error_response(e, "fetch lots")),
+    }
+}
+
+async fn add_lot(
+    path: web::Path<String>,
+    lot: web::Json<LotInput>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.add_lot(&path.into_inner(), lot.into_inner()).await {
+        Ok(lot) => Ok(HttpResponse::Ok().json(lot)),
+        Err(e) => Ok(product_error_response(e, "add lot")),
+    }
+}
+
+async fn get_movements(
+    path: web::Path<String>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.get_movements(&path.into_inner()).await {
+        Ok(movements) => Ok(HttpResponse::Ok().json(movements)),
+        Err(e) => Ok(product_error_response(e, "fetch movements")),
+    }
+}
+
+async fn record_movement(
+    path: web::Path<String>,
+    movement: web::Json<MovementInput>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.record_movement(&path.into_inner(), movement.into_inner()).await {
+        Ok(movements) => Ok(HttpResponse::Ok().json(movements)),
+        Err(e) => Ok(product_error_response(e, "record movement")),
+    }
+}
+
+async fn get_sales(
+    path: web::Path<String>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.get_sales(&path.into_inner()).await {
+        Ok(sales) => Ok(HttpResponse::Ok().json(sales)),
+        Err(e) => Ok(product_error_response(e, "fetch sales")),
+    }
+}
+
+async fn get_velocity(
+    path: web::Path<String>,
+    db: web::Data<Arc<Database>>,
+    inventory: web::Data<InventoryConfig>
+) -> Result<HttpResponse> {
+    let barcode = path.into_inner();
+    
+    match tokio::try_join!(db.get_product_by_barcode(&barcode), db.get_sales(&barcode)) {
+        Ok((product, sales)) => Ok(HttpResponse::Ok().json(
+            InventoryManager::velocity(&product, &sales, &inventory.velocity_windows)
+        )),
+        Err(e) => Ok(product_error_response(e, "compute velocity")),
+    }
+}
+
+async fn get_costing(
+    path: web::Path<String>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.product_costing(&path.into_inner()).await {
+        Ok(costing) => Ok(HttpResponse::Ok().json(costing)),
+        Err(e) => Ok(product_error_response(e, "compute landed cost")),
+    }
+}
+
+async fn get_price_history(
+    path: web::Path<String>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.get_price_history(&path.into_inner()).await {
+        Ok(history) => Ok(HttpResponse::Ok().json(history)),
+        Err(e) => Ok(product_error_response(e, "fetch price history")),
+    }
+}
+
+#[derive(Deserialize)]
+struct SalesImportRequest {
+    csv: String,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+async fn import_sales(
+    request: web::Json<SalesImportRequest>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match csv_io::import_sales_csv(&db, request.csv.as_bytes(), request.dry_run).await {
+        Ok(report) if report.errors.is_empty() => Ok(HttpResponse::Ok().json(report)),
+        Ok(report) => Ok(HttpResponse::UnprocessableEntity().json(report)),
+        Err(e) => Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to import sales: {}", e)
+        })))
+    }
+}
+
+fn product_error_response(error: AppError, action: &str) -> HttpResponse {
+    match error {
+        AppError::NotFound => HttpResponse::NotFound().json(json!({
+            "error": "Product not found"
+        })),
+        e => HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to {}: {}", action, e)
+        })),
+    }
+}
+
+async fn lookup_barcode(
+    path: web::Path<String>,
+    collector: web::Data<Arc<DataCollector>>
+) -> Result<HttpResponse> {
+    match collector.fetch_from_barcode(&path.into_inner()).await {
+        Ok(input) => Ok(HttpResponse::Ok().json(input)),
+        Err(AppError::NotFound) => Ok(HttpResponse::NotFound().json(json!({
+            "error": "Barcode not found"
+        }))),
+        Err(e) => Ok(HttpResponse::BadGateway().json(json!({
+            "error": format!("Failed to look up barcode: {}", e)
+        })))
+    }
+}
+
+#[derive(Deserialize)]
+struct AlertQuery {
+    /// `open` (the default), `acknowledged`, `snoozed`, `resolved` or `all`
+    status: Option<String>,
+}
+
+async fn get_alerts(
+    query: web::Query<AlertQuery>,
+    db: web::Data<Arc<Database>>,
+    inventory: web::Data<InventoryConfig>,
+    rules: web::Data<RuleSet>
+) -> Result<HttpResponse> {
+    let status = match query.status.as_deref() {
+        Some("all") => None,
+        Some(status) => match status.parse::<AlertStatus>() {
+            Ok(status) => Some(status),
+            Err(e) => return Ok(HttpResponse::BadRequest().json(json!({
+                "error": format!("Failed to fetch alerts: {}", e)
+            }))),
         },
+        None => Some(AlertStatus::Open),
+    };
+    
+    let alerts = match db.refresh_alerts(&inventory, &rules).await {
+        Ok(_) => db.get_alerts(status).await,
+        Err(e) => Err(e),
+    };
+    match alerts {
+        Ok(alerts) => Ok(HttpResponse::Ok().json(alerts)),
         Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
             "error": format!("Failed to fetch alerts: {}", e)
         })))
     }
 }
 
-async fn get_stats(db: web::Data<Arc<Database>>) -> Result<HttpResponse> {
-    match db.get_all_products().await {
-        Ok(products) => {
+async fn get_alert(
+    path: web::Path<i64>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.get_alert(path.into_inner()).await {
+        Ok(alert) => Ok(HttpResponse::Ok().json(alert)),
+        Err(e) => Ok(alert_error_response(e, "fetch alert")),
+    }
+}
+
+async fn update_alert(
+    path: web::Path<i64>,
+    update: web::Json<AlertUpdate>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.update_alert(path.into_inner(), update.into_inner()).await {
+        Ok(alert) => Ok(HttpResponse::Ok().json(alert)),
+        Err(e) => Ok(alert_error_response(e, "update alert")),
+    }
+}
+
+fn alert_error_response(error: AppError, action: &str) -> HttpResponse {
+    match error {
+        AppError::NotFound => HttpResponse::NotFound().json(json!({
+            "error": "Alert not found"
+        })),
+        e => HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to {}: {}", action, e)
+        })),
+    }
+}
+
+#[derive(Deserialize)]
+struct NotifyQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+async fn send_notifications(
+    query: web::Query<NotifyQuery>,
+    db: web::Data<Arc<Database>>,
+    inventory: web::Data<InventoryConfig>,
+    rules: web::Data<RuleSet>,
+    notifier: web::Data<Notifier>
+) -> Result<HttpResponse> {
+    let reports = match db.refresh_alerts(&inventory, &rules).await {
+        Ok(_) => notifier.dispatch(&db, query.dry_run).await,
+        Err(e) => Err(e),
+    };
+    match reports {
+        Ok(reports) => Ok(HttpResponse::Ok().json(reports)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to send notifications: {}", e)
+        })))
+    }
+}
+
+async fn get_jobs(scheduler: web::Data<Scheduler>) -> Result<HttpResponse> {
+    match scheduler.status().await {
+        Ok(jobs) => Ok(HttpResponse::Ok().json(jobs)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to fetch jobs: {}", e)
+        })))
+    }
+}
+
+#[derive(Deserialize)]
+struct JobRunsQuery {
+    job: Option<String>,
+    limit: Option<u32>,
+}
+
+async fn get_job_runs(
+    query: web::Query<JobRunsQuery>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    let job = match query.job.as_deref().map(str::parse::<JobKind>).transpose() {
+        Ok(job) => job,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to fetch job runs: {}", e)
+        }))),
+    };
+    
+    match db.get_job_runs(job, query.limit.unwrap_or(20)).await {
+        Ok(runs) => Ok(HttpResponse::Ok().json(runs)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to fetch job runs: {}", e)
+        })))
+    }
+}
+
+async fn run_job(
+    path: web::Path<String>,
+    scheduler: web::Data<Scheduler>
+) -> Result<HttpResponse> {
+    let job = match path.parse::<JobKind>() {
+        Ok(job) => job,
+        Err(_) => return Ok(HttpResponse::NotFound().json(json!({
+            "error": "Job not found"
+        }))),
+    };
+    
+    match scheduler.run_job(job, "manual").await {
+        Ok(run) => Ok(HttpResponse::Ok().json(run)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to run job: {}", e)
+        })))
+    }
+}
+
+async fn get_suppliers(db: web::Data<Arc<Database>>) -> Result<HttpResponse> {
+    match db.get_suppliers().await {
+        Ok(suppliers) => Ok(HttpResponse::Ok().json(suppliers)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to fetch suppliers: {}", e)
+        })))
+    }
+}
+
+async fn add_supplier(
+    patch: web::Json<SupplierPatch>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    let added = match patch.into_inner().into_supplier() {
+        Ok(supplier) => db.add_supplier(supplier).await,
+        Err(e) => Err(e),
+    };
+    match added {
+        Ok(supplier) => Ok(HttpResponse::Ok().json(supplier)),
+        Err(e) => Ok(supplier_error_response(e, "add supplier")),
+    }
+}
+
+async fn get_supplier(
+    path: web::Path<i64>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.get_supplier(path.into_inner()).await {
+        Ok(supplier) => Ok(HttpResponse::Ok().json(supplier)),
+        Err(e) => Ok(supplier_error_response(e, "fetch supplier")),
+    }
+}
+
+async fn update_supplier(
+    path: web::Path<i64>,
+    patch: web::Json<SupplierPatch>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.update_supplier(path.into_inner(), patch.into_inner()).await {
+        Ok(supplier) => Ok(HttpResponse::Ok().json(supplier)),
+        Err(e) => Ok(supplier_error_response(e, "update supplier")),
+    }
+}
+
+async fn delete_supplier(
+    path: web::Path<i64>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.delete_supplier(path.into_inner()).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({
+            "message": "Supplier deleted successfully"
+        }))),
+        Err(e) => Ok(supplier_error_response(e, "delete supplier")),
+    }
+}
+
+async fn get_supplier_products(
+    path: web::Path<i64>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.get_supplier_products(path.into_inner()).await {
+        Ok(products) => Ok(HttpResponse::Ok().json(products)),
+        Err(e) => Ok(supplier_error_response(e, "fetch supplier products")),
+    }
+}
+
+#[derive(Deserialize)]
+struct SpendQuery {
+    /// Count receipts and orders from this date (YYYY-MM-DD)
+    since: Option<chrono::NaiveDate>,
+}
+
+async fn get_supplier_spend(
+    query: web::Query<SpendQuery>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.supplier_spend(query.since).await {
+        Ok(spend) => Ok(HttpResponse::Ok().json(spend)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to fetch supplier spend: {}", e)
+        })))
+    }
+}
+
+fn supplier_error_response(error: AppError, action: &str) -> HttpResponse {
+    match error {
+        AppError::NotFound => HttpResponse::NotFound().json(json!({
+            "error": "Supplier not found"
+        })),
+        e => HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to {}: {}", action, e)
+        })),
+    }
+}
+
+#[derive(Deserialize)]
+struct RatesQuery {
+    currency: Option<String>,
+}
+
+async fn get_exchange_rates(
+    query: web::Query<RatesQuery>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    let currency = query.currency.as_ref().map(|currency| currency.to_ascii_uppercase());
+    match db.get_exchange_rates(currency.as_deref()).await {
+        Ok(rates) => Ok(HttpResponse::Ok().json(rates)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to fetch exchange rates: {}", e)
+        })))
+    }
+}
+
+async fn set_exchange_rate(
+    input: web::Json<ExchangeRateInput>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    let mut input = input.into_inner();
+    input.currency = input.currency.trim().to_ascii_uppercase();
+    match db.set_exchange_rate(input).await {
+        Ok(rate) => Ok(HttpResponse::Ok().json(rate)),
+        Err(e) => Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to set exchange rate: {}", e)
+        })))
+    }
+}
+
+#[derive(Deserialize)]
+struct RateImportRequest {
+    csv: String,
+    /// Recorded for rows without a source column
+    source: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+async fn import_exchange_rates(
+    request: web::Json<RateImportRequest>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match csv_io::import_rates_csv(&db, request.csv.as_bytes(), request.source.as_deref(), request.dry_run).await {
+        Ok(report) if report.errors.is_empty() => Ok(HttpResponse::Ok().json(report)),
+        Ok(report) => Ok(HttpResponse::UnprocessableEntity().json(report)),
+        Err(e) => Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to import exchange rates: {}", e)
+        })))
+    }
+}
+
+async fn delete_exchange_rate(
+    path: web::Path<(String, chrono::NaiveDate)>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    let (currency, valid_from) = path.into_inner();
+    match db.delete_exchange_rate(&currency.to_ascii_uppercase(), valid_from).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({
+            "message": "Exchange rate deleted successfully"
+        }))),
+        Err(AppError::NotFound) => Ok(HttpResponse::NotFound().json(json!({
+            "error": "Exchange rate not found"
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to delete exchange rate: {}", e)
+        })))
+    }
+}
+
+async fn get_reorder_suggestions(
+    db: web::Data<Arc<Database>>,
+    inventory: web::Data<InventoryConfig>,
+    reorder: web::Data<ReorderConfig>
+) -> Result<HttpResponse> {
+    match db.reorder_suggestions(&inventory, &reorder).await {
+        Ok(suggestions) => Ok(HttpResponse::Ok().json(suggestions)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to compute reorder suggestions: {}", e)
+        })))
+    }
+}
+
+async fn draft_purchase_orders(
+    db: web::Data<Arc<Database>>,
+    inventory: web::Data<InventoryConfig>,
+    reorder: web::Data<ReorderConfig>
+) -> Result<HttpResponse> {
+    match db.draft_purchase_orders(&inventory, &reorder).await {
+        Ok(orders) => Ok(HttpResponse::Ok().json(orders)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to draft purchase orders: {}", e)
+        })))
+    }
+}
+
+#[derive(Deserialize)]
+struct OrderQuery {
+    /// `draft`, `approved`, `received` or `cancelled`; all orders when missing
+    status: Option<String>,
+}
+
+async fn get_purchase_orders(
+    query: web::Query<OrderQuery>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    let status = match query.status.as_deref().map(str::parse::<OrderStatus>).transpose() {
+        Ok(status) => status,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to fetch purchase orders: {}", e)
+        }))),
+    };
+    
+    match db.get_purchase_orders(status).await {
+        Ok(orders) => Ok(HttpResponse::Ok().json(orders)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to fetch purchase orders: {}", e)
+        })))
+    }
+}
+
+async fn get_purchase_order(
+    path: web::Path<i64>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.get_purchase_order(path.into_inner()).await {
+        Ok(order) => Ok(HttpResponse::Ok().json(order)),
+        Err(e) => Ok(order_error_response(e, "fetch purchase order")),
+    }
+}
+
+async fn update_purchase_order(
+    path: web::Path<i64>,
+    update: web::Json<PurchaseOrderUpdate>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    match db.update_purchase_order(path.into_inner(), update.into_inner()).await {
+        Ok(order) => Ok(HttpResponse::Ok().json(order)),
+        Err(e) => Ok(order_error_response(e, "update purchase order")),
+    }
+}
+
+#[derive(Deserialize)]
+struct OrderLineUpdate {
+    quantity: i32,
+}
+
+async fn set_order_line(
+    path: web::Path<(i64, String)>,
+    update: web::Json<OrderLineUpdate>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    let (id, barcode) = path.into_inner();
+    match db.set_order_line(id, &barcode, update.quantity).await {
+        Ok(order) => Ok(HttpResponse::Ok().json(order)),
+        Err(AppError::NotFound) => Ok(HttpResponse::NotFound().json(json!({
+            "error": "Purchase order or product not found"
+        }))),
+        Err(e) => Ok(order_error_response(e, "update purchase order")),
+    }
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    /// `csv` (the default) or `pdf`
+    format: Option<String>,
+}
+
+async fn export_purchase_order(
+    path: web::Path<i64>,
+    query: web::Query<ExportQuery>,
+    db: web::Data<Arc<Database>>
+) -> Result<HttpResponse> {
+    let order = match db.get_purchase_order(path.into_inner()).await {
+        Ok(order) => order,
+        Err(e) => return Ok(order_error_response(e, "export purchase order")),
+    };
+    
+    let (content_type, extension, exported) = match query.format.as_deref().unwrap_or("csv") {
+        "csv" => {
+            let mut body = Vec::new();
+            let exported = csv_io::export_order_csv(&order, &mut body).map(|()| body);
+            ("text/csv; charset=utf-8", "csv", exported)
+        }
+        "pdf" => ("application/pdf", "pdf", order_pdf::render(&order)),
+        other => return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to export purchase order: unknown format {:?}", other)
+        }))),
+    };
+    
+    match exported {
+        Ok(body) => Ok(HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"purchase-order-{}.{}\"", order.id, extension)))
+            .body(body)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to export purchase order: {}", e)
+        })))
+    }
+}
+
+fn order_error_response(error: AppError, action: &str) -> HttpResponse {
+    match error {
+        AppError::NotFound => HttpResponse::NotFound().json(json!({
+            "error": "Purchase order not found"
+        })),
+        e => HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to {}: {}", action, e)
+        })),
+    }
+}
+
+async fn get_code_schemes(codes: web::Data<CodeSchemes>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(codes.get_ref()))
+}
+
+#[derive(Deserialize)]
+struct DecodeQuery {
+    code: String,
+}
+
+async fn decode_code(query: web::Query<DecodeQuery>, codes: web::Data<CodeSchemes>) -> Result<HttpResponse> {
+    match codes.parse_internal_code(&query.code) {
+        Ok(decoded) => Ok(HttpResponse::Ok().json(decoded)),
+        Err(e) => Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to decode code: {}", e)
+        }))),
+    }
+}
+
+async fn get_pricing_rules(pricing: web::Data<PricingRules>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(pricing.get_ref()))
+}
+
+#[derive(Deserialize)]
+struct RecalculateRequest {
+    #[serde(flatten)]
+    scope: PricingScope,
+    /// Write the new prices; without it the response is only a preview
+    #[serde(default)]
+    apply: bool,
+    #[serde(flatten)]
+    note: ChangeNote,
+}
+
+async fn recalculate_prices(
+    request: web::Json<RecalculateRequest>,
+    db: web::Data<Arc<Database>>,
+    pricing: web::Data<PricingRules>
+) -> Result<HttpResponse> {
+    match db.recalculate_prices(&pricing, &request.scope, request.apply, &request.note).await {
+        Ok(report) => Ok(HttpResponse::Ok().json(report)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to recalculate prices: {}", e)
+        })))
+    }
+}
+
+#[derive(Deserialize)]
+struct ReportQuery {
+    #[serde(flatten)]
+    params: ReportParams,
+    /// `json` (the default), `text`, `csv`, `html` or `pdf`
+    format: Option<String>,
+}
+
+async fn get_report(
+    path: web::Path<String>,
+    query: web::Query<ReportQuery>,
+    db: web::Data<Arc<Database>>,
+    report_config: web::Data<ReportsConfig>
+) -> Result<HttpResponse> {
+    let kind: ReportKind = match path.into_inner().parse() {
+        Ok(kind) => kind,
+        Err(e) => return Ok(HttpResponse::NotFound().json(json!({
+            "error": format!("{}", e)
+        }))),
+    };
+    let query = query.into_inner();
+    let format = query.format.as_deref().unwrap_or("json").parse::<ReportFormat>();
+    let period = query.params.period(report_config.period_days, chrono::Local::now().date_naive());
+    let (format, (from, to)) = match (format, period) {
+        (Ok(format), Ok(period)) => (format, period),
+        (Err(e), _) | (_, Err(e)) => return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Failed to generate report: {}", e)
+        }))),
+    };
+    
+    let rendered = match db.report_data(from, to).await {
+        Ok(data) => reports::build(kind, &data, &query.params).and_then(|report| report.render(format)),
+        Err(e) => Err(e),
+    };
+    match rendered {
+        Ok(body) if format == ReportFormat::Json => Ok(HttpResponse::Ok().content_type(format.content_type()).body(body)),
+        Ok(body) => Ok(HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header(("Content-Disposition", format!("inline; filename=\"{}-{}-{}.{}\"", kind.as_str(), from, to, format.extension())))
+            .body(body)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to generate report: {}", e)
+        })))
+    }
+}
+
+async fn get_rules(
+    db: web::Data<Arc<Database>>,
+    rules: web::Data<RuleSet>
+) -> Result<HttpResponse> {
+    match db.load_rules(&rules).await {
+        Ok(rules) => Ok(HttpResponse::Ok().json(rules.rules)),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to fetch rules: {}", e)
+        })))
+    }
+}
+
+#[derive(Deserialize)]
+struct RuleUpdate {
+    enabled: bool,
+}
+
+async fn update_rule(
+    path: web::Path<String>,
+    update: web::Json<RuleUpdate>,
+    db: web::Data<Arc<Database>>,
+    rules: web::Data<RuleSet>
+) -> Result<HttpResponse> {
+    let id = path.into_inner();
+    if rules.get(&id).is_none() {
+        return Ok(HttpResponse::NotFound().json(json!({
+            "error": "Rule not found"
+        })));
+    }
+    
+    match db.set_rule_enabled(&id, update.enabled).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({
+            "id": id,
+            "enabled": update.enabled
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to update rule: {}", e)
+        })))
+    }
+}
+
+async fn get_stats(
+    db: web::Data<Arc<Database>>,
+    inventory: web::Data<InventoryConfig>,
+    rules: web::Data<RuleSet>
+) -> Result<HttpResponse> {
+    let loaded = match db.refresh_alerts(&inventory, &rules).await {
+        Ok(_) => tokio::try_join!(db.get_all_products(), db.get_alerts(Some(AlertStatus::Open))),
+        Err(e) => Err(e),
+    };
+    match loaded {
+        Ok((products, alerts)) => {
             let total_products = products.len();
             let total_stock: i32 = products.iter().map(|p| p.stock_quantity).sum();
             let low_stock_count = products.iter().filter(|p| p.stock_quantity < p.min_threshold).count();
-            let alerts = InventoryManager::check_inventory(&products);
-            let expiring_soon = alerts.iter().filter(|a| a.alert_type == "Expiry Warning").count();
+            let expiring_soon = alerts.iter().filter(|a| a.alert_type == AlertType::ExpiryWarning).count();
             
             Ok(HttpResponse::Ok().json(json!({
                 "total_products": total_products,
@@ -121,4 +1076,77 @@ async fn get_stats(db: web::Data<Arc<Database>>) -> Result<HttpResponse> {
             "error": format!("Failed to fetch stats: {}", e)
         })))
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+    use crate::database::tests::{chickpeas, memory_db};
+    
+    async fn product_db() -> Arc<Database> {
+        let db = memory_db().await;
+        db.add_product(chickpeas("5281234567896"), &ChangeNote::default()).await.unwrap();
+        Arc::new(db)
+    }
+    
+    #[actix_web::test]
+    async fn put_rejects_an_invalid_product() {
+        let db = product_db().await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(routes)).await;
+        let mut product = serde_json::to_value(db.get_product_by_barcode("5281234567896").await.unwrap()).unwrap();
+        product["stock_quantity"] = json!(-5);
+        product["case_pack"] = json!(0);
+        
+        let request = test::TestRequest::put().uri("/api/products/5281234567896").set_json(&product).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), 400);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert!(body["error"].as_str().unwrap().contains("stock_quantity must not be negative"), "{}", body);
+        assert_eq!(db.get_product_by_barcode("5281234567896").await.unwrap().stock_quantity, 24);
+    }
+    
+    #[actix_web::test]
+    async fn patch_rejects_an_invalid_product() {
+        let db = product_db().await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(routes)).await;
+        
+        let request = test::TestRequest::patch()
+            .uri("/api/products/5281234567896")
+            .set_json(json!({ "retail_price": "-3", "expiry_date": "2000-01-01" }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), 400);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        let error = body["error"].as_str().unwrap();
+        assert!(error.contains("retail_price must not be negative"), "{}", error);
+        assert!(error.contains("expiry_date must not be before production_date"), "{}", error);
+        
+        let request = test::TestRequest::patch()
+            .uri("/api/products/5281234567896")
+            .set_json(json!({ "barcode": "5281234567890" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 400);
+    }
+    
+    #[actix_web::test]
+    async fn patch_regenerates_the_internal_code_only_when_asked() {
+        let db = product_db().await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(routes)).await;
+        let printed = db.get_product_by_barcode("5281234567896").await.unwrap().internal_code;
+        
+        let request = test::TestRequest::patch()
+            .uri("/api/products/5281234567896")
+            .set_json(json!({ "batch_id": 7 }))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(body["internal_code"], printed);
+        
+        let request = test::TestRequest::patch()
+            .uri("/api/products/5281234567896?regenerate_code=true")
+            .set_json(json!({ "batch_id": 7 }))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_ne!(body["internal_code"], printed);
+        assert!(body["internal_code"].as_str().unwrap().contains("-007-"), "{}", body);
+    }
+}